@@ -1,46 +1,8 @@
-use clap::{Parser, ValueEnum};
+use clap::Parser;
 use photo::ImageRGBA;
 use rand::rng;
-use std::{num::ParseIntError, path::PathBuf, str::FromStr};
-use wave_function::{Map, Tileset, WaveFunctionBacktracking, WaveFunctionFast};
-
-/// Only these three algorithms allowed
-#[derive(ValueEnum, Debug, Clone)]
-enum Algorithm {
-    Fast,
-    Backtracking,
-}
-
-/// Holds “NxM” and parses into two usize fields
-#[derive(Debug, Clone)]
-struct MapSize {
-    width: usize,
-    height: usize,
-}
-
-impl FromStr for MapSize {
-    type Err = String;
-    fn from_str(s: &str) -> Result<Self, String> {
-        let mut parts = s.split('x');
-        let w = parts
-            .next()
-            .ok_or("missing width")?
-            .parse()
-            .map_err(|e: ParseIntError| e.to_string())?;
-        let h = parts
-            .next()
-            .ok_or("missing height")?
-            .parse()
-            .map_err(|e: ParseIntError| e.to_string())?;
-        if parts.next().is_some() {
-            return Err("too many parts".into());
-        }
-        Ok(MapSize {
-            width: w,
-            height: h,
-        })
-    }
-}
+use std::path::PathBuf;
+use wave_function::{AlgorithmRegistry, Map, MapSize, Tileset};
 
 /// Image processing configuration.
 #[derive(Parser, Debug)]
@@ -52,8 +14,9 @@ struct Config {
     #[arg(short, long)]
     output_filepath: PathBuf,
 
+    /// Name of a registered algorithm (fast, backtracking, local-reset)
     #[arg(short, long)]
-    algorithm: Algorithm,
+    algorithm: String,
 
     #[arg(short, long)]
     map_size: MapSize,
@@ -87,11 +50,8 @@ fn main() {
     if config.verbose {
         println!("Input image       : {}", config.input_tileset.display());
         println!("Output directory  : {}", config.output_filepath.display());
-        println!("Algorithm         : {:?}", config.algorithm);
-        println!(
-            "Map size          : {}x{}",
-            config.map_size.width, config.map_size.height
-        );
+        println!("Algorithm         : {}", config.algorithm);
+        println!("Map size          : {}", config.map_size);
         println!("Tile size         : {}", config.tile_size);
         println!("Border size       : {}", config.border_size);
     }
@@ -102,17 +62,21 @@ fn main() {
         print_tileset_images(&tileset);
     }
 
+    let registry = AlgorithmRegistry::default();
+    let algorithm = registry.get(&config.algorithm).unwrap_or_else(|| {
+        panic!(
+            "Unknown algorithm: {} (expected one of: {})",
+            config.algorithm,
+            registry.names().collect::<Vec<_>>().join(", ")
+        )
+    });
+
     let mut rng = rng();
 
-    let template = Map::empty((config.map_size.width, config.map_size.height));
-    let map = match config.algorithm {
-        Algorithm::Fast => template
-            .collapse::<WaveFunctionFast>(tileset.rules(), &mut rng)
-            .expect("Failed to collapse map"),
-        Algorithm::Backtracking => template
-            .collapse::<WaveFunctionBacktracking>(tileset.rules(), &mut rng)
-            .expect("Failed to collapse map"),
-    };
+    let template = Map::with_size(config.map_size);
+    let map = algorithm
+        .collapse_dyn(&template, tileset.rules(), &mut rng)
+        .expect("Failed to collapse map");
 
     let img = map.render(&tileset);
     img.save(&config.output_filepath)
@@ -1,9 +1,11 @@
 use clap::{Parser, ValueEnum};
-use ndarray::Array2;
-use photo::{Direction, ImageRGBA};
-use rand::{Rng, rng};
-use std::{num::ParseIntError, path::PathBuf, str::FromStr};
-use wave_function::{Map, Rules, Tileset, WaveFunctionBacktracking, WaveFunctionFast};
+use photo::ImageRGBA;
+use rand::rng;
+use std::path::PathBuf;
+use wave_function::{
+    ChunkedGenerator, Map, MapSize, Rules, Tileset, WaveFunction, WaveFunctionBacktracking,
+    WaveFunctionFast,
+};
 
 /// Only these three algorithms allowed
 #[derive(ValueEnum, Debug, Clone)]
@@ -12,37 +14,6 @@ enum Algorithm {
     Backtracking,
 }
 
-/// Holds “NxM” and parses into two usize fields
-#[derive(Debug, Clone)]
-struct MapSize {
-    width: usize,
-    height: usize,
-}
-
-impl FromStr for MapSize {
-    type Err = String;
-    fn from_str(s: &str) -> Result<Self, String> {
-        let mut parts = s.split('x');
-        let w = parts
-            .next()
-            .ok_or("missing width")?
-            .parse()
-            .map_err(|e: ParseIntError| e.to_string())?;
-        let h = parts
-            .next()
-            .ok_or("missing height")?
-            .parse()
-            .map_err(|e: ParseIntError| e.to_string())?;
-        if parts.next().is_some() {
-            return Err("too many parts".into());
-        }
-        Ok(MapSize {
-            width: w,
-            height: h,
-        })
-    }
-}
-
 /// Image processing configuration.
 #[derive(Parser, Debug)]
 #[clap(author, version, about)]
@@ -68,6 +39,10 @@ struct Config {
     #[arg(short, long)]
     border_size: usize,
 
+    /// Generate chunks which do not touch in parallel
+    #[arg(short, long)]
+    parallel: bool,
+
     #[clap(short, long)]
     verbose: bool,
 }
@@ -86,22 +61,34 @@ fn print_tileset_images(tileset: &Tileset) {
     .unwrap();
 }
 
+// Generate the map chunk by chunk, so each chunk is collapsed against the edges of those around it
+fn generate<W: WaveFunction + Sync>(
+    generator: &ChunkedGenerator,
+    template: &Map,
+    algorithm: &W,
+    rules: &Rules,
+    rng: &mut impl rand::Rng,
+    parallel: bool,
+) -> Map {
+    if parallel {
+        generator.generate_parallel(template, algorithm, rules, rng)
+    } else {
+        generator.generate(template, algorithm, rules, rng)
+    }
+    .expect("Failed to generate map")
+}
+
 fn main() {
     let config = Config::parse();
     if config.verbose {
         println!("Input image       : {}", config.input_tileset.display());
         println!("Output directory  : {}", config.output_filepath.display());
         println!("Algorithm         : {:?}", config.algorithm);
-        println!(
-            "Chunk size        : {}x{}",
-            config.chunk_size.width, config.chunk_size.height
-        );
-        println!(
-            "Number of chunks  : {}x{}",
-            config.num_chunks.width, config.num_chunks.height
-        );
+        println!("Chunk size        : {}", config.chunk_size);
+        println!("Number of chunks  : {}", config.num_chunks);
         println!("Tile size         : {}", config.tile_size);
         println!("Border size       : {}", config.border_size);
+        println!("Parallel          : {}", config.parallel);
     }
 
     let tileset = Tileset::load(config.tile_size, config.border_size, &config.input_tileset);
@@ -112,91 +99,34 @@ fn main() {
 
     let mut rng = rng();
 
-    // Initialize array of empty chunks with valid dimensions
-    let mut chunks = Array2::from_elem(
-        (config.num_chunks.height, config.num_chunks.width),
-        Map::empty((config.chunk_size.width, config.chunk_size.height)),
-    );
-
-    // Define a function to collapse a chunk based on the selected algorithm
-    fn collapse_map<R: rand::Rng>(
-        map: Map,
-        rules: &Rules,
-        rng: &mut R,
-        algorithm: &Algorithm,
-    ) -> Map {
-        match algorithm {
-            Algorithm::Fast => map
-                .collapse::<WaveFunctionFast>(rules, rng)
-                .expect("Failed to collapse map"),
-            Algorithm::Backtracking => map
-                .collapse::<WaveFunctionBacktracking>(rules, rng)
-                .expect("Failed to collapse map"),
-        }
-    }
-
-    // Generate chunks in a deterministic order to ensure border consistency
-
-    // First, generate all chunks independently
-    for y in 0..config.num_chunks.height {
-        for x in 0..config.num_chunks.width {
-            let empty_map = Map::empty((config.chunk_size.width, config.chunk_size.height));
-            chunks[(y, x)] = collapse_map(empty_map, tileset.rules(), &mut rng, &config.algorithm);
-
-            if config.verbose {
-                println!("Generated initial chunk at position ({}, {})", x, y);
-            }
-        }
-    }
-
-    // Process borders in a way that avoids borrow checker issues
-    // We'll use a separate loop for each direction
-
-    // Process North-South borders (rows)
-    for y in 1..config.num_chunks.height {
-        for x in 0..config.num_chunks.width {
-            // Create a bordering chunk from the northern neighbor
-            let border = chunks[(y - 1, x)].bordering_chunk(Direction::South, config.border_size);
-
-            // Create a new map with the border constraints
-            let mut new_map = Map::empty((config.chunk_size.width, config.chunk_size.height));
-            new_map.set_shared_border(&border, Direction::North, config.border_size);
-
-            // Collapse the map with these constraints and update the chunk
-            chunks[(y, x)] = collapse_map(new_map, tileset.rules(), &mut rng, &config.algorithm);
-
-            if config.verbose {
-                println!("Processed North-South border at ({}, {})", x, y);
-            }
-        }
-    }
-
-    // Process West-East borders (columns)
-    for x in 1..config.num_chunks.width {
-        for y in 0..config.num_chunks.height {
-            // Create a bordering chunk from the western neighbor
-            let border = chunks[(y, x - 1)].bordering_chunk(Direction::East, config.border_size);
-
-            // Create a new map with the border constraints
-            let mut new_map = Map::empty((config.chunk_size.width, config.chunk_size.height));
-            new_map.set_shared_border(&border, Direction::West, config.border_size);
-
-            // Collapse the map with these constraints and update the chunk
-            chunks[(y, x)] = collapse_map(new_map, tileset.rules(), &mut rng, &config.algorithm);
-
-            if config.verbose {
-                println!("Processed West-East border at ({}, {})", x, y);
-            }
-        }
-    }
-
-    // Render all chunks and merge into one image
-    let imgs = chunks
-        .mapv(|c| c.render(&tileset))
-        .map(|img| img.interior(config.border_size / 2));
-
-    // Create final image from tiles
-    let img = ImageRGBA::from_tiles(&imgs);
+    let generator = ChunkedGenerator::builder()
+        .chunk_size(config.chunk_size.height_width())
+        .build();
+    let template = Map::with_size(MapSize {
+        width: config.chunk_size.width * config.num_chunks.width,
+        height: config.chunk_size.height * config.num_chunks.height,
+    });
+
+    let map = match config.algorithm {
+        Algorithm::Fast => generate(
+            &generator,
+            &template,
+            &WaveFunctionFast::default(),
+            tileset.rules(),
+            &mut rng,
+            config.parallel,
+        ),
+        Algorithm::Backtracking => generate(
+            &generator,
+            &template,
+            &WaveFunctionBacktracking::default(),
+            tileset.rules(),
+            &mut rng,
+            config.parallel,
+        ),
+    };
+
+    let img = map.render(&tileset);
     img.save(&config.output_filepath)
         .expect("Failed to save image");
 }
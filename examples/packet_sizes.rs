@@ -0,0 +1,94 @@
+use clap::Parser;
+use rand::rng;
+use std::{path::PathBuf, time::Instant};
+use wave_function::{AlgorithmRegistry, Map, MapSize, PacketCompression, Tileset};
+
+/// Compare the sizes of the encodings available for sending generated maps to clients.
+#[derive(Parser, Debug)]
+#[clap(author, version, about)]
+struct Config {
+    #[arg(short, long)]
+    input_tileset: PathBuf,
+
+    /// Name of a registered algorithm (fast, backtracking, local-reset)
+    #[arg(short, long)]
+    algorithm: String,
+
+    #[arg(short, long)]
+    map_size: MapSize,
+
+    #[arg(short = 's', long)]
+    tile_size: usize,
+
+    #[arg(short, long)]
+    border_size: usize,
+
+    /// Fraction of cells re-collapsed to make the map sent as a delta
+    #[arg(short = 'r', long, default_value_t = 0.05)]
+    mutation_rate: f64,
+}
+
+fn report(name: &str, packet: &[u8], raw_size: usize, decode: impl Fn(&[u8]) -> Map) {
+    let start = Instant::now();
+    let decoded = decode(packet);
+    let elapsed = start.elapsed();
+    assert_eq!(decoded.size().0 * decoded.size().1, raw_size);
+    println!(
+        "{:<18}: {:>9} bytes ({:>6.2} bits per cell), decoded in {:?}",
+        name,
+        packet.len(),
+        8.0 * packet.len() as f64 / raw_size as f64,
+        elapsed
+    );
+}
+
+fn main() {
+    let config = Config::parse();
+    let tileset = Tileset::load(config.tile_size, config.border_size, &config.input_tileset);
+    let registry = AlgorithmRegistry::default();
+    let algorithm = registry.get(&config.algorithm).unwrap_or_else(|| {
+        panic!(
+            "Unknown algorithm: {} (expected one of: {})",
+            config.algorithm,
+            registry.names().collect::<Vec<_>>().join(", ")
+        )
+    });
+
+    let template = Map::with_size(config.map_size);
+    let map = algorithm
+        .collapse_dyn(&template, tileset.rules(), &mut rng())
+        .expect("Failed to collapse map");
+    let edited = map
+        .variant(0, config.mutation_rate, tileset.rules())
+        .expect("Failed to collapse variant");
+    let num_cells = config.map_size.num_cells();
+
+    println!("Map size          : {}", config.map_size);
+    println!("Number of tiles   : {}", tileset.len());
+    println!("{:<18}: {:>9} bytes", "Text", map.to_string().len());
+
+    let decode = |packet: &[u8]| Map::from_packet(packet, None).expect("Failed to decode packet");
+    let decode_delta =
+        |packet: &[u8]| Map::from_packet(packet, Some(&map)).expect("Failed to decode packet");
+
+    report(
+        "RLE",
+        &map.to_packet(PacketCompression::None),
+        num_cells,
+        decode,
+    );
+    let delta = edited
+        .to_delta_packet(&map, PacketCompression::None)
+        .expect("Failed to encode delta");
+    report("Delta + RLE", &delta, num_cells, decode_delta);
+
+    #[cfg(feature = "zstd")]
+    {
+        let zstd = PacketCompression::Zstd(3);
+        report("RLE + zstd", &map.to_packet(zstd), num_cells, decode);
+        let delta = edited
+            .to_delta_packet(&map, zstd)
+            .expect("Failed to encode delta");
+        report("Delta + RLE + zstd", &delta, num_cells, decode_delta);
+    }
+}
@@ -0,0 +1,6 @@
+cargo run --release --features zstd --example packet_sizes -- \
+    --input-tileset ./output/tiles/tiles.txt \
+    --algorithm fast \
+    --map-size 256x256 \
+    --tile-size 3 \
+    --border-size 1
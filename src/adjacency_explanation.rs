@@ -0,0 +1,187 @@
+#[cfg(feature = "render")]
+use ndarray::{Array3, ArrayView1, s};
+#[cfg(feature = "render")]
+use photo::ImageRGBA;
+use std::sync::Arc;
+
+use crate::{Direction, Rules};
+
+#[cfg(feature = "render")]
+const MISMATCH_COLOUR: [u8; 4] = [255, 0, 0, 255]; // Marks the border pixels which differ in a diff image
+
+/// Why the rules allow or forbid one tile in a direction of another, as given by `Rules::explain_adjacency`.
+#[derive(Clone)]
+pub struct AdjacencyExplanation {
+    /// Whether the rules allow the neighbour in that direction.
+    pub allowed: bool,
+    /// What the adjacency was derived from.
+    pub reason: AdjacencyReason,
+}
+
+/// What an adjacency rule was derived from.
+#[derive(Clone)]
+pub enum AdjacencyReason {
+    /// The rules were built by `Rules::from_sockets`, which allows a neighbour when its socket on the shared
+    /// edge equals the tile's.
+    Sockets {
+        socket: String,
+        other_socket: String,
+    },
+    /// The rules belong to a tileset with borders, whose adjacencies are derived by comparing the facing
+    /// borders pixel by pixel.
+    #[cfg(feature = "render")]
+    Borders(BorderComparison),
+    /// The rules were given directly, such as by an adjacency matrix or examples, so there is nothing to explain
+    /// the adjacency by.
+    Given,
+}
+
+/// How the borders of two tiles compare along a shared edge.
+#[cfg(feature = "render")]
+#[derive(Clone)]
+pub struct BorderComparison {
+    /// Whether the border pixels match exactly, which is how adjacencies are derived from tile images.
+    /// This differs from the rules if they were edited or loaded from a hand-written tileset file.
+    pub borders_match: bool,
+    /// Number of border pixels which differ between the two tiles.
+    pub num_mismatched: usize,
+    /// Number of pixels in the shared border.
+    pub num_pixels: usize,
+    /// The tile's border, with every pixel that differs from the neighbour's border painted red.
+    pub diff: ImageRGBA<u8>,
+}
+
+#[cfg(feature = "render")]
+impl BorderComparison {
+    /// Compare the border of a tile with the facing border of its neighbour.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the borders are not the same size.
+    #[must_use]
+    pub fn new(border: &ImageRGBA<u8>, other_border: &ImageRGBA<u8>) -> Self {
+        let (border, other_border) = (&border.data, &other_border.data);
+        assert_eq!(
+            border.dim(),
+            other_border.dim(),
+            "Tile borders must be the same size"
+        );
+
+        let (height, width, _) = border.dim();
+        let mut diff = Array3::zeros((height, width, 4));
+        let mut num_mismatched = 0;
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = border.slice(s![y, x, ..]);
+                let matches = pixel == other_border.slice(s![y, x, ..]);
+                let mut diff_pixel = diff.slice_mut(s![y, x, ..]);
+                if matches {
+                    diff_pixel.assign(&pixel);
+                } else {
+                    num_mismatched += 1;
+                    diff_pixel.assign(&ArrayView1::from(&MISMATCH_COLOUR));
+                }
+            }
+        }
+
+        Self {
+            borders_match: num_mismatched == 0,
+            num_mismatched,
+            num_pixels: height * width,
+            diff: ImageRGBA::new(diff),
+        }
+    }
+}
+
+// What the adjacencies of a rule set were derived from, kept so they can be explained.
+// Shared between clones of the rules, which are frequent and never change it.
+#[derive(Clone)]
+pub(crate) enum AdjacencySource {
+    // Socket label on each side of each tile, indexed by `Direction::index()`
+    Sockets(Arc<Vec<[String; 4]>>),
+    // Border of each tile on each side, indexed by `Direction::index()`
+    #[cfg(feature = "render")]
+    Borders(Arc<Vec<[ImageRGBA<u8>; 4]>>),
+}
+
+impl AdjacencySource {
+    // Source of a subset of the tiles, where tile `tiles[n]` becomes tile `n`, as in `Rules::restrict_to`
+    pub(crate) fn restrict_to(&self, tiles: &[usize]) -> Self {
+        match self {
+            Self::Sockets(sockets) => Self::Sockets(Arc::new(
+                tiles.iter().map(|&tile| sockets[tile].clone()).collect(),
+            )),
+            #[cfg(feature = "render")]
+            Self::Borders(borders) => Self::Borders(Arc::new(
+                tiles.iter().map(|&tile| borders[tile].clone()).collect(),
+            )),
+        }
+    }
+}
+
+impl Rules {
+    /// Explain whether `other` may be placed in a direction of `tile`, by the sockets or tile borders the
+    /// adjacency was derived from.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either tile is not part of the rules.
+    #[must_use]
+    pub fn explain_adjacency(
+        &self,
+        tile: usize,
+        other: usize,
+        direction: Direction,
+    ) -> AdjacencyExplanation {
+        assert!(
+            tile < self.len() && other < self.len(),
+            "Tile index out of bounds for ruleset"
+        );
+        let (side, other_side) = (direction.index(), direction.opposite().index());
+        let reason = match self.adjacency_source() {
+            Some(AdjacencySource::Sockets(sockets)) => AdjacencyReason::Sockets {
+                socket: sockets[tile][side].clone(),
+                other_socket: sockets[other][other_side].clone(),
+            },
+            #[cfg(feature = "render")]
+            Some(AdjacencySource::Borders(borders)) => AdjacencyReason::Borders(
+                BorderComparison::new(&borders[tile][side], &borders[other][other_side]),
+            ),
+            None => AdjacencyReason::Given,
+        };
+        AdjacencyExplanation {
+            allowed: self.is_allowed(tile, other, direction),
+            reason,
+        }
+    }
+
+    /// Explain why `other` may be placed in a direction of `tile`, or `None` if the rules forbid it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either tile is not part of the rules.
+    #[must_use]
+    pub fn why_allowed(
+        &self,
+        tile: usize,
+        other: usize,
+        direction: Direction,
+    ) -> Option<AdjacencyExplanation> {
+        Some(self.explain_adjacency(tile, other, direction)).filter(|e| e.allowed)
+    }
+
+    /// Explain why `other` may not be placed in a direction of `tile`, or `None` if the rules allow it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either tile is not part of the rules.
+    #[must_use]
+    pub fn why_forbidden(
+        &self,
+        tile: usize,
+        other: usize,
+        direction: Direction,
+    ) -> Option<AdjacencyExplanation> {
+        Some(self.explain_adjacency(tile, other, direction)).filter(|e| !e.allowed)
+    }
+}
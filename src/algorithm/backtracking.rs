@@ -1,347 +1,579 @@
-use anyhow::{Result, bail};
-use fixedbitset::FixedBitSet;
-use indicatif::{ProgressBar, ProgressStyle};
+use anyhow::Result;
 use ndarray::Array2;
-use rand::{distr::weighted::WeightedIndex, prelude::*};
-use std::collections::{HashMap, HashSet};
+use rand::prelude::*;
+use std::collections::{BTreeSet, HashSet};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
-use super::common::{calculate_neighbours, initial_propagation, propagate_constraints};
-use crate::{Cell, Map, Rules, WaveFunction};
-
-const MAX_ITERATIONS: usize = 1_000_000; // Max iterations for constraint propagation
-const MAX_BACKTRACK_ATTEMPTS: usize = 100; // Max number of backtracking attempts
-const MAX_BACKTRACK_DEPTH: usize = 50; // Max depth for backtracking stack
-
-// Structure to store state for backtracking
-#[derive(Clone)]
-pub struct BacktrackState {
-    // Modified state tracking
-    pub changed_cells: HashSet<(usize, usize)>,
-    pub domain_copies: HashMap<(usize, usize), FixedBitSet>,
-    pub domain_size_copies: HashMap<(usize, usize), usize>,
-    pub cell: (usize, usize),
-    pub tried_values: HashSet<usize>,
-    pub collapsed_cells: HashSet<(usize, usize)>,
+use super::{
+    common::{Boundary, Neighbour, calculate_neighbours, choose_weighted_at},
+    error::WfcError,
+    nogoods::{Assignment, Explainer, MAX_NOGOOD_SIZE, Nogoods},
+    options::CollapseOptions,
+    precheck::check_quotas_with_boundary,
+    progress::{CellProgress, ProgressSink},
+    selection::Heuristic,
+    stats::CollapseStats,
+    support::{Consistency, ReviseStrategy, Supports},
+    wave::Wave,
+};
+use crate::{AlgorithmInfo, Map, Provenance, Rules, WaveFunction};
+
+// A decision which can be revisited: the cell and its tile, the tiles already tried for it, and where the
+// wave's trail stood before it was made, so undoing the trail back to there restores the domains it was made from
+struct BacktrackState {
+    cell: (usize, usize),
+    tile: usize,
+    tried_values: HashSet<usize>,
+    // Earlier decisions which, with each tile tried, led to a contradiction, or `None` if too many did to be
+    // worth recording
+    conflicts: Option<BTreeSet<Assignment>>,
+    trail_mark: usize,
+    // Cells collapsed before the decision
+    collapsed: usize,
 }
 
-pub struct WaveFunctionBacktracking;
+pub struct WaveFunctionBacktracking {
+    options: CollapseOptions,
+    revise_strategy: ReviseStrategy,
+    consistency: Consistency,
+    max_nogood_size: usize,
+}
+
+impl WaveFunctionBacktracking {
+    #[must_use]
+    pub fn builder() -> WaveFunctionBacktrackingBuilder {
+        WaveFunctionBacktrackingBuilder::default()
+    }
+}
+
+impl Default for WaveFunctionBacktracking {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+/// Builder for configuring a `WaveFunctionBacktracking`.
+pub struct WaveFunctionBacktrackingBuilder {
+    options: CollapseOptions,
+    revise_strategy: ReviseStrategy,
+    consistency: Consistency,
+    max_nogood_size: usize,
+}
+
+impl Default for WaveFunctionBacktrackingBuilder {
+    fn default() -> Self {
+        Self {
+            options: CollapseOptions::default(),
+            revise_strategy: ReviseStrategy::default(),
+            consistency: Consistency::default(),
+            max_nogood_size: MAX_NOGOOD_SIZE,
+        }
+    }
+}
+
+impl WaveFunctionBacktrackingBuilder {
+    /// Maximum number of constraint revisions in a single propagation.
+    #[must_use]
+    pub fn max_iterations(mut self, max_iterations: usize) -> Self {
+        self.options.max_iterations = max_iterations;
+        self
+    }
+
+    /// Maximum number of backtracks before giving up.
+    #[must_use]
+    pub fn max_backtrack_attempts(mut self, max_backtrack_attempts: usize) -> Self {
+        self.options.max_backtracks = max_backtrack_attempts;
+        self
+    }
+
+    /// Maximum number of decisions kept on the backtracking stack.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the depth is zero.
+    #[must_use]
+    pub fn max_backtrack_depth(mut self, max_backtrack_depth: usize) -> Self {
+        assert!(
+            max_backtrack_depth > 0,
+            "Backtrack depth must be greater than zero"
+        );
+        self.options.backtrack_depth = max_backtrack_depth;
+        self
+    }
+
+    /// Order in which cells are chosen for collapse.
+    #[must_use]
+    pub fn heuristic(mut self, heuristic: Heuristic) -> Self {
+        self.options.heuristic = heuristic;
+        self
+    }
+
+    /// How support for a cell's options is checked during propagation.
+    #[must_use]
+    pub fn revise_strategy(mut self, revise_strategy: ReviseStrategy) -> Self {
+        self.revise_strategy = revise_strategy;
+        self
+    }
+
+    /// How cells on the edges of the map are constrained, such as wrapping around for tileable maps.
+    #[must_use]
+    pub fn boundary(mut self, boundary: Boundary) -> Self {
+        self.options.boundary = boundary;
+        self
+    }
+
+    /// How far the consequences of each decision are propagated.
+    #[must_use]
+    pub fn consistency(mut self, consistency: Consistency) -> Self {
+        self.consistency = consistency;
+        self
+    }
+
+    /// Most decisions in a combination learned to lead to a contradiction for it to be recorded.
+    /// Tiles which would complete a recorded combination are never chosen, so the search does not walk into
+    /// the same dead end again by a different route. Zero records nothing.
+    #[must_use]
+    pub fn max_nogood_size(mut self, max_nogood_size: usize) -> Self {
+        self.max_nogood_size = max_nogood_size;
+        self
+    }
+
+    /// Where progress is reported while collapsing.
+    #[must_use]
+    pub fn progress(mut self, sink: Arc<dyn ProgressSink>) -> Self {
+        self.options.progress = sink;
+        self
+    }
+
+    /// Replace every setting shared with the other solvers at once.
+    #[must_use]
+    pub fn options(mut self, options: CollapseOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    #[must_use]
+    pub fn build(self) -> WaveFunctionBacktracking {
+        WaveFunctionBacktracking {
+            options: self.options,
+            revise_strategy: self.revise_strategy,
+            consistency: self.consistency,
+            max_nogood_size: self.max_nogood_size,
+        }
+    }
+}
 
 impl WaveFunction for WaveFunctionBacktracking {
+    fn info(&self) -> AlgorithmInfo {
+        AlgorithmInfo {
+            name: "backtracking",
+            supports_backtracking: true,
+            deterministic: true,
+            complexity: format!(
+                "As fast until a contradiction, which undoes the changes made since one of the last {} choices and tries another tile there, learning combinations of up to {} choices to avoid; gives up after {} backtracks",
+                self.options.backtrack_depth, self.max_nogood_size, self.options.max_backtracks
+            ),
+        }
+    }
+
+    fn boundary(&self) -> Boundary {
+        self.options.boundary
+    }
+
+    fn settings(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("revise_strategy", format!("{:?}", self.revise_strategy)),
+            ("consistency", format!("{:?}", self.consistency)),
+            ("max_nogood_size", self.max_nogood_size.to_string()),
+        ]
+    }
+
     /// Collapses a map using a backtracking-capable Wave Function Collapse algorithm
     /// Returns a new map with all wildcards collapsed to fixed values.
-    fn collapse(map: &Map, rules: &Rules, rng: &mut impl Rng) -> Result<Map> {
+    fn collapse(&self, map: &Map, rules: &Rules, rng: &mut impl Rng) -> Result<Map> {
+        self.collapse_with_options(map, rules, &self.options, rng)
+    }
+
+    fn collapse_with_options(
+        &self,
+        map: &Map,
+        rules: &Rules,
+        options: &CollapseOptions,
+        rng: &mut impl Rng,
+    ) -> Result<Map> {
+        Ok(self.run(map, rules, options, rng)?.0)
+    }
+
+    fn collapse_with_stats(
+        &self,
+        map: &Map,
+        rules: &Rules,
+        rng: &mut impl Rng,
+    ) -> Result<(Map, CollapseStats)> {
+        self.run(map, rules, &self.options, rng)
+    }
+}
+
+impl WaveFunctionBacktracking {
+    // Collapse a map with the given options, measuring the collapse
+    fn run(
+        &self,
+        map: &Map,
+        rules: &Rules,
+        options: &CollapseOptions,
+        rng: &mut impl Rng,
+    ) -> Result<(Map, CollapseStats)> {
+        assert!(
+            options.backtrack_depth > 0,
+            "Backtrack depth must be greater than zero"
+        );
+
+        let start_time = Instant::now();
+        let mut stats = CollapseStats::default();
+
+        check_quotas_with_boundary(map, rules, options.boundary)?;
+
         let (height, width) = map.size();
         let num_tiles = rules.len();
 
-        // Use Array2 for domains and mask
-        let mut domains = map.domains(num_tiles);
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!(
+            "collapse",
+            algorithm = "backtracking",
+            height,
+            width,
+            num_tiles
+        )
+        .entered();
+
+        // All domain changes go through the wave, which keeps domain sizes and buckets in step
         let is_ignore = map.mask();
+        let neighbors = calculate_neighbours(height, width, &is_ignore, options.boundary);
+        let mut supports = Supports::new(self.revise_strategy, (height, width), num_tiles);
+        let mut wave = Wave::from_template(
+            map,
+            rules,
+            options.heuristic,
+            self.consistency,
+            rng.random(),
+        );
 
-        // Pre-compute and cache domain sizes
-        let mut domain_sizes = Array2::from_elem((height, width), 0);
-        for y in 0..height {
-            for x in 0..width {
-                if !is_ignore[(y, x)] {
-                    domain_sizes[(y, x)] = domains[(y, x)].count_ones(..);
-                }
-            }
-        }
+        // Initial propagation - full AC-3
+        wave.propagate_all(rules, &neighbors, &mut supports, options.max_iterations)?;
 
-        // Precompute neighbors using common function
-        let neighbors = calculate_neighbours(height, width, &is_ignore);
+        // Count cells to collapse for progress bar
+        let cells_to_collapse = wave.remaining();
+
+        let pb = CellProgress::new(&*options.progress, &self.info(), cells_to_collapse);
 
-        // Initial propagation - full AC-3 using common function
-        initial_propagation(
-            &mut domains,
-            &mut domain_sizes,
+        let mut search = Search {
+            map,
             rules,
-            height,
-            width,
-            &is_ignore,
-            &neighbors,
-            MAX_ITERATIONS,
-        )?;
+            neighbors: &neighbors,
+            explainer: Explainer::new(&wave, rules, self.max_nogood_size),
+            wave,
+            supports,
+            backtrack_stack: Vec::with_capacity(options.backtrack_depth),
+            forgotten: Vec::new(),
+            nogoods: Nogoods::new(self.max_nogood_size),
+            max_nogood_size: self.max_nogood_size,
+            collapsed: 0,
+            backtracks: 0,
+        };
 
-        // Count cells to collapse for progress bar
-        let mut cells_to_collapse = 0;
-        for y in 0..height {
-            for x in 0..width {
-                if !is_ignore[(y, x)] && domain_sizes[(y, x)] > 1 {
-                    cells_to_collapse += 1;
-                }
+        // Main collapse loop with backtracking
+        while let Some(best_idx) = search.wave.next_cell() {
+            search.settle(best_idx, options, &pb, &mut stats, rng)?;
+
+            // Periodically report progress and check timeout
+            if start_time.elapsed() > Duration::from_secs(10) && search.backtracks > 0 {
+                pb.println(&format!(
+                    "Progress: {}/{cells_to_collapse} cells, {} backtracks so far",
+                    search.collapsed, search.backtracks
+                ));
             }
         }
 
-        let pb = ProgressBar::new(cells_to_collapse as u64);
-        pb.set_style(
-            ProgressStyle::with_template(
-                "{spinner:.green} [{elapsed_precise}] {bar:40.cyan/blue} {pos}/{len} cells (Backtracked: {msg})"
-            )
-            .unwrap()
-            .progress_chars("##-"),
-        );
-        pb.set_message("0");
+        pb.finish();
 
-        // More robust bucket management using HashSet to track cells by entropy
-        let mut bucket_sets: Vec<HashSet<(usize, usize)>> = vec![HashSet::new(); num_tiles + 1];
+        let Search {
+            wave, backtracks, ..
+        } = search;
+        trace_event!(info, backtracks, "collapse complete");
 
-        // Initial population of entropy buckets
-        for y in 0..height {
-            for x in 0..width {
-                if !is_ignore[(y, x)] && domain_sizes[(y, x)] > 1 {
-                    bucket_sets[domain_sizes[(y, x)]].insert((y, x));
-                }
-            }
+        // If we had to backtrack, report the final count
+        if backtracks > 0 {
+            pb.println(&format!(
+                "Completed with {backtracks} backtracking attempts"
+            ));
         }
 
-        // Backtracking stack
-        let mut backtrack_stack: Vec<BacktrackState> = Vec::with_capacity(MAX_BACKTRACK_DEPTH);
-        let mut backtrack_count = 0;
-        let mut collapsed_cells = HashSet::new();
-        let start_time = Instant::now();
+        // Build the final map
+        let mut result = wave.to_map(map)?;
 
-        // Main collapse loop with backtracking
-        'outer: while let Some(entropy) = (2..=num_tiles).find(|&e| !bucket_sets[e].is_empty()) {
-            // Extract a cell from the current entropy bucket
-            let best_idx = *bucket_sets[entropy].iter().next().unwrap();
-            bucket_sets[entropy].remove(&best_idx);
-
-            // Get available options for this cell
-            let options: Vec<usize> = domains[best_idx].ones().collect();
-            if options.is_empty() {
-                // This shouldn't happen normally, but handle it just in case
-                if backtrack_stack.is_empty() {
-                    bail!(
-                        "No options remain for cell at ({}, {}), but backtrack stack is empty",
-                        best_idx.0,
-                        best_idx.1
-                    );
-                }
+        // Undersized bodies are only visible once every cell is fixed
+        if rules.has_min_body_sizes() {
+            result = result.enforce_min_bodies(map, rules, self, options, rng)?;
+        }
+        stats.elapsed = start_time.elapsed();
+        stats.revisions = wave.revisions();
+        stats.backtracks = backtracks;
+        result.set_provenance(Some(Provenance::new(
+            self.info().name,
+            &self.settings(),
+            options,
+            rules,
+            stats.elapsed,
+            stats.backtracks + stats.restarts,
+        )));
+        Ok((result, stats))
+    }
+}
 
-                continue; // Skip this cell and try the next one
-            }
+// Decisions of one collapse which can be revisited, and what has been learned from the contradictions they
+// led to
+struct Search<'a> {
+    map: &'a Map,
+    rules: &'a Rules,
+    neighbors: &'a Array2<Vec<Neighbour>>,
+    explainer: Explainer,
+    // All domain changes go through the wave, which keeps domain sizes and buckets in step
+    wave: Wave,
+    supports: Supports,
+    // Backtracking stack, the decisions dropped from its bottom, which still hold, and the combinations of
+    // decisions learned to lead to a contradiction
+    backtrack_stack: Vec<BacktrackState>,
+    forgotten: Vec<Assignment>,
+    nogoods: Nogoods,
+    max_nogood_size: usize,
+    // Cells collapsed so far, and contradictions backtracked out of
+    collapsed: usize,
+    backtracks: usize,
+}
 
-            // Calculate weights for weighted random selection
-            let weights: Vec<usize> = options.iter().map(|&t| rules.frequencies()[t]).collect();
-
-            // Choose a tile using weighted distribution
-            let choice = if weights.iter().any(|&w| w == 0) {
-                // Handle zero weights case - use uniform distribution
-                options[rng.random_range(0..options.len())]
-            } else {
-                // Use weighted distribution
-                let dist = WeightedIndex::new(&weights).unwrap();
-                options[dist.sample(rng)]
+impl Search<'_> {
+    // Make a decision for a cell and propagate it, backtracking out of each contradiction it leads to, until
+    // the wave settles
+    fn settle(
+        &mut self,
+        cell: (usize, usize),
+        options: &CollapseOptions,
+        pb: &CellProgress,
+        stats: &mut CollapseStats,
+        rng: &mut impl Rng,
+    ) -> Result<()> {
+        let mut decision = self.decide(cell, options.backtrack_depth, rng);
+        loop {
+            let e = match decision {
+                Some((cell, tile)) => {
+                    // Fix the chosen cell
+                    self.wave.collapse(cell, tile, self.neighbors)?;
+                    self.collapsed += 1;
+                    pb.inc();
+
+                    // Propagate constraints from the collapsed cell
+                    let Err(e) = self.wave.propagate(
+                        self.rules,
+                        self.neighbors,
+                        &mut self.supports,
+                        options.max_iterations,
+                    ) else {
+                        return Ok(());
+                    };
+                    e
+                }
+                // Every tile of the cell completes a nogood, so an earlier decision is wrong
+                None => WfcError::Contradiction {
+                    cell,
+                    direction: None,
+                }
+                .into(),
             };
 
-            // Save state for backtracking only if we have multiple options
-            if options.len() > 1 {
-                // Only save state worth backtracking to (cells with multiple options)
-                let mut tried_values = HashSet::new();
-                tried_values.insert(choice); // Pre-mark our current choice as tried
-
-                let backtrack_state = BacktrackState {
-                    changed_cells: HashSet::new(),
-                    domain_copies: HashMap::new(),
-                    domain_size_copies: HashMap::new(),
-                    cell: best_idx,
-                    tried_values,
-                    collapsed_cells: collapsed_cells.clone(),
-                };
-
-                // If backtrack stack is too large, remove oldest entries
-                while backtrack_stack.len() >= MAX_BACKTRACK_DEPTH {
-                    backtrack_stack.remove(0);
-                }
+            // Constraint propagation failed - backtrack
+            stats.record_contradiction(self.collapsed);
+            self.backtracks += 1;
+            pb.set_count(self.backtracks);
+            trace_event!(
+                debug,
+                cell = ?decision.map_or(cell, |(cell, _)| cell),
+                backtracks = self.backtracks,
+                depth = self.backtrack_stack.len(),
+                "backtrack"
+            );
 
-                // Push current state to backtrack stack
-                backtrack_stack.push(backtrack_state);
+            if self.backtracks > options.max_backtracks {
+                return Err(WfcError::MaxBacktracks(options.max_backtracks).into());
             }
 
-            // Fix the chosen cell
-            domains[best_idx].clear();
-            domains[best_idx].insert(choice);
-            domain_sizes[best_idx] = 1;
-            collapsed_cells.insert(best_idx);
-
-            pb.inc(1);
-
-            // Propagate constraints using common function - pass None for backtrack_state
-            let propagation_result = propagate_constraints(
-                &mut domains,
-                &mut domain_sizes,
-                rules,
-                &neighbors,
-                best_idx,
-                MAX_ITERATIONS,
-                None, // No tracking for now - we only need tracking when backtracking
-            );
+            self.learn_from(decision, cell, &e);
+            let Some(next) = self.backtrack(rng) else {
+                return Err(e);
+            };
+            decision = Some(next);
+            pb.set_position(self.collapsed);
+        }
+    }
 
-            match propagation_result {
-                Ok(affected_cells) => {
-                    // Update buckets for all affected cells
-                    for &cell_idx in &affected_cells {
-                        // Remove from old bucket
-                        for e in 2..=num_tiles {
-                            bucket_sets[e].remove(&cell_idx);
-                        }
-
-                        // Add to new bucket if still has multiple options
-                        if domain_sizes[cell_idx] > 1 {
-                            bucket_sets[domain_sizes[cell_idx]].insert(cell_idx);
-                        }
-                    }
-                }
-                Err(_) => {
-                    // Constraint propagation failed - backtrack
-                    backtrack_count += 1;
-                    pb.set_message(backtrack_count.to_string());
+    // Choose a tile for a cell, saving the decision to backtrack to if the cell has other options, or return
+    // `None` if every tile of the cell completes a learned nogood
+    fn decide(
+        &mut self,
+        cell: (usize, usize),
+        backtrack_depth: usize,
+        rng: &mut impl Rng,
+    ) -> Option<Assignment> {
+        // Tiles which would complete a learned nogood are never chosen
+        let allowed = self.nogoods.allowed(&self.wave, cell);
+        if allowed.is_clear() {
+            return None;
+        }
 
-                    if backtrack_count > MAX_BACKTRACK_ATTEMPTS {
-                        bail!("Maximum backtracking attempts exceeded");
-                    }
+        // Choose a tile using weighted distribution
+        let choice = choose_weighted_at(
+            &allowed,
+            self.rules,
+            self.map,
+            &self.wave,
+            &self.neighbors[cell],
+            cell,
+            rng,
+        );
 
-                    // Pop the last state from the stack
-                    if let Some(state) = backtrack_stack.pop() {
-                        // Restore domains - just use full clone for now since we don't have the optimized approach
-                        // In the full implementation, this would use the changed_cells, domain_copies, etc.
-
-                        // Restore the collapsed cells set
-                        collapsed_cells = state.collapsed_cells.clone();
-
-                        // Get remaining options that haven't been tried yet for the cell
-                        let original_domain = domains[state.cell].clone();
-                        let remaining_options: Vec<usize> = original_domain
-                            .ones()
-                            .filter(|&opt| !state.tried_values.contains(&opt))
-                            .collect();
-
-                        if remaining_options.is_empty() {
-                            // No options left for this cell, need to backtrack further
-                            continue 'outer;
-                        }
-
-                        // Choose a different option
-                        let weights: Vec<usize> = remaining_options
-                            .iter()
-                            .map(|&t| rules.frequencies()[t])
-                            .collect();
-
-                        let new_choice = if weights.iter().any(|&w| w == 0) {
-                            // Use uniform distribution
-                            remaining_options[rng.random_range(0..remaining_options.len())]
-                        } else {
-                            // Use weighted distribution
-                            let dist = WeightedIndex::new(&weights).unwrap();
-                            remaining_options[dist.sample(rng)]
-                        };
-
-                        // Create a new backtrack state with updated tried values
-                        let mut new_tried_values = state.tried_values.clone();
-                        new_tried_values.insert(new_choice);
-
-                        let new_state = BacktrackState {
-                            changed_cells: HashSet::new(),
-                            domain_copies: HashMap::new(),
-                            domain_size_copies: HashMap::new(),
-                            cell: state.cell,
-                            tried_values: new_tried_values,
-                            collapsed_cells: collapsed_cells.clone(),
-                        };
-
-                        backtrack_stack.push(new_state);
-
-                        // Update the cell with new choice
-                        domains[state.cell].clear();
-                        domains[state.cell].insert(new_choice);
-                        domain_sizes[state.cell] = 1;
-                        collapsed_cells.insert(state.cell);
-
-                        // After backtracking, recalculate the entire grid state to ensure consistency
-                        // This is inefficient but ensures correctness
-                        // Initialize domains from current state
-                        for y in 0..height {
-                            for x in 0..width {
-                                if !is_ignore[(y, x)] && (y, x) != state.cell {
-                                    // Reset domain for cells other than the one we just fixed
-                                    if collapsed_cells.contains(&(y, x)) {
-                                        // Keep collapsed cells collapsed
-                                        // (domains are already correct)
-                                    } else {
-                                        // Reset uncollapsed cells to all options
-                                        domains[(y, x)].clear();
-                                        domains[(y, x)].insert_range(..num_tiles);
-                                        domain_sizes[(y, x)] = num_tiles;
-                                    }
-                                }
-                            }
-                        }
-
-                        // Run initial propagation again with all constraints
-                        initial_propagation(
-                            &mut domains,
-                            &mut domain_sizes,
-                            rules,
-                            height,
-                            width,
-                            &is_ignore,
-                            &neighbors,
-                            MAX_ITERATIONS,
-                        )?;
-
-                        // Rebuild buckets from current domain sizes
-                        for e in 2..=num_tiles {
-                            bucket_sets[e].clear();
-                        }
-
-                        for y in 0..height {
-                            for x in 0..width {
-                                if !is_ignore[(y, x)]
-                                    && !collapsed_cells.contains(&(y, x))
-                                    && domain_sizes[(y, x)] > 1
-                                {
-                                    bucket_sets[domain_sizes[(y, x)]].insert((y, x));
-                                }
-                            }
-                        }
+        // Save state for backtracking only if we have multiple options
+        if self.wave.domain_size(cell) > 1 {
+            // If backtrack stack is too large, forget the oldest decision and the changes only it could undo
+            if self.backtrack_stack.len() >= backtrack_depth {
+                let dropped = self.backtrack_stack.remove(0);
+                self.forgotten.push((dropped.cell, dropped.tile));
+                if let Some(oldest) = self.backtrack_stack.first() {
+                    let forgotten = oldest.trail_mark;
+                    self.wave.forget_before(forgotten);
+                    for state in &mut self.backtrack_stack {
+                        state.trail_mark -= forgotten;
                     }
                 }
             }
 
-            // Periodically report progress and check timeout
-            if start_time.elapsed() > Duration::from_secs(10) && backtrack_count > 0 {
-                pb.println(format!(
-                    "Progress: {}/{} cells, {} backtracks so far",
-                    collapsed_cells.len(),
-                    cells_to_collapse,
-                    backtrack_count
-                ));
-            }
+            // Pre-mark our current choice, and the tiles nogoods rule out, as tried
+            let mut tried_values: HashSet<usize> =
+                self.wave.domain(cell).difference(&allowed).collect();
+            tried_values.insert(choice);
+            self.backtrack_stack.push(BacktrackState {
+                cell,
+                tile: choice,
+                tried_values,
+                conflicts: Some(BTreeSet::new()),
+                trail_mark: self.wave.mark(),
+                collapsed: self.collapsed,
+            });
         }
+        Some((cell, choice))
+    }
 
-        pb.finish_and_clear();
+    // Learn which decisions led to a contradiction, found by propagating a decision, or by every tile of a
+    // cell completing a nogood if no decision could be made
+    fn learn_from(
+        &mut self,
+        decision: Option<Assignment>,
+        cell: (usize, usize),
+        e: &anyhow::Error,
+    ) {
+        let seeds = if decision.is_none() {
+            let mut seeds = self.nogoods.reasons(&self.wave, cell);
+            seeds.push(cell);
+            Some(seeds)
+        } else if let Some(&WfcError::Contradiction { cell, .. }) = e.downcast_ref::<WfcError>() {
+            Some(vec![cell])
+        } else {
+            None
+        };
+        let decided = self.decisions().collect();
+        let nogood = self
+            .explainer
+            .explain(&self.wave, self.neighbors, &decided, seeds);
+        self.learn(nogood);
+    }
 
-        // If we had to backtrack, report the final count
-        if backtrack_count > 0 {
-            println!("Completed with {} backtracking attempts", backtrack_count);
+    // Undo decisions until one has an option left to try, and try it, or return `None` if none has
+    fn backtrack(&mut self, rng: &mut impl Rng) -> Option<Assignment> {
+        loop {
+            let mut state = self.backtrack_stack.pop()?;
+            self.wave.undo_to(state.trail_mark);
+            self.collapsed = state.collapsed;
+
+            // Get remaining options that haven't been tried yet for the cell
+            let mut remaining_options = self.nogoods.allowed(&self.wave, state.cell);
+            for &tried in &state.tried_values {
+                remaining_options.set(tried, false);
+            }
+            if remaining_options.is_clear() {
+                // No options left for this cell, so the decisions which ruled out its tiles lead to a
+                // contradiction too, and we need to backtrack further
+                let mut seeds = self.nogoods.reasons(&self.wave, state.cell);
+                seeds.push(state.cell);
+                let decided = self.decisions().collect();
+                let nogood = self
+                    .explainer
+                    .explain(&self.wave, self.neighbors, &decided, Some(seeds))
+                    .zip(state.conflicts)
+                    .map(|(mut nogood, conflicts)| {
+                        nogood.extend(conflicts);
+                        nogood
+                    })
+                    .filter(|nogood| nogood.len() <= self.max_nogood_size);
+                self.learn(nogood);
+                continue;
+            }
+
+            // Choose a different option
+            let new_choice = choose_weighted_at(
+                &remaining_options,
+                self.rules,
+                self.map,
+                &self.wave,
+                &self.neighbors[state.cell],
+                state.cell,
+                rng,
+            );
+            state.tried_values.insert(new_choice);
+            state.tile = new_choice;
+            let cell = state.cell;
+            self.backtrack_stack.push(state);
+            return Some((cell, new_choice));
         }
+    }
 
-        // Build the final map
-        let mut result = map.clone();
-        for y in 0..height {
-            for x in 0..width {
-                if !is_ignore[(y, x)] {
-                    let mut bits = domains[(y, x)].ones();
-                    let tile = match bits.next() {
-                        Some(t) => t,
-                        None => bail!("No possibilities for cell at ({}, {})", y, x),
-                    };
-                    result[(y, x)] = Cell::Fixed(tile);
-                }
+    // Every decision still in force, oldest first
+    fn decisions(&self) -> impl Iterator<Item = Assignment> + '_ {
+        self.forgotten.iter().copied().chain(
+            self.backtrack_stack
+                .iter()
+                .map(|state| (state.cell, state.tile)),
+        )
+    }
+
+    // Record a combination of decisions which leads to a contradiction, if it was small enough to find, and
+    // charge the rest of it to the latest decision, which is tried with another tile next
+    fn learn(&mut self, nogood: Option<BTreeSet<Assignment>>) {
+        let latest = self.backtrack_stack.last_mut();
+        let Some(nogood) = nogood else {
+            if let Some(latest) = latest {
+                latest.conflicts = None;
+            }
+            return;
+        };
+        self.nogoods.record(nogood.iter().copied());
+        if let Some(latest) = latest {
+            let decision = (latest.cell, latest.tile);
+            if let Some(conflicts) = latest.conflicts.as_mut() {
+                conflicts.extend(
+                    nogood
+                        .into_iter()
+                        .filter(|&assignment| assignment != decision),
+                );
             }
         }
-
-        Ok(result)
     }
 }
@@ -0,0 +1,389 @@
+use anyhow::{Result, anyhow};
+use ndarray::Array2;
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64;
+use rayon::prelude::*;
+use std::ops::Range;
+
+use crate::{Cell, Map, Rules, WaveFunction};
+
+const CHUNK_SIZE: (usize, usize) = (32, 32); // Height and width of each chunk
+const MAX_RETRIES: usize = 3; // Extra attempts at a region with new seeds
+const MAX_EXPANSIONS: usize = 1; // Times a failing region may grow to take in its neighbouring chunks
+
+/// Generates a large map one chunk at a time, in row-major order.
+/// Each chunk is collapsed along with a one-cell ring of its surroundings, so it always fits the chunks
+/// generated before it and leaves room for those after it.
+/// A failing chunk is retried with new seeds; if it still fails, the region is expanded to regenerate the
+/// neighbouring chunks along with it, so one unlucky chunk does not abort the whole map.
+/// With `generate_parallel`, chunks which do not touch are generated at the same time on the rayon thread pool.
+pub struct ChunkedGenerator {
+    chunk_size: (usize, usize),
+    max_retries: usize,
+    max_expansions: usize,
+}
+
+impl ChunkedGenerator {
+    #[must_use]
+    pub fn builder() -> ChunkedGeneratorBuilder {
+        ChunkedGeneratorBuilder::default()
+    }
+}
+
+impl Default for ChunkedGenerator {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+/// Builder for configuring a `ChunkedGenerator`.
+pub struct ChunkedGeneratorBuilder {
+    chunk_size: (usize, usize),
+    max_retries: usize,
+    max_expansions: usize,
+}
+
+impl Default for ChunkedGeneratorBuilder {
+    fn default() -> Self {
+        Self {
+            chunk_size: CHUNK_SIZE,
+            max_retries: MAX_RETRIES,
+            max_expansions: MAX_EXPANSIONS,
+        }
+    }
+}
+
+impl ChunkedGeneratorBuilder {
+    /// Height and width of each chunk. Chunks on the far edges are cut short to fit the map.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either dimension is zero.
+    #[must_use]
+    pub fn chunk_size(mut self, chunk_size: (usize, usize)) -> Self {
+        assert!(
+            chunk_size.0 > 0 && chunk_size.1 > 0,
+            "Chunk size must be greater than zero"
+        );
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Number of extra attempts, each with a new seed, before a failing region is expanded.
+    #[must_use]
+    pub fn max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Number of times a failing region may grow by one chunk in every direction.
+    #[must_use]
+    pub fn max_expansions(mut self, max_expansions: usize) -> Self {
+        self.max_expansions = max_expansions;
+        self
+    }
+
+    #[must_use]
+    pub fn build(self) -> ChunkedGenerator {
+        ChunkedGenerator {
+            chunk_size: self.chunk_size,
+            max_retries: self.max_retries,
+            max_expansions: self.max_expansions,
+        }
+    }
+}
+
+impl ChunkedGenerator {
+    /// Collapse a template chunk by chunk using the given algorithm.
+    /// Fixed and ignored cells of the template are kept, including when a region is regenerated.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a chunk cannot be collapsed within its retries and expansions, with the algorithm's last error as
+    /// the source.
+    pub fn generate(
+        &self,
+        template: &Map,
+        algorithm: &impl WaveFunction,
+        rules: &Rules,
+        rng: &mut impl Rng,
+    ) -> Result<Map> {
+        let (height, width) = template.size();
+        let num_chunks = (
+            height.div_ceil(self.chunk_size.0),
+            width.div_ceil(self.chunk_size.1),
+        );
+
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!(
+            "generate_chunked",
+            height,
+            width,
+            chunk_rows = num_chunks.0,
+            chunk_cols = num_chunks.1
+        )
+        .entered();
+
+        let mut world = template.clone();
+        let mut generated = Array2::from_elem(num_chunks, false);
+        for chunk_y in 0..num_chunks.0 {
+            for chunk_x in 0..num_chunks.1 {
+                if !generated[(chunk_y, chunk_x)] {
+                    self.generate_chunk(
+                        &mut world,
+                        template,
+                        &mut generated,
+                        (chunk_y, chunk_x),
+                        algorithm,
+                        rules,
+                        rng,
+                    )?;
+                }
+            }
+        }
+        Ok(world)
+    }
+
+    /// Collapse a template chunk by chunk as `generate` does, but generating chunks which do not touch in
+    /// parallel on the rayon thread pool.
+    /// Chunks are taken in four passes, by whether their row and column are odd or even, so no two chunks in a
+    /// pass touch and each sees the edges of the chunks generated in earlier passes.
+    /// Each chunk draws from a generator seeded for it up front, so the map depends only on `rng` and not on
+    /// the number of threads, though it differs from the map `generate` gives.
+    /// Chunks still failing after their retries are expanded one at a time once their pass is done.
+    ///
+    /// # Errors
+    ///
+    /// Fails as `generate` does.
+    pub fn generate_parallel(
+        &self,
+        template: &Map,
+        algorithm: &(impl WaveFunction + Sync),
+        rules: &Rules,
+        rng: &mut impl Rng,
+    ) -> Result<Map> {
+        let (height, width) = template.size();
+        let num_chunks = (
+            height.div_ceil(self.chunk_size.0),
+            width.div_ceil(self.chunk_size.1),
+        );
+
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!(
+            "generate_chunked",
+            height,
+            width,
+            chunk_rows = num_chunks.0,
+            chunk_cols = num_chunks.1,
+            parallel = true
+        )
+        .entered();
+
+        let mut world = template.clone();
+        let mut generated = Array2::from_elem(num_chunks, false);
+        let seeds: Array2<u64> = Array2::from_shape_simple_fn(num_chunks, || rng.random());
+        for parity in [(0, 0), (0, 1), (1, 0), (1, 1)] {
+            let pass: Vec<(usize, usize)> = generated
+                .indexed_iter()
+                .filter(|&((chunk_y, chunk_x), &is_generated)| {
+                    !is_generated && (chunk_y % 2, chunk_x % 2) == parity
+                })
+                .map(|(chunk, _)| chunk)
+                .collect();
+
+            // The chunks of a pass only read cells outside each other, so all see the world as it was before it
+            let results: Vec<_> = pass
+                .par_iter()
+                .map(|&chunk| {
+                    let (rows, cols) = self.bounds(chunk, 0, num_chunks, (height, width));
+                    let mut chunk_rng = Pcg64::seed_from_u64(seeds[chunk]);
+                    let mut attempts = 0;
+                    let result = loop {
+                        let seed = chunk_rng.random();
+                        let result = Self::collapse_region(
+                            &world, template, &rows, &cols, algorithm, rules, seed,
+                        );
+                        attempts += 1;
+                        if result.is_ok() || attempts > self.max_retries {
+                            break result;
+                        }
+                    };
+                    (result, chunk_rng)
+                })
+                .collect();
+
+            let mut failed = Vec::new();
+            for (&chunk, (result, chunk_rng)) in pass.iter().zip(results) {
+                match result {
+                    Ok(cells) => {
+                        let (rows, cols) = self.bounds(chunk, 0, num_chunks, (height, width));
+                        Self::write_region(&mut world, &cells, &rows, &cols);
+                        generated[chunk] = true;
+                    }
+                    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+                    Err(e) => {
+                        trace_event!(debug, chunk = ?chunk, error = %e, "chunk failed");
+                        failed.push((chunk, chunk_rng));
+                    }
+                }
+            }
+
+            // Expanded regions overlap other chunks of the pass, so they are generated one after the other
+            for (chunk, mut chunk_rng) in failed {
+                if !generated[chunk] {
+                    self.generate_chunk(
+                        &mut world,
+                        template,
+                        &mut generated,
+                        chunk,
+                        algorithm,
+                        rules,
+                        &mut chunk_rng,
+                    )?;
+                }
+            }
+        }
+        Ok(world)
+    }
+
+    // Rows and columns of the cells of the chunks within a number of chunks of one, and the range of those
+    // chunks' rows and columns
+    fn bounds(
+        &self,
+        chunk: (usize, usize),
+        expansion: usize,
+        num_chunks: (usize, usize),
+        size: (usize, usize),
+    ) -> (Range<usize>, Range<usize>) {
+        let (chunk_rows, chunk_cols) = Self::chunk_ranges(chunk, expansion, num_chunks);
+        (
+            chunk_rows.start * self.chunk_size.0..(chunk_rows.end * self.chunk_size.0).min(size.0),
+            chunk_cols.start * self.chunk_size.1..(chunk_cols.end * self.chunk_size.1).min(size.1),
+        )
+    }
+
+    // Rows and columns of the chunks within a number of chunks of one
+    fn chunk_ranges(
+        chunk: (usize, usize),
+        expansion: usize,
+        num_chunks: (usize, usize),
+    ) -> (Range<usize>, Range<usize>) {
+        (
+            chunk.0.saturating_sub(expansion)..(chunk.0 + expansion + 1).min(num_chunks.0),
+            chunk.1.saturating_sub(expansion)..(chunk.1 + expansion + 1).min(num_chunks.1),
+        )
+    }
+
+    // Generate one chunk, retrying with new seeds and then over ever larger regions around it
+    #[allow(clippy::too_many_arguments)]
+    fn generate_chunk(
+        &self,
+        world: &mut Map,
+        template: &Map,
+        generated: &mut Array2<bool>,
+        chunk: (usize, usize),
+        algorithm: &impl WaveFunction,
+        rules: &Rules,
+        rng: &mut impl Rng,
+    ) -> Result<()> {
+        let (height, width) = template.size();
+        let num_chunks = generated.dim();
+
+        let mut last_error = None;
+        for expansion in 0..=self.max_expansions {
+            let (chunk_rows, chunk_cols) = Self::chunk_ranges(chunk, expansion, num_chunks);
+            let (rows, cols) = self.bounds(chunk, expansion, num_chunks, (height, width));
+
+            for _ in 0..=self.max_retries {
+                let seed = rng.random();
+                match Self::collapse_region(world, template, &rows, &cols, algorithm, rules, seed) {
+                    Ok(cells) => {
+                        Self::write_region(world, &cells, &rows, &cols);
+                        for chunk_y in chunk_rows.clone() {
+                            for chunk_x in chunk_cols.clone() {
+                                generated[(chunk_y, chunk_x)] = true;
+                            }
+                        }
+                        return Ok(());
+                    }
+                    Err(e) => {
+                        trace_event!(
+                            debug,
+                            chunk = ?chunk,
+                            expansion,
+                            seed,
+                            error = %e,
+                            "chunk failed"
+                        );
+                        last_error = Some(e);
+                    }
+                }
+            }
+        }
+
+        // The last failure is kept as the source, so callers can still downcast it to a `WfcError`
+        let message = format!(
+            "Failed to generate chunk ({}, {}) after {} retries and {} expansions",
+            chunk.0, chunk.1, self.max_retries, self.max_expansions
+        );
+        Err(match last_error {
+            Some(error) => error.context(message),
+            None => anyhow!(message),
+        })
+    }
+
+    // Collapse a region of the world from its template values, constrained by a one-cell ring of its
+    // current surroundings, returning the region's cells
+    fn collapse_region(
+        world: &Map,
+        template: &Map,
+        rows: &Range<usize>,
+        cols: &Range<usize>,
+        algorithm: &impl WaveFunction,
+        rules: &Rules,
+        seed: u64,
+    ) -> Result<Array2<Cell>> {
+        let (height, width) = world.size();
+        let top = rows.start.saturating_sub(1);
+        let left = cols.start.saturating_sub(1);
+        let bottom = (rows.end + 1).min(height);
+        let right = (cols.end + 1).min(width);
+
+        let mut region = Map::new(Array2::from_shape_fn(
+            (bottom - top, right - left),
+            |(y, x)| {
+                let pos = (top + y, left + x);
+                if rows.contains(&pos.0) && cols.contains(&pos.1) {
+                    template[pos]
+                } else {
+                    world[pos]
+                }
+            },
+        ));
+        for y in top..bottom {
+            for x in left..right {
+                if let Some(tiles) = template.restriction((y, x)) {
+                    region.restrict((y - top, x - left), tiles);
+                }
+            }
+        }
+
+        let mut region_rng = Pcg64::seed_from_u64(seed);
+        let collapsed = algorithm.collapse(&region, rules, &mut region_rng)?;
+        Ok(Array2::from_shape_fn((rows.len(), cols.len()), |(y, x)| {
+            collapsed[(rows.start - top + y, cols.start - left + x)]
+        }))
+    }
+
+    // Write a collapsed region's cells back into the world
+    fn write_region(
+        world: &mut Map,
+        cells: &Array2<Cell>,
+        rows: &Range<usize>,
+        cols: &Range<usize>,
+    ) {
+        for ((y, x), &cell) in cells.indexed_iter() {
+            world[(rows.start + y, cols.start + x)] = cell;
+        }
+    }
+}
@@ -0,0 +1,953 @@
+use anyhow::{Context, Result, anyhow, bail};
+use fixedbitset::FixedBitSet;
+use ndarray::Array2;
+use rand::prelude::*;
+use rand_pcg::Pcg64;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    ops::Range,
+    path::Path,
+    sync::{
+        Arc, Weak,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use super::{
+    common::{Boundary, Neighbour, calculate_neighbours, choose_weighted_at, hole_mask},
+    error::WfcError,
+    selection::{Heuristic, Selector},
+    support::{Consistency, ReviseStrategy, Supports},
+    wave::{Pending, Wave},
+};
+use crate::{ALL_DIRECTIONS, Cell, Direction, Landmark, Map, Profile, Rules};
+
+const MAX_ITERATIONS: usize = 1_000_000; // Max iterations for constraint propagation
+const CHECKPOINT_VERSION: u32 = 8;
+
+// Source of the ids telling collapsers apart, so snapshots are only restored into the collapser they came from
+static NEXT_COLLAPSER_ID: AtomicU64 = AtomicU64::new(0);
+
+// Mutable state of the wave
+#[derive(Clone)]
+struct WaveState {
+    // Uncollapsed cells are grouped by priority level, with soft holes first within each, then ordered by
+    // the heuristic
+    wave: Wave,
+    // Shared with snapshots, so they can restore the priorities without copying them
+    priorities: Arc<Array2<usize>>,
+    // Distinct priorities present in the map, highest first
+    levels: Vec<usize>,
+    // Soft holes of the template, if the rules have a hole tile for them
+    holes: Option<Arc<Array2<bool>>>,
+    heuristic: Heuristic,
+    // Every cell collapsed by a decision, with its chosen tile, in order
+    trail: Vec<((usize, usize), usize)>,
+    // Source of every random choice, so restoring the state also rewinds the random sequence
+    rng: Pcg64,
+    // Bumped whenever the cells are re-bucketed, so a restored snapshot only re-buckets them if needed
+    layout: u64,
+}
+
+impl WaveState {
+    fn rebuild_buckets(&mut self, is_ignore: &Array2<bool>) {
+        self.levels = self
+            .priorities
+            .indexed_iter()
+            .filter(|&(pos, _)| !is_ignore[pos])
+            .map(|(_, &p)| p)
+            .collect::<Vec<_>>();
+        self.levels.sort_unstable_by(|a, b| b.cmp(a));
+        self.levels.dedup();
+
+        let stride = self.group_stride();
+        let groups = Array2::from_shape_fn(self.priorities.dim(), |pos| {
+            let level = self
+                .levels
+                .iter()
+                .position(|&p| p == self.priorities[pos])
+                .unwrap_or_default();
+            let is_hole = self.holes.as_ref().is_some_and(|holes| holes[pos]);
+            level * stride + usize::from(stride > 1 && !is_hole)
+        });
+        self.wave
+            .regroup(groups, self.levels.len().max(1) * stride, self.heuristic);
+        self.layout += 1;
+    }
+
+    // Number of selection groups in each priority level
+    fn group_stride(&self) -> usize {
+        if self.holes.is_some() { 2 } else { 1 }
+    }
+}
+
+// On-disk form of a collapser, with directions stored by index
+#[derive(Serialize, Deserialize)]
+struct Checkpoint {
+    version: u32,
+    size: (usize, usize),
+    rules_hash: u64,
+    template: String,
+    landmarks: Vec<Landmark>,
+    profiles: Vec<Profile>,
+    num_tiles: usize,
+    domains: Vec<Vec<usize>>,
+    priorities: Vec<usize>,
+    sweep: Option<usize>,
+    shannon: bool,
+    forward_checking: bool,
+    revise_strategy: usize,
+    propagation_budget: Option<usize>,
+    neighbours: Vec<Vec<(usize, usize, usize)>>,
+    trail: Vec<((usize, usize), usize)>,
+    noise_seed: u64,
+    rng: Pcg64,
+}
+
+/// Snapshot of a `Collapser`'s wave state, taken with `Collapser::snapshot`.
+/// Snapshots copy no domains: once one is taken, the collapser records each cell's domain the first time it
+/// changes, so taking a snapshot costs next to nothing and restoring one costs only as much as the changes
+/// made since.
+/// Restoring a snapshot discards those taken after it, so snapshots branch like a stack: any number of
+/// alternatives can be explored from one, returning to it after each.
+#[derive(Clone)]
+pub struct WaveSnapshot {
+    // Held weakly by the collapser, which stops recording changes for a snapshot once every copy is dropped
+    token: Arc<()>,
+    collapser: u64,
+    // Position in the collapser's record of changes, counted from the start of recording
+    position: usize,
+    // Number of decisions made
+    decisions: usize,
+    pending: Pending,
+    layout: u64,
+    heuristic: Heuristic,
+    priorities: Arc<Array2<usize>>,
+    noise_seed: u64,
+    consistency: Consistency,
+    rng: Pcg64,
+}
+
+/// Incremental Wave Function Collapse, advancing one decision at a time.
+/// If a step fails the wave is left part-way through propagation, so callers should `restore` a snapshot.
+/// The collapser owns its random number generator, which is captured by snapshots and checkpoints,
+/// so a restored or resumed collapse continues with exactly the same random sequence.
+/// It is seeded from the thread-local generator unless `seed` or `set_rng` is called.
+pub struct Collapser<'a> {
+    template: Map,
+    rules: &'a Rules,
+    is_ignore: Array2<bool>,
+    neighbors: Array2<Vec<Neighbour>>,
+    supports: Supports,
+    // Most revisions made by a single step, if limited
+    propagation_budget: Option<usize>,
+    state: WaveState,
+    id: u64,
+    // Snapshots which may still be restored, with their positions in the record of changes
+    snapshots: Vec<(Weak<()>, usize)>,
+    // Changes dropped from the start of the record, once no snapshot needed them
+    forgotten: usize,
+}
+
+impl<'a> Collapser<'a> {
+    /// Prepare a template for collapsing, running the initial constraint propagation.
+    /// The edges of the map are open; see `with_boundary` for wrapped maps.
+    ///
+    /// # Errors
+    ///
+    /// Fails with a `WfcError` if propagating the template leaves a cell with no options.
+    pub fn new(template: &Map, rules: &'a Rules) -> Result<Self> {
+        Self::with_boundary(template, rules, Boundary::Open)
+    }
+
+    /// Prepare a template for collapsing as `new` does, with cells on the edges constrained by the boundary.
+    ///
+    /// # Errors
+    ///
+    /// Fails as `new` does.
+    pub fn with_boundary(template: &Map, rules: &'a Rules, boundary: Boundary) -> Result<Self> {
+        let (height, width) = template.size();
+        let neighbors = calculate_neighbours(height, width, &template.mask(), boundary);
+        Self::with_neighbours(template, rules, neighbors)
+    }
+
+    /// Prepare a template for collapsing using precomputed neighbour lists.
+    pub(crate) fn with_neighbours(
+        template: &Map,
+        rules: &'a Rules,
+        neighbors: Array2<Vec<Neighbour>>,
+    ) -> Result<Self> {
+        let (height, width) = template.size();
+        let num_tiles = rules.len();
+
+        let is_ignore = template.mask();
+        let mut supports = Supports::new(ReviseStrategy::default(), (height, width), num_tiles);
+
+        let mut rng = Pcg64::from_rng(&mut rand::rng());
+        let mut wave = Wave::new(
+            template.domains(num_tiles),
+            is_ignore.clone(),
+            Heuristic::default(),
+            rng.random(),
+            rules.frequencies(),
+        );
+        wave.propagate_all(rules, &neighbors, &mut supports, MAX_ITERATIONS)?;
+
+        let mut state = WaveState {
+            wave,
+            priorities: Arc::new(Array2::zeros((height, width))),
+            levels: Vec::new(),
+            holes: hole_mask(template, rules).map(Arc::new),
+            heuristic: Heuristic::default(),
+            trail: Vec::new(),
+            rng,
+            layout: 0,
+        };
+        state.rebuild_buckets(&is_ignore);
+
+        Ok(Self {
+            template: template.clone(),
+            rules,
+            is_ignore,
+            neighbors,
+            supports,
+            propagation_budget: None,
+            state,
+            id: NEXT_COLLAPSER_ID.fetch_add(1, Ordering::Relaxed),
+            snapshots: Vec::new(),
+            forgotten: 0,
+        })
+    }
+
+    #[must_use]
+    pub fn rules(&self) -> &Rules {
+        self.rules
+    }
+
+    /// Swap in modified rules over the same tiles, for example to see the effect of tweaked frequencies
+    /// or adjacencies on the uncollapsed remainder of the map.
+    /// Cells fixed by the template or by a decision keep their tiles, every other cell gets its template
+    /// options back, and the wave is propagated again under the new rules.
+    ///
+    /// # Errors
+    ///
+    /// Fails with a `WfcError`, leaving the collapser unchanged, if the rules have a different number of tiles or
+    /// the fixed cells are not consistent with them.
+    pub fn set_rules(&mut self, rules: &'a Rules) -> Result<()> {
+        if rules.len() != self.rules.len() {
+            return Err(WfcError::InvalidRules(format!(
+                "replacement rules have {} tiles, but the collapser is using {}",
+                rules.len(),
+                self.rules.len()
+            ))
+            .into());
+        }
+        let num_tiles = rules.len();
+
+        let mut domains = self.template.domains(num_tiles);
+        for &(pos, tile) in &self.state.trail {
+            domains[pos].clear();
+            domains[pos].insert(tile);
+        }
+
+        let mut state = self.state.clone();
+        state.wave.set_frequencies(rules.frequencies());
+        state.holes = hole_mask(&self.template, rules).map(Arc::new);
+        for (pos, domain) in domains.indexed_iter() {
+            if !self.is_ignore[pos] {
+                state.wave.reset(pos, domain.clone());
+            }
+        }
+        let mut supports = Supports::new(self.supports.strategy(), self.template.size(), num_tiles);
+        state
+            .wave
+            .propagate_all(rules, &self.neighbors, &mut supports, MAX_ITERATIONS)
+            .context("Fixed cells are not consistent with the new rules")?;
+        state.rebuild_buckets(&self.is_ignore);
+
+        self.rules = rules;
+        self.supports = supports;
+        self.state = state;
+        Ok(())
+    }
+
+    #[must_use]
+    pub fn size(&self) -> (usize, usize) {
+        self.template.size()
+    }
+
+    /// Remaining options for a cell.
+    #[must_use]
+    pub fn domain(&self, pos: (usize, usize)) -> &FixedBitSet {
+        self.state.wave.domain(pos)
+    }
+
+    /// Number of cells which still have more than one option.
+    #[must_use]
+    pub fn remaining(&self) -> usize {
+        self.state.wave.remaining()
+    }
+
+    #[must_use]
+    pub fn is_complete(&self) -> bool {
+        self.remaining() == 0 && self.is_settled()
+    }
+
+    /// Whether the consequences of every decision so far have been fully propagated.
+    /// Only false between steps when a propagation budget has deferred some of the work.
+    #[must_use]
+    pub fn is_settled(&self) -> bool {
+        self.state.wave.is_settled()
+    }
+
+    #[must_use]
+    pub fn propagation_budget(&self) -> Option<usize> {
+        self.propagation_budget
+    }
+
+    /// Cap the constraint revisions made by each step, smoothing the cost of steps for real-time use, such as
+    /// stepping once per frame.
+    /// When a decision's propagation exceeds the budget, the rest is deferred to the following steps, which
+    /// continue it before making any new decision; such a step returns the cell of the decision still being
+    /// propagated. Contradictions may therefore surface a few steps after the decision which caused them.
+    /// `None`, the default, propagates each decision fully within its step.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the budget is zero.
+    pub fn set_propagation_budget(&mut self, budget: Option<usize>) {
+        assert!(budget != Some(0), "Propagation budget must be positive");
+        self.propagation_budget = budget;
+    }
+
+    /// Finish any propagation deferred by the budget, however much work it takes.
+    ///
+    /// # Errors
+    ///
+    /// Fails with a `WfcError` if propagation leaves a cell with no options.
+    pub fn settle(&mut self) -> Result<()> {
+        if !self.is_settled() {
+            self.state.wave.propagate(
+                self.rules,
+                &self.neighbors,
+                &mut self.supports,
+                MAX_ITERATIONS,
+            )?;
+        }
+        Ok(())
+    }
+
+    #[must_use]
+    pub fn heuristic(&self) -> Heuristic {
+        self.state.heuristic
+    }
+
+    /// Set the order in which cells are chosen for collapse within each priority region.
+    pub fn set_heuristic(&mut self, heuristic: Heuristic) {
+        let state = &mut self.state;
+        state.heuristic = heuristic;
+        state.rebuild_buckets(&self.is_ignore);
+    }
+
+    /// Set how support for a cell's options is checked during propagation.
+    pub fn set_revise_strategy(&mut self, revise_strategy: ReviseStrategy) {
+        self.supports = Supports::new(revise_strategy, self.template.size(), self.rules.len());
+    }
+
+    /// Set how far the consequences of each decision are propagated.
+    pub fn set_consistency(&mut self, consistency: Consistency) {
+        self.state.wave.set_consistency(consistency);
+    }
+
+    #[must_use]
+    pub fn priorities(&self) -> &Array2<usize> {
+        &self.state.priorities
+    }
+
+    /// Set the collapse priority of every cell.
+    /// Cells with a higher priority are all collapsed before any cell with a lower priority.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the priorities are not the size of the map.
+    pub fn set_priorities(&mut self, priorities: Array2<usize>) {
+        assert_eq!(
+            priorities.dim(),
+            self.template.size(),
+            "Priorities must match the map size"
+        );
+        self.state.priorities = Arc::new(priorities);
+        self.state.rebuild_buckets(&self.is_ignore);
+    }
+
+    /// Set the collapse priority of a rectangular region of cells.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the region reaches outside the map.
+    pub fn set_region_priority(
+        &mut self,
+        rows: Range<usize>,
+        cols: Range<usize>,
+        priority: usize,
+    ) -> Result<()> {
+        let (height, width) = self.size();
+        if rows.end > height || cols.end > width {
+            bail!(
+                "Region of rows {rows:?} and columns {cols:?} reaches outside the {height}x{width} map"
+            );
+        }
+        let mut priorities = (*self.state.priorities).clone();
+        for y in rows {
+            for x in cols.clone() {
+                priorities[(y, x)] = priority;
+            }
+        }
+        self.set_priorities(priorities);
+        Ok(())
+    }
+
+    /// Check whether every cell with at least the given priority has been collapsed.
+    #[must_use]
+    pub fn is_region_complete(&self, priority: usize) -> bool {
+        self.state
+            .levels
+            .iter()
+            .zip(
+                self.state
+                    .wave
+                    .selectors()
+                    .chunks(self.state.group_stride()),
+            )
+            .filter(|&(&p, _)| p >= priority)
+            .all(|(_, selectors)| selectors.iter().all(Selector::is_empty))
+    }
+
+    /// Reseed the random number generator for reproducible collapses.
+    pub fn seed(&mut self, seed: u64) {
+        self.set_rng(Pcg64::seed_from_u64(seed));
+    }
+
+    /// Replace the random number generator.
+    /// The tie-breaking order of equally constrained cells is also drawn from it.
+    pub fn set_rng(&mut self, mut rng: Pcg64) {
+        self.state.wave.set_noise_seed(rng.random());
+        self.state.rebuild_buckets(&self.is_ignore);
+        self.state.rng = rng;
+    }
+
+    #[must_use]
+    pub fn rng(&self) -> &Pcg64 {
+        &self.state.rng
+    }
+
+    /// Collapse decisions made so far, in order, as cells and their chosen tiles.
+    #[must_use]
+    pub fn trail(&self) -> &[((usize, usize), usize)] {
+        &self.state.trail
+    }
+
+    /// Capture the current wave state.
+    /// This borrows the collapser mutably, since it starts recording the changes which restoring will undo.
+    /// Snapshots nest like a stack, so restoring one discards every snapshot taken after it; see
+    /// `WaveSnapshot`.
+    pub fn snapshot(&mut self) -> WaveSnapshot {
+        self.forget_unused();
+        let position = self.forgotten + self.state.wave.mark();
+        let token = Arc::new(());
+        self.snapshots.push((Arc::downgrade(&token), position));
+        WaveSnapshot {
+            token,
+            collapser: self.id,
+            position,
+            decisions: self.state.trail.len(),
+            pending: self.state.wave.pending(),
+            layout: self.state.layout,
+            heuristic: self.state.heuristic,
+            priorities: Arc::clone(&self.state.priorities),
+            noise_seed: self.state.wave.noise_seed(),
+            consistency: self.state.wave.consistency(),
+            rng: self.state.rng.clone(),
+        }
+    }
+
+    /// Return the wave to a previously captured state, undoing the changes made since, and discard the
+    /// snapshots taken after it.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the snapshot was taken from another collapser, or discarded by restoring an earlier one.
+    pub fn restore(&mut self, snapshot: &WaveSnapshot) -> Result<()> {
+        let is_live = self
+            .snapshots
+            .iter()
+            .any(|(token, _)| token.as_ptr() == Arc::as_ptr(&snapshot.token));
+        if snapshot.collapser != self.id || !is_live {
+            bail!(
+                "Snapshot was not taken from this collapser, or was discarded by restoring an earlier one"
+            );
+        }
+
+        let state = &mut self.state;
+        state.wave.undo_to(snapshot.position - self.forgotten);
+        state.wave.set_pending(snapshot.pending.clone());
+        state.wave.set_consistency(snapshot.consistency);
+        state.trail.truncate(snapshot.decisions);
+        state.rng = snapshot.rng.clone();
+        if state.layout != snapshot.layout {
+            state.heuristic = snapshot.heuristic;
+            state.priorities = Arc::clone(&snapshot.priorities);
+            state.wave.set_noise_seed(snapshot.noise_seed);
+            state.rebuild_buckets(&self.is_ignore);
+        }
+        self.snapshots
+            .retain(|&(_, position)| position <= snapshot.position);
+        self.forget_unused();
+        Ok(())
+    }
+
+    // Drop the record of changes older than every snapshot still held, stopping recording if there are none
+    fn forget_unused(&mut self) {
+        self.snapshots.retain(|(token, _)| token.strong_count() > 0);
+        if let Some(oldest) = self.snapshots.iter().map(|&(_, position)| position).min() {
+            self.state.wave.forget_before(oldest - self.forgotten);
+            self.forgotten = oldest;
+        } else {
+            self.state.wave.stop_trail();
+            self.forgotten = 0;
+        }
+    }
+
+    /// Collapse the next cell of the highest priority region, as chosen by the heuristic, and propagate the consequences.
+    /// Returns the collapsed cell, or `None` if every cell has already been collapsed.
+    ///
+    /// # Errors
+    ///
+    /// Fails with a `WfcError` if propagation leaves a cell with no options.
+    pub fn step(&mut self) -> Result<Option<(usize, usize)>> {
+        if !self.is_settled() {
+            let state = &mut self.state;
+            state.wave.propagate_within(
+                self.rules,
+                &self.neighbors,
+                &mut self.supports,
+                MAX_ITERATIONS,
+                self.propagation_budget,
+            )?;
+            return Ok(state.trail.last().map(|&(pos, _)| pos));
+        }
+
+        let Some(best_idx) = self.state.wave.next_cell() else {
+            return Ok(None);
+        };
+
+        let state = &mut self.state;
+        let choice = choose_weighted_at(
+            state.wave.domain(best_idx),
+            self.rules,
+            &self.template,
+            &state.wave,
+            &self.neighbors[best_idx],
+            best_idx,
+            &mut state.rng,
+        );
+
+        self.collapse_cell(best_idx, choice)?;
+        Ok(Some(best_idx))
+    }
+
+    /// Fix a cell to the given tile and propagate the consequences, within the propagation budget if one is set.
+    /// Any propagation deferred from earlier decisions is finished first, so the tile is checked against
+    /// consistent options.
+    ///
+    /// # Errors
+    ///
+    /// Fails with a `WfcError` if the tile is not an option for the cell, or if propagation leaves a cell with
+    /// no options.
+    pub fn collapse_cell(&mut self, pos: (usize, usize), tile: usize) -> Result<()> {
+        self.settle()?;
+        // Ignored cells have no options
+        if self.is_ignore[pos] || !self.state.wave.domain(pos).contains(tile) {
+            return Err(WfcError::InvalidChoice { cell: pos, tile }.into());
+        }
+
+        let state = &mut self.state;
+        state.trail.push((pos, tile));
+
+        state.wave.collapse(pos, tile, &self.neighbors)?;
+        state.wave.propagate_within(
+            self.rules,
+            &self.neighbors,
+            &mut self.supports,
+            MAX_ITERATIONS,
+            self.propagation_budget,
+        )?;
+
+        Ok(())
+    }
+
+    /// Step until every cell has been collapsed.
+    ///
+    /// # Errors
+    ///
+    /// Fails as `step` does, leaving the cells collapsed so far in place.
+    pub fn run(&mut self) -> Result<()> {
+        while self.step()?.is_some() {}
+        Ok(())
+    }
+
+    /// Step until every cell with at least the given priority has been collapsed.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a step fails before the region is complete.
+    pub fn run_region(&mut self, priority: usize) -> Result<()> {
+        while !self.is_region_complete(priority) {
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    /// Step the collapse only as far as needed to yield each row of the map, top to bottom, as soon as
+    /// every cell in it is fixed, so rows can be rendered or written while later ones are still generating.
+    /// Rows finish in order fastest with the `Sweep(Direction::South)` heuristic.
+    /// Fixed cells never change, so a yielded row is final; if a later step fails the error is yielded and
+    /// the stream ends.
+    pub fn rows(&mut self) -> RowStream<'_, 'a> {
+        RowStream {
+            collapser: self,
+            next_row: 0,
+            failed: false,
+        }
+    }
+
+    fn is_row_fixed(&self, row: usize) -> bool {
+        (0..self.template.width())
+            .all(|x| self.is_ignore[(row, x)] || self.state.wave.domain_size((row, x)) == 1)
+    }
+
+    fn row_cells(&self, row: usize) -> Vec<Cell> {
+        (0..self.template.width())
+            .map(|x| match self.state.wave.domain((row, x)).ones().next() {
+                Some(tile) if !self.is_ignore[(row, x)] => Cell::Fixed(tile),
+                _ => self.template[(row, x)],
+            })
+            .collect()
+    }
+
+    /// Save the full wave state to disk, including the random number generator and propagation settings, so a
+    /// long generation can be resumed after the process exits and continue exactly as it would have done.
+    ///
+    /// # Errors
+    ///
+    /// Fails if propagation has been deferred by the budget, so `settle` first, if a custom heuristic is set,
+    /// since it could not be restored on resuming, or if the file cannot be written.
+    pub fn checkpoint(&self, path: &Path) -> Result<()> {
+        if !self.is_settled() {
+            bail!("Cannot checkpoint while propagation is deferred");
+        }
+        if matches!(self.state.heuristic, Heuristic::Custom(_)) {
+            bail!("Cannot checkpoint with a custom heuristic, which cannot be saved");
+        }
+        let (height, width) = self.template.size();
+        let checkpoint = Checkpoint {
+            version: CHECKPOINT_VERSION,
+            size: (height, width),
+            rules_hash: self.rules.content_hash(),
+            template: self.template.to_string(),
+            landmarks: self.template.landmarks().to_vec(),
+            profiles: self.template.profiles().to_vec(),
+            num_tiles: self.rules.len(),
+            domains: self
+                .state
+                .wave
+                .domains()
+                .iter()
+                .map(|domain| domain.as_slice().to_vec())
+                .collect(),
+            priorities: self.state.priorities.iter().copied().collect(),
+            sweep: match self.state.heuristic {
+                Heuristic::MinimumEntropy | Heuristic::Custom(_) | Heuristic::ShannonEntropy => {
+                    None
+                }
+                Heuristic::Sweep(direction) => Some(direction.index()),
+            },
+            shannon: matches!(self.state.heuristic, Heuristic::ShannonEntropy),
+            forward_checking: self.state.wave.consistency() == Consistency::ForwardChecking,
+            revise_strategy: match self.supports.strategy() {
+                ReviseStrategy::Scan => 0,
+                ReviseStrategy::LastSupport => 1,
+                ReviseStrategy::SupportCount => 2,
+            },
+            propagation_budget: self.propagation_budget,
+            neighbours: self
+                .neighbors
+                .iter()
+                .map(|neighbours| {
+                    neighbours
+                        .iter()
+                        .map(|n| (n.pos.0, n.pos.1, n.dir.index()))
+                        .collect()
+                })
+                .collect(),
+            trail: self.state.trail.clone(),
+            noise_seed: self.state.wave.noise_seed(),
+            rng: self.state.rng.clone(),
+        };
+        debug_assert_eq!(checkpoint.domains.len(), height * width);
+
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create checkpoint {}", path.display()))?;
+        bincode::serialize_into(BufWriter::new(file), &checkpoint)?;
+        Ok(())
+    }
+
+    /// Load a collapser from a checkpoint written by `checkpoint`.
+    /// The rules must be the same as those the checkpointed collapser was using.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `WfcError::InvalidRules` if the checkpoint was taken with other rules, and fails if it
+    /// cannot be read, was written by another version or is corrupt.
+    pub fn resume(path: &Path, rules: &'a Rules) -> Result<Self> {
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open checkpoint {}", path.display()))?;
+        let checkpoint: Checkpoint = bincode::deserialize_from(BufReader::new(file))?;
+        if checkpoint.version != CHECKPOINT_VERSION {
+            bail!(
+                "Unsupported checkpoint version {} (expected {})",
+                checkpoint.version,
+                CHECKPOINT_VERSION
+            );
+        }
+        if checkpoint.num_tiles != rules.len() {
+            return Err(WfcError::InvalidRules(format!(
+                "checkpoint was taken with {} tiles, but the rules have {}",
+                checkpoint.num_tiles,
+                rules.len()
+            ))
+            .into());
+        }
+        if checkpoint.rules_hash != rules.content_hash() {
+            return Err(WfcError::InvalidRules(
+                "checkpoint was taken with different rules".to_string(),
+            )
+            .into());
+        }
+
+        let mut template = saved_template(&checkpoint.template, checkpoint.size, rules)?;
+        for landmark in checkpoint.landmarks {
+            template.add_landmark(landmark);
+        }
+        for profile in checkpoint.profiles {
+            template.add_profile(profile);
+        }
+        let size = checkpoint.size;
+        let num_tiles = rules.len();
+        if checkpoint.domains.len() != size.0 * size.1
+            || checkpoint.priorities.len() != size.0 * size.1
+            || checkpoint.neighbours.len() != size.0 * size.1
+        {
+            bail!("Checkpoint is corrupt: cell count does not match the template");
+        }
+        let domains = saved_domains(checkpoint.domains, &template, num_tiles)?;
+        let neighbors = saved_neighbours(size, checkpoint.neighbours)?;
+
+        let is_ignore = template.mask();
+        let revise_strategy = match checkpoint.revise_strategy {
+            0 => ReviseStrategy::Scan,
+            1 => ReviseStrategy::LastSupport,
+            2 => ReviseStrategy::SupportCount,
+            _ => bail!("Checkpoint is corrupt: unknown revise strategy"),
+        };
+        let supports = Supports::new(revise_strategy, size, num_tiles);
+        let mut state = WaveState {
+            wave: Wave::new(
+                domains,
+                is_ignore.clone(),
+                Heuristic::default(),
+                checkpoint.noise_seed,
+                rules.frequencies(),
+            ),
+            priorities: Arc::new(Array2::from_shape_vec(size, checkpoint.priorities)?),
+            levels: Vec::new(),
+            holes: hole_mask(&template, rules).map(Arc::new),
+            heuristic: match (checkpoint.sweep, checkpoint.shannon) {
+                (Some(dir_index), _) => Heuristic::Sweep(saved_direction(dir_index)?),
+                (None, true) => Heuristic::ShannonEntropy,
+                (None, false) => Heuristic::MinimumEntropy,
+            },
+            trail: saved_trail(checkpoint.trail, size, num_tiles)?,
+            rng: checkpoint.rng,
+            layout: 0,
+        };
+        state.wave.set_consistency(if checkpoint.forward_checking {
+            Consistency::ForwardChecking
+        } else {
+            Consistency::Maintained
+        });
+        state.rebuild_buckets(&is_ignore);
+
+        Ok(Self {
+            template,
+            rules,
+            is_ignore,
+            neighbors,
+            supports,
+            propagation_budget: checkpoint.propagation_budget,
+            state,
+            id: NEXT_COLLAPSER_ID.fetch_add(1, Ordering::Relaxed),
+            snapshots: Vec::new(),
+            forgotten: 0,
+        })
+    }
+
+    /// The current state as a map.
+    /// Cells with a single remaining option are fixed, all others keep their template value.
+    #[must_use]
+    pub fn to_map(&self) -> Map {
+        let (height, width) = self.template.size();
+        let mut result = self.template.clone();
+        for y in 0..height {
+            for x in 0..width {
+                if !self.is_ignore[(y, x)]
+                    && self.state.wave.domain_size((y, x)) == 1
+                    && let Some(tile) = self.state.wave.domain((y, x)).ones().next()
+                {
+                    result[(y, x)] = Cell::Fixed(tile);
+                }
+            }
+        }
+        result
+    }
+}
+
+// Template saved in a checkpoint, which must have the saved size and only fix tiles of the rules
+fn saved_template(template: &str, size: (usize, usize), rules: &Rules) -> Result<Map> {
+    let template = Map::parse(template).context("Checkpoint is corrupt")?;
+    if template.size() != size {
+        bail!(
+            "Checkpoint is corrupt: template is {:?} but the map was {size:?}",
+            template.size()
+        );
+    }
+    if let Some(tile) = template.max_index()
+        && tile >= rules.len()
+    {
+        return Err(WfcError::InvalidRules(format!(
+            "checkpoint template uses tile {tile}, but the rules have {}",
+            rules.len()
+        ))
+        .into());
+    }
+    Ok(template)
+}
+
+// Domains saved in a checkpoint as bitset blocks, which may only allow tiles their template cells allow
+fn saved_domains(
+    domains: Vec<Vec<usize>>,
+    template: &Map,
+    num_tiles: usize,
+) -> Result<Array2<FixedBitSet>> {
+    let num_blocks = FixedBitSet::with_capacity(num_tiles).as_slice().len();
+    if domains.iter().any(|blocks| blocks.len() != num_blocks) {
+        bail!("Checkpoint is corrupt: domain size does not match the rules");
+    }
+    let domains = Array2::from_shape_vec(
+        template.size(),
+        domains
+            .into_iter()
+            .map(|blocks| FixedBitSet::with_capacity_and_blocks(num_tiles, blocks))
+            .collect(),
+    )?;
+    if let Some(((y, x), _)) = domains
+        .indexed_iter()
+        .find(|&(pos, domain)| !domain.is_subset(&template[pos].domain(num_tiles)))
+    {
+        bail!("Checkpoint is corrupt: cell ({y}, {x}) allows tiles its template cell does not");
+    }
+    Ok(domains)
+}
+
+// Decisions saved in a checkpoint, which must lie within the map and the rules
+fn saved_trail(
+    trail: Vec<((usize, usize), usize)>,
+    size: (usize, usize),
+    num_tiles: usize,
+) -> Result<Vec<((usize, usize), usize)>> {
+    if let Some(&((y, x), tile)) = trail
+        .iter()
+        .find(|&&((y, x), tile)| y >= size.0 || x >= size.1 || tile >= num_tiles)
+    {
+        bail!("Checkpoint is corrupt: decision {tile} at ({y}, {x}) is outside the map or rules");
+    }
+    Ok(trail)
+}
+
+// Direction saved in a checkpoint by its index
+fn saved_direction(index: usize) -> Result<Direction> {
+    ALL_DIRECTIONS
+        .get(index)
+        .copied()
+        .ok_or_else(|| anyhow!("Checkpoint is corrupt: unknown direction {index}"))
+}
+
+// Neighbours of each cell saved in a checkpoint, as (row, column, direction index)
+fn saved_neighbours(
+    size: (usize, usize),
+    neighbours: Vec<Vec<(usize, usize, usize)>>,
+) -> Result<Array2<Vec<Neighbour>>> {
+    let neighbours = neighbours
+        .into_iter()
+        .map(|neighbours| {
+            neighbours
+                .into_iter()
+                .map(|(y, x, dir_index)| {
+                    if y >= size.0 || x >= size.1 {
+                        bail!("Checkpoint is corrupt: neighbour ({y}, {x}) is outside the map");
+                    }
+                    let dir = saved_direction(dir_index)?;
+                    Ok(Neighbour {
+                        pos: (y, x),
+                        dir,
+                        opp_dir: dir.opposite(),
+                    })
+                })
+                .collect()
+        })
+        .collect::<Result<_>>()?;
+    Ok(Array2::from_shape_vec(size, neighbours)?)
+}
+
+/// Rows of a `Collapser`'s map, each yielded once all of its cells are fixed. Created by `Collapser::rows`.
+pub struct RowStream<'c, 'a> {
+    collapser: &'c mut Collapser<'a>,
+    next_row: usize,
+    failed: bool,
+}
+
+impl Iterator for RowStream<'_, '_> {
+    type Item = Result<Vec<Cell>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.next_row >= self.collapser.template.height() {
+            return None;
+        }
+        while !self.collapser.is_row_fixed(self.next_row) {
+            match self.collapser.step() {
+                Ok(Some(_)) => {}
+                Ok(None) => break,
+                Err(e) => {
+                    self.failed = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+
+        let row = self.collapser.row_cells(self.next_row);
+        self.next_row += 1;
+        Some(Ok(row))
+    }
+}
@@ -1,10 +1,12 @@
-use anyhow::{Result, bail};
 use fixedbitset::FixedBitSet;
 use ndarray::Array2;
-use photo::{ALL_DIRECTIONS, Direction};
-use std::collections::{HashSet, VecDeque};
+use rand::{
+    Rng,
+    distr::{Distribution, Uniform},
+};
 
-use super::backtracking::BacktrackState;
+use super::{support::Supports, wave::Wave};
+use crate::{ALL_DIRECTIONS, Cell, Direction, Map, Rules};
 
 // Precomputed neighbour data structure that works with 2D coordinates
 #[derive(Clone, Debug)]
@@ -14,11 +16,48 @@ pub struct Neighbour {
     pub opp_dir: Direction,
 }
 
+/// How cells on the edges of a map are constrained.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Boundary {
+    /// Edge cells have no neighbours beyond the edge, so anything may sit against them.
+    #[default]
+    Open,
+    /// The map wraps around like a torus: cells on the east edge neighbour those on the west edge, and cells
+    /// on the north edge those on the south edge, giving seamless maps which tile when repeated.
+    /// Run limits and buffer zones follow the wrapped neighbours too, while line constraints already cover whole
+    /// rows and columns.
+    Wrap,
+}
+
+impl Boundary {
+    // The neighbouring position in a direction, if the boundary gives the cell one
+    fn neighbour(
+        self,
+        direction: Direction,
+        pos: (usize, usize),
+        size: (usize, usize),
+    ) -> Option<(usize, usize)> {
+        match self {
+            Self::Open => direction.apply_to(pos, size),
+            Self::Wrap => {
+                let ((y, x), (height, width)) = (pos, size);
+                Some(match direction {
+                    Direction::North => ((y + height - 1) % height, x),
+                    Direction::East => (y, (x + 1) % width),
+                    Direction::South => ((y + 1) % height, x),
+                    Direction::West => (y, (x + width - 1) % width),
+                })
+            }
+        }
+    }
+}
+
 // Efficiently calculate neighborhood information for a grid
 pub fn calculate_neighbours(
     height: usize,
     width: usize,
     is_ignore: &Array2<bool>,
+    boundary: Boundary,
 ) -> Array2<Vec<Neighbour>> {
     let mut neighbors: Array2<Vec<Neighbour>> = Array2::from_elem((height, width), Vec::new());
     let bounds = (height, width);
@@ -30,15 +69,15 @@ pub fn calculate_neighbours(
             }
 
             for dir in ALL_DIRECTIONS.iter() {
-                // Use the direction's apply_to method for safer coordinate calculation
-                if let Some(neighbor_pos) = dir.apply_to((y, x), bounds) {
-                    if !is_ignore[neighbor_pos] {
-                        neighbors[(y, x)].push(Neighbour {
-                            pos: neighbor_pos,
-                            dir: *dir,
-                            opp_dir: dir.opposite(),
-                        });
-                    }
+                // Use the boundary to find the neighbour, wrapping around the edges if asked
+                if let Some(neighbor_pos) = boundary.neighbour(*dir, (y, x), bounds)
+                    && !is_ignore[neighbor_pos]
+                {
+                    neighbors[(y, x)].push(Neighbour {
+                        pos: neighbor_pos,
+                        dir: *dir,
+                        opp_dir: dir.opposite(),
+                    });
                 }
             }
         }
@@ -47,213 +86,164 @@ pub fn calculate_neighbours(
     neighbors
 }
 
-// Optimized constraint revision function
-pub fn revise(
-    domains: &mut Array2<FixedBitSet>,
-    domain_sizes: &mut Array2<usize>,
-    rules: &crate::Rules,
-    xi: (usize, usize),
-    xj: (usize, usize),
-    dir: Direction,
-) -> bool {
-    let mut modified = false;
-    let dir_index = dir.index();
-
-    // Early exit if domain is already a singleton
-    if domain_sizes[xi] <= 1 {
-        return false;
-    }
-
-    // Fast path: if we have only one option in xj, we can directly filter xi
-    if domain_sizes[xj] == 1 {
-        let v = domains[xj].ones().next().unwrap();
-
-        // Track which options to remove (without modifying domain during iteration)
-        let mut remove_count = 0;
-
-        // Use a stack-allocated bitvector for small domains, fallback to Vec for larger
-        if domains[xi].len() <= 128 {
-            let mut to_remove = [false; 128];
-
-            for u in domains[xi].ones() {
-                if !rules.masks()[u][dir_index].contains(v) {
-                    if u < 128 {
-                        to_remove[u] = true;
-                        remove_count += 1;
-                    }
-                }
-            }
-
-            if remove_count > 0 {
-                for u in 0..domains[xi].len().min(128) {
-                    if to_remove[u] && domains[xi].contains(u) {
-                        domains[xi].remove(u);
-                    }
-                }
-                domain_sizes[xi] -= remove_count;
-                modified = true;
-            }
-        } else {
-            // For larger domains, use the bitvector directly
-            // Create a temporary bitvector for efficient operations
-            let mut domain_copy = domains[xi].clone();
-
-            // Efficiently remove values without support by iterating once
-            for u in domains[xi].ones() {
-                if !rules.masks()[u][dir_index].contains(v) {
-                    domain_copy.set(u, false);
-                    remove_count += 1;
-                }
-            }
-
-            if remove_count > 0 {
-                // Replace the original domain with our modified copy
-                domains[xi] = domain_copy;
-                domain_sizes[xi] -= remove_count;
-                modified = true;
-            }
+// Choose one of the tiles in a domain, weighted by tile frequency, without allocating.
+// Draws the same random number and picks the same tile as sampling a `WeightedIndex` built over the
+// domain's frequencies, so seeded runs are unchanged: the draw is uniform below the total frequency, and the
+// choice is the first tile whose cumulative frequency exceeds it.
+pub fn choose_weighted(domain: &FixedBitSet, frequencies: &[usize], rng: &mut impl Rng) -> usize {
+    let total: usize = domain.ones().map(|tile| frequencies[tile]).sum();
+    let draw = Uniform::new(0, total)
+        .expect("Cell must have an option with a positive frequency")
+        .sample(rng);
+    let mut cumulative = 0;
+    for tile in domain.ones() {
+        cumulative += frequencies[tile];
+        if cumulative > draw {
+            return tile;
         }
-
-        return modified;
     }
+    unreachable!("Draw is below the total frequency")
+}
 
-    // Standard case: check each value in xi domain against possible supports in xj
-    // Create a temporary bitvector for efficient operations
-    let mut domain_copy = domains[xi].clone();
-    let mut modified_count = 0;
-
-    for u in domains[xi].ones() {
-        let mask = &rules.masks()[u][dir_index];
-        let mut has_support = false;
-
-        // Use early exit loop for better performance
-        for v in domains[xj].ones() {
-            if mask.contains(v) {
-                has_support = true;
-                break;
-            }
-        }
-
-        if !has_support {
-            domain_copy.set(u, false);
-            modified_count += 1;
-        }
+// Choose one of the tiles in a cell's domain, weighted by tile frequency scaled by the map's landmarks and
+// profiles, and by the weight of the tile's adjacency with each neighbour already fixed to a single tile.
+// Without any scaling this is `choose_weighted`, so such maps collapse as before.
+// A soft hole takes the hole tile whenever it is still possible, so a hole is only filled once a search has
+// backtracked out of leaving it empty, or propagation has ruled that out.
+pub fn choose_weighted_at(
+    domain: &FixedBitSet,
+    rules: &Rules,
+    map: &Map,
+    wave: &Wave,
+    neighbors: &[Neighbour],
+    pos: (usize, usize),
+    rng: &mut impl Rng,
+) -> usize {
+    if map[pos] == Cell::Hole
+        && let Some(hole_tile) = rules.hole_tile().filter(|&tile| domain.contains(tile))
+    {
+        return hole_tile;
     }
-
-    if modified_count > 0 {
-        domains[xi] = domain_copy;
-        domain_sizes[xi] -= modified_count;
-        modified = true;
+    let frequencies = rules.frequencies();
+    if !map.has_frequency_multipliers() && !rules.has_adjacency_weights() {
+        return choose_weighted(domain, frequencies, rng);
     }
+    let fixed_neighbors: Vec<(Direction, usize)> = neighbors
+        .iter()
+        .filter(|neighbor| neighbor.pos != pos && wave.domain_size(neighbor.pos) == 1)
+        .filter_map(|neighbor| Some((neighbor.dir, wave.domain(neighbor.pos).minimum()?)))
+        .collect();
+    let weight = |tile: usize| {
+        let adjacency: f64 = fixed_neighbors
+            .iter()
+            .map(|&(dir, other)| rules.adjacency_weight(tile, dir, other) as f64)
+            .product();
+        map.frequency_multiplier(pos, tile) * frequencies[tile] as f64 * adjacency
+    };
+    let total: f64 = domain.ones().map(weight).sum();
+    let draw = rng.random::<f64>() * total;
+    let mut cumulative = 0.0;
+    let mut chosen = None;
+    for tile in domain.ones().filter(|&tile| frequencies[tile] > 0) {
+        cumulative += weight(tile);
+        chosen = Some(tile);
+        if cumulative > draw {
+            break;
+        }
+    }
+    chosen.expect("Cell must have an option with a positive frequency")
+}
 
-    modified
+// Soft holes of a map, if it has any and the rules have a hole tile for them to take.
+// Solvers put holes in a selection group of their own ahead of every other cell, so each hole is decided
+// before its surroundings can rule out leaving it empty.
+pub fn hole_mask(map: &Map, rules: &Rules) -> Option<Array2<bool>> {
+    rules.hole_tile()?;
+    let holes = Array2::from_shape_fn(map.size(), |pos| map[pos] == Cell::Hole);
+    holes.iter().any(|&is_hole| is_hole).then_some(holes)
 }
 
-// Propagate constraints from a starting cell
-pub fn propagate_constraints(
-    domains: &mut Array2<FixedBitSet>,
-    domain_sizes: &mut Array2<usize>,
+// Find the options of xi which have no support from xj, where dir is the direction from xi to xj.
+// Returns None if every option is supported.
+pub fn unsupported(
+    domains: &Array2<FixedBitSet>,
+    domain_sizes: &Array2<usize>,
     rules: &crate::Rules,
-    neighbors: &Array2<Vec<Neighbour>>,
-    start_cell: (usize, usize),
-    max_iterations: usize,
-    mut backtrack_state: Option<&mut BacktrackState>,
-) -> Result<HashSet<(usize, usize)>> {
-    let mut queue = VecDeque::new();
-    let mut affected_cells = HashSet::new();
+    supports: &mut Supports,
+    xi: (usize, usize),
+    xj: (usize, usize),
+    dir: Direction,
+) -> Option<FixedBitSet> {
+    let dir_index = dir.index();
 
-    // Start with the neighbors of the given cell
-    for neighbor in &neighbors[start_cell] {
-        queue.push_back((neighbor.pos, start_cell, neighbor.opp_dir));
+    // Singletons are still revised, since a fixed cell may have lost its support
+    if domain_sizes[xi] == 0 {
+        return None;
     }
 
-    let mut iteration_count = 0;
-    while let Some((xi, xj, dir)) = queue.pop_front() {
-        // Before modifying a domain, save its state if tracking for backtracking
-        if let Some(state) = &mut backtrack_state {
-            if !state.changed_cells.contains(&xi) {
-                state.changed_cells.insert(xi);
-                state.domain_copies.insert(xi, domains[xi].clone());
-                state.domain_size_copies.insert(xi, domain_sizes[xi]);
-            }
-        }
-
-        iteration_count += 1;
-        if iteration_count > max_iterations {
-            bail!("Too many constraint propagation iterations");
-        }
-
-        if revise(domains, domain_sizes, rules, xi, xj, dir) {
-            if domain_sizes[xi] == 0 {
-                bail!("No valid tiles remain at cell ({}, {})", xi.0, xi.1);
-            }
-
-            // Track affected cells for domain bucket updates
-            affected_cells.insert(xi);
+    let mut removals = FixedBitSet::with_capacity(domains[xi].len());
 
-            // Add all affected neighbors to queue except xj
-            for neighbor in &neighbors[xi] {
-                if neighbor.pos != xj {
-                    queue.push_back((neighbor.pos, xi, neighbor.opp_dir));
-                }
+    // Fast path: if we have only one option in xj, we can directly filter xi
+    if domain_sizes[xj] == 1 {
+        let v = domains[xj].ones().next().unwrap();
+        for u in domains[xi].ones() {
+            if !rules.is_allowed(u, v, dir) {
+                removals.insert(u);
             }
         }
-    }
-
-    Ok(affected_cells)
-}
-
-// Perform initial constraint propagation on the entire grid
-pub fn initial_propagation(
-    domains: &mut Array2<FixedBitSet>,
-    domain_sizes: &mut Array2<usize>,
-    rules: &crate::Rules,
-    height: usize,
-    width: usize,
-    is_ignore: &Array2<bool>,
-    neighbors: &Array2<Vec<Neighbour>>,
-    max_iterations: usize,
-) -> Result<()> {
-    let mut queue = VecDeque::with_capacity(4 * width * height);
-
-    // Initial queue population with all constraints
-    for y in 0..height {
-        for x in 0..width {
-            if is_ignore[(y, x)] {
+    } else {
+        // Standard case: check each value in xi domain against possible supports in xj
+        for u in domains[xi].ones() {
+            // Re-check the last known support before scanning
+            if let Some(v) = supports.get(xi, u, dir_index)
+                && domains[xj].contains(v)
+            {
                 continue;
             }
 
-            for neighbor in &neighbors[(y, x)] {
-                queue.push_back(((y, x), neighbor.pos, neighbor.dir));
+            let support = if rules.is_sparse() {
+                // Sparse rules: scan the short adjacency list rather than the neighbour's domain
+                rules.adjacency_list(u)[dir_index]
+                    .iter()
+                    .copied()
+                    .find(|&v| domains[xj].contains(v))
+            } else {
+                let mask = rules.allowed(u, dir);
+
+                // Use early exit loop for better performance
+                domains[xj].ones().find(|&v| mask.contains(v))
+            };
+
+            match support {
+                Some(v) => supports.set(xi, u, dir_index, v),
+                None => removals.insert(u),
             }
         }
     }
 
-    // Initial propagation - full AC-3
-    let mut iteration_count = 0;
-    while let Some((xi, xj, dir)) = queue.pop_front() {
-        iteration_count += 1;
-        if iteration_count > max_iterations {
-            bail!("Too many initial constraint propagation iterations");
-        }
-
-        if revise(domains, domain_sizes, rules, xi, xj, dir) {
-            if domain_sizes[xi] == 0 {
-                bail!(
-                    "No valid tiles remain at cell ({}, {}) during initial propagation",
-                    xi.0,
-                    xi.1
-                );
-            }
-
-            // Add all affected neighbors to queue except xj
-            for neighbor in &neighbors[xi] {
-                if neighbor.pos != xj {
-                    queue.push_back((neighbor.pos, xi, neighbor.opp_dir));
-                }
-            }
-        }
+    if removals.is_clear() {
+        None
+    } else {
+        Some(removals)
     }
+}
 
-    Ok(())
+// Find the options of a cell which cannot sit in a direction of themselves, for a cell which is its own
+// neighbour, as on a wrapped map one cell across. Returns None if every option can.
+pub fn unsupported_by_self(
+    domain: &FixedBitSet,
+    rules: &crate::Rules,
+    dir: Direction,
+) -> Option<FixedBitSet> {
+    let mut removals = FixedBitSet::with_capacity(domain.len());
+    removals.extend(
+        domain
+            .ones()
+            .filter(|&tile| !rules.is_allowed(tile, tile, dir)),
+    );
+    if removals.is_clear() {
+        None
+    } else {
+        Some(removals)
+    }
 }
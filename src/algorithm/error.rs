@@ -0,0 +1,126 @@
+use std::fmt::{Display, Formatter};
+
+use crate::Direction;
+
+/// Reason a collapse failed.
+/// The solvers return `anyhow` errors, which carry a `WfcError` for failures of the collapse itself, recovered
+/// with `error.downcast_ref::<WfcError>()`. This tells a contradiction, which another seed or a backtracking
+/// solver may get past, apart from a problem with the template, rules or configuration, which no retry will fix.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WfcError {
+    /// Propagation removed every option from a cell.
+    /// `direction` points to the neighbour whose options no longer supported any of the cell's, and is `None`
+    /// when another constraint emptied the cell, such as a run limit or buffer zone.
+    Contradiction {
+        cell: (usize, usize),
+        direction: Option<Direction>,
+    },
+    /// Propagation removed every option from a cell of a volume, at (floor, row, column).
+    VolumeContradiction { cell: (usize, usize, usize) },
+    /// The template's fixed cells and restrictions leave a cell with no options before anything is collapsed,
+    /// so the collapse fails whatever the seed.
+    Unsatisfiable { cell: (usize, usize) },
+    /// Propagation ran for more than the given number of iterations.
+    MaxIterations(usize),
+    /// Backtracking gave up after the given number of backtracks.
+    MaxBacktracks(usize),
+    /// Local resets gave up after the given number of resets.
+    MaxResets(usize),
+    /// The rules cannot be used here, for the reason given.
+    InvalidRules(String),
+    /// The quotas of the rules cannot all be met on the template, for the reason given.
+    UnsatisfiableQuotas(String),
+    /// A cell was given a tile which is not one of its options.
+    InvalidChoice { cell: (usize, usize), tile: usize },
+}
+
+impl WfcError {
+    /// Whether the collapse failed on a contradiction reached by its random choices, so a retry with
+    /// another seed, or with a backtracking solver, may succeed.
+    #[must_use]
+    pub fn is_contradiction(&self) -> bool {
+        matches!(
+            self,
+            Self::Contradiction { .. } | Self::VolumeContradiction { .. }
+        )
+    }
+}
+
+/// Error raised when propagation removes every option from a cell.
+#[deprecated(
+    note = "Collapses and propagation fail with `WfcError::Contradiction`, so match on that instead"
+)]
+#[derive(Debug)]
+pub struct Contradiction {
+    pub cell: (usize, usize),
+}
+
+#[allow(deprecated)]
+impl Display for Contradiction {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "No valid tiles remain at cell ({}, {})",
+            self.cell.0, self.cell.1
+        )
+    }
+}
+
+#[allow(deprecated)]
+impl std::error::Error for Contradiction {}
+
+#[allow(deprecated)]
+impl From<Contradiction> for WfcError {
+    fn from(contradiction: Contradiction) -> Self {
+        Self::Contradiction {
+            cell: contradiction.cell,
+            direction: None,
+        }
+    }
+}
+
+impl Display for WfcError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Self::Contradiction {
+                cell,
+                direction: Some(direction),
+            } => write!(
+                f,
+                "No valid tiles remain at cell ({}, {}), with no support from the {:?}",
+                cell.0, cell.1, direction
+            ),
+            Self::Contradiction {
+                cell,
+                direction: None,
+            } => write!(f, "No valid tiles remain at cell ({}, {})", cell.0, cell.1),
+            Self::VolumeContradiction { cell } => write!(
+                f,
+                "No valid tiles remain at cell ({}, {}, {})",
+                cell.0, cell.1, cell.2
+            ),
+            Self::Unsatisfiable { cell } => write!(
+                f,
+                "The template leaves no valid tiles for cell ({}, {})",
+                cell.0, cell.1
+            ),
+            Self::MaxIterations(limit) => write!(
+                f,
+                "Too many constraint propagation iterations (limit {limit})"
+            ),
+            Self::MaxBacktracks(limit) => {
+                write!(f, "Maximum backtracking attempts exceeded ({limit})")
+            }
+            Self::MaxResets(limit) => write!(f, "Maximum local resets exceeded ({limit})"),
+            Self::InvalidRules(reason) => write!(f, "Invalid rules: {reason}"),
+            Self::UnsatisfiableQuotas(reason) => write!(f, "Quotas cannot be met: {reason}"),
+            Self::InvalidChoice { cell, tile } => write!(
+                f,
+                "Tile {} is not an option for cell at ({}, {})",
+                tile, cell.0, cell.1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for WfcError {}
@@ -1,172 +1,236 @@
-use anyhow::{Result, bail};
-use indicatif::{ProgressBar, ProgressStyle};
-use ndarray::Array2;
-use rand::{distr::weighted::WeightedIndex, prelude::*};
-use std::collections::HashSet;
+use anyhow::Result;
+use rand::prelude::*;
+use std::{sync::Arc, time::Instant};
+
+use super::{
+    common::{Boundary, calculate_neighbours, choose_weighted_at},
+    options::CollapseOptions,
+    precheck::check_quotas_with_boundary,
+    progress::{CellProgress, ProgressSink},
+    selection::Heuristic,
+    stats::CollapseStats,
+    support::{Consistency, ReviseStrategy, Supports},
+    wave::Wave,
+};
+use crate::{AlgorithmInfo, Map, Provenance, Rules, WaveFunction};
+
+pub struct WaveFunctionFast {
+    options: CollapseOptions,
+    revise_strategy: ReviseStrategy,
+    consistency: Consistency,
+}
+
+impl WaveFunctionFast {
+    #[must_use]
+    pub fn builder() -> WaveFunctionFastBuilder {
+        WaveFunctionFastBuilder::default()
+    }
+}
 
-use super::common::{calculate_neighbours, initial_propagation, propagate_constraints};
-use crate::{Cell, Map, Rules, WaveFunction};
+impl Default for WaveFunctionFast {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
 
-const MAX_ITERATIONS: usize = 1_000_000; // Max iterations for constraint propagation
+/// Builder for configuring a `WaveFunctionFast`.
+#[derive(Default)]
+pub struct WaveFunctionFastBuilder {
+    options: CollapseOptions,
+    revise_strategy: ReviseStrategy,
+    consistency: Consistency,
+}
 
-pub struct WaveFunctionFast;
+impl WaveFunctionFastBuilder {
+    /// Maximum number of constraint revisions in a single propagation.
+    #[must_use]
+    pub fn max_iterations(mut self, max_iterations: usize) -> Self {
+        self.options.max_iterations = max_iterations;
+        self
+    }
+
+    /// Order in which cells are chosen for collapse.
+    #[must_use]
+    pub fn heuristic(mut self, heuristic: Heuristic) -> Self {
+        self.options.heuristic = heuristic;
+        self
+    }
+
+    /// How support for a cell's options is checked during propagation.
+    #[must_use]
+    pub fn revise_strategy(mut self, revise_strategy: ReviseStrategy) -> Self {
+        self.revise_strategy = revise_strategy;
+        self
+    }
+
+    /// How cells on the edges of the map are constrained, such as wrapping around for tileable maps.
+    #[must_use]
+    pub fn boundary(mut self, boundary: Boundary) -> Self {
+        self.options.boundary = boundary;
+        self
+    }
+
+    /// How far the consequences of each decision are propagated.
+    #[must_use]
+    pub fn consistency(mut self, consistency: Consistency) -> Self {
+        self.consistency = consistency;
+        self
+    }
+
+    /// Where progress is reported while collapsing.
+    #[must_use]
+    pub fn progress(mut self, sink: Arc<dyn ProgressSink>) -> Self {
+        self.options.progress = sink;
+        self
+    }
+
+    /// Replace every setting shared with the other solvers at once.
+    #[must_use]
+    pub fn options(mut self, options: CollapseOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    #[must_use]
+    pub fn build(self) -> WaveFunctionFast {
+        WaveFunctionFast {
+            options: self.options,
+            revise_strategy: self.revise_strategy,
+            consistency: self.consistency,
+        }
+    }
+}
 
 impl WaveFunction for WaveFunctionFast {
+    fn info(&self) -> AlgorithmInfo {
+        AlgorithmInfo {
+            name: "fast",
+            supports_backtracking: false,
+            deterministic: true,
+            complexity: format!(
+                "Collapses each cell once, propagating at most {} revisions after each; fails at the first contradiction",
+                self.options.max_iterations
+            ),
+        }
+    }
+
+    fn boundary(&self) -> Boundary {
+        self.options.boundary
+    }
+
+    fn settings(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("revise_strategy", format!("{:?}", self.revise_strategy)),
+            ("consistency", format!("{:?}", self.consistency)),
+        ]
+    }
+
     /// Collapses a map using a hybrid optimized Wave Function Collapse algorithm
     /// Returns a new map with all wildcards collapsed to fixed values.
-    fn collapse(map: &Map, rules: &Rules, rng: &mut impl Rng) -> Result<Map> {
-        let (height, width) = map.size();
-        let num_tiles = rules.len();
+    fn collapse(&self, map: &Map, rules: &Rules, rng: &mut impl Rng) -> Result<Map> {
+        self.collapse_with_options(map, rules, &self.options, rng)
+    }
 
-        // Use Array2 for domains and mask
-        let mut domains = map.domains(num_tiles);
-        let is_ignore = map.mask();
+    fn collapse_with_options(
+        &self,
+        map: &Map,
+        rules: &Rules,
+        options: &CollapseOptions,
+        rng: &mut impl Rng,
+    ) -> Result<Map> {
+        Ok(self.run(map, rules, options, rng)?.0)
+    }
 
-        // Pre-compute and cache domain sizes to avoid repeated counting
-        let mut domain_sizes = Array2::from_elem((height, width), 0);
+    fn collapse_with_stats(
+        &self,
+        map: &Map,
+        rules: &Rules,
+        rng: &mut impl Rng,
+    ) -> Result<(Map, CollapseStats)> {
+        self.run(map, rules, &self.options, rng)
+    }
+}
 
-        // One-time calculation of domain sizes
-        for y in 0..height {
-            for x in 0..width {
-                if !is_ignore[(y, x)] {
-                    domain_sizes[(y, x)] = domains[(y, x)].count_ones(..);
-                }
-            }
-        }
+impl WaveFunctionFast {
+    // Collapse a map with the given options, measuring the collapse
+    fn run(
+        &self,
+        map: &Map,
+        rules: &Rules,
+        options: &CollapseOptions,
+        rng: &mut impl Rng,
+    ) -> Result<(Map, CollapseStats)> {
+        let start = Instant::now();
+        let mut stats = CollapseStats::default();
 
-        // Precompute neighbors for faster access
-        let neighbors = calculate_neighbours(height, width, &is_ignore);
+        check_quotas_with_boundary(map, rules, options.boundary)?;
 
-        // Initial constraint propagation across the entire grid
-        initial_propagation(
-            &mut domains,
-            &mut domain_sizes,
-            rules,
-            height,
-            width,
-            &is_ignore,
-            &neighbors,
-            MAX_ITERATIONS,
-        )?;
-
-        // Count cells to collapse for progress bar
-        let mut cells_to_collapse = 0;
-        for y in 0..height {
-            for x in 0..width {
-                if !is_ignore[(y, x)] && domain_sizes[(y, x)] > 1 {
-                    cells_to_collapse += 1;
-                }
-            }
-        }
+        let (height, width) = map.size();
+        let num_tiles = rules.len();
 
-        let pb = ProgressBar::new(cells_to_collapse as u64);
-        pb.set_style(
-            ProgressStyle::with_template("{bar:40.cyan/blue} {pos}/{len} cells")
-                .unwrap()
-                .progress_chars("##-"),
+        #[cfg(feature = "tracing")]
+        let _span =
+            tracing::info_span!("collapse", algorithm = "fast", height, width, num_tiles).entered();
+
+        // All domain changes go through the wave, which keeps domain sizes and buckets in step
+        let is_ignore = map.mask();
+        let neighbors = calculate_neighbours(height, width, &is_ignore, options.boundary);
+        let mut supports = Supports::new(self.revise_strategy, (height, width), num_tiles);
+        let mut wave = Wave::from_template(
+            map,
+            rules,
+            options.heuristic,
+            self.consistency,
+            rng.random(),
         );
 
-        // More efficient bucket management - fixed-size array of hashsets
-        // Each bucket corresponds to an entropy level (number of possible states)
-        let mut bucket_sets: Vec<HashSet<(usize, usize)>> = vec![HashSet::new(); num_tiles + 1];
-
-        // Initial population of entropy buckets
-        for y in 0..height {
-            for x in 0..width {
-                if !is_ignore[(y, x)] && domain_sizes[(y, x)] > 1 {
-                    bucket_sets[domain_sizes[(y, x)]].insert((y, x));
-                }
-            }
-        }
+        // Initial constraint propagation across the entire grid
+        wave.propagate_all(rules, &neighbors, &mut supports, options.max_iterations)?;
 
-        // Main collapse loop with bucketed entropy selection
-        'outer: while let Some(entropy) = (2..=num_tiles).find(|&e| !bucket_sets[e].is_empty()) {
-            // Extract a cell from the current entropy bucket
-            let best_idx = *bucket_sets[entropy].iter().next().unwrap();
-            bucket_sets[entropy].remove(&best_idx);
-
-            // Quick verification that domain size is correct
-            // Only verify when we've taken a cell from a bucket, not on every domain change
-            if domain_sizes[best_idx] != domains[best_idx].count_ones(..) {
-                domain_sizes[best_idx] = domains[best_idx].count_ones(..);
-                if domain_sizes[best_idx] != entropy {
-                    // Our bucket assignment was wrong, put it in the right bucket
-                    if domain_sizes[best_idx] > 1 {
-                        bucket_sets[domain_sizes[best_idx]].insert(best_idx);
-                    }
-                    continue 'outer;
-                }
-            }
-
-            // Get options and their frequencies
-            let options: Vec<usize> = domains[best_idx].ones().collect();
-            let weights: Vec<usize> = options.iter().map(|&t| rules.frequencies()[t]).collect();
+        let pb = CellProgress::new(&*options.progress, &self.info(), wave.remaining());
 
+        // Main collapse loop with bucketed entropy selection
+        while let Some(best_idx) = wave.next_cell() {
             // Choose a tile based on frequency weights
-            let choice = if weights.iter().any(|&w| w == 0) {
-                // Handle zero weights case - use uniform distribution
-                options[rng.random_range(0..options.len())]
-            } else {
-                // Use weighted distribution
-                let dist = WeightedIndex::new(&weights).unwrap();
-                options[dist.sample(rng)]
-            };
+            let choice = choose_weighted_at(
+                wave.domain(best_idx),
+                rules,
+                map,
+                &wave,
+                &neighbors[best_idx],
+                best_idx,
+                rng,
+            );
 
             // Fix the chosen cell
-            domains[best_idx].clear();
-            domains[best_idx].insert(choice);
-            domain_sizes[best_idx] = 1;
+            wave.collapse(best_idx, choice, &neighbors)?;
 
-            pb.inc(1);
+            pb.inc();
 
-            // Propagate constraints from the collapsed cell using shared function
-            match propagate_constraints(
-                &mut domains,
-                &mut domain_sizes,
-                rules,
-                &neighbors,
-                best_idx,
-                MAX_ITERATIONS,
-                None, // No backtracking for fast algorithm
-            ) {
-                Ok(affected_cells) => {
-                    // Update buckets for all affected cells
-                    for &cell_idx in &affected_cells {
-                        // First remove from all buckets (faster than trying to track which bucket)
-                        for e in 2..=num_tiles {
-                            bucket_sets[e].remove(&cell_idx);
-                        }
-
-                        // Now add to correct bucket if the cell still has multiple options
-                        if domain_sizes[cell_idx] > 1 {
-                            bucket_sets[domain_sizes[cell_idx]].insert(cell_idx);
-                        }
-                    }
-                }
-                Err(e) => {
-                    // Handle constraint propagation failure
-                    bail!("Constraint propagation failed: {}", e);
-                }
-            }
+            // Propagate constraints from the collapsed cell
+            wave.propagate(rules, &neighbors, &mut supports, options.max_iterations)?;
         }
 
-        pb.finish_and_clear();
+        pb.finish();
+        trace_event!(info, "collapse complete");
 
         // Build the final map
-        let mut result = map.clone();
-        for y in 0..height {
-            for x in 0..width {
-                if !is_ignore[(y, x)] {
-                    let mut bits = domains[(y, x)].ones();
-                    let tile = match bits.next() {
-                        Some(t) => t,
-                        None => bail!("No possibilities for cell at ({}, {})", y, x),
-                    };
-                    result[(y, x)] = Cell::Fixed(tile);
-                }
-            }
-        }
+        let mut result = wave.to_map(map)?;
 
-        Ok(result)
+        // Undersized bodies are only visible once every cell is fixed
+        if rules.has_min_body_sizes() {
+            result = result.enforce_min_bodies(map, rules, self, options, rng)?;
+        }
+        stats.elapsed = start.elapsed();
+        stats.revisions = wave.revisions();
+        result.set_provenance(Some(Provenance::new(
+            self.info().name,
+            &self.settings(),
+            options,
+            rules,
+            stats.elapsed,
+            stats.backtracks + stats.restarts,
+        )));
+        Ok((result, stats))
     }
 }
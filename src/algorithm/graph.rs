@@ -0,0 +1,66 @@
+use anyhow::Result;
+use ndarray::Array2;
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64;
+
+use super::{collapser::Collapser, common::Neighbour};
+use crate::{GraphMap, Map, ReviseStrategy, Rules};
+
+/// Collapse a graph of cells, propagating constraints along its edges rather than a grid.
+///
+/// # Errors
+///
+/// Fails with a `WfcError` if propagation leaves a node with no options.
+pub fn collapse_graph(graph: &GraphMap, rules: &Rules, rng: &mut impl Rng) -> Result<GraphMap> {
+    collapse_graph_with_strategy(graph, rules, ReviseStrategy::default(), rng)
+}
+
+/// Collapse a graph of cells as `collapse_graph` does, checking support during propagation with the given
+/// strategy.
+///
+/// # Errors
+///
+/// Fails as `collapse_graph` does.
+pub fn collapse_graph_with_strategy(
+    graph: &GraphMap,
+    rules: &Rules,
+    revise_strategy: ReviseStrategy,
+    rng: &mut impl Rng,
+) -> Result<GraphMap> {
+    // Lay the nodes out in a single row, linked only by the graph's edges
+    let num_nodes = graph.len();
+    let template = Map::new(Array2::from_shape_fn((1, num_nodes), |(_, index)| {
+        graph[index]
+    }));
+
+    let is_ignore = template.mask();
+    let mut neighbors: Array2<Vec<Neighbour>> = Array2::from_elem((1, num_nodes), Vec::new());
+    for &(from, to, direction) in graph.edges() {
+        let (a, b) = ((0, from), (0, to));
+        if is_ignore[a] || is_ignore[b] {
+            continue;
+        }
+        neighbors[a].push(Neighbour {
+            pos: b,
+            dir: direction,
+            opp_dir: direction.opposite(),
+        });
+        neighbors[b].push(Neighbour {
+            pos: a,
+            dir: direction.opposite(),
+            opp_dir: direction,
+        });
+    }
+
+    let mut collapser = Collapser::with_neighbours(&template, rules, neighbors)?;
+    collapser.set_revise_strategy(revise_strategy);
+    collapser.set_rng(Pcg64::from_rng(rng));
+    collapser.run()?;
+    let row = collapser.to_map();
+
+    let mut result = graph.clone();
+    for node in 0..num_nodes {
+        result[node] = row[(0, node)];
+    }
+    Ok(result)
+}
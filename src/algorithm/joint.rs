@@ -0,0 +1,146 @@
+use anyhow::Result;
+use ndarray::Array2;
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64;
+
+use super::{
+    collapser::Collapser,
+    common::{Boundary, Neighbour, calculate_neighbours},
+};
+use crate::{Cell, Direction, Map, Rules};
+
+/// Declares that map `to` lies directly in `direction` of map `from`, so their facing edges are adjacent.
+#[derive(Clone, Copy, Debug)]
+pub struct MapLink {
+    pub from: usize,
+    pub to: usize,
+    pub direction: Direction,
+}
+
+impl MapLink {
+    #[must_use]
+    pub fn new(from: usize, to: usize, direction: Direction) -> Self {
+        Self {
+            from,
+            to,
+            direction,
+        }
+    }
+}
+
+/// Collapse several maps at once, propagating constraints across the linked edges during the collapse.
+/// Returns the collapsed maps in the same order as they were given.
+///
+/// # Errors
+///
+/// Fails with a `WfcError` if propagation leaves a cell of any of the maps with no options.
+///
+/// # Panics
+///
+/// Panics if no maps are given, if a link refers to a map which was not given, or if linked maps do not
+/// share the length of their facing edges.
+pub fn collapse_joint(
+    maps: &[Map],
+    links: &[MapLink],
+    rules: &Rules,
+    rng: &mut impl Rng,
+) -> Result<Vec<Map>> {
+    assert!(!maps.is_empty(), "At least one map must be given");
+
+    // Place the maps side by side, separated by a column of ignored cells
+    let height = maps.iter().map(Map::height).max().unwrap();
+    let mut offsets = Vec::with_capacity(maps.len());
+    let mut width = 0;
+    for map in maps {
+        offsets.push(width);
+        width += map.width() + 1;
+    }
+    let mut combined = Map::new(Array2::from_elem((height, width - 1), Cell::Ignore));
+    for (map, &offset) in maps.iter().zip(&offsets) {
+        for y in 0..map.height() {
+            for x in 0..map.width() {
+                combined[(y, offset + x)] = map[(y, x)];
+            }
+        }
+    }
+
+    let is_ignore = combined.mask();
+    let mut neighbors = calculate_neighbours(height, width - 1, &is_ignore, Boundary::Open);
+
+    // Connect the facing edges of each linked pair of maps
+    for link in links {
+        assert!(
+            link.from < maps.len() && link.to < maps.len(),
+            "Map link index out of bounds"
+        );
+        let (from, to) = (&maps[link.from], &maps[link.to]);
+        let (from_offset, to_offset) = (offsets[link.from], offsets[link.to]);
+        let pairs: Vec<((usize, usize), (usize, usize))> = match link.direction {
+            Direction::North => {
+                assert!(from.width() == to.width(), "Maps must have the same width");
+                (0..from.width())
+                    .map(|x| ((0, from_offset + x), (to.height() - 1, to_offset + x)))
+                    .collect()
+            }
+            Direction::East => {
+                assert!(
+                    from.height() == to.height(),
+                    "Maps must have the same height"
+                );
+                (0..from.height())
+                    .map(|y| ((y, from_offset + from.width() - 1), (y, to_offset)))
+                    .collect()
+            }
+            Direction::South => {
+                assert!(from.width() == to.width(), "Maps must have the same width");
+                (0..from.width())
+                    .map(|x| ((from.height() - 1, from_offset + x), (0, to_offset + x)))
+                    .collect()
+            }
+            Direction::West => {
+                assert!(
+                    from.height() == to.height(),
+                    "Maps must have the same height"
+                );
+                (0..from.height())
+                    .map(|y| ((y, from_offset), (y, to_offset + to.width() - 1)))
+                    .collect()
+            }
+        };
+        for (a, b) in pairs {
+            if is_ignore[a] || is_ignore[b] {
+                continue;
+            }
+            neighbors[a].push(Neighbour {
+                pos: b,
+                dir: link.direction,
+                opp_dir: link.direction.opposite(),
+            });
+            neighbors[b].push(Neighbour {
+                pos: a,
+                dir: link.direction.opposite(),
+                opp_dir: link.direction,
+            });
+        }
+    }
+
+    let mut collapser = Collapser::with_neighbours(&combined, rules, neighbors)?;
+    collapser.set_rng(Pcg64::from_rng(rng));
+    collapser.run()?;
+    let joined = collapser.to_map();
+
+    // Split the combined map back into its parts
+    Ok(maps
+        .iter()
+        .zip(&offsets)
+        .map(|(map, &offset)| {
+            let mut result = map.clone();
+            for y in 0..map.height() {
+                for x in 0..map.width() {
+                    result[(y, x)] = joined[(y, offset + x)];
+                }
+            }
+            result
+        })
+        .collect())
+}
@@ -0,0 +1,326 @@
+use anyhow::Result;
+use rand::prelude::*;
+use std::{sync::Arc, time::Instant};
+
+use super::{
+    common::{Boundary, calculate_neighbours, choose_weighted_at},
+    error::WfcError,
+    options::CollapseOptions,
+    precheck::check_quotas_with_boundary,
+    progress::{CellProgress, ProgressSink},
+    selection::Heuristic,
+    stats::CollapseStats,
+    support::{Consistency, ReviseStrategy, Supports},
+    wave::Wave,
+};
+use crate::{AlgorithmInfo, Map, Provenance, Rules, WaveFunction};
+
+const RADIUS: usize = 2; // Distance around a contradiction within which cells are reset
+const MAX_RESETS: usize = 100; // Max number of local resets
+
+/// Wave Function Collapse which recovers from contradictions by resetting every cell within
+/// a radius (Chebyshev distance) of the failed cell back to its template domain, then
+/// re-propagating from the edge of the reset region and carrying on.
+/// Gives up after a maximum number of resets.
+pub struct WaveFunctionLocalReset {
+    options: CollapseOptions,
+    radius: usize,
+    max_resets: usize,
+    revise_strategy: ReviseStrategy,
+    consistency: Consistency,
+}
+
+impl WaveFunctionLocalReset {
+    #[must_use]
+    pub fn builder() -> WaveFunctionLocalResetBuilder {
+        WaveFunctionLocalResetBuilder::default()
+    }
+}
+
+impl Default for WaveFunctionLocalReset {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+/// Builder for configuring a `WaveFunctionLocalReset`.
+pub struct WaveFunctionLocalResetBuilder {
+    options: CollapseOptions,
+    radius: usize,
+    max_resets: usize,
+    revise_strategy: ReviseStrategy,
+    consistency: Consistency,
+}
+
+impl Default for WaveFunctionLocalResetBuilder {
+    fn default() -> Self {
+        Self {
+            options: CollapseOptions::default(),
+            radius: RADIUS,
+            max_resets: MAX_RESETS,
+            revise_strategy: ReviseStrategy::default(),
+            consistency: Consistency::default(),
+        }
+    }
+}
+
+impl WaveFunctionLocalResetBuilder {
+    /// Distance around a contradiction within which cells are reset.
+    #[must_use]
+    pub fn radius(mut self, radius: usize) -> Self {
+        self.radius = radius;
+        self
+    }
+
+    /// Maximum number of resets before giving up.
+    #[must_use]
+    pub fn max_resets(mut self, max_resets: usize) -> Self {
+        self.max_resets = max_resets;
+        self
+    }
+
+    /// Maximum number of constraint revisions in a single propagation.
+    #[must_use]
+    pub fn max_iterations(mut self, max_iterations: usize) -> Self {
+        self.options.max_iterations = max_iterations;
+        self
+    }
+
+    /// Order in which cells are chosen for collapse.
+    #[must_use]
+    pub fn heuristic(mut self, heuristic: Heuristic) -> Self {
+        self.options.heuristic = heuristic;
+        self
+    }
+
+    /// How support for a cell's options is checked during propagation.
+    #[must_use]
+    pub fn revise_strategy(mut self, revise_strategy: ReviseStrategy) -> Self {
+        self.revise_strategy = revise_strategy;
+        self
+    }
+
+    /// How cells on the edges of the map are constrained, such as wrapping around for tileable maps.
+    #[must_use]
+    pub fn boundary(mut self, boundary: Boundary) -> Self {
+        self.options.boundary = boundary;
+        self
+    }
+
+    /// How far the consequences of each decision are propagated.
+    #[must_use]
+    pub fn consistency(mut self, consistency: Consistency) -> Self {
+        self.consistency = consistency;
+        self
+    }
+
+    /// Where progress is reported while collapsing.
+    #[must_use]
+    pub fn progress(mut self, sink: Arc<dyn ProgressSink>) -> Self {
+        self.options.progress = sink;
+        self
+    }
+
+    /// Replace every setting shared with the other solvers at once.
+    #[must_use]
+    pub fn options(mut self, options: CollapseOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    #[must_use]
+    pub fn build(self) -> WaveFunctionLocalReset {
+        WaveFunctionLocalReset {
+            options: self.options,
+            radius: self.radius,
+            max_resets: self.max_resets,
+            revise_strategy: self.revise_strategy,
+            consistency: self.consistency,
+        }
+    }
+}
+
+impl WaveFunction for WaveFunctionLocalReset {
+    fn info(&self) -> AlgorithmInfo {
+        AlgorithmInfo {
+            name: "local-reset",
+            supports_backtracking: false,
+            deterministic: true,
+            complexity: format!(
+                "As fast until a contradiction, which resets the cells within {} steps of it and re-propagates only those; gives up after {} resets",
+                self.radius, self.max_resets
+            ),
+        }
+    }
+
+    fn boundary(&self) -> Boundary {
+        self.options.boundary
+    }
+
+    fn settings(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("radius", self.radius.to_string()),
+            ("max_resets", self.max_resets.to_string()),
+            ("revise_strategy", format!("{:?}", self.revise_strategy)),
+            ("consistency", format!("{:?}", self.consistency)),
+        ]
+    }
+
+    /// Collapses a map, locally resetting the neighbourhood of any contradiction.
+    /// Returns a new map with all wildcards collapsed to fixed values.
+    fn collapse(&self, map: &Map, rules: &Rules, rng: &mut impl Rng) -> Result<Map> {
+        self.collapse_with_options(map, rules, &self.options, rng)
+    }
+
+    fn collapse_with_options(
+        &self,
+        map: &Map,
+        rules: &Rules,
+        options: &CollapseOptions,
+        rng: &mut impl Rng,
+    ) -> Result<Map> {
+        Ok(self.run(map, rules, options, rng)?.0)
+    }
+
+    fn collapse_with_stats(
+        &self,
+        map: &Map,
+        rules: &Rules,
+        rng: &mut impl Rng,
+    ) -> Result<(Map, CollapseStats)> {
+        self.run(map, rules, &self.options, rng)
+    }
+}
+
+impl WaveFunctionLocalReset {
+    // Collapse a map with the given options, measuring the collapse
+    fn run(
+        &self,
+        map: &Map,
+        rules: &Rules,
+        options: &CollapseOptions,
+        rng: &mut impl Rng,
+    ) -> Result<(Map, CollapseStats)> {
+        let start = Instant::now();
+        let mut stats = CollapseStats::default();
+
+        check_quotas_with_boundary(map, rules, options.boundary)?;
+
+        let (height, width) = map.size();
+        let num_tiles = rules.len();
+
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!(
+            "collapse",
+            algorithm = "local_reset",
+            height,
+            width,
+            num_tiles
+        )
+        .entered();
+
+        let is_ignore = map.mask();
+        let neighbors = calculate_neighbours(height, width, &is_ignore, options.boundary);
+        let mut supports = Supports::new(self.revise_strategy, (height, width), num_tiles);
+        let mut wave = Wave::from_template(
+            map,
+            rules,
+            options.heuristic,
+            self.consistency,
+            rng.random(),
+        );
+
+        wave.propagate_all(rules, &neighbors, &mut supports, options.max_iterations)?;
+
+        let cells_to_collapse = wave.remaining();
+        let pb = CellProgress::new(&*options.progress, &self.info(), cells_to_collapse);
+
+        let mut reset_count = 0;
+
+        while let Some(best_idx) = wave.next_cell() {
+            let choice = choose_weighted_at(
+                wave.domain(best_idx),
+                rules,
+                map,
+                &wave,
+                &neighbors[best_idx],
+                best_idx,
+                rng,
+            );
+
+            wave.collapse(best_idx, choice, &neighbors)?;
+
+            pb.inc();
+
+            if let Err(e) = wave.propagate(rules, &neighbors, &mut supports, options.max_iterations)
+            {
+                let Some(&WfcError::Contradiction { cell, .. }) = e.downcast_ref::<WfcError>()
+                else {
+                    return Err(e);
+                };
+                stats.record_contradiction(cells_to_collapse.saturating_sub(wave.remaining()));
+
+                // Keep resetting until the reset region re-propagates cleanly
+                let mut dead_cell = cell;
+                loop {
+                    reset_count += 1;
+                    pb.set_count(reset_count);
+                    trace_event!(debug, cell = ?dead_cell, resets = reset_count, "local reset");
+                    if reset_count > self.max_resets {
+                        return Err(WfcError::MaxResets(self.max_resets).into());
+                    }
+
+                    let mut reset_cells = Vec::new();
+                    for y in dead_cell.0.saturating_sub(self.radius)
+                        ..=(dead_cell.0 + self.radius).min(height - 1)
+                    {
+                        for x in dead_cell.1.saturating_sub(self.radius)
+                            ..=(dead_cell.1 + self.radius).min(width - 1)
+                        {
+                            if !is_ignore[(y, x)] {
+                                wave.reset((y, x), map.cell_domain((y, x), num_tiles));
+                                reset_cells.push((y, x));
+                            }
+                        }
+                    }
+
+                    for &cell in &reset_cells {
+                        wave.enqueue_cell(cell, &neighbors);
+                    }
+                    match wave.propagate(rules, &neighbors, &mut supports, options.max_iterations) {
+                        Ok(()) => break,
+                        Err(e) => match e.downcast_ref::<WfcError>() {
+                            Some(&WfcError::Contradiction { cell, .. }) => dead_cell = cell,
+                            _ => return Err(e),
+                        },
+                    }
+                }
+
+                pb.set_position(cells_to_collapse.saturating_sub(wave.remaining()));
+            }
+        }
+
+        pb.finish();
+        trace_event!(info, resets = reset_count, "collapse complete");
+
+        // Build the final map
+        let mut result = wave.to_map(map)?;
+
+        // Undersized bodies are only visible once every cell is fixed
+        if rules.has_min_body_sizes() {
+            result = result.enforce_min_bodies(map, rules, self, options, rng)?;
+        }
+        stats.elapsed = start.elapsed();
+        stats.revisions = wave.revisions();
+        stats.restarts = reset_count;
+        result.set_provenance(Some(Provenance::new(
+            self.info().name,
+            &self.settings(),
+            options,
+            rules,
+            stats.elapsed,
+            stats.backtracks + stats.restarts,
+        )));
+        Ok((result, stats))
+    }
+}
@@ -0,0 +1,564 @@
+use anyhow::Result;
+use ndarray::{Array2, Array3};
+use rand::{Rng, seq::SliceRandom};
+use std::{
+    collections::{HashMap, VecDeque},
+    time::Instant,
+};
+
+use super::common::{Boundary, Neighbour, calculate_neighbours};
+use crate::rules::{FNV_OFFSET, fnv_write};
+use crate::{
+    AlgorithmInfo, Cell, CollapseOptions, CollapseStats, Direction, Map, Rules, WaveFunction,
+};
+
+const MAX_SWEEPS: usize = 16; // Passes of local search over the map before settling
+const MIN_IMPROVEMENT: f64 = 1e-9; // Smallest cost reduction accepted as an improvement
+
+/// Collapses a map with another algorithm, then lowers the total cost of its adjacencies by local search,
+/// so aesthetically better adjacencies can be preferred among the legal ones.
+/// Each pass visits the cells in a random order and greedily moves each to its cheapest tile, then each pair
+/// of neighbouring cells to their cheapest pair of tiles, keeping only moves which leave the map valid.
+/// Passes repeat until one makes no improvement, or the pass limit is reached.
+/// Cells fixed by the template, and holes, keep the tiles they were collapsed to, and no move may leave a body
+/// smaller than its category's minimum size.
+/// The result is a local minimum of the cost rather than the global one.
+pub struct WaveFunctionMinCost<W> {
+    algorithm: W,
+    costs: Array3<f64>,
+    max_sweeps: usize,
+}
+
+impl WaveFunctionMinCost<()> {
+    #[must_use]
+    pub fn builder() -> WaveFunctionMinCostBuilder {
+        WaveFunctionMinCostBuilder::default()
+    }
+}
+
+impl<W: WaveFunction> WaveFunctionMinCost<W> {
+    // Record the search's settings in the provenance the inner algorithm gave a map
+    fn add_provenance(&self, map: &mut Map) {
+        let provenance = map
+            .provenance()
+            .cloned()
+            .map(|provenance| provenance.with_settings(&self.settings()));
+        map.set_provenance(provenance);
+    }
+
+    /// Total cost of every pair of neighbouring fixed cells of a map, including pairs across the edges if the
+    /// inner algorithm wraps them.
+    pub fn total_cost(&self, map: &Map) -> f64 {
+        let (height, width) = map.size();
+        let neighbors = calculate_neighbours(height, width, &map.mask(), self.algorithm.boundary());
+        self.cost(map, &neighbors)
+    }
+}
+
+impl<W> WaveFunctionMinCost<W> {
+    // Total cost of the pairs of neighbouring fixed cells, each counted from the cell to its west or south
+    fn cost(&self, map: &Map, neighbors: &Array2<Vec<Neighbour>>) -> f64 {
+        let mut total = 0.0;
+        for (pos, cell_neighbors) in neighbors.indexed_iter() {
+            let Cell::Fixed(tile) = map[pos] else {
+                continue;
+            };
+            for neighbor in cell_neighbors {
+                if let (Direction::East | Direction::North, Cell::Fixed(other)) =
+                    (neighbor.dir, map[neighbor.pos])
+                {
+                    total += self.pair_cost(tile, neighbor.dir, other);
+                }
+            }
+        }
+        total
+    }
+
+    fn check_costs(&self, rules: &Rules) {
+        assert_eq!(
+            self.costs.shape()[0],
+            rules.len(),
+            "Costs must match number of tiles"
+        );
+    }
+
+    // Cost of a tile's adjacencies with the fixed neighbours of a cell, where a cell wrapped onto itself
+    // neighbours the tile
+    fn local_cost(
+        &self,
+        map: &Map,
+        pos: (usize, usize),
+        neighbors: &[Neighbour],
+        tile: usize,
+    ) -> f64 {
+        neighbors
+            .iter()
+            .filter_map(|neighbor| match map[neighbor.pos] {
+                _ if neighbor.pos == pos => Some(self.pair_cost(tile, neighbor.dir, tile)),
+                Cell::Fixed(other) => Some(self.pair_cost(tile, neighbor.dir, other)),
+                _ => None,
+            })
+            .sum()
+    }
+
+    // Cost of placing `other` in a direction of `tile`
+    fn pair_cost(&self, tile: usize, direction: Direction, other: usize) -> f64 {
+        match direction {
+            Direction::East => self.costs[[tile, other, 0]],
+            Direction::West => self.costs[[other, tile, 0]],
+            Direction::North => self.costs[[tile, other, 1]],
+            Direction::South => self.costs[[other, tile, 1]],
+        }
+    }
+
+    // Move cells to cheaper tiles until no move improves the cost
+    fn improve(
+        &self,
+        map: &mut Map,
+        template: &Map,
+        rules: &Rules,
+        boundary: Boundary,
+        rng: &mut impl Rng,
+    ) {
+        let (height, width) = map.size();
+        let num_tiles = rules.len();
+        let neighbors = calculate_neighbours(height, width, &template.mask(), boundary);
+        let checker = Checker::new(rules, &neighbors, template);
+        // Holes are left as collapsed, so a hole given the hole tile keeps it
+        let mut cells: Vec<(usize, usize)> = template
+            .domains(num_tiles)
+            .indexed_iter()
+            .filter(|&(pos, domain)| template[pos] == Cell::Wildcard && domain.count_ones(..) > 1)
+            .map(|(pos, _)| pos)
+            .collect();
+        let domains: HashMap<(usize, usize), Vec<usize>> = cells
+            .iter()
+            .map(|&pos| (pos, template.cell_domain(pos, num_tiles).ones().collect()))
+            .collect();
+
+        for _ in 0..self.max_sweeps {
+            let mut improved = false;
+            cells.shuffle(rng);
+
+            for &pos in &cells {
+                let Cell::Fixed(current) = map[pos] else {
+                    continue;
+                };
+                let mut best = (self.local_cost(map, pos, &neighbors[pos], current), current);
+                for &tile in &domains[&pos] {
+                    if tile == current {
+                        continue;
+                    }
+                    let cost = self.local_cost(map, pos, &neighbors[pos], tile);
+                    if cost < best.0 - MIN_IMPROVEMENT && checker.keeps_quotas(current, tile) {
+                        map[pos] = Cell::Fixed(tile);
+                        if checker.fits(map, pos) && checker.keeps_bodies(map, pos, current) {
+                            best = (cost, tile);
+                        }
+                        map[pos] = Cell::Fixed(current);
+                    }
+                }
+                if best.1 != current {
+                    map[pos] = Cell::Fixed(best.1);
+                    improved = true;
+                }
+            }
+
+            for &pos in &cells {
+                for neighbor in &neighbors[pos] {
+                    // A cell wrapped onto itself is already moved alone
+                    let is_forward = matches!(neighbor.dir, Direction::East | Direction::South);
+                    if is_forward && neighbor.pos != pos && domains.contains_key(&neighbor.pos) {
+                        improved |= self.improve_pair(map, pos, neighbor, &domains, &checker);
+                    }
+                }
+            }
+
+            trace_event!(
+                debug,
+                cost = self.cost(map, &neighbors),
+                "local search pass"
+            );
+            if !improved {
+                break;
+            }
+        }
+    }
+
+    // Move a pair of neighbouring cells to their cheapest valid pair of tiles, returning whether they moved
+    fn improve_pair(
+        &self,
+        map: &mut Map,
+        pos: (usize, usize),
+        neighbor: &Neighbour,
+        domains: &HashMap<(usize, usize), Vec<usize>>,
+        checker: &Checker,
+    ) -> bool {
+        let other_pos = neighbor.pos;
+        let (Cell::Fixed(current), Cell::Fixed(other_current)) = (map[pos], map[other_pos]) else {
+            return false;
+        };
+        let neighbors = checker.neighbors;
+        // Adjacencies between the two cells count once, even when wrapping makes them neighbours both ways
+        let pair_cost = |map: &Map, tile: usize, other: usize| {
+            let shared: f64 = neighbors[pos]
+                .iter()
+                .filter(|n| n.pos == other_pos)
+                .map(|n| self.pair_cost(tile, n.dir, other))
+                .sum();
+            self.local_cost(map, pos, &neighbors[pos], tile)
+                + self.local_cost(map, other_pos, &neighbors[other_pos], other)
+                - shared
+        };
+        let mut best = (
+            pair_cost(map, current, other_current),
+            current,
+            other_current,
+        );
+
+        for &tile in &domains[&pos] {
+            for &other in &domains[&other_pos] {
+                if (tile, other) == (current, other_current)
+                    || !checker.rules.is_allowed(tile, other, neighbor.dir)
+                    || !checker.keeps_quotas(current, tile)
+                    || !checker.keeps_quotas(other_current, other)
+                {
+                    continue;
+                }
+                map[pos] = Cell::Fixed(tile);
+                map[other_pos] = Cell::Fixed(other);
+                let cost = pair_cost(map, tile, other);
+                if cost < best.0 - MIN_IMPROVEMENT
+                    && checker.fits(map, pos)
+                    && checker.fits(map, other_pos)
+                    && checker.keeps_bodies(map, pos, current)
+                    && checker.keeps_bodies(map, other_pos, other_current)
+                {
+                    best = (cost, tile, other);
+                }
+            }
+        }
+        map[pos] = Cell::Fixed(best.1);
+        map[other_pos] = Cell::Fixed(best.2);
+        (best.1, best.2) != (current, other_current)
+    }
+}
+
+/// Builder for configuring a `WaveFunctionMinCost`.
+pub struct WaveFunctionMinCostBuilder {
+    max_sweeps: usize,
+}
+
+impl Default for WaveFunctionMinCostBuilder {
+    fn default() -> Self {
+        Self {
+            max_sweeps: MAX_SWEEPS,
+        }
+    }
+}
+
+impl WaveFunctionMinCostBuilder {
+    /// Maximum number of local search passes over the map.
+    #[must_use]
+    pub fn max_sweeps(mut self, max_sweeps: usize) -> Self {
+        self.max_sweeps = max_sweeps;
+        self
+    }
+
+    /// Build around the algorithm giving the initial valid collapse.
+    /// `costs` has the shape of the adjacency matrix of `Rules::new`: `costs[[i, j, 0]]` is the cost of tile `j`
+    /// east of tile `i`, and `costs[[i, j, 1]]` the cost of tile `j` north of tile `i`.
+    /// Costs of adjacencies the rules forbid are never incurred.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `costs` is not of shape `[n, n, 2]`.
+    pub fn build<W: WaveFunction>(
+        self,
+        algorithm: W,
+        costs: Array3<f64>,
+    ) -> WaveFunctionMinCost<W> {
+        assert!(
+            costs.shape()[0] == costs.shape()[1] && costs.shape()[2] == 2,
+            "Costs must be shape [n, n, 2]"
+        );
+        WaveFunctionMinCost {
+            algorithm,
+            costs,
+            max_sweeps: self.max_sweeps,
+        }
+    }
+}
+
+impl<W: WaveFunction> WaveFunction for WaveFunctionMinCost<W> {
+    fn info(&self) -> AlgorithmInfo {
+        let inner = self.algorithm.info();
+        AlgorithmInfo {
+            name: "min-cost",
+            supports_backtracking: inner.supports_backtracking,
+            deterministic: inner.deterministic,
+            complexity: format!(
+                "Collapses with {} ({}), then makes up to {} local search passes, each trying every tile of each cell and every pair of tiles of each pair of neighbouring cells",
+                inner.name, inner.complexity, self.max_sweeps
+            ),
+        }
+    }
+
+    fn boundary(&self) -> Boundary {
+        self.algorithm.boundary()
+    }
+
+    fn settings(&self) -> Vec<(&'static str, String)> {
+        let mut costs = FNV_OFFSET;
+        for cost in &self.costs {
+            fnv_write(&mut costs, &cost.to_le_bytes());
+        }
+        vec![
+            ("max_sweeps", self.max_sweeps.to_string()),
+            ("costs", format!("{costs:016x}")),
+        ]
+    }
+
+    fn collapse(&self, map: &Map, rules: &Rules, rng: &mut impl Rng) -> Result<Map> {
+        self.check_costs(rules);
+
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("collapse", algorithm = "min-cost").entered();
+
+        let mut result = self.algorithm.collapse(map, rules, rng)?;
+        self.improve(&mut result, map, rules, self.algorithm.boundary(), rng);
+        self.add_provenance(&mut result);
+        Ok(result)
+    }
+
+    fn collapse_with_options(
+        &self,
+        map: &Map,
+        rules: &Rules,
+        options: &CollapseOptions,
+        rng: &mut impl Rng,
+    ) -> Result<Map> {
+        self.check_costs(rules);
+
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("collapse", algorithm = "min-cost").entered();
+
+        let mut result = self
+            .algorithm
+            .collapse_with_options(map, rules, options, rng)?;
+        self.improve(&mut result, map, rules, options.boundary, rng);
+        self.add_provenance(&mut result);
+        Ok(result)
+    }
+
+    fn collapse_with_stats(
+        &self,
+        map: &Map,
+        rules: &Rules,
+        rng: &mut impl Rng,
+    ) -> Result<(Map, CollapseStats)> {
+        self.check_costs(rules);
+
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("collapse", algorithm = "min-cost").entered();
+
+        let start = Instant::now();
+        let (mut result, mut stats) = self.algorithm.collapse_with_stats(map, rules, rng)?;
+        self.improve(&mut result, map, rules, self.algorithm.boundary(), rng);
+        self.add_provenance(&mut result);
+        stats.elapsed = start.elapsed();
+        Ok((result, stats))
+    }
+}
+
+// Checks a cell's tile against every rule enforced during propagation: adjacency, run limits, buffer zones,
+// line uniqueness and quotas, and against the minimum body sizes enforced after collapsing
+struct Checker<'a> {
+    rules: &'a Rules,
+    neighbors: &'a Array2<Vec<Neighbour>>,
+    // Furthest distance any tile keeps other tiles away
+    max_buffer: usize,
+    // Cells the template leaves open, whose bodies must reach their minimum size
+    is_open: Array2<bool>,
+}
+
+impl<'a> Checker<'a> {
+    fn new(rules: &'a Rules, neighbors: &'a Array2<Vec<Neighbour>>, template: &Map) -> Self {
+        let max_buffer = (0..rules.len())
+            .filter_map(|tile| {
+                rules
+                    .buffer_zones(tile)
+                    .last()
+                    .map(|&(distance, _)| distance)
+            })
+            .max()
+            .unwrap_or(0);
+        let is_open = Array2::from_shape_fn(template.size(), |pos| {
+            matches!(template[pos], Cell::Wildcard | Cell::Hole)
+        });
+        Self {
+            rules,
+            neighbors,
+            max_buffer,
+            is_open,
+        }
+    }
+
+    fn fits(&self, map: &Map, pos: (usize, usize)) -> bool {
+        let Cell::Fixed(tile) = map[pos] else {
+            return true;
+        };
+        self.neighbors[pos]
+            .iter()
+            .all(|neighbor| match map[neighbor.pos] {
+                Cell::Fixed(other) => self.rules.is_allowed(tile, other, neighbor.dir),
+                _ => true,
+            })
+            && self.fits_runs(map, pos, tile)
+            && self.fits_buffers(map, pos, tile)
+            && self.fits_lines(map, pos, tile)
+    }
+
+    // Whether replacing one tile with another leaves the number of cells of every category with a quota as it was
+    fn keeps_quotas(&self, from: usize, to: usize) -> bool {
+        let categories = self.rules.categories();
+        categories[from] == categories[to]
+            || (self.rules.quota(categories[from]).is_none()
+                && self.rules.quota(categories[to]).is_none())
+    }
+
+    // Whether moving a cell from a tile leaves no body smaller than its category's minimum size, neither the one
+    // the cell now belongs to nor any of its neighbours' bodies it may have shrunk or split
+    fn keeps_bodies(&self, map: &Map, pos: (usize, usize), from: usize) -> bool {
+        if !self.rules.has_min_body_sizes() {
+            return true;
+        }
+        let categories = self.rules.categories();
+        let was_in_body = |other: (usize, usize)| match map[other] {
+            Cell::Fixed(tile) => categories[tile] == categories[from],
+            _ => false,
+        };
+        std::iter::once(pos)
+            .chain(
+                self.neighbors[pos]
+                    .iter()
+                    .map(|neighbor| neighbor.pos)
+                    .filter(|&other| was_in_body(other)),
+            )
+            .all(|start| self.is_large_body(map, start))
+    }
+
+    // Whether the body holding a cell reaches its category's minimum size, or is made only of cells fixed by
+    // the template, which are left as they are
+    fn is_large_body(&self, map: &Map, start: (usize, usize)) -> bool {
+        let Cell::Fixed(tile) = map[start] else {
+            return true;
+        };
+        let Some(min_size) = self.rules.min_body_size(tile) else {
+            return true;
+        };
+        let categories = self.rules.categories();
+        let in_category = |pos: (usize, usize)| matches!(map[pos], Cell::Fixed(other) if categories[other] == categories[tile]);
+
+        // Flood fill only until the body is known to be large enough
+        let mut body = vec![start];
+        let mut next = 0;
+        while next < body.len() && body.len() < min_size {
+            let cell = body[next];
+            next += 1;
+            for neighbor in &self.neighbors[cell] {
+                if in_category(neighbor.pos) && !body.contains(&neighbor.pos) {
+                    body.push(neighbor.pos);
+                }
+            }
+        }
+        body.len() >= min_size || !body.iter().any(|&pos| self.is_open[pos])
+    }
+
+    fn fits_runs(&self, map: &Map, pos: (usize, usize), tile: usize) -> bool {
+        let Some(max_run) = self.rules.max_run(tile) else {
+            return true;
+        };
+        [
+            (Direction::West, Direction::East),
+            (Direction::North, Direction::South),
+        ]
+        .into_iter()
+        .all(|(backward, forward)| {
+            1 + self.run(map, pos, backward, tile, max_run)
+                + self.run(map, pos, forward, tile, max_run)
+                <= max_run
+        })
+    }
+
+    // Number of cells fixed to a tile running in a direction from a cell, up to one more than the limit,
+    // as propagation counts them, so a run wrapping all the way around ends
+    fn run(
+        &self,
+        map: &Map,
+        pos: (usize, usize),
+        dir: Direction,
+        tile: usize,
+        max_run: usize,
+    ) -> usize {
+        let mut length = 0;
+        let mut current = pos;
+        while let Some(next) = self.neighbors[current].iter().find(|n| n.dir == dir) {
+            if length > max_run || map[next.pos] != Cell::Fixed(tile) {
+                break;
+            }
+            length += 1;
+            current = next.pos;
+        }
+        length
+    }
+
+    // Whether no other cell in the row or column holds the tile, if it is unique per line
+    fn fits_lines(&self, map: &Map, pos: (usize, usize), tile: usize) -> bool {
+        if !self.rules.is_line_unique(tile) {
+            return true;
+        }
+        let (height, width) = map.size();
+        let column = (0..height).map(|y| (y, pos.1));
+        let row = (0..width).map(|x| (pos.0, x));
+        column
+            .chain(row)
+            .all(|other| other == pos || map[other] != Cell::Fixed(tile))
+    }
+
+    // Whether no tile within a buffer zone of the cell is kept away from its tile, or keeps its tile away
+    fn fits_buffers(&self, map: &Map, pos: (usize, usize), tile: usize) -> bool {
+        if self.max_buffer == 0 {
+            return true;
+        }
+        let is_within = |zones: &[(usize, fixedbitset::FixedBitSet)], distance, other| {
+            zones
+                .iter()
+                .any(|(zone_distance, tiles)| distance <= *zone_distance && tiles.contains(other))
+        };
+
+        // Breadth-first, so each cell is reached at its distance in steps
+        let mut distances = HashMap::from([(pos, 0)]);
+        let mut frontier = VecDeque::from([pos]);
+        while let Some(current) = frontier.pop_front() {
+            let distance = distances[&current];
+            if distance == self.max_buffer {
+                continue;
+            }
+            for neighbor in &self.neighbors[current] {
+                if distances.contains_key(&neighbor.pos) {
+                    continue;
+                }
+                distances.insert(neighbor.pos, distance + 1);
+                frontier.push_back(neighbor.pos);
+                if let Cell::Fixed(other) = map[neighbor.pos]
+                    && (is_within(self.rules.buffer_zones(tile), distance + 1, other)
+                        || is_within(self.rules.buffer_zones(other), distance + 1, tile))
+                {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+}
@@ -1,8 +1,60 @@
+/// Emit a `tracing` event when the `tracing` feature is enabled, otherwise compile to nothing.
+macro_rules! trace_event {
+    ($level:ident, $($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        tracing::$level!($($arg)+);
+    };
+}
+
 mod backtracking;
+mod chunked;
+mod collapser;
 mod common;
+mod error;
 mod fast;
+mod graph;
+mod joint;
+mod local_reset;
+mod min_cost;
+mod nogoods;
+mod options;
+mod portfolio;
+mod precheck;
 mod progress;
+mod propagation;
+mod restarts;
+mod selection;
+mod stats;
+mod support;
+mod tuning;
+mod volume;
+mod wave;
+mod world;
 
-pub use backtracking::WaveFunctionBacktracking;
-pub use fast::WaveFunctionFast;
-pub use progress::WfcProgress;
+pub use backtracking::{WaveFunctionBacktracking, WaveFunctionBacktrackingBuilder};
+pub use chunked::{ChunkedGenerator, ChunkedGeneratorBuilder};
+pub use collapser::{Collapser, RowStream, WaveSnapshot};
+pub use common::Boundary;
+pub(crate) use common::calculate_neighbours;
+#[allow(deprecated)]
+pub use error::{Contradiction, WfcError};
+pub use fast::{WaveFunctionFast, WaveFunctionFastBuilder};
+pub use graph::{collapse_graph, collapse_graph_with_strategy};
+pub use joint::{MapLink, collapse_joint};
+pub use local_reset::{WaveFunctionLocalReset, WaveFunctionLocalResetBuilder};
+pub use min_cost::{WaveFunctionMinCost, WaveFunctionMinCostBuilder};
+pub use options::CollapseOptions;
+pub(crate) use options::hash_settings;
+pub use portfolio::{WaveFunctionPortfolio, WaveFunctionPortfolioBuilder};
+pub use precheck::{check_quotas, check_quotas_with_boundary, precheck, precheck_with_boundary};
+#[cfg(feature = "progress")]
+pub use progress::TerminalProgress;
+pub use progress::{LogProgress, NoProgress, ProgressHandle, ProgressSink, WfcProgress};
+pub use propagation::{DomainMap, PropagationReport, propagate, propagate_with_boundary};
+pub use restarts::{WaveFunctionWithRestarts, WaveFunctionWithRestartsBuilder};
+pub use selection::Heuristic;
+pub use stats::CollapseStats;
+pub use support::{Consistency, ReviseStrategy};
+pub use tuning::{WeightTuner, WeightTunerBuilder};
+pub use volume::{collapse_volume, collapse_volume_with_options};
+pub use world::{WorldGenerator, WorldGeneratorBuilder};
@@ -0,0 +1,174 @@
+use fixedbitset::FixedBitSet;
+use ndarray::Array2;
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::{common::Neighbour, wave::Wave};
+use crate::Rules;
+
+pub(crate) const MAX_NOGOOD_SIZE: usize = 16; // Most choices in a recorded combination known to fail
+const MAX_NOGOODS: usize = 100_000; // Most combinations recorded, to bound memory
+
+// A cell fixed to a tile
+pub(crate) type Assignment = ((usize, usize), usize);
+
+// Combinations of assignments learned to lead to a contradiction, so searches can skip choices which would
+// complete one rather than finding the dead end again
+pub(crate) struct Nogoods {
+    sets: Vec<Vec<Assignment>>,
+    // Indices of the sets containing each assignment
+    by_assignment: BTreeMap<Assignment, Vec<usize>>,
+    // Larger combinations are met again less often, so recording them costs more than it saves
+    max_size: usize,
+}
+
+impl Nogoods {
+    pub fn new(max_size: usize) -> Self {
+        Self {
+            sets: Vec::new(),
+            by_assignment: BTreeMap::new(),
+            max_size,
+        }
+    }
+
+    /// Record a combination of assignments which cannot all hold at once, unless it is too large or empty.
+    pub fn record(&mut self, assignments: impl IntoIterator<Item = Assignment>) {
+        let mut set: Vec<Assignment> = assignments.into_iter().take(self.max_size + 1).collect();
+        if set.is_empty() || set.len() > self.max_size || self.sets.len() >= MAX_NOGOODS {
+            return;
+        }
+        set.sort_unstable();
+
+        let index = self.sets.len();
+        for &assignment in &set {
+            self.by_assignment
+                .entry(assignment)
+                .or_default()
+                .push(index);
+        }
+        self.sets.push(set);
+    }
+
+    /// Whether fixing a cell to a tile would complete a recorded combination, given the cells already fixed.
+    pub fn forbids(&self, wave: &Wave, assignment: Assignment) -> bool {
+        self.forbidding(wave, assignment).next().is_some()
+    }
+
+    /// Options of a cell which would not complete a recorded combination.
+    pub fn allowed(&self, wave: &Wave, cell: (usize, usize)) -> FixedBitSet {
+        let mut allowed = wave.domain(cell).clone();
+        for tile in wave.domain(cell).ones() {
+            if self.forbids(wave, (cell, tile)) {
+                allowed.set(tile, false);
+            }
+        }
+        allowed
+    }
+
+    /// Cells fixed by the other assignments of the combinations which rule out a cell's options.
+    pub fn reasons(&self, wave: &Wave, cell: (usize, usize)) -> Vec<(usize, usize)> {
+        let mut cells = Vec::new();
+        for tile in wave.domain(cell).ones() {
+            if let Some(set) = self.forbidding(wave, (cell, tile)).next() {
+                cells.extend(set.iter().map(|&(pos, _)| pos).filter(|&pos| pos != cell));
+            }
+        }
+        cells
+    }
+
+    // Recorded combinations which fixing a cell to a tile would complete
+    fn forbidding<'a>(
+        &'a self,
+        wave: &'a Wave,
+        assignment: Assignment,
+    ) -> impl Iterator<Item = &'a Vec<Assignment>> + 'a {
+        self.by_assignment
+            .get(&assignment)
+            .into_iter()
+            .flatten()
+            .map(|&index| &self.sets[index])
+            .filter(move |set| {
+                set.iter().all(|&(pos, tile)| {
+                    (pos, tile) == assignment
+                        || (wave.domain_size(pos) == 1 && wave.domain(pos).contains(tile))
+                })
+            })
+    }
+}
+
+// Finds the decisions behind a contradiction, so the combinations learned from it are small enough to be met
+// again rather than holding every decision made
+pub(crate) struct Explainer {
+    // Domains before the first decision
+    initial: Array2<FixedBitSet>,
+    // Whether every removal follows from neighbouring domains, as it does without constraints spanning the map
+    is_local: bool,
+    // Most decisions in an explanation worth finding
+    max_size: usize,
+}
+
+impl Explainer {
+    pub fn new(wave: &Wave, rules: &Rules, max_size: usize) -> Self {
+        Self {
+            initial: wave.domains().clone(),
+            max_size,
+            is_local: !rules.has_quotas()
+                && !rules.has_run_limits()
+                && !rules.has_buffer_zones()
+                && !rules.has_line_uniqueness(),
+        }
+    }
+
+    /// Decisions which together explain the domains of some cells, searching out from them through the cells
+    /// whose domains have narrowed and stopping at cells fixed by decisions: every removal in the region
+    /// searched follows from those decisions and the unchanged cells around it.
+    /// Without cells to start from, or with constraints spanning the map, every decision is returned.
+    /// Returns `None` once the explanation holds more than the most decisions worth recording.
+    pub fn explain(
+        &self,
+        wave: &Wave,
+        neighbors: &Array2<Vec<Neighbour>>,
+        decisions: &BTreeMap<(usize, usize), usize>,
+        seeds: Option<Vec<(usize, usize)>>,
+    ) -> Option<BTreeSet<Assignment>> {
+        if self.max_size == 0 {
+            return None;
+        }
+        let Some(seeds) = seeds.filter(|_| self.is_local) else {
+            return (decisions.len() <= self.max_size)
+                .then(|| decisions.iter().map(|(&pos, &tile)| (pos, tile)).collect());
+        };
+
+        let mut culprits = BTreeSet::new();
+        let mut visited = BTreeSet::new();
+        let mut frontier = Vec::new();
+        for seed in seeds {
+            if visited.insert(seed) {
+                if let Some(&tile) = decisions.get(&seed) {
+                    culprits.insert((seed, tile));
+                }
+                // The cells which ran out of options are searched from even when fixed by a decision
+                frontier.push(seed);
+            }
+        }
+        while let Some(pos) = frontier.pop() {
+            if wave.domain(pos) == &self.initial[pos] {
+                continue;
+            }
+            for neighbor in &neighbors[pos] {
+                if !visited.insert(neighbor.pos) {
+                    continue;
+                }
+                match decisions.get(&neighbor.pos) {
+                    Some(&tile) => {
+                        culprits.insert((neighbor.pos, tile));
+                        if culprits.len() > self.max_size {
+                            return None;
+                        }
+                    }
+                    None => frontier.push(neighbor.pos),
+                }
+            }
+        }
+        (culprits.len() <= self.max_size).then_some(culprits)
+    }
+}
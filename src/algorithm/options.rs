@@ -0,0 +1,88 @@
+use std::sync::Arc;
+
+use super::{
+    common::Boundary,
+    progress::{ProgressSink, default_sink},
+    selection::Heuristic,
+};
+use crate::rules::{FNV_OFFSET, fnv_write};
+
+const MAX_ITERATIONS: usize = 1_000_000; // Max iterations for constraint propagation
+const MAX_BACKTRACKS: usize = 100; // Max number of backtracking attempts
+const BACKTRACK_DEPTH: usize = 50; // Max depth for backtracking stack
+
+/// Settings shared by the crate's solvers, which their builders start from and which can be passed to
+/// `WaveFunction::collapse_with_options` to override an algorithm's configuration for one collapse,
+/// such as raising the limits for a large map.
+/// Each solver uses the settings which apply to it and ignores the rest.
+#[derive(Clone)]
+pub struct CollapseOptions {
+    /// Maximum number of constraint revisions in a single propagation.
+    pub max_iterations: usize,
+    /// Maximum number of backtracks before giving up.
+    pub max_backtracks: usize,
+    /// Maximum number of decisions kept on the backtracking stack.
+    pub backtrack_depth: usize,
+    /// Order in which cells are chosen for collapse.
+    pub heuristic: Heuristic,
+    /// How cells on the edges of the map are constrained.
+    pub boundary: Boundary,
+    /// Where progress is reported while collapsing.
+    pub progress: Arc<dyn ProgressSink>,
+}
+
+impl Default for CollapseOptions {
+    fn default() -> Self {
+        Self {
+            max_iterations: MAX_ITERATIONS,
+            max_backtracks: MAX_BACKTRACKS,
+            backtrack_depth: BACKTRACK_DEPTH,
+            heuristic: Heuristic::default(),
+            boundary: Boundary::default(),
+            progress: default_sink(),
+        }
+    }
+}
+
+impl CollapseOptions {
+    /// Hash of the settings which affect what is generated, which leaves out the progress sink.
+    /// Settings particular to an algorithm, such as its consistency level, are added to it in the hash
+    /// recorded by `Provenance`.
+    /// Custom heuristics all hash the same, since their functions cannot be compared.
+    /// The hash is stable between runs and platforms.
+    #[must_use]
+    pub fn content_hash(&self) -> u64 {
+        let heuristic = match self.heuristic {
+            Heuristic::MinimumEntropy => 0,
+            Heuristic::Sweep(direction) => 1 + direction.index() as u64,
+            Heuristic::Custom(_) => 5,
+            Heuristic::ShannonEntropy => 6,
+        };
+        let boundary = match self.boundary {
+            Boundary::Open => 0,
+            Boundary::Wrap => 1,
+        };
+        let mut hash = FNV_OFFSET;
+        for value in [
+            self.max_iterations as u64,
+            self.max_backtracks as u64,
+            self.backtrack_depth as u64,
+            heuristic,
+            boundary,
+        ] {
+            fnv_write(&mut hash, &value.to_le_bytes());
+        }
+        hash
+    }
+}
+
+// Extend a content hash with the (name, value) settings of an algorithm
+pub(crate) fn hash_settings(mut hash: u64, settings: &[(&str, String)]) -> u64 {
+    for (name, value) in settings {
+        for bytes in [name.as_bytes(), value.as_bytes()] {
+            fnv_write(&mut hash, bytes);
+            fnv_write(&mut hash, &[0]);
+        }
+    }
+    hash
+}
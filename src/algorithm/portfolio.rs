@@ -0,0 +1,479 @@
+use anyhow::Result;
+use fixedbitset::FixedBitSet;
+use ndarray::Array2;
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64;
+use std::{
+    sync::{
+        RwLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::Instant,
+};
+
+use super::{
+    common::{Boundary, Neighbour, calculate_neighbours, choose_weighted_at},
+    error::WfcError,
+    nogoods::{Assignment, MAX_NOGOOD_SIZE, Nogoods},
+    options::CollapseOptions,
+    precheck::check_quotas_with_boundary,
+    selection::Heuristic,
+    stats::CollapseStats,
+    support::{Consistency, ReviseStrategy, Supports},
+    wave::Wave,
+};
+use crate::{AlgorithmInfo, Map, Provenance, Rules, WaveFunction};
+
+/// Searches for a collapse on several threads at once, each backtracking through the same search tree in a
+/// different order: cells are ordered with different tie-breaking, and tiles drawn with different generators.
+/// Whenever a search finds that a combination of its choices leads to a contradiction it shares it, and every
+/// search skips choices which would complete a shared combination, so the searches prune each other's trees.
+/// The first solution found is returned, which makes tilesets on which a single search gets stuck far more
+/// tractable on multicore machines.
+/// Each search backtracks chronologically without a depth limit, undoing its choices from a trail, until it finds
+/// a solution, another search finishes first, or it gives up after the backtrack limit. A search which runs out
+/// of choices fails with a contradiction and leaves the others to carry on.
+/// Which search finishes first depends on timing, so results are not reproducible from a seed.
+/// Progress is not reported, since the searches are at different points.
+pub struct WaveFunctionPortfolio {
+    options: CollapseOptions,
+    threads: usize,
+    max_nogood_size: usize,
+    revise_strategy: ReviseStrategy,
+    consistency: Consistency,
+}
+
+impl WaveFunctionPortfolio {
+    #[must_use]
+    pub fn builder() -> WaveFunctionPortfolioBuilder {
+        WaveFunctionPortfolioBuilder::default()
+    }
+}
+
+impl Default for WaveFunctionPortfolio {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+/// Builder for configuring a `WaveFunctionPortfolio`.
+pub struct WaveFunctionPortfolioBuilder {
+    options: CollapseOptions,
+    threads: usize,
+    max_nogood_size: usize,
+    revise_strategy: ReviseStrategy,
+    consistency: Consistency,
+}
+
+impl Default for WaveFunctionPortfolioBuilder {
+    fn default() -> Self {
+        Self {
+            options: CollapseOptions::default(),
+            threads: rayon::current_num_threads(),
+            max_nogood_size: MAX_NOGOOD_SIZE,
+            revise_strategy: ReviseStrategy::default(),
+            consistency: Consistency::default(),
+        }
+    }
+}
+
+impl WaveFunctionPortfolioBuilder {
+    /// Number of searches run in parallel, each on its own thread. Defaults to the number of threads rayon uses.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `threads` is zero.
+    #[must_use]
+    pub fn threads(mut self, threads: usize) -> Self {
+        assert!(threads > 0, "Number of threads must be greater than zero");
+        self.threads = threads;
+        self
+    }
+
+    /// Most choices in a combination known to fail for it to be shared with the other searches.
+    /// Larger combinations are met again less often, so sharing them costs more than it saves.
+    #[must_use]
+    pub fn max_nogood_size(mut self, max_nogood_size: usize) -> Self {
+        self.max_nogood_size = max_nogood_size;
+        self
+    }
+
+    /// Maximum number of constraint revisions in a single propagation.
+    #[must_use]
+    pub fn max_iterations(mut self, max_iterations: usize) -> Self {
+        self.options.max_iterations = max_iterations;
+        self
+    }
+
+    /// Maximum number of backtracks of each search before it gives up.
+    #[must_use]
+    pub fn max_backtrack_attempts(mut self, max_backtrack_attempts: usize) -> Self {
+        self.options.max_backtracks = max_backtrack_attempts;
+        self
+    }
+
+    /// Order in which cells are chosen for collapse.
+    #[must_use]
+    pub fn heuristic(mut self, heuristic: Heuristic) -> Self {
+        self.options.heuristic = heuristic;
+        self
+    }
+
+    /// How cells on the edges of the map are constrained, such as wrapping around for tileable maps.
+    #[must_use]
+    pub fn boundary(mut self, boundary: Boundary) -> Self {
+        self.options.boundary = boundary;
+        self
+    }
+
+    /// How each search checks a cell's options for support from its neighbours during propagation.
+    #[must_use]
+    pub fn revise_strategy(mut self, revise_strategy: ReviseStrategy) -> Self {
+        self.revise_strategy = revise_strategy;
+        self
+    }
+
+    /// How far each search propagates a decision before making the next.
+    #[must_use]
+    pub fn consistency(mut self, consistency: Consistency) -> Self {
+        self.consistency = consistency;
+        self
+    }
+
+    /// Replace every setting shared with the other solvers at once.
+    #[must_use]
+    pub fn options(mut self, options: CollapseOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    #[must_use]
+    pub fn build(self) -> WaveFunctionPortfolio {
+        WaveFunctionPortfolio {
+            options: self.options,
+            threads: self.threads,
+            max_nogood_size: self.max_nogood_size,
+            revise_strategy: self.revise_strategy,
+            consistency: self.consistency,
+        }
+    }
+}
+
+// State shared between the searches
+struct Shared {
+    nogoods: RwLock<Nogoods>,
+    // Set once a search has found a solution, so the others stop
+    done: AtomicBool,
+    max_nogood_size: usize,
+}
+
+impl Shared {
+    // Share the combination of a search's earlier choices and a choice made under them which fails
+    fn record(&self, decisions: &[Decision], assignment: Assignment) {
+        // Checked before taking the lock, since most combinations found deep in a search are too large
+        if decisions.len() >= self.max_nogood_size {
+            return;
+        }
+        let set = decisions
+            .iter()
+            .map(|decision| (decision.cell, decision.tile))
+            .chain(std::iter::once(assignment));
+        self.nogoods
+            .write()
+            .expect("Nogood store poisoned")
+            .record(set);
+    }
+
+    // Whether fixing a cell to a tile would complete a shared combination, given the cells already fixed
+    fn forbids(&self, wave: &Wave, assignment: Assignment) -> bool {
+        self.nogoods
+            .read()
+            .expect("Nogood store poisoned")
+            .forbids(wave, assignment)
+    }
+}
+
+// A choice of tile for a cell, with the tiles tried there so far and where the wave's trail stood before it
+struct Decision {
+    cell: (usize, usize),
+    tile: usize,
+    tried: FixedBitSet,
+    trail_mark: usize,
+}
+
+impl WaveFunctionPortfolio {
+    // Run the searches, returning the first solution found with the statistics of every search combined
+    fn run(
+        &self,
+        map: &Map,
+        rules: &Rules,
+        options: &CollapseOptions,
+        rng: &mut impl Rng,
+    ) -> Result<(Map, CollapseStats)> {
+        let start = Instant::now();
+        check_quotas_with_boundary(map, rules, options.boundary)?;
+
+        #[cfg(feature = "tracing")]
+        let _span =
+            tracing::info_span!("collapse", algorithm = "portfolio", threads = self.threads)
+                .entered();
+
+        let shared = Shared {
+            nogoods: RwLock::new(Nogoods::new(self.max_nogood_size)),
+            done: AtomicBool::new(false),
+            max_nogood_size: self.max_nogood_size,
+        };
+        let seeds: Vec<u64> = (0..self.threads).map(|_| rng.random()).collect();
+        let outcomes: Vec<(Result<Option<Map>>, CollapseStats)> = thread::scope(|scope| {
+            let workers: Vec<_> = seeds
+                .iter()
+                .map(|&seed| {
+                    let shared = &shared;
+                    scope.spawn(move || {
+                        let mut stats = CollapseStats::default();
+                        let outcome = self.search(map, rules, options, seed, shared, &mut stats);
+                        (outcome, stats)
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .map(|worker| worker.join().expect("Portfolio search panicked"))
+                .collect()
+        });
+
+        let mut stats = CollapseStats::default();
+        let mut solution = None;
+        let mut error = None;
+        for (outcome, search_stats) in outcomes {
+            stats.revisions += search_stats.revisions;
+            stats.backtracks += search_stats.backtracks;
+            if let Some(depth) = search_stats.deepest_contradiction {
+                stats.record_contradiction(depth);
+            }
+            match outcome {
+                Ok(Some(result)) => {
+                    solution.get_or_insert(result);
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    error.get_or_insert(e);
+                }
+            }
+        }
+        let Some(mut result) = solution else {
+            return Err(error.expect("Every search stops with a solution or an error"));
+        };
+
+        trace_event!(info, backtracks = stats.backtracks, "collapse complete");
+
+        // Undersized bodies are only visible once every cell is fixed
+        if rules.has_min_body_sizes() {
+            result = result.enforce_min_bodies(map, rules, self, options, rng)?;
+        }
+        stats.elapsed = start.elapsed();
+        result.set_provenance(Some(Provenance::new(
+            self.info().name,
+            &self.settings(),
+            options,
+            rules,
+            stats.elapsed,
+            stats.backtracks,
+        )));
+        Ok((result, stats))
+    }
+
+    // One search, ordering its choices by a generator of its own
+    fn search(
+        &self,
+        map: &Map,
+        rules: &Rules,
+        options: &CollapseOptions,
+        seed: u64,
+        shared: &Shared,
+        stats: &mut CollapseStats,
+    ) -> Result<Option<Map>> {
+        let (height, width) = map.size();
+        let num_tiles = rules.len();
+        let mut rng = Pcg64::seed_from_u64(seed);
+
+        let is_ignore = map.mask();
+        let neighbors = calculate_neighbours(height, width, &is_ignore, options.boundary);
+        let mut supports = Supports::new(self.revise_strategy, (height, width), num_tiles);
+        let mut wave = Wave::from_template(
+            map,
+            rules,
+            options.heuristic,
+            self.consistency,
+            rng.random(),
+        );
+        let outcome = wave
+            .propagate_all(rules, &neighbors, &mut supports, options.max_iterations)
+            .and_then(|()| {
+                Self::explore(
+                    &mut wave,
+                    map,
+                    rules,
+                    &neighbors,
+                    options,
+                    &mut rng,
+                    &mut supports,
+                    shared,
+                    stats,
+                )
+            });
+        stats.revisions = wave.revisions();
+        if matches!(outcome, Ok(false)) {
+            return Ok(None);
+        }
+        if outcome.is_err() || shared.done.swap(true, Ordering::Relaxed) {
+            return outcome.map(|_| None);
+        }
+
+        Ok(Some(wave.to_map(map)?))
+    }
+
+    // Depth-first search over the choices of tile for each cell, until every cell is fixed, returning true,
+    // or another search is done, returning false
+    #[allow(clippy::too_many_arguments)]
+    fn explore(
+        wave: &mut Wave,
+        map: &Map,
+        rules: &Rules,
+        neighbors: &Array2<Vec<Neighbour>>,
+        options: &CollapseOptions,
+        rng: &mut impl Rng,
+        supports: &mut Supports,
+        shared: &Shared,
+        stats: &mut CollapseStats,
+    ) -> Result<bool> {
+        let num_tiles = rules.len();
+        let mut decisions: Vec<Decision> = Vec::new();
+
+        'search: while let Some(cell) = wave.next_cell() {
+            let mut decision = Decision {
+                cell,
+                tile: 0,
+                tried: FixedBitSet::with_capacity(num_tiles),
+                trail_mark: wave.mark(),
+            };
+            loop {
+                if shared.done.load(Ordering::Relaxed) {
+                    return Ok(false);
+                }
+
+                let mut remaining = wave.domain(decision.cell).clone();
+                remaining.difference_with(&decision.tried);
+                if remaining.is_clear() {
+                    // Every tile of the cell fails under the choices above it, so the last of those is wrong
+                    let Some(parent) = decisions.pop() else {
+                        return Err(WfcError::Contradiction {
+                            cell: decision.cell,
+                            direction: None,
+                        }
+                        .into());
+                    };
+                    wave.undo_to(parent.trail_mark);
+                    shared.record(&decisions, (parent.cell, parent.tile));
+                    decision = parent;
+                    continue;
+                }
+
+                let tile = choose_weighted_at(
+                    &remaining,
+                    rules,
+                    map,
+                    wave,
+                    &neighbors[decision.cell],
+                    decision.cell,
+                    rng,
+                );
+                decision.tried.insert(tile);
+                decision.tile = tile;
+                if shared.forbids(wave, (decision.cell, tile)) {
+                    continue;
+                }
+
+                wave.collapse(decision.cell, tile, neighbors)?;
+                match wave.propagate(rules, neighbors, supports, options.max_iterations) {
+                    Ok(()) => {
+                        decisions.push(decision);
+                        continue 'search;
+                    }
+                    Err(e)
+                        if e.downcast_ref::<WfcError>()
+                            .is_some_and(WfcError::is_contradiction) =>
+                    {
+                        stats.record_contradiction(decisions.len());
+                        stats.backtracks += 1;
+                        trace_event!(
+                            debug,
+                            cell = ?decision.cell,
+                            backtracks = stats.backtracks,
+                            depth = decisions.len(),
+                            "backtrack"
+                        );
+                        if stats.backtracks > options.max_backtracks {
+                            return Err(WfcError::MaxBacktracks(options.max_backtracks).into());
+                        }
+                        shared.record(&decisions, (decision.cell, tile));
+                        wave.undo_to(decision.trail_mark);
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        Ok(true)
+    }
+}
+
+impl WaveFunction for WaveFunctionPortfolio {
+    fn info(&self) -> AlgorithmInfo {
+        AlgorithmInfo {
+            name: "portfolio",
+            supports_backtracking: true,
+            deterministic: false,
+            complexity: format!(
+                "Runs {} backtracking searches in parallel, each ordering its choices differently and skipping combinations of up to {} choices another has found to fail; returns the first solution found, with each search giving up after {} backtracks",
+                self.threads, self.max_nogood_size, self.options.max_backtracks
+            ),
+        }
+    }
+
+    fn boundary(&self) -> Boundary {
+        self.options.boundary
+    }
+
+    fn settings(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("threads", self.threads.to_string()),
+            ("max_nogood_size", self.max_nogood_size.to_string()),
+            ("revise_strategy", format!("{:?}", self.revise_strategy)),
+            ("consistency", format!("{:?}", self.consistency)),
+        ]
+    }
+
+    fn collapse(&self, map: &Map, rules: &Rules, rng: &mut impl Rng) -> Result<Map> {
+        self.collapse_with_options(map, rules, &self.options, rng)
+    }
+
+    fn collapse_with_options(
+        &self,
+        map: &Map,
+        rules: &Rules,
+        options: &CollapseOptions,
+        rng: &mut impl Rng,
+    ) -> Result<Map> {
+        Ok(self.run(map, rules, options, rng)?.0)
+    }
+
+    fn collapse_with_stats(
+        &self,
+        map: &Map,
+        rules: &Rules,
+        rng: &mut impl Rng,
+    ) -> Result<(Map, CollapseStats)> {
+        self.run(map, rules, &self.options, rng)
+    }
+}
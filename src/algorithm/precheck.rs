@@ -0,0 +1,230 @@
+use anyhow::Result;
+use fixedbitset::FixedBitSet;
+use ndarray::Array2;
+
+use super::{
+    common::{Boundary, calculate_neighbours},
+    error::WfcError,
+    selection::Heuristic,
+    support::{ReviseStrategy, Supports},
+    wave::Wave,
+};
+use crate::{Map, Rules};
+
+const MAX_ITERATIONS: usize = 1_000_000; // Max iterations for constraint propagation
+
+/// Check a template for conflicts before collapsing it, which is far cheaper than running into them
+/// part-way through a long collapse.
+/// Arc consistency is established over the whole map, then singleton arc consistency over the boundary
+/// cells, those still undecided next to a fixed cell: each of their remaining tiles is tried in turn and
+/// removed if it leads to a contradiction, until nothing more can be removed.
+/// Passing is not a guarantee that the template can be collapsed, since conflicts further from the
+/// fixed cells are not searched for.
+/// The quotas of the rules are checked first, with `check_quotas`.
+/// The edges of the map are open; see `precheck_with_boundary` for wrapped maps.
+///
+/// # Errors
+///
+/// Fails with the first conflict proven, as a `WfcError::Unsatisfiable` naming the cell which was left without
+/// options, or as `check_quotas` does.
+pub fn precheck(template: &Map, rules: &Rules) -> Result<()> {
+    precheck_with_boundary(template, rules, Boundary::Open)
+}
+
+/// Check a template for conflicts as `precheck` does, with cells on the edges constrained by the boundary.
+///
+/// # Errors
+///
+/// Fails as `precheck` does.
+pub fn precheck_with_boundary(template: &Map, rules: &Rules, boundary: Boundary) -> Result<()> {
+    check_quotas_with_boundary(template, rules, boundary)?;
+
+    let (height, width) = template.size();
+    let num_tiles = rules.len();
+
+    let is_ignore = template.mask();
+    let neighbors = calculate_neighbours(height, width, &is_ignore, boundary);
+    let mut supports = Supports::new(ReviseStrategy::default(), (height, width), num_tiles);
+    let mut wave = Wave::new(
+        template.domains(num_tiles),
+        is_ignore.clone(),
+        Heuristic::default(),
+        0,
+        rules.frequencies(),
+    );
+    wave.propagate_all(rules, &neighbors, &mut supports, MAX_ITERATIONS)?;
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for y in 0..height {
+            for x in 0..width {
+                let pos = (y, x);
+                let is_boundary = !is_ignore[pos]
+                    && wave.domain_size(pos) > 1
+                    && neighbors[pos]
+                        .iter()
+                        .any(|neighbor| wave.domain_size(neighbor.pos) == 1);
+                if !is_boundary {
+                    continue;
+                }
+
+                // Tiles which cannot be placed here without emptying some other cell
+                let mut unplaceable = FixedBitSet::with_capacity(num_tiles);
+                for tile in wave.domain(pos).ones() {
+                    let mut trial = wave.clone();
+                    trial.collapse(pos, tile, &neighbors)?;
+                    if let Err(e) =
+                        trial.propagate(rules, &neighbors, &mut supports, MAX_ITERATIONS)
+                    {
+                        if !e
+                            .downcast_ref::<WfcError>()
+                            .is_some_and(WfcError::is_contradiction)
+                        {
+                            return Err(e);
+                        }
+                        unplaceable.insert(tile);
+                    }
+                }
+                if unplaceable.is_clear() {
+                    continue;
+                }
+
+                trace_event!(
+                    debug,
+                    cell = ?pos,
+                    removed = unplaceable.count_ones(..),
+                    "singleton consistency removal"
+                );
+                if unplaceable.count_ones(..) == wave.domain_size(pos) {
+                    return Err(WfcError::Unsatisfiable { cell: pos }.into());
+                }
+                wave.remove_many(pos, &unplaceable, &neighbors)?;
+                wave.propagate(rules, &neighbors, &mut supports, MAX_ITERATIONS)?;
+                changed = true;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Check the quotas of the rules can be met on a template, as the solvers do before collapsing.
+/// Counting bounds are checked first: each quota must allow some whole number of cells, the template must not
+/// already fix more cells of a category than its quota allows, the minimums must fit in the map together, and
+/// the maximums together must cover the cells which can only take tiles with quotas.
+/// Then each category must be reachable: once arc consistency is established, ignoring the quotas, enough
+/// cells must still be able to take its tiles to meet its minimum.
+/// Passing is not a guarantee that the quotas can be met, since they are not checked against each other's
+/// placement, or against the other constraints of the rules.
+/// The edges of the map are open; see `check_quotas_with_boundary` for wrapped maps.
+///
+/// # Errors
+///
+/// Fails with a `WfcError::UnsatisfiableQuotas` explaining the first problem found.
+pub fn check_quotas(template: &Map, rules: &Rules) -> Result<()> {
+    check_quotas_with_boundary(template, rules, Boundary::Open)
+}
+
+/// Check the quotas of the rules can be met as `check_quotas` does, with cells on the edges constrained by the
+/// boundary when finding which cells can reach each category.
+///
+/// # Errors
+///
+/// Fails as `check_quotas` does.
+pub fn check_quotas_with_boundary(template: &Map, rules: &Rules, boundary: Boundary) -> Result<()> {
+    if !rules.has_quotas() {
+        return Ok(());
+    }
+    let (height, width) = template.size();
+    let num_tiles = rules.len();
+    let is_ignore = template.mask();
+    let num_cells = is_ignore.iter().filter(|&&is_ignore| !is_ignore).count();
+    let domains = template.domains(num_tiles);
+    let unsatisfiable = |reason: String| Err(WfcError::UnsatisfiableQuotas(reason).into());
+
+    // Open cells whose options meet a set of tiles, and those fixed to one of them
+    let count = |domains: &Array2<FixedBitSet>, tiles: &FixedBitSet| {
+        let mut possible = 0;
+        let mut fixed = 0;
+        for (domain, _) in domains
+            .iter()
+            .zip(&is_ignore)
+            .filter(|&(_, &is_ignore)| !is_ignore)
+        {
+            if !domain.is_disjoint(tiles) {
+                possible += 1;
+                fixed += usize::from(domain.count_ones(..) == 1);
+            }
+        }
+        (possible, fixed)
+    };
+
+    let quotas: Vec<_> = rules
+        .quotas()
+        .map(|(category, quota)| {
+            let (min, max) = quota.bounds(num_cells);
+            (category, rules.category_tiles(category), min, max)
+        })
+        .collect();
+    let mut total_min = 0;
+    let mut total_max = 0_usize;
+    let mut limited = FixedBitSet::with_capacity(num_tiles);
+    for (category, tiles, min, max) in &quotas {
+        if min > max {
+            return unsatisfiable(format!(
+                "category {category} needs at least {min} cells but allows at most {max}"
+            ));
+        }
+        let (_, fixed) = count(&domains, tiles);
+        if fixed > *max {
+            return unsatisfiable(format!(
+                "the template fixes {fixed} cells of category {category}, more than its maximum of {max}"
+            ));
+        }
+        total_min += min;
+        total_max = total_max.saturating_add(*max);
+        limited.union_with(tiles);
+    }
+    if total_min > num_cells {
+        return unsatisfiable(format!(
+            "the categories need at least {total_min} cells together, but the map has {num_cells} open cells"
+        ));
+    }
+    let num_confined = domains
+        .iter()
+        .zip(&is_ignore)
+        .filter(|&(domain, &is_ignore)| !is_ignore && domain.is_subset(&limited))
+        .count();
+    if num_confined > total_max {
+        return unsatisfiable(format!(
+            "{num_confined} cells can only take tiles with quotas, but the quotas allow at most {total_max} cells together"
+        ));
+    }
+
+    // Reachability, from the options left once the adjacencies and the template's cells have been propagated
+    let mut unlimited = rules.clone();
+    for (category, _, _, _) in &quotas {
+        unlimited.set_quota(*category, None);
+    }
+    let neighbors = calculate_neighbours(height, width, &is_ignore, boundary);
+    let mut supports = Supports::new(ReviseStrategy::default(), (height, width), num_tiles);
+    let mut wave = Wave::new(
+        domains,
+        is_ignore.clone(),
+        Heuristic::default(),
+        0,
+        rules.frequencies(),
+    );
+    wave.propagate_all(&unlimited, &neighbors, &mut supports, MAX_ITERATIONS)?;
+    for (category, tiles, min, _) in &quotas {
+        let (possible, _) = count(wave.domains(), tiles);
+        if possible < *min {
+            return unsatisfiable(format!(
+                "only {possible} cells can take tiles of category {category}, which needs at least {min}"
+            ));
+        }
+    }
+
+    Ok(())
+}
@@ -1,37 +1,272 @@
-use indicatif::{ProgressBar, ProgressStyle};
+#[cfg(feature = "progress")]
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use ndarray::Array2;
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
 
-/// Progress bar tracking for WFC algorithms
+use crate::AlgorithmInfo;
+
+#[cfg(feature = "progress")]
+const BACKTRACKING_TEMPLATE: &str = "{spinner:.green} [{elapsed_precise}] {bar:40.cyan/blue} {pos}/{len} cells (Backtracked: {msg})";
+#[cfg(feature = "progress")]
+const RESET_TEMPLATE: &str = "{bar:40.cyan/blue} {pos}/{len} cells (Resets: {msg})";
+#[cfg(feature = "progress")]
+const STANDARD_TEMPLATE: &str = "{bar:40.cyan/blue} {pos}/{len} cells";
+const LOG_INTERVAL: usize = 10; // Percentage of the cells between lines of a `LogProgress`
+
+/// Receiver of progress reports from a collapse, so applications can show progress in their own way,
+/// such as a terminal bar, a log, or a widget in a GUI.
+/// Algorithms are given a sink by their builders, or with the `CollapseOptions` of each collapse; the default
+/// is `TerminalProgress` with the `progress` feature enabled, and `NoProgress` otherwise.
+/// A sink may be shared between threads collapsing in parallel: each collapse gets its own handle from `start`,
+/// which identifies it in the reports that follow.
+pub trait ProgressSink: Send + Sync {
+    /// A collapse with the given algorithm has started, with `total` cells left to collapse.
+    fn start(&self, info: &AlgorithmInfo, total: usize) -> ProgressHandle;
+
+    /// `collapsed` of the cells have now been collapsed.
+    fn update(&self, handle: ProgressHandle, collapsed: usize);
+
+    /// The algorithm has recovered from `count` contradictions so far, by backtracking or resetting cells.
+    fn recoveries(&self, handle: ProgressHandle, count: usize) {
+        let _ = (handle, count);
+    }
+
+    /// A note for the user, such as a summary of the work done.
+    fn message(&self, handle: ProgressHandle, message: &str) {
+        let _ = (handle, message);
+    }
+
+    /// The collapse has ended, successfully or not.
+    fn finish(&self, handle: ProgressHandle);
+}
+
+/// Identifies one collapse reporting to a `ProgressSink`, as returned by `ProgressSink::start`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ProgressHandle(pub usize);
+
+/// Progress sink which ignores every report.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoProgress;
+
+impl ProgressSink for NoProgress {
+    fn start(&self, _info: &AlgorithmInfo, _total: usize) -> ProgressHandle {
+        ProgressHandle(0)
+    }
+
+    fn update(&self, _handle: ProgressHandle, _collapsed: usize) {}
+
+    fn finish(&self, _handle: ProgressHandle) {}
+}
+
+/// Progress sink drawing a terminal progress bar with indicatif for each collapse in progress.
+#[cfg(feature = "progress")]
+#[derive(Default)]
+pub struct TerminalProgress {
+    bars: MultiProgress,
+    // Bars of the collapses in progress, by handle
+    progress_bars: Mutex<HashMap<ProgressHandle, ProgressBar>>,
+    next_handle: AtomicUsize,
+}
+
+#[cfg(feature = "progress")]
+impl TerminalProgress {
+    fn with_bar(&self, handle: ProgressHandle, f: impl FnOnce(&ProgressBar)) {
+        if let Some(progress_bar) = self.progress_bars.lock().unwrap().get(&handle) {
+            f(progress_bar);
+        }
+    }
+}
+
+#[cfg(feature = "progress")]
+impl ProgressSink for TerminalProgress {
+    fn start(&self, info: &AlgorithmInfo, total: usize) -> ProgressHandle {
+        let template = match info.name {
+            "backtracking" => BACKTRACKING_TEMPLATE,
+            "local-reset" => RESET_TEMPLATE,
+            _ => STANDARD_TEMPLATE,
+        };
+        let progress_bar = self.bars.add(ProgressBar::new(total as u64));
+        progress_bar.set_style(
+            ProgressStyle::with_template(template)
+                .unwrap()
+                .progress_chars("##-"),
+        );
+        progress_bar.set_message("0");
+        let handle = ProgressHandle(self.next_handle.fetch_add(1, Ordering::Relaxed));
+        self.progress_bars
+            .lock()
+            .unwrap()
+            .insert(handle, progress_bar);
+        handle
+    }
+
+    fn update(&self, handle: ProgressHandle, collapsed: usize) {
+        self.with_bar(handle, |progress_bar| {
+            progress_bar.set_position(collapsed as u64);
+        });
+    }
+
+    fn recoveries(&self, handle: ProgressHandle, count: usize) {
+        self.with_bar(handle, |progress_bar| {
+            progress_bar.set_message(count.to_string());
+        });
+    }
+
+    // Messages go above the bars, on standard error with them, so standard output is left to the application
+    fn message(&self, handle: ProgressHandle, message: &str) {
+        match self.progress_bars.lock().unwrap().get(&handle) {
+            Some(progress_bar) if !progress_bar.is_hidden() => progress_bar.println(message),
+            _ => eprintln!("{message}"),
+        }
+    }
+
+    fn finish(&self, handle: ProgressHandle) {
+        if let Some(progress_bar) = self.progress_bars.lock().unwrap().remove(&handle) {
+            progress_bar.finish_and_clear();
+            self.bars.remove(&progress_bar);
+        }
+    }
+}
+
+/// Progress sink writing a line to standard error as each tenth of the cells of a collapse is collapsed, and
+/// for every message, for servers and other places without an interactive terminal.
+/// Lines of parallel collapses are told apart by their handles.
+#[derive(Default)]
+pub struct LogProgress {
+    // Algorithm name, number of cells to collapse, and percentage reached by the last line written, of the
+    // collapses in progress by handle
+    collapses: Mutex<HashMap<ProgressHandle, (&'static str, usize, usize)>>,
+    next_handle: AtomicUsize,
+}
+
+impl ProgressSink for LogProgress {
+    fn start(&self, info: &AlgorithmInfo, total: usize) -> ProgressHandle {
+        let handle = ProgressHandle(self.next_handle.fetch_add(1, Ordering::Relaxed));
+        self.collapses
+            .lock()
+            .unwrap()
+            .insert(handle, (info.name, total, 0));
+        eprintln!("{} #{}: collapsing {total} cells", info.name, handle.0);
+        handle
+    }
+
+    fn update(&self, handle: ProgressHandle, collapsed: usize) {
+        let mut in_progress = self.collapses.lock().unwrap();
+        let Some((name, total, logged)) = in_progress.get_mut(&handle) else {
+            return;
+        };
+        let percent = (100 * collapsed).checked_div(*total).unwrap_or(100);
+        let step = percent / LOG_INTERVAL * LOG_INTERVAL;
+        if step > *logged {
+            *logged = step;
+            eprintln!("{name} #{}: {step}% ({collapsed}/{total} cells)", handle.0);
+        }
+    }
+
+    fn message(&self, _handle: ProgressHandle, message: &str) {
+        eprintln!("{message}");
+    }
+
+    fn finish(&self, handle: ProgressHandle) {
+        self.collapses.lock().unwrap().remove(&handle);
+    }
+}
+
+// Sink used by algorithms whose builder was not given one
+pub(crate) fn default_sink() -> Arc<dyn ProgressSink> {
+    #[cfg(feature = "progress")]
+    {
+        Arc::new(TerminalProgress::default())
+    }
+    #[cfg(not(feature = "progress"))]
+    {
+        Arc::new(NoProgress)
+    }
+}
+
+// Count of collapsed cells during one collapse, reported to a sink
+pub(crate) struct CellProgress<'a> {
+    sink: &'a dyn ProgressSink,
+    handle: ProgressHandle,
+    position: Cell<usize>,
+}
+
+impl<'a> CellProgress<'a> {
+    // Start reporting a collapse of a number of cells
+    pub(crate) fn new(sink: &'a dyn ProgressSink, info: &AlgorithmInfo, len: usize) -> Self {
+        let handle = sink.start(info, len);
+        Self {
+            sink,
+            handle,
+            position: Cell::new(0),
+        }
+    }
+
+    pub(crate) fn inc(&self) {
+        self.position.set(self.position.get() + 1);
+        self.sink.update(self.handle, self.position.get());
+    }
+
+    pub(crate) fn set_position(&self, position: usize) {
+        self.position.set(position);
+        self.sink.update(self.handle, position);
+    }
+
+    pub(crate) fn set_count(&self, count: usize) {
+        self.sink.recoveries(self.handle, count);
+    }
+
+    pub(crate) fn println(&self, message: &str) {
+        self.sink.message(self.handle, message);
+    }
+
+    pub(crate) fn finish(&self) {
+        self.sink.finish(self.handle);
+    }
+}
+
+/// Progress tracking for WFC algorithms, reporting to a `ProgressSink`.
 pub struct WfcProgress {
-    progress_bar: ProgressBar,
+    sink: Arc<dyn ProgressSink>,
+    handle: ProgressHandle,
+    collapsed: AtomicUsize,
     backtrack_count: usize,
 }
 
 impl WfcProgress {
-    /// Creates a new progress tracker for standard WFC
+    /// Creates a new progress tracker for standard WFC, reporting to the default sink
     pub fn new(cells_to_collapse: usize, with_backtracking: bool) -> Self {
-        let pb = ProgressBar::new(cells_to_collapse as u64);
-
-        // Use different style based on algorithm type
-        if with_backtracking {
-            pb.set_style(
-                ProgressStyle::with_template(
-                    "{spinner:.green} [{elapsed_precise}] {bar:40.cyan/blue} {pos}/{len} cells (Backtracked: {msg})"
-                )
-                .unwrap()
-                .progress_chars("##-"),
-            );
-            pb.set_message("0");
-        } else {
-            pb.set_style(
-                ProgressStyle::with_template("{bar:40.cyan/blue} {pos}/{len} cells")
-                    .unwrap()
-                    .progress_chars("##-"),
-            );
-        }
+        Self::with_sink(default_sink(), cells_to_collapse, with_backtracking)
+    }
 
+    /// Creates a new progress tracker reporting to the given sink
+    pub fn with_sink(
+        sink: Arc<dyn ProgressSink>,
+        cells_to_collapse: usize,
+        with_backtracking: bool,
+    ) -> Self {
+        let info = AlgorithmInfo {
+            name: if with_backtracking {
+                "backtracking"
+            } else {
+                "fast"
+            },
+            supports_backtracking: with_backtracking,
+            deterministic: true,
+            complexity: String::new(),
+        };
+        let handle = sink.start(&info, cells_to_collapse);
         Self {
-            progress_bar: pb,
+            sink,
+            handle,
+            collapsed: AtomicUsize::new(0),
             backtrack_count: 0,
         }
     }
@@ -57,14 +292,14 @@ impl WfcProgress {
 
     /// Increment progress
     pub fn increment(&self) {
-        self.progress_bar.inc(1);
+        let collapsed = self.collapsed.fetch_add(1, Ordering::Relaxed) + 1;
+        self.sink.update(self.handle, collapsed);
     }
 
     /// Record a backtrack event
     pub fn record_backtrack(&mut self) {
         self.backtrack_count += 1;
-        self.progress_bar
-            .set_message(self.backtrack_count.to_string());
+        self.sink.recoveries(self.handle, self.backtrack_count);
     }
 
     /// Get current backtrack count
@@ -72,19 +307,22 @@ impl WfcProgress {
         self.backtrack_count
     }
 
-    /// Print a message through the progress bar
+    /// Print a message through the progress sink
     pub fn println(&self, message: String) {
-        self.progress_bar.println(message);
+        self.sink.message(self.handle, &message);
     }
 
-    /// Finish and clear progress display
+    /// Finish the progress display
     pub fn finish(self) {
-        self.progress_bar.finish_and_clear();
+        self.sink.finish(self.handle);
 
         if self.backtrack_count > 0 {
-            println!(
-                "Completed with {} backtracking attempts",
-                self.backtrack_count
+            self.sink.message(
+                self.handle,
+                &format!(
+                    "Completed with {} backtracking attempts",
+                    self.backtrack_count
+                ),
             );
         }
     }
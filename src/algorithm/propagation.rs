@@ -0,0 +1,97 @@
+use anyhow::Result;
+use fixedbitset::FixedBitSet;
+use ndarray::Array2;
+
+use super::{
+    common::{Boundary, calculate_neighbours},
+    selection::Heuristic,
+    support::{ReviseStrategy, Supports},
+    wave::Wave,
+};
+use crate::Rules;
+
+/// Set of tiles each cell may still take, indexed by (y, x), as given by `Map::domains`.
+pub type DomainMap = Array2<FixedBitSet>;
+
+/// What a call to `propagate` removed.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PropagationReport {
+    /// Cells whose domains were narrowed, in row-major order.
+    pub changed_cells: Vec<(usize, usize)>,
+    /// Total number of options removed across all cells.
+    pub num_removed: usize,
+    /// Number of changed cells left with a single option.
+    pub num_fixed: usize,
+}
+
+/// Remove every option which the rules leave without support from a neighbour, using the same propagation
+/// core as the crate's solvers, so custom solvers can be built on top of it.
+/// With `from`, only the consequences of that cell having been narrowed are propagated, which is all a
+/// solver needs after collapsing or restricting a single cell of an already consistent wave. Without it,
+/// every cell is revised against its neighbours, as before the first collapse.
+/// Run limits and buffer zones in the rules are enforced around cells which become fixed.
+/// Cells with empty domains are treated as outside the map, like ignored cells.
+/// Returns what was removed.
+/// The edges of the map are open; see `propagate_with_boundary` for wrapped maps.
+///
+/// # Errors
+///
+/// Fails with a `WfcError::Contradiction` naming a cell left without options, in which case the domains are
+/// restored to how they were before the call.
+pub fn propagate(
+    wave: &mut DomainMap,
+    rules: &Rules,
+    from: Option<(usize, usize)>,
+) -> Result<PropagationReport> {
+    propagate_with_boundary(wave, rules, from, Boundary::Open)
+}
+
+/// Remove every option left without support as `propagate` does, with cells on the edges constrained by the
+/// boundary.
+///
+/// # Errors
+///
+/// Fails as `propagate` does.
+pub fn propagate_with_boundary(
+    wave: &mut DomainMap,
+    rules: &Rules,
+    from: Option<(usize, usize)>,
+    boundary: Boundary,
+) -> Result<PropagationReport> {
+    let (height, width) = wave.dim();
+    let is_ignore = wave.map(FixedBitSet::is_clear);
+    let neighbors = calculate_neighbours(height, width, &is_ignore, boundary);
+    let mut supports = Supports::new(ReviseStrategy::default(), (height, width), rules.len());
+    let before = wave.clone();
+
+    let mut state = Wave::new(
+        std::mem::take(wave),
+        is_ignore.clone(),
+        Heuristic::default(),
+        0,
+        rules.frequencies(),
+    );
+    match from {
+        Some(cell) if !is_ignore[cell] => state.enqueue_neighbours(cell, &neighbors),
+        Some(_) => {}
+        None => state.enqueue_all(&neighbors),
+    }
+    if let Err(e) = state.propagate(rules, &neighbors, &mut supports, usize::MAX) {
+        *wave = before;
+        return Err(e);
+    }
+    *wave = state.into_domains();
+
+    let mut report = PropagationReport::default();
+    for ((pos, domain), old) in wave.indexed_iter().zip(&before) {
+        let num_removed = old.count_ones(..) - domain.count_ones(..);
+        if num_removed > 0 {
+            report.changed_cells.push(pos);
+            report.num_removed += num_removed;
+            if domain.count_ones(..) == 1 {
+                report.num_fixed += 1;
+            }
+        }
+    }
+    Ok(report)
+}
@@ -0,0 +1,179 @@
+use anyhow::Result;
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64;
+use std::time::Instant;
+
+use super::{
+    backtracking::WaveFunctionBacktracking, common::Boundary, error::WfcError,
+    fast::WaveFunctionFast, options::CollapseOptions, stats::CollapseStats,
+};
+use crate::{AlgorithmInfo, Map, Rules, WaveFunction};
+
+const MAX_RESTARTS: usize = 10; // Retries of the first algorithm before falling back
+
+/// Collapses a map with one algorithm, restarting from scratch with a freshly seeded generator whenever it
+/// meets a contradiction, and falls back to another once the restarts run out.
+/// By default this restarts `WaveFunctionFast` and falls back to `WaveFunctionBacktracking`: for most tilesets
+/// a few random restarts are far faster than backtracking deeply out of a contradiction.
+/// Each attempt is seeded from the generator passed in, so the result is still reproducible from its seed.
+/// Errors other than contradictions are returned at once, since a retry would not get past them.
+pub struct WaveFunctionWithRestarts<W = WaveFunctionFast, F = WaveFunctionBacktracking> {
+    algorithm: W,
+    fallback: F,
+    max_restarts: usize,
+}
+
+impl WaveFunctionWithRestarts {
+    #[must_use]
+    pub fn builder() -> WaveFunctionWithRestartsBuilder {
+        WaveFunctionWithRestartsBuilder::default()
+    }
+}
+
+impl Default for WaveFunctionWithRestarts {
+    fn default() -> Self {
+        Self::builder().build(
+            WaveFunctionFast::default(),
+            WaveFunctionBacktracking::default(),
+        )
+    }
+}
+
+impl<W: WaveFunction, F: WaveFunction> WaveFunctionWithRestarts<W, F> {
+    // Collapse with the first algorithm until an attempt avoids a contradiction, then with the fallback,
+    // passing the options on to both if given
+    fn run(
+        &self,
+        map: &Map,
+        rules: &Rules,
+        options: Option<&CollapseOptions>,
+        rng: &mut impl Rng,
+    ) -> Result<(Map, CollapseStats)> {
+        let start = Instant::now();
+        for restart in 0..=self.max_restarts {
+            let seed = rng.random();
+            let attempt_rng = &mut Pcg64::seed_from_u64(seed);
+            let attempt = match options {
+                Some(options) => self
+                    .algorithm
+                    .collapse_with_options(map, rules, options, attempt_rng)
+                    .map(|result| (result, CollapseStats::default())),
+                None => self.algorithm.collapse_with_stats(map, rules, attempt_rng),
+            };
+            match attempt {
+                Ok((result, mut stats)) => {
+                    stats.restarts += restart;
+                    stats.elapsed = start.elapsed();
+                    return Ok((result, stats));
+                }
+                Err(e)
+                    if e.downcast_ref::<WfcError>()
+                        .is_some_and(WfcError::is_contradiction) =>
+                {
+                    trace_event!(debug, restart, seed, "restart");
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        trace_event!(debug, restarts = self.max_restarts, "falling back");
+        let (result, mut stats) = match options {
+            Some(options) => (
+                self.fallback
+                    .collapse_with_options(map, rules, options, rng)?,
+                CollapseStats::default(),
+            ),
+            None => self.fallback.collapse_with_stats(map, rules, rng)?,
+        };
+        stats.restarts += self.max_restarts + 1;
+        stats.elapsed = start.elapsed();
+        Ok((result, stats))
+    }
+}
+
+/// Builder for configuring a `WaveFunctionWithRestarts`.
+pub struct WaveFunctionWithRestartsBuilder {
+    max_restarts: usize,
+}
+
+impl Default for WaveFunctionWithRestartsBuilder {
+    fn default() -> Self {
+        Self {
+            max_restarts: MAX_RESTARTS,
+        }
+    }
+}
+
+impl WaveFunctionWithRestartsBuilder {
+    /// Maximum number of times the first algorithm is restarted after a contradiction before falling back.
+    #[must_use]
+    pub fn max_restarts(mut self, max_restarts: usize) -> Self {
+        self.max_restarts = max_restarts;
+        self
+    }
+
+    /// Build around the algorithm to restart, and the algorithm to fall back to once the restarts run out.
+    pub fn build<W: WaveFunction, F: WaveFunction>(
+        self,
+        algorithm: W,
+        fallback: F,
+    ) -> WaveFunctionWithRestarts<W, F> {
+        WaveFunctionWithRestarts {
+            algorithm,
+            fallback,
+            max_restarts: self.max_restarts,
+        }
+    }
+}
+
+impl<W: WaveFunction, F: WaveFunction> WaveFunction for WaveFunctionWithRestarts<W, F> {
+    fn info(&self) -> AlgorithmInfo {
+        let inner = self.algorithm.info();
+        let fallback = self.fallback.info();
+        AlgorithmInfo {
+            name: "restarts",
+            supports_backtracking: fallback.supports_backtracking,
+            deterministic: inner.deterministic && fallback.deterministic,
+            complexity: format!(
+                "Collapses with {} ({}), restarting up to {} times after a contradiction, then falls back to {} ({})",
+                inner.name, inner.complexity, self.max_restarts, fallback.name, fallback.complexity
+            ),
+        }
+    }
+
+    fn boundary(&self) -> Boundary {
+        self.algorithm.boundary()
+    }
+
+    fn collapse(&self, map: &Map, rules: &Rules, rng: &mut impl Rng) -> Result<Map> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("collapse", algorithm = "restarts").entered();
+
+        Ok(self.run(map, rules, None, rng)?.0)
+    }
+
+    fn collapse_with_options(
+        &self,
+        map: &Map,
+        rules: &Rules,
+        options: &CollapseOptions,
+        rng: &mut impl Rng,
+    ) -> Result<Map> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("collapse", algorithm = "restarts").entered();
+
+        Ok(self.run(map, rules, Some(options), rng)?.0)
+    }
+
+    fn collapse_with_stats(
+        &self,
+        map: &Map,
+        rules: &Rules,
+        rng: &mut impl Rng,
+    ) -> Result<(Map, CollapseStats)> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("collapse", algorithm = "restarts").entered();
+
+        self.run(map, rules, None, rng)
+    }
+}
@@ -0,0 +1,187 @@
+use fixedbitset::FixedBitSet;
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Arc,
+};
+
+use crate::{Direction, rules::weighted_entropy};
+
+/// Order in which cells are chosen for collapse.
+#[derive(Clone, Copy, Debug, Default)]
+pub enum Heuristic {
+    /// Collapse the cell with the fewest remaining options first.
+    /// Growth spreads out from wherever the constraints are tightest.
+    #[default]
+    MinimumEntropy,
+    /// Collapse the cells one row (or column) at a time, sweeping towards the given direction,
+    /// and the cell with the fewest remaining options within the leading row first.
+    /// Sweeping `South` grows the map top to bottom, which keeps structures consistent with
+    /// gravity in side-on levels; the sweep front tends to see more contradictions than
+    /// entropy-ordered growth, so pair it with an algorithm which can recover from them.
+    /// Propagation itself is unaffected, since it reaches the same fixed point in any order.
+    Sweep(Direction),
+    /// Collapse the cell with the lowest cost first, as given by a function of the cell's position and its
+    /// number of remaining options, such as entropy plus distance from the camera or from fixed cells.
+    /// The cost of a cell is recalculated whenever its number of options changes.
+    /// Custom heuristics cannot be saved, so checkpointing a `Collapser` using one fails: switch to another
+    /// heuristic before the checkpoint and set the custom one again after resuming.
+    Custom(fn(pos: (usize, usize), entropy: usize) -> f64),
+    /// Collapse the cell with the lowest Shannon entropy first, weighting each remaining option by its
+    /// frequency in the rules, so a cell left choosing between one common tile and a few rare ones goes before
+    /// a cell with evenly weighted options. This usually gives better maps than counting options when
+    /// frequencies are skewed, at the cost of recomputing a cell's entropy whenever its options change.
+    /// Ties are broken by the same seeded noise as the other heuristics.
+    ShannonEntropy,
+}
+
+// Cells of a bucket, ordered by their custom cost (zero for the built-in heuristics), then their noise
+type Bucket = BTreeSet<(u64, u64, (usize, usize))>;
+
+// Uncollapsed cells bucketed by the order in which the heuristic visits them.
+// Within a bucket, ties are broken by a hash of the noise seed and the cell's coordinates. This scatters
+// the choice across the map rather than working along scanlines, while keeping seeded runs reproducible.
+#[derive(Clone)]
+pub(crate) struct Selector {
+    heuristic: Heuristic,
+    size: (usize, usize),
+    num_tiles: usize,
+    noise_seed: u64,
+    // Frequency of each tile, weighting the options of a cell for Shannon entropy
+    frequencies: Arc<[usize]>,
+    bucket_sets: Vec<Bucket>,
+    // Custom cost or Shannon entropy of each cell, as it was when the cell was inserted
+    costs: HashMap<(usize, usize), u64>,
+    // No bucket before this index is occupied
+    cursor: usize,
+}
+
+impl Selector {
+    pub fn new(
+        heuristic: Heuristic,
+        size: (usize, usize),
+        num_tiles: usize,
+        noise_seed: u64,
+        frequencies: Arc<[usize]>,
+    ) -> Self {
+        let num_buckets = match heuristic {
+            Heuristic::MinimumEntropy => num_tiles + 1,
+            Heuristic::Sweep(Direction::North | Direction::South) => size.0 * (num_tiles + 1),
+            Heuristic::Sweep(Direction::East | Direction::West) => size.1 * (num_tiles + 1),
+            Heuristic::Custom(_) | Heuristic::ShannonEntropy => 1,
+        };
+        Self {
+            heuristic,
+            size,
+            num_tiles,
+            noise_seed,
+            frequencies,
+            bucket_sets: vec![BTreeSet::new(); num_buckets],
+            costs: HashMap::new(),
+            cursor: 0,
+        }
+    }
+
+    // Index of the row or column of a cell in sweep order
+    fn line(&self, pos: (usize, usize)) -> usize {
+        let (height, width) = self.size;
+        match self.heuristic {
+            Heuristic::MinimumEntropy | Heuristic::Custom(_) | Heuristic::ShannonEntropy => 0,
+            Heuristic::Sweep(Direction::North) => height - 1 - pos.0,
+            Heuristic::Sweep(Direction::East) => pos.1,
+            Heuristic::Sweep(Direction::South) => pos.0,
+            Heuristic::Sweep(Direction::West) => width - 1 - pos.1,
+        }
+    }
+
+    // Tie-breaking key of a cell, from the SplitMix64 finaliser
+    fn noise(&self, pos: (usize, usize)) -> u64 {
+        let coords = ((pos.0 as u64) << 32) | pos.1 as u64;
+        let mut z = self.noise_seed ^ coords.wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn bucket(&self, pos: (usize, usize), entropy: usize) -> usize {
+        match self.heuristic {
+            Heuristic::Custom(_) | Heuristic::ShannonEntropy => 0,
+            _ => self.line(pos) * (self.num_tiles + 1) + entropy,
+        }
+    }
+
+    /// Add a cell with the given remaining options, of which there are `entropy`.
+    pub fn insert(&mut self, pos: (usize, usize), entropy: usize, domain: &FixedBitSet) {
+        debug_assert!(entropy > 1, "Only uncollapsed cells can be selected");
+        let bucket = self.bucket(pos, entropy);
+        let cost = match self.heuristic {
+            Heuristic::Custom(cost) => ordered_bits(cost(pos, entropy)),
+            Heuristic::ShannonEntropy => ordered_bits(weighted_entropy(domain, &self.frequencies)),
+            _ => 0,
+        };
+        if let Heuristic::Custom(_) | Heuristic::ShannonEntropy = self.heuristic {
+            self.costs.insert(pos, cost);
+        }
+        let key = (cost, self.noise(pos), pos);
+        self.bucket_sets[bucket].insert(key);
+        self.cursor = self.cursor.min(bucket);
+    }
+
+    /// Remove a cell, whatever its number of remaining options.
+    pub fn remove(&mut self, pos: (usize, usize)) {
+        let noise = self.noise(pos);
+        if let Heuristic::Custom(_) | Heuristic::ShannonEntropy = self.heuristic {
+            if let Some(cost) = self.costs.remove(&pos) {
+                self.bucket_sets[0].remove(&(cost, noise, pos));
+            }
+            return;
+        }
+        let first = self.bucket(pos, 0);
+        for bucket in &mut self.bucket_sets[first + 2..=first + self.num_tiles] {
+            bucket.remove(&(0, noise, pos));
+        }
+    }
+
+    /// Move a cell to the bucket matching its new remaining options.
+    pub fn update(&mut self, pos: (usize, usize), entropy: usize, domain: &FixedBitSet) {
+        self.remove(pos);
+        if entropy > 1 {
+            self.insert(pos, entropy, domain);
+        }
+    }
+
+    /// Next cell to collapse, skipping past buckets which have emptied.
+    pub fn first(&mut self) -> Option<(usize, usize)> {
+        while self.cursor < self.bucket_sets.len() {
+            if let Some(&(_, _, pos)) = self.bucket_sets[self.cursor].first() {
+                return Some(pos);
+            }
+            self.cursor += 1;
+        }
+        None
+    }
+
+    /// Next cell to collapse, without moving past empty buckets.
+    pub fn peek(&self) -> Option<(usize, usize)> {
+        self.bucket_sets[self.cursor.min(self.bucket_sets.len())..]
+            .iter()
+            .find_map(|bucket| bucket.first().map(|&(_, _, pos)| pos))
+    }
+
+    pub fn len(&self) -> usize {
+        self.bucket_sets.iter().map(BTreeSet::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.peek().is_none()
+    }
+}
+
+// Map a cost onto an integer with the same ordering, so lower costs sort first
+fn ordered_bits(cost: f64) -> u64 {
+    let bits = cost.to_bits();
+    if bits >> 63 == 1 {
+        !bits
+    } else {
+        bits | 1 << 63
+    }
+}
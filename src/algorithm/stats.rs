@@ -0,0 +1,46 @@
+use std::{
+    fmt::{Display, Formatter},
+    time::Duration,
+};
+
+/// Measurements of one collapse, returned by `WaveFunction::collapse_with_stats` for comparing algorithms
+/// and tuning tilesets.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CollapseStats {
+    /// Time taken by the whole collapse.
+    pub elapsed: Duration,
+    /// Number of times a cell's options were revised against a neighbour's during propagation.
+    pub revisions: usize,
+    /// Number of decisions undone by backtracking.
+    pub backtracks: usize,
+    /// Number of restarts after a contradiction: of regions, by local resets, or of whole collapses, by
+    /// `WaveFunctionWithRestarts`.
+    pub restarts: usize,
+    /// Most cells collapsed when a contradiction was recovered from, or `None` if none was met.
+    pub deepest_contradiction: Option<usize>,
+}
+
+impl CollapseStats {
+    // Note a contradiction met after a number of cells had been collapsed
+    pub(crate) fn record_contradiction(&mut self, depth: usize) {
+        self.deepest_contradiction =
+            Some(self.deepest_contradiction.map_or(depth, |d| d.max(depth)));
+    }
+}
+
+impl Display for CollapseStats {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{:.3}s, {} revisions, {} backtracks, {} restarts",
+            self.elapsed.as_secs_f64(),
+            self.revisions,
+            self.backtracks,
+            self.restarts
+        )?;
+        if let Some(depth) = self.deepest_contradiction {
+            write!(f, ", deepest contradiction after {depth} cells")?;
+        }
+        Ok(())
+    }
+}
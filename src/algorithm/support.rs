@@ -0,0 +1,264 @@
+use fixedbitset::FixedBitSet;
+use ndarray::Array2;
+use std::sync::Arc;
+
+use super::common::Neighbour;
+use crate::Rules;
+
+/// How a cell's options are checked for support from a neighbour during propagation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReviseStrategy {
+    /// Scan the neighbour's options afresh every time.
+    #[default]
+    Scan,
+    /// Remember the neighbour option which last supported each (cell, tile, direction), and check it
+    /// before scanning (as in AC-2001).
+    /// Substantially reduces revision time on dense rule sets, at the cost of one entry of memory per
+    /// cell, tile and direction.
+    LastSupport,
+    /// Keep a count of the options of each neighbour supporting each option of each cell, decremented as
+    /// options are removed from the neighbour, so a revision only looks for counts which have reached zero
+    /// and cells are only revised when one has (as in AC-4, and the reference WFC implementation).
+    /// Makes far fewer revisions, which pays off where propagation spreads widely but removes few options
+    /// from each cell. Costs one count per cell, tile and neighbour, and an update of the counts of each
+    /// neighbouring option a removed tile supported, so on rule sets whose cells collapse quickly it can be
+    /// slower than scanning.
+    SupportCount,
+}
+
+/// How far the consequences of each collapse decision are propagated.
+/// Weaker consistency does less work per decision but leaves options which cannot be part of a solution, so
+/// contradictions surface later; it can be faster overall on easy rule sets when paired with an algorithm which
+/// repairs contradictions, while hard rule sets need full propagation.
+/// Both levels propagate fully before the first decision, and revise with the chosen `ReviseStrategy`, so
+/// maintained consistency with `Scan` is MAC-3, with `LastSupport` is MAC-2001 and with `SupportCount` is MAC-4.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Consistency {
+    /// Only remove options of the neighbours of a fixed cell which conflict with it. A cell left with a single
+    /// option counts as fixed, so its neighbours are checked in turn, but no other change is passed on.
+    ForwardChecking,
+    /// Maintain arc consistency: propagate every removal until every remaining option of every cell is
+    /// supported by each of its neighbours.
+    #[default]
+    Maintained,
+}
+
+const NO_SUPPORT: u32 = u32::MAX;
+
+// Last known supporting neighbour tile for each (cell, tile, direction).
+// Entries are only hints: a cached tile is used only if it is still in the neighbour's domain,
+// so they stay sound when domains are widened again by backtracking or resets.
+pub(crate) struct Supports {
+    strategy: ReviseStrategy,
+    width: usize,
+    num_tiles: usize,
+    last: Vec<u32>,
+}
+
+impl Supports {
+    pub fn new(strategy: ReviseStrategy, size: (usize, usize), num_tiles: usize) -> Self {
+        let last = match strategy {
+            ReviseStrategy::Scan | ReviseStrategy::SupportCount => Vec::new(),
+            ReviseStrategy::LastSupport => vec![NO_SUPPORT; size.0 * size.1 * num_tiles * 4],
+        };
+        Self {
+            strategy,
+            width: size.1,
+            num_tiles,
+            last,
+        }
+    }
+
+    fn index(&self, pos: (usize, usize), tile: usize, dir_index: usize) -> usize {
+        ((pos.0 * self.width + pos.1) * self.num_tiles + tile) * 4 + dir_index
+    }
+
+    pub fn strategy(&self) -> ReviseStrategy {
+        self.strategy
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.last.is_empty()
+    }
+
+    pub fn get(&self, pos: (usize, usize), tile: usize, dir_index: usize) -> Option<usize> {
+        if !self.is_enabled() {
+            return None;
+        }
+        match self.last[self.index(pos, tile, dir_index)] {
+            NO_SUPPORT => None,
+            support => Some(support as usize),
+        }
+    }
+
+    pub fn set(&mut self, pos: (usize, usize), tile: usize, dir_index: usize, support: usize) {
+        if self.is_enabled() {
+            let index = self.index(pos, tile, dir_index);
+            self.last[index] = support as u32;
+        }
+    }
+}
+
+// Number of options of each neighbour supporting each option of each cell.
+// Counts are kept per neighbour rather than per direction, since a cell of a graph may have several neighbours
+// in one direction, and a cell of a narrow wrapped map the same neighbour in several.
+// The wave passes every change of a domain on, so the counts always match the domains they were started from.
+#[derive(Clone)]
+pub(crate) struct SupportCounts {
+    width: usize,
+    num_tiles: usize,
+    counts: Vec<u32>,
+    // Position of each cell's first neighbour among all neighbours, in row-major order of the cells
+    offsets: Arc<[usize]>,
+    // Index, within each neighbour's own list, of the neighbour leading back, in the same order as the counts
+    reverse: Arc<[usize]>,
+    // Tiles supported by each tile in each direction, indexed by tile and then direction: those which allow
+    // the tile in that direction of them
+    supported: Arc<[Vec<usize>]>,
+    neighbors: Arc<Array2<Vec<Neighbour>>>,
+}
+
+impl SupportCounts {
+    pub fn new(
+        rules: &Rules,
+        domains: &Array2<FixedBitSet>,
+        neighbors: &Array2<Vec<Neighbour>>,
+    ) -> Self {
+        let width = domains.dim().1;
+        let num_tiles = rules.len();
+        let mut supported = vec![Vec::new(); num_tiles * 4];
+        for tile in 0..num_tiles {
+            for (dir_index, others) in rules.adjacency_list(tile).iter().enumerate() {
+                for &other in others {
+                    supported[other * 4 + dir_index].push(tile);
+                }
+            }
+        }
+
+        let mut offsets = Vec::with_capacity(neighbors.len() + 1);
+        offsets.push(0);
+        for cell_neighbours in neighbors {
+            offsets.push(offsets[offsets.len() - 1] + cell_neighbours.len());
+        }
+
+        let mut counts = vec![0; offsets[offsets.len() - 1] * num_tiles];
+        let mut reverse = Vec::with_capacity(offsets[offsets.len() - 1]);
+        for (pos, cell_neighbours) in neighbors.indexed_iter() {
+            for (n, neighbor) in cell_neighbours.iter().enumerate() {
+                // Repeated links between two cells in the same direction are paired up in order
+                let repeat = cell_neighbours[..n]
+                    .iter()
+                    .filter(|other| other.pos == neighbor.pos && other.dir == neighbor.dir)
+                    .count();
+                let back = neighbors[neighbor.pos]
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, other)| other.pos == pos && other.dir == neighbor.opp_dir)
+                    .nth(repeat)
+                    .map(|(m, _)| m)
+                    .expect("Every neighbour must link back to the cell");
+                reverse.push(back);
+
+                let dir_index = neighbor.dir.index();
+                let domain = &domains[neighbor.pos];
+                let start = (offsets[pos.0 * width + pos.1] + n) * num_tiles;
+                for tile in 0..num_tiles {
+                    let count = if rules.is_sparse() {
+                        rules.adjacency_list(tile)[dir_index]
+                            .iter()
+                            .filter(|&&other| domain.contains(other))
+                            .count()
+                    } else {
+                        rules.allowed(tile, neighbor.dir).intersection_count(domain)
+                    };
+                    counts[start + tile] = count as u32;
+                }
+            }
+        }
+
+        Self {
+            width,
+            num_tiles,
+            counts,
+            offsets: offsets.into(),
+            reverse: reverse.into(),
+            supported: supported.into(),
+            neighbors: Arc::new(neighbors.clone()),
+        }
+    }
+
+    // Position of a cell's neighbour among all neighbours
+    fn link(&self, pos: (usize, usize), n: usize) -> usize {
+        self.offsets[pos.0 * self.width + pos.1] + n
+    }
+
+    // Tiles are innermost, so the counts updated together and scanned by a revision are contiguous
+    fn index(&self, link: usize, tile: usize) -> usize {
+        link * self.num_tiles + tile
+    }
+
+    /// Take the support of tiles removed from a cell away from its neighbours' options.
+    /// Returns the neighbours, by their index in the cell's list, for which one of their options lost its
+    /// last support.
+    pub fn remove(
+        &mut self,
+        cell: (usize, usize),
+        tiles: &FixedBitSet,
+        domains: &Array2<FixedBitSet>,
+    ) -> FixedBitSet {
+        let neighbors = Arc::clone(&self.neighbors);
+        let mut exhausted = FixedBitSet::with_capacity(neighbors[cell].len());
+        for (n, neighbor) in neighbors[cell].iter().enumerate() {
+            // The neighbour's options are supported in the direction back towards the cell
+            let dir_index = neighbor.opp_dir.index();
+            let link = self.link(neighbor.pos, self.reverse[self.link(cell, n)]);
+            for tile in tiles.ones() {
+                for &other in &self.supported[tile * 4 + dir_index] {
+                    let index = self.index(link, other);
+                    self.counts[index] -= 1;
+                    if self.counts[index] == 0 && domains[neighbor.pos].contains(other) {
+                        exhausted.insert(n);
+                    }
+                }
+            }
+        }
+        exhausted
+    }
+
+    /// Give the support of tiles restored to a cell back to its neighbours' options.
+    pub fn add(&mut self, cell: (usize, usize), tiles: &FixedBitSet) {
+        let neighbors = Arc::clone(&self.neighbors);
+        for (n, neighbor) in neighbors[cell].iter().enumerate() {
+            let dir_index = neighbor.opp_dir.index();
+            let link = self.link(neighbor.pos, self.reverse[self.link(cell, n)]);
+            for tile in tiles.ones() {
+                for &other in &self.supported[tile * 4 + dir_index] {
+                    let index = self.index(link, other);
+                    self.counts[index] += 1;
+                }
+            }
+        }
+    }
+
+    /// Options of a cell with no support left from one of its neighbours, given by its index in the cell's
+    /// list, or `None` if every option is supported.
+    pub fn unsupported(
+        &self,
+        domain: &FixedBitSet,
+        cell: (usize, usize),
+        n: usize,
+    ) -> Option<FixedBitSet> {
+        let link = self.link(cell, n);
+        let mut removals = FixedBitSet::with_capacity(domain.len());
+        for tile in domain.ones() {
+            if self.counts[self.index(link, tile)] == 0 {
+                removals.insert(tile);
+            }
+        }
+        if removals.is_clear() {
+            None
+        } else {
+            Some(removals)
+        }
+    }
+}
@@ -0,0 +1,233 @@
+use anyhow::{Result, bail};
+use rand::Rng;
+
+use crate::{Cell, Map, Rules, WaveFunction};
+
+const TOLERANCE: f64 = 0.02; // Largest accepted difference between a tile's proportion and its target
+const MAX_ROUNDS: usize = 20; // Weight updates before settling for the best weights found
+const TRIALS: usize = 4; // Generations averaged over to measure the proportions of each round
+const LEARNING_RATE: f64 = 0.5; // Exponent applied to each target / observed ratio
+const MAX_STEP: f64 = 4.0; // Largest factor a weight may change by in one round
+const WEIGHT_TOTAL: f64 = 1_000_000.0; // Sum the tuned weights are scaled to before rounding
+
+/// Tunes tile frequencies so generated maps contain each tile in a target proportion.
+/// Adjacency constraints skew the output away from the raw frequencies, so the weights are found by
+/// iteration: each round generates several maps, measures the average proportion of each tile, and
+/// multiplies each weight by its target over observed ratio.
+pub struct WeightTuner {
+    tolerance: f64,
+    max_rounds: usize,
+    trials: usize,
+    learning_rate: f64,
+}
+
+impl WeightTuner {
+    #[must_use]
+    pub fn builder() -> WeightTunerBuilder {
+        WeightTunerBuilder::default()
+    }
+}
+
+impl Default for WeightTuner {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+/// Builder for configuring a `WeightTuner`.
+pub struct WeightTunerBuilder {
+    tolerance: f64,
+    max_rounds: usize,
+    trials: usize,
+    learning_rate: f64,
+}
+
+impl Default for WeightTunerBuilder {
+    fn default() -> Self {
+        Self {
+            tolerance: TOLERANCE,
+            max_rounds: MAX_ROUNDS,
+            trials: TRIALS,
+            learning_rate: LEARNING_RATE,
+        }
+    }
+}
+
+impl WeightTunerBuilder {
+    /// Largest difference allowed between any tile's average proportion and its target.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tolerance` is not positive.
+    #[must_use]
+    pub fn tolerance(mut self, tolerance: f64) -> Self {
+        assert!(tolerance > 0.0, "Tolerance must be positive");
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Maximum number of weight updates.
+    #[must_use]
+    pub fn max_rounds(mut self, max_rounds: usize) -> Self {
+        self.max_rounds = max_rounds;
+        self
+    }
+
+    /// Number of maps generated to measure the proportions of each round.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `trials` is zero.
+    #[must_use]
+    pub fn trials(mut self, trials: usize) -> Self {
+        assert!(trials > 0, "Number of trials must be greater than zero");
+        self.trials = trials;
+        self
+    }
+
+    /// Exponent applied to the multiplicative updates; lower values converge more slowly but more steadily.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `learning_rate` is not in (0, 1].
+    #[must_use]
+    pub fn learning_rate(mut self, learning_rate: f64) -> Self {
+        assert!(
+            learning_rate > 0.0 && learning_rate <= 1.0,
+            "Learning rate must be in (0, 1]"
+        );
+        self.learning_rate = learning_rate;
+        self
+    }
+
+    #[must_use]
+    pub fn build(self) -> WeightTuner {
+        WeightTuner {
+            tolerance: self.tolerance,
+            max_rounds: self.max_rounds,
+            trials: self.trials,
+            learning_rate: self.learning_rate,
+        }
+    }
+}
+
+impl WeightTuner {
+    /// Find frequencies for which maps collapsed from the template contain each tile in the target proportion,
+    /// returning a copy of the rules with those frequencies.
+    /// Targets are given per tile and are normalised to sum to one. If the tolerance is not met within the
+    /// maximum number of rounds, the closest weights found are returned.
+    ///
+    /// # Errors
+    ///
+    /// Fails if there is not one target per tile, any target is negative or they sum to zero, or the algorithm
+    /// cannot collapse the template.
+    pub fn tune(
+        &self,
+        template: &Map,
+        algorithm: &impl WaveFunction,
+        rules: &Rules,
+        targets: &[f64],
+        rng: &mut impl Rng,
+    ) -> Result<Rules> {
+        if targets.len() != rules.len() {
+            bail!(
+                "Expected {} target proportions, but {} were given",
+                rules.len(),
+                targets.len()
+            );
+        }
+        let total: f64 = targets.iter().sum();
+        if targets.iter().any(|&t| t < 0.0) || total <= 0.0 {
+            bail!("Target proportions must be non-negative and not all zero");
+        }
+        let targets: Vec<f64> = targets.iter().map(|&t| t / total).collect();
+
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("tune_weights", num_tiles = rules.len()).entered();
+
+        let mut tuned = rules.clone();
+        let mut weights: Vec<f64> = rules.frequencies().iter().map(|&f| f as f64).collect();
+        let mut best: Option<(f64, Vec<usize>)> = None;
+        // The round is only read by tracing
+        #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+        for round in 0..=self.max_rounds {
+            let frequencies = Self::to_frequencies(&weights);
+            tuned.set_frequencies(frequencies.clone());
+
+            let proportions = self.measure(template, algorithm, &tuned, rng)?;
+            let error = proportions
+                .iter()
+                .zip(&targets)
+                .map(|(p, t)| (p - t).abs())
+                .fold(0.0, f64::max);
+            trace_event!(debug, round, error, "weight tuning round");
+
+            if best
+                .as_ref()
+                .is_none_or(|(best_error, _)| error < *best_error)
+            {
+                best = Some((error, frequencies));
+            }
+            if error <= self.tolerance {
+                break;
+            }
+
+            for ((weight, &observed), &target) in weights.iter_mut().zip(&proportions).zip(&targets)
+            {
+                let ratio = if observed > 0.0 {
+                    target / observed
+                } else {
+                    MAX_STEP
+                };
+                *weight *= ratio
+                    .powf(self.learning_rate)
+                    .clamp(1.0 / MAX_STEP, MAX_STEP);
+            }
+        }
+
+        if let Some((_, frequencies)) = best {
+            tuned.set_frequencies(frequencies);
+        }
+        Ok(tuned)
+    }
+
+    // Average proportion of each tile over the fixed cells of several collapsed maps
+    fn measure(
+        &self,
+        template: &Map,
+        algorithm: &impl WaveFunction,
+        rules: &Rules,
+        rng: &mut impl Rng,
+    ) -> Result<Vec<f64>> {
+        let mut counts = vec![0usize; rules.len()];
+        let mut failures = 0;
+        for _ in 0..self.trials {
+            let Ok(map) = algorithm.collapse(template, rules, rng) else {
+                failures += 1;
+                continue;
+            };
+            for y in 0..map.height() {
+                for x in 0..map.width() {
+                    if let Cell::Fixed(tile) = map[(y, x)] {
+                        counts[tile] += 1;
+                    }
+                }
+            }
+        }
+        if failures == self.trials {
+            bail!("Every trial generation failed while tuning weights");
+        }
+
+        let total = counts.iter().sum::<usize>().max(1) as f64;
+        Ok(counts.iter().map(|&count| count as f64 / total).collect())
+    }
+
+    // Scale weights to integer frequencies, keeping every tile possible
+    fn to_frequencies(weights: &[f64]) -> Vec<usize> {
+        let total: f64 = weights.iter().sum();
+        weights
+            .iter()
+            .map(|&w| ((w / total * WEIGHT_TOTAL).round() as usize).max(1))
+            .collect()
+    }
+}
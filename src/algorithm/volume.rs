@@ -0,0 +1,215 @@
+use anyhow::Result;
+use fixedbitset::FixedBitSet;
+use ndarray::Array3;
+use rand::Rng;
+use std::{collections::VecDeque, sync::Arc};
+
+use super::{
+    common::choose_weighted,
+    error::WfcError,
+    options::CollapseOptions,
+    selection::{Heuristic, Selector},
+};
+use crate::{ALL_DIRECTIONS_3, Cell, Map3, Rules3};
+
+// A decision which can be revisited: the cell, the tiles tried there so far, and where the trail stood before
+struct Decision {
+    pos: (usize, usize, usize),
+    tried: FixedBitSet,
+    trail_mark: usize,
+}
+
+// Domains of a volume's cells, with the uncollapsed ones ordered for selection and every change recorded so
+// it can be undone
+struct Volume<'a> {
+    rules: &'a Rules3,
+    domains: Array3<FixedBitSet>,
+    is_ignore: Array3<bool>,
+    // Uncollapsed cells with the fewest options first, keyed by floor-major (z * height + y, x) positions
+    selector: Selector,
+    // Each cell's domain before every change, oldest first
+    trail: Vec<((usize, usize, usize), FixedBitSet)>,
+}
+
+impl<'a> Volume<'a> {
+    fn new(map: &Map3, rules: &'a Rules3, noise_seed: u64) -> Self {
+        let num_tiles = rules.len();
+        let (depth, height, width) = map.size();
+        let domains = Array3::from_shape_fn(map.size(), |pos| map[pos].domain(num_tiles));
+        let is_ignore = domains.mapv(|domain: FixedBitSet| domain.is_clear());
+        let mut selector = Selector::new(
+            Heuristic::MinimumEntropy,
+            (depth * height, width),
+            num_tiles,
+            noise_seed,
+            Arc::from(rules.frequencies()),
+        );
+        for (pos, domain) in domains.indexed_iter() {
+            let count = domain.count_ones(..);
+            if count > 1 {
+                selector.insert(Self::key(pos, height), count, domain);
+            }
+        }
+        Self {
+            rules,
+            domains,
+            is_ignore,
+            selector,
+            trail: Vec::new(),
+        }
+    }
+
+    fn key((z, y, x): (usize, usize, usize), height: usize) -> (usize, usize) {
+        (z * height + y, x)
+    }
+
+    fn height(&self) -> usize {
+        self.domains.dim().1
+    }
+
+    // Replace a cell's domain, recording the old one and reselecting the cell
+    fn set(&mut self, pos: (usize, usize, usize), domain: FixedBitSet) {
+        let count = domain.count_ones(..);
+        let old = std::mem::replace(&mut self.domains[pos], domain);
+        self.trail.push((pos, old));
+        self.selector
+            .update(Self::key(pos, self.height()), count, &self.domains[pos]);
+    }
+
+    // Undo every change made since the trail was at the given length
+    fn undo_to(&mut self, mark: usize) {
+        while self.trail.len() > mark {
+            let (pos, domain) = self.trail.pop().expect("Trail is longer than the mark");
+            let count = domain.count_ones(..);
+            self.domains[pos] = domain;
+            self.selector
+                .update(Self::key(pos, self.height()), count, &self.domains[pos]);
+        }
+    }
+
+    // Fix a cell to a tile and propagate the consequences
+    fn collapse(&mut self, pos: (usize, usize, usize), tile: usize) -> Result<()> {
+        let mut domain = FixedBitSet::with_capacity(self.rules.len());
+        domain.insert(tile);
+        self.set(pos, domain);
+        self.propagate(VecDeque::from([pos]))
+    }
+
+    // The template with every cell it does not ignore fixed to its one remaining tile
+    fn to_map(&self, template: &Map3) -> Map3 {
+        let mut map = template.clone();
+        for pos in template.positions().filter(|&pos| !self.is_ignore[pos]) {
+            let tile = self.domains[pos]
+                .ones()
+                .next()
+                .expect("Collapsed cell has a tile");
+            map[pos] = Cell::Fixed(tile);
+        }
+        map
+    }
+
+    // Remove unsupported options from the neighbours of every queued cell until nothing changes
+    fn propagate(&mut self, mut queue: VecDeque<(usize, usize, usize)>) -> Result<()> {
+        let size = self.domains.dim();
+        while let Some(pos) = queue.pop_front() {
+            for direction in ALL_DIRECTIONS_3 {
+                let Some(other) = direction.apply_to(pos, size) else {
+                    continue;
+                };
+                if self.is_ignore[other] {
+                    continue;
+                }
+
+                let mut supported = FixedBitSet::with_capacity(self.rules.len());
+                for tile in self.domains[pos].ones() {
+                    supported.union_with(self.rules.allowed(tile, direction));
+                }
+                if self.domains[other].is_subset(&supported) {
+                    continue;
+                }
+                let mut narrowed = self.domains[other].clone();
+                narrowed.intersect_with(&supported);
+                if narrowed.is_clear() {
+                    return Err(WfcError::VolumeContradiction { cell: other }.into());
+                }
+                self.set(other, narrowed);
+                queue.push_back(other);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Collapse a volume of cells, propagating constraints up and down between floors as well as within them.
+/// Cells with the fewest options are collapsed first, with ties broken at random, and a contradiction is
+/// undone by backtracking to the latest decision with another tile left to try.
+///
+/// # Errors
+///
+/// Fails as `collapse_volume_with_options` does with the default options.
+pub fn collapse_volume(map: &Map3, rules: &Rules3, rng: &mut impl Rng) -> Result<Map3> {
+    collapse_volume_with_options(map, rules, &CollapseOptions::default(), rng)
+}
+
+/// Collapse a volume as `collapse_volume` does, with the backtracking limits of the given options.
+/// The other options only apply to maps.
+///
+/// # Errors
+///
+/// Fails with `WfcError::VolumeContradiction` if no choice of tiles within the backtracking depth satisfies
+/// the rules, or `WfcError::MaxBacktracks` once the limit on backtracks is reached.
+pub fn collapse_volume_with_options(
+    map: &Map3,
+    rules: &Rules3,
+    options: &CollapseOptions,
+    rng: &mut impl Rng,
+) -> Result<Map3> {
+    let mut volume = Volume::new(map, rules, rng.random());
+    let unfixed = map.positions().filter(|&pos| !volume.is_ignore[pos]);
+    volume.propagate(unfixed.collect())?;
+    let height = volume.height();
+
+    let mut decisions: Vec<Decision> = Vec::with_capacity(options.backtrack_depth);
+    let mut backtracks = 0;
+    while let Some((row, x)) = volume.selector.first() {
+        let pos = (row / height, row % height, x);
+        let tile = choose_weighted(&volume.domains[pos], rules.frequencies(), rng);
+        if decisions.len() >= options.backtrack_depth {
+            decisions.remove(0);
+        }
+        let mut tried = FixedBitSet::with_capacity(rules.len());
+        tried.insert(tile);
+        decisions.push(Decision {
+            pos,
+            tried,
+            trail_mark: volume.trail.len(),
+        });
+
+        let mut outcome = volume.collapse(pos, tile);
+        while let Err(error) = outcome {
+            backtracks += 1;
+            if backtracks > options.max_backtracks {
+                return Err(WfcError::MaxBacktracks(options.max_backtracks).into());
+            }
+
+            // Return to the latest decision with an untried tile left, and try one of those instead
+            let Some(mut decision) = decisions.pop() else {
+                return Err(error);
+            };
+            volume.undo_to(decision.trail_mark);
+            let mut remaining = volume.domains[decision.pos].clone();
+            remaining.difference_with(&decision.tried);
+            if remaining.is_clear() {
+                outcome = Err(error);
+                continue;
+            }
+            let tile = choose_weighted(&remaining, rules.frequencies(), rng);
+            decision.tried.insert(tile);
+            let pos = decision.pos;
+            decisions.push(decision);
+            outcome = volume.collapse(pos, tile);
+        }
+    }
+
+    Ok(volume.to_map(map))
+}
@@ -0,0 +1,852 @@
+use anyhow::Result;
+use fixedbitset::FixedBitSet;
+use ndarray::Array2;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+};
+
+use super::{
+    common::{Neighbour, hole_mask, unsupported, unsupported_by_self},
+    error::WfcError,
+    selection::{Heuristic, Selector},
+    support::{Consistency, ReviseStrategy, SupportCounts, Supports},
+};
+use crate::{Cell, Direction, Map, Rules};
+
+// Revision of the first cell against the second, in the direction from the first to the second
+type Revision = ((usize, usize), (usize, usize), Direction);
+
+// Cell domains together with everything derived from them: domain sizes, the selection buckets and the
+// propagation queue. Domains are only modified through this type, so the bookkeeping always stays in step.
+#[derive(Clone)]
+pub(crate) struct Wave {
+    domains: Array2<FixedBitSet>,
+    domain_sizes: Array2<usize>,
+    is_ignore: Array2<bool>,
+    // Selector each cell is bucketed in
+    groups: Array2<usize>,
+    selectors: Vec<Selector>,
+    // Seed for breaking ties between equally constrained cells
+    noise_seed: u64,
+    // Tile frequencies, shared with the selectors for weighted entropy
+    frequencies: Arc<[usize]>,
+    // Pending revisions
+    queue: VecDeque<Revision>,
+    // Cells fixed since their runs, buffer zones and lines were last checked
+    fixed: Vec<(usize, usize)>,
+    // Whether removals from cells which are not fixed are passed on to their neighbours
+    consistency: Consistency,
+    // Revisions made by every propagation so far
+    revisions: usize,
+    // Record of domain changes for undoing decisions, once the first mark is taken
+    trail: Option<Trail>,
+    // Counts for the categories with quotas, once propagating with rules which set them
+    tallies: Option<Tallies>,
+    // Supports of each option from each neighbour, once propagating with `ReviseStrategy::SupportCount`
+    counts: Option<SupportCounts>,
+}
+
+// Domains of cells as they were before each change since the trail was started.
+// Each cell is saved at most once between marks, the first time it changes.
+#[derive(Clone)]
+struct Trail {
+    entries: Vec<((usize, usize), FixedBitSet)>,
+    // Generation in which each cell's domain was last saved
+    saved: Array2<usize>,
+    // Bumped by every mark and undo, so cells are saved again after either
+    generation: usize,
+}
+
+// Revisions and fixed cells waiting to be propagated, taken out of a wave to be put back later
+#[derive(Clone)]
+pub(crate) struct Pending {
+    queue: VecDeque<Revision>,
+    fixed: Vec<(usize, usize)>,
+}
+
+// Number of cells fixed to, and able to take, the tiles of each category with a quota
+#[derive(Clone)]
+struct Tallies {
+    // Tiles of each limited category, with the fewest and most cells allowed
+    quotas: Vec<(FixedBitSet, usize, usize)>,
+    fixed: Vec<usize>,
+    possible: Vec<usize>,
+    // Whether the quota has been enforced over the whole map since domains were last widened
+    swept: Vec<bool>,
+}
+
+impl Wave {
+    pub fn new(
+        domains: Array2<FixedBitSet>,
+        is_ignore: Array2<bool>,
+        heuristic: Heuristic,
+        noise_seed: u64,
+        frequencies: &[usize],
+    ) -> Self {
+        let domain_sizes = domains.map(|domain| domain.count_ones(..));
+        let mut wave = Self {
+            groups: Array2::zeros(domains.dim()),
+            domains,
+            domain_sizes,
+            is_ignore,
+            selectors: Vec::new(),
+            noise_seed,
+            frequencies: frequencies.into(),
+            queue: VecDeque::new(),
+            fixed: Vec::new(),
+            consistency: Consistency::default(),
+            revisions: 0,
+            trail: None,
+            tallies: None,
+            counts: None,
+        };
+        wave.regroup(Array2::zeros(wave.domains.dim()), 1, heuristic);
+        wave
+    }
+
+    // Wave of a template's domains for the solvers, whose soft holes are decided first, each keeping the hole
+    // tile unless the search backtracks out of it
+    pub fn from_template(
+        template: &Map,
+        rules: &Rules,
+        heuristic: Heuristic,
+        consistency: Consistency,
+        noise_seed: u64,
+    ) -> Self {
+        let mut wave = Self::new(
+            template.domains(rules.len()),
+            template.mask(),
+            heuristic,
+            noise_seed,
+            rules.frequencies(),
+        );
+        wave.set_consistency(consistency);
+        if let Some(holes) = hole_mask(template, rules) {
+            wave.regroup(holes.mapv(|is_hole| usize::from(!is_hole)), 2, heuristic);
+        }
+        wave
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        self.domains.dim()
+    }
+
+    pub fn domains(&self) -> &Array2<FixedBitSet> {
+        &self.domains
+    }
+
+    pub fn domain(&self, pos: (usize, usize)) -> &FixedBitSet {
+        &self.domains[pos]
+    }
+
+    pub fn into_domains(self) -> Array2<FixedBitSet> {
+        self.domains
+    }
+
+    // Copy of the template with each open cell fixed to the first tile of its domain
+    pub fn to_map(&self, template: &Map) -> Result<Map> {
+        let mut map = template.clone();
+        for (pos, domain) in self.domains.indexed_iter() {
+            if !self.is_ignore[pos] {
+                let tile = domain.ones().next().ok_or(WfcError::Contradiction {
+                    cell: pos,
+                    direction: None,
+                })?;
+                map[pos] = Cell::Fixed(tile);
+            }
+        }
+        Ok(map)
+    }
+
+    pub fn domain_size(&self, pos: (usize, usize)) -> usize {
+        self.domain_sizes[pos]
+    }
+
+    pub fn selectors(&self) -> &[Selector] {
+        &self.selectors
+    }
+
+    /// Number of cells which still have more than one option.
+    pub fn remaining(&self) -> usize {
+        self.selectors.iter().map(Selector::len).sum()
+    }
+
+    /// Next cell to collapse, taken from the first non-empty group.
+    pub fn next_cell(&mut self) -> Option<(usize, usize)> {
+        self.selectors.iter_mut().find_map(Selector::first)
+    }
+
+    pub fn noise_seed(&self) -> u64 {
+        self.noise_seed
+    }
+
+    /// Change the seed used to break ties between equally constrained cells.
+    /// Cells are only re-ordered by the next `regroup`.
+    pub fn set_noise_seed(&mut self, noise_seed: u64) {
+        self.noise_seed = noise_seed;
+    }
+
+    pub fn consistency(&self) -> Consistency {
+        self.consistency
+    }
+
+    /// Set how far the consequences of a decision are propagated.
+    pub fn set_consistency(&mut self, consistency: Consistency) {
+        self.consistency = consistency;
+    }
+
+    /// Change the tile frequencies weighting Shannon entropy, such as after swapping in new rules.
+    /// Cells are only re-ordered by the next `regroup`.
+    pub fn set_frequencies(&mut self, frequencies: &[usize]) {
+        self.frequencies = frequencies.into();
+    }
+
+    /// Re-bucket every uncollapsed cell into the selector of its group.
+    pub fn regroup(&mut self, groups: Array2<usize>, num_groups: usize, heuristic: Heuristic) {
+        debug_assert_eq!(groups.dim(), self.domains.dim());
+        let num_tiles = self.domains.first().map_or(0, FixedBitSet::len);
+        self.selectors = vec![
+            Selector::new(
+                heuristic,
+                self.size(),
+                num_tiles,
+                self.noise_seed,
+                Arc::clone(&self.frequencies),
+            );
+            num_groups
+        ];
+        for ((pos, &size), &group) in self.domain_sizes.indexed_iter().zip(&groups) {
+            if !self.is_ignore[pos] && size > 1 {
+                self.selectors[group].insert(pos, size, &self.domains[pos]);
+            }
+        }
+        self.groups = groups;
+    }
+
+    /// Remove a set of tiles from a cell's domain, updating its size and selection bucket and queueing
+    /// its neighbours for revision.
+    /// Returns whether anything was removed, or a `WfcError::Contradiction` if no options remain.
+    pub fn remove_many(
+        &mut self,
+        cell: (usize, usize),
+        tiles: &FixedBitSet,
+        neighbors: &Array2<Vec<Neighbour>>,
+    ) -> Result<bool> {
+        self.remove(cell, tiles, neighbors, None)
+    }
+
+    /// Fix a cell to a single tile and queue its neighbours for revision.
+    pub fn collapse(
+        &mut self,
+        cell: (usize, usize),
+        tile: usize,
+        neighbors: &Array2<Vec<Neighbour>>,
+    ) -> Result<()> {
+        let mut others = self.domains[cell].clone();
+        others.set(tile, false);
+        self.remove_many(cell, &others, neighbors)?;
+        Ok(())
+    }
+
+    /// Replace a cell's domain, typically widening it again after a contradiction.
+    /// Nothing is queued, so the caller should queue the cell with `enqueue_cell` once all resets are done.
+    pub fn reset(&mut self, cell: (usize, usize), domain: FixedBitSet) {
+        self.save(cell);
+        self.restore(cell, domain);
+    }
+
+    /// Start recording domain changes, if not already, and return a mark which `undo_to` can later return the
+    /// domains to.
+    pub fn mark(&mut self) -> usize {
+        let size = self.size();
+        let trail = self.trail.get_or_insert_with(|| Trail {
+            entries: Vec::new(),
+            saved: Array2::zeros(size),
+            generation: 0,
+        });
+        trail.generation += 1;
+        trail.entries.len()
+    }
+
+    /// Undo every domain change made since a mark was taken.
+    /// The propagation queue is left as it is, so this should follow a failed propagation, which clears it.
+    pub fn undo_to(&mut self, mark: usize) {
+        let Some(trail) = self.trail.as_mut() else {
+            return;
+        };
+        trail.generation += 1;
+        let entries = trail.entries.split_off(mark.min(trail.entries.len()));
+        for (cell, domain) in entries.into_iter().rev() {
+            self.restore(cell, domain);
+        }
+    }
+
+    /// Discard the changes recorded before a mark, which can then no longer be undone.
+    /// Later marks are shifted down by `mark`.
+    pub fn forget_before(&mut self, mark: usize) {
+        if let Some(trail) = self.trail.as_mut() {
+            trail.entries.drain(..mark.min(trail.entries.len()));
+        }
+    }
+
+    /// Stop recording domain changes, discarding those recorded, until the next mark.
+    pub fn stop_trail(&mut self) {
+        self.trail = None;
+    }
+
+    /// Copy of the work waiting to be propagated, to put back with `set_pending` after undoing changes.
+    pub fn pending(&self) -> Pending {
+        Pending {
+            queue: self.queue.clone(),
+            fixed: self.fixed.clone(),
+        }
+    }
+
+    /// Replace the work waiting to be propagated.
+    pub fn set_pending(&mut self, pending: Pending) {
+        self.queue = pending.queue;
+        self.fixed = pending.fixed;
+    }
+
+    // Record a cell's domain before it is changed, unless it was already recorded since the last mark
+    fn save(&mut self, cell: (usize, usize)) {
+        let Some(trail) = self.trail.as_mut() else {
+            return;
+        };
+        if trail.saved[cell] != trail.generation {
+            trail.saved[cell] = trail.generation;
+            trail.entries.push((cell, self.domains[cell].clone()));
+        }
+    }
+
+    // Replace a cell's domain without recording the change
+    fn restore(&mut self, cell: (usize, usize), domain: FixedBitSet) {
+        if let Some(counts) = self.counts.as_mut() {
+            let mut lost = self.domains[cell].clone();
+            lost.difference_with(&domain);
+            let mut gained = domain.clone();
+            gained.difference_with(&self.domains[cell]);
+            counts.remove(cell, &lost, &self.domains);
+            counts.add(cell, &gained);
+        }
+        self.tally(cell, false);
+        self.domain_sizes[cell] = domain.count_ones(..);
+        self.domains[cell] = domain;
+        self.tally(cell, true);
+        if let Some(tallies) = self.tallies.as_mut() {
+            tallies.swept.fill(false);
+        }
+        if !self.is_ignore[cell] {
+            self.selectors[self.groups[cell]].update(
+                cell,
+                self.domain_sizes[cell],
+                &self.domains[cell],
+            );
+        }
+    }
+
+    /// Queue a cell for revision against all of its neighbours.
+    pub fn enqueue_cell(&mut self, cell: (usize, usize), neighbors: &Array2<Vec<Neighbour>>) {
+        if self.domain_sizes[cell] == 1 {
+            self.fixed.push(cell);
+        }
+        for neighbor in &neighbors[cell] {
+            self.queue.push_back((cell, neighbor.pos, neighbor.dir));
+        }
+    }
+
+    /// Queue a cell's neighbours for revision against it, as after its domain was narrowed from outside.
+    pub fn enqueue_neighbours(&mut self, cell: (usize, usize), neighbors: &Array2<Vec<Neighbour>>) {
+        if self.domain_sizes[cell] == 1 {
+            self.fixed.push(cell);
+        }
+        for neighbor in &neighbors[cell] {
+            self.queue.push_back((neighbor.pos, cell, neighbor.opp_dir));
+        }
+    }
+
+    /// Queue every cell for revision against all of its neighbours.
+    pub fn enqueue_all(&mut self, neighbors: &Array2<Vec<Neighbour>>) {
+        for (pos, cell_neighbours) in neighbors.indexed_iter() {
+            if !self.is_ignore[pos] {
+                if self.domain_sizes[pos] == 1 {
+                    self.fixed.push(pos);
+                }
+                for neighbor in cell_neighbours {
+                    self.queue.push_back((pos, neighbor.pos, neighbor.dir));
+                }
+            }
+        }
+    }
+
+    /// Revise queued cells until no more options can be removed.
+    /// On failure the queue is cleared, but the domains are left part-way through propagation.
+    pub fn propagate(
+        &mut self,
+        rules: &Rules,
+        neighbors: &Array2<Vec<Neighbour>>,
+        supports: &mut Supports,
+        max_iterations: usize,
+    ) -> Result<()> {
+        self.propagate_within(rules, neighbors, supports, max_iterations, None)?;
+        Ok(())
+    }
+
+    /// Revise queued cells as `propagate` does, but stop once `budget` revisions have been made, leaving the
+    /// rest of the queue for a later call.
+    /// Returns whether propagation finished, so that the domains are consistent again.
+    pub fn propagate_within(
+        &mut self,
+        rules: &Rules,
+        neighbors: &Array2<Vec<Neighbour>>,
+        supports: &mut Supports,
+        max_iterations: usize,
+        budget: Option<usize>,
+    ) -> Result<bool> {
+        let result = self.drain_queue(rules, neighbors, supports, max_iterations, budget);
+        if result.is_err() {
+            self.queue.clear();
+            self.fixed.clear();
+        }
+        result
+    }
+
+    /// Number of revisions of a cell against a neighbour made by every propagation so far.
+    pub fn revisions(&self) -> usize {
+        self.revisions
+    }
+
+    /// Whether nothing is waiting to be propagated.
+    pub fn is_settled(&self) -> bool {
+        self.queue.is_empty() && self.fixed.is_empty()
+    }
+
+    /// Revise every cell against all of its neighbours, as done before the first collapse.
+    pub fn propagate_all(
+        &mut self,
+        rules: &Rules,
+        neighbors: &Array2<Vec<Neighbour>>,
+        supports: &mut Supports,
+        max_iterations: usize,
+    ) -> Result<()> {
+        // Support counts are started again, since this may follow a change of rules
+        self.counts = None;
+
+        // The first propagation is always complete, whatever the consistency level
+        let consistency = std::mem::replace(&mut self.consistency, Consistency::Maintained);
+        self.enqueue_all(neighbors);
+        let result = self.propagate(rules, neighbors, supports, max_iterations);
+        self.consistency = consistency;
+        if let Err(e) = result {
+            if let Some(&WfcError::Contradiction { cell, .. }) = e.downcast_ref::<WfcError>() {
+                return Err(WfcError::Unsatisfiable { cell }.into());
+            }
+            return Err(e);
+        }
+
+        trace_event!(debug, "initial propagation complete");
+        Ok(())
+    }
+
+    fn drain_queue(
+        &mut self,
+        rules: &Rules,
+        neighbors: &Array2<Vec<Neighbour>>,
+        supports: &mut Supports,
+        max_iterations: usize,
+        budget: Option<usize>,
+    ) -> Result<bool> {
+        if rules.has_quotas() && self.tallies.is_none() {
+            self.start_tallies(rules);
+        }
+        if supports.strategy() == ReviseStrategy::SupportCount && self.counts.is_none() {
+            self.counts = Some(SupportCounts::new(rules, &self.domains, neighbors));
+        }
+
+        let mut iteration_count = 0;
+        loop {
+            while let Some(&(xi, xj, dir)) = self.queue.front() {
+                if budget.is_some_and(|budget| iteration_count >= budget) {
+                    trace_event!(trace, iterations = iteration_count, "propagation deferred");
+                    return Ok(false);
+                }
+                self.queue.pop_front();
+                iteration_count += 1;
+                self.revisions += 1;
+                if iteration_count > max_iterations {
+                    return Err(WfcError::MaxIterations(max_iterations).into());
+                }
+
+                // A cell which is its own neighbour holds one tile on both sides of the arc, so needs a tile
+                // allowed next to itself rather than support from the rest of its options
+                let removals = match &self.counts {
+                    _ if xi == xj => unsupported_by_self(&self.domains[xi], rules, dir),
+                    Some(counts) => {
+                        // Repeated links to a neighbour in one direction have the same counts, so any will do
+                        let n = neighbors[xi]
+                            .iter()
+                            .position(|neighbor| neighbor.pos == xj && neighbor.dir == dir)
+                            .expect("Queued revisions must be between neighbours");
+                        counts.unsupported(&self.domains[xi], xi, n)
+                    }
+                    None => unsupported(
+                        &self.domains,
+                        &self.domain_sizes,
+                        rules,
+                        supports,
+                        xi,
+                        xj,
+                        dir,
+                    ),
+                };
+                if let Some(removals) = removals {
+                    self.remove(xi, &removals, neighbors, Some((xj, dir)))?;
+                }
+            }
+
+            // Adjacency is settled, so apply the quotas and the constraints around any newly fixed cells
+            if self.enforce_quotas(neighbors)? {
+                continue;
+            }
+            if !rules.has_run_limits() && !rules.has_buffer_zones() && !rules.has_line_uniqueness()
+            {
+                self.fixed.clear();
+            }
+            match self.fixed.pop() {
+                Some(cell) => {
+                    self.limit_runs(cell, rules, neighbors)?;
+                    self.clear_buffer_zones(cell, rules, neighbors)?;
+                    self.clear_lines(cell, rules, neighbors)?;
+                }
+                None => break,
+            }
+        }
+
+        trace_event!(trace, iterations = iteration_count, "propagation batch");
+        Ok(true)
+    }
+
+    // Check the runs through a fixed cell against its tile's maximum run length, and remove the tile from
+    // the cells at either end of each run if it could not grow through them
+    fn limit_runs(
+        &mut self,
+        cell: (usize, usize),
+        rules: &Rules,
+        neighbors: &Array2<Vec<Neighbour>>,
+    ) -> Result<()> {
+        if self.domain_sizes[cell] != 1 {
+            return Ok(());
+        }
+        let Some(tile) = self.domains[cell].ones().next() else {
+            return Ok(());
+        };
+        let Some(max_run) = rules.max_run(tile) else {
+            return Ok(());
+        };
+
+        for (backward, forward) in [
+            (Direction::West, Direction::East),
+            (Direction::North, Direction::South),
+        ] {
+            let (before, start) = self.run(cell, backward, tile, max_run, neighbors);
+            let (after, end) = self.run(cell, forward, tile, max_run, neighbors);
+            let length = before + 1 + after;
+            if length > max_run {
+                trace_event!(debug, cell = ?cell, tile, length, "run too long");
+                return Err(WfcError::Contradiction {
+                    cell,
+                    direction: None,
+                }
+                .into());
+            }
+
+            for (beyond, dir) in [(start, backward), (end, forward)] {
+                let Some(beyond) = beyond else {
+                    continue;
+                };
+                if self.domain_sizes[beyond] > 1 && self.domains[beyond].contains(tile) {
+                    let (extra, _) = self.run(beyond, dir, tile, max_run, neighbors);
+                    if length + 1 + extra > max_run {
+                        let mut tiles = FixedBitSet::with_capacity(self.domains[beyond].len());
+                        tiles.insert(tile);
+                        self.remove(beyond, &tiles, neighbors, None)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Remove the tiles kept away from a fixed cell's tile from every cell within their buffer distance
+    fn clear_buffer_zones(
+        &mut self,
+        cell: (usize, usize),
+        rules: &Rules,
+        neighbors: &Array2<Vec<Neighbour>>,
+    ) -> Result<()> {
+        if self.domain_sizes[cell] != 1 {
+            return Ok(());
+        }
+        let Some(tile) = self.domains[cell].ones().next() else {
+            return Ok(());
+        };
+        let zones = rules.buffer_zones(tile);
+        let Some(&(max_distance, _)) = zones.last() else {
+            return Ok(());
+        };
+
+        // Breadth-first, so each cell is reached at its distance in steps
+        let mut distances = HashMap::from([(cell, 0)]);
+        let mut frontier = VecDeque::from([cell]);
+        while let Some(pos) = frontier.pop_front() {
+            let distance = distances[&pos];
+            if distance == max_distance {
+                continue;
+            }
+            for neighbor in &neighbors[pos] {
+                if distances.contains_key(&neighbor.pos) {
+                    continue;
+                }
+                distances.insert(neighbor.pos, distance + 1);
+                frontier.push_back(neighbor.pos);
+                for (zone_distance, tiles) in zones {
+                    if distance < *zone_distance {
+                        self.remove(neighbor.pos, tiles, neighbors, None)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Remove a fixed cell's tile from every other cell in its row and column, if it is unique per line
+    fn clear_lines(
+        &mut self,
+        cell: (usize, usize),
+        rules: &Rules,
+        neighbors: &Array2<Vec<Neighbour>>,
+    ) -> Result<()> {
+        if self.domain_sizes[cell] != 1 {
+            return Ok(());
+        }
+        let Some(tile) = self.domains[cell].ones().next() else {
+            return Ok(());
+        };
+        if !rules.is_line_unique(tile) {
+            return Ok(());
+        }
+
+        let (height, width) = self.domains.dim();
+        let mut tiles = FixedBitSet::with_capacity(self.domains[cell].len());
+        tiles.insert(tile);
+        let column = (0..height).map(|y| (y, cell.1));
+        let row = (0..width).map(|x| (cell.0, x));
+        for pos in column.chain(row).filter(|&pos| pos != cell) {
+            self.remove(pos, &tiles, neighbors, None)?;
+        }
+        Ok(())
+    }
+
+    // Number of cells fixed to a tile running in a direction from a cell, up to one more than the limit,
+    // and the first cell past the end of the run
+    fn run(
+        &self,
+        cell: (usize, usize),
+        dir: Direction,
+        tile: usize,
+        max_run: usize,
+        neighbors: &Array2<Vec<Neighbour>>,
+    ) -> (usize, Option<(usize, usize)>) {
+        let mut length = 0;
+        let mut current = cell;
+        while let Some(next) = neighbors[current].iter().find(|n| n.dir == dir) {
+            if length > max_run
+                || self.domain_sizes[next.pos] != 1
+                || !self.domains[next.pos].contains(tile)
+            {
+                return (length, Some(next.pos));
+            }
+            length += 1;
+            current = next.pos;
+        }
+        (length, None)
+    }
+
+    // Count the cells fixed to, and able to take, the tiles of each category with a quota
+    fn start_tallies(&mut self, rules: &Rules) {
+        let num_cells = self
+            .is_ignore
+            .iter()
+            .filter(|&&is_ignore| !is_ignore)
+            .count();
+        let quotas: Vec<_> = rules
+            .quotas()
+            .map(|(category, quota)| {
+                let (min, max) = quota.bounds(num_cells);
+                (rules.category_tiles(category), min, max)
+            })
+            .collect();
+        let num_quotas = quotas.len();
+        self.tallies = Some(Tallies {
+            quotas,
+            fixed: vec![0; num_quotas],
+            possible: vec![0; num_quotas],
+            swept: vec![false; num_quotas],
+        });
+        let (height, width) = self.size();
+        for y in 0..height {
+            for x in 0..width {
+                self.tally((y, x), true);
+            }
+        }
+    }
+
+    // Add a cell to the counts of the categories it is fixed to or can take, or take it away
+    fn tally(&mut self, cell: (usize, usize), add: bool) {
+        let Some(tallies) = self.tallies.as_mut() else {
+            return;
+        };
+        if self.is_ignore[cell] {
+            return;
+        }
+        let domain = &self.domains[cell];
+        for (n, (tiles, _, _)) in tallies.quotas.iter().enumerate() {
+            if domain.is_disjoint(tiles) {
+                continue;
+            }
+            let is_fixed = self.domain_sizes[cell] == 1;
+            if add {
+                tallies.possible[n] += 1;
+                tallies.fixed[n] += usize::from(is_fixed);
+            } else {
+                tallies.possible[n] -= 1;
+                tallies.fixed[n] -= usize::from(is_fixed);
+            }
+        }
+    }
+
+    // Whether some category is fixed to more cells than its quota allows, or can no longer take as many as it needs
+    fn is_over_quota(&self) -> bool {
+        self.tallies.as_ref().is_some_and(|tallies| {
+            tallies
+                .quotas
+                .iter()
+                .enumerate()
+                .any(|(n, &(_, min, max))| tallies.fixed[n] > max || tallies.possible[n] < min)
+        })
+    }
+
+    // Remove a category's tiles from the cells not fixed to them once it has all the cells it may have, and
+    // limit the cells which can take its tiles to them once it needs every one of those cells.
+    // Returns whether any options were removed.
+    fn enforce_quotas(&mut self, neighbors: &Array2<Vec<Neighbour>>) -> Result<bool> {
+        let Some(tallies) = self.tallies.as_mut() else {
+            return Ok(false);
+        };
+        // Tiles of each category to sweep, and whether they are removed from the cells not fixed to them, rather
+        // than the cells which can take them being limited to them
+        let mut sweeps = Vec::new();
+        for (n, (tiles, min, max)) in tallies.quotas.iter().enumerate() {
+            if tallies.swept[n] {
+                continue;
+            }
+            if tallies.fixed[n] == *max && tallies.possible[n] > *max {
+                sweeps.push((tiles.clone(), true));
+            } else if tallies.possible[n] == *min && tallies.fixed[n] < *min {
+                sweeps.push((tiles.clone(), false));
+            } else {
+                continue;
+            }
+            tallies.swept[n] = true;
+        }
+
+        let mut changed = false;
+        let (height, width) = self.size();
+        for (tiles, is_full) in sweeps {
+            let mut others = tiles.clone();
+            others.toggle_range(..);
+            for y in 0..height {
+                for x in 0..width {
+                    let pos = (y, x);
+                    if self.is_ignore[pos] || self.domains[pos].is_disjoint(&tiles) {
+                        continue;
+                    }
+                    if !is_full {
+                        changed |= self.remove(pos, &others, neighbors, None)?;
+                    } else if self.domain_sizes[pos] > 1 {
+                        changed |= self.remove(pos, &tiles, neighbors, None)?;
+                    }
+                }
+            }
+        }
+        Ok(changed)
+    }
+
+    fn remove(
+        &mut self,
+        cell: (usize, usize),
+        tiles: &FixedBitSet,
+        neighbors: &Array2<Vec<Neighbour>>,
+        source: Option<((usize, usize), Direction)>,
+    ) -> Result<bool> {
+        let removed = self.domains[cell].intersection_count(tiles);
+        if removed == 0 {
+            return Ok(false);
+        }
+        self.save(cell);
+        self.tally(cell, false);
+        // Neighbours which may have lost support for an option, by their index; all of them without counts
+        let mut exhausted = None;
+        if let Some(counts) = self.counts.as_mut() {
+            let mut lost = self.domains[cell].clone();
+            lost.intersect_with(tiles);
+            exhausted = Some(counts.remove(cell, &lost, &self.domains));
+        }
+        self.domains[cell].difference_with(tiles);
+        self.domain_sizes[cell] -= removed;
+        self.tally(cell, true);
+        self.selectors[self.groups[cell]].update(
+            cell,
+            self.domain_sizes[cell],
+            &self.domains[cell],
+        );
+
+        if self.domain_sizes[cell] == 0 {
+            trace_event!(debug, cell = ?cell, "contradiction");
+            return Err(WfcError::Contradiction {
+                cell,
+                direction: source.map(|(_, dir)| dir),
+            }
+            .into());
+        }
+        if self.is_over_quota() {
+            trace_event!(debug, cell = ?cell, "quota contradiction");
+            return Err(WfcError::Contradiction {
+                cell,
+                direction: None,
+            }
+            .into());
+        }
+        if self.domain_sizes[cell] == 1 {
+            self.fixed.push(cell);
+        }
+
+        if self.consistency == Consistency::ForwardChecking && self.domain_sizes[cell] > 1 {
+            return Ok(true);
+        }
+
+        // The arc the removal was made across already supports every remaining option, though the same
+        // neighbour may lie in another direction too, as on narrow wrapped maps
+        for (n, neighbor) in neighbors[cell].iter().enumerate() {
+            if exhausted
+                .as_ref()
+                .is_none_or(|exhausted| exhausted.contains(n))
+                && source != Some((neighbor.pos, neighbor.dir))
+            {
+                self.queue.push_back((neighbor.pos, cell, neighbor.opp_dir));
+            }
+        }
+        Ok(true)
+    }
+}
@@ -0,0 +1,516 @@
+use anyhow::{Result, anyhow, bail};
+use ndarray::Array2;
+use rand::SeedableRng;
+use rand_pcg::Pcg64;
+use rayon::prelude::*;
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc,
+        mpsc::{self, Receiver},
+    },
+};
+
+use crate::{Cell, ChunkStore, Map, Rules, WaveFunction, WorldVersion};
+
+const CHUNK_SIZE: (usize, usize) = (32, 32); // Height and width of each chunk
+const VIEW_RADIUS: usize = 2; // Chunks around each focus point which must be generated
+const PREFETCH_RADIUS: usize = 1; // Extra ring of chunks generated ahead of need
+const BUDGET: usize = 4; // Chunks generated per tick
+const MAX_RETRIES: usize = 3; // Extra attempts at a chunk with new seeds
+
+// A prefetch chunk generated in the background
+type Prefetched = ((i64, i64), Result<Map>);
+
+/// Generates an unbounded world chunk by chunk as it is explored, nearest the focus points (such as player
+/// positions) first.
+/// Each call to `tick` starts at most a budget of chunks, taking every chunk within the view radius of a
+/// focus point before those in the prefetch ring beyond it, so calling it once a frame keeps the world ready
+/// ahead of the players without stalling.
+/// View chunks are generated before `tick` returns, those which do not touch in parallel. Prefetch chunks are
+/// generated in the background on the rayon pool, and are collected by the next call to `tick`, which waits
+/// for any still running. Each chunk is collapsed along with a one-cell ring of the chunks around it, and is
+/// seeded from the world seed and its coordinates, so the same sequence of focus points and ticks always
+/// gives the same world, however long the background work takes.
+/// With a `ChunkStore`, chunks are saved as they are generated and loaded rather than regenerated.
+pub struct WorldGenerator<W> {
+    algorithm: Arc<W>,
+    rules: Arc<Rules>,
+    seed: u64,
+    chunk_size: (usize, usize),
+    view_radius: usize,
+    prefetch_radius: usize,
+    budget: usize,
+    max_retries: usize,
+    focus_points: Vec<(i64, i64)>,
+    chunks: HashMap<(i64, i64), Map>,
+    store: Option<Box<dyn ChunkStore>>,
+    // Prefetch chunks being generated in the background, in the order they were started
+    prefetching: Vec<(i64, i64)>,
+    prefetched: Option<Receiver<Prefetched>>,
+}
+
+impl WorldGenerator<()> {
+    #[must_use]
+    pub fn builder() -> WorldGeneratorBuilder {
+        WorldGeneratorBuilder::default()
+    }
+}
+
+/// Builder for configuring a `WorldGenerator`.
+pub struct WorldGeneratorBuilder {
+    chunk_size: (usize, usize),
+    view_radius: usize,
+    prefetch_radius: usize,
+    budget: usize,
+    max_retries: usize,
+}
+
+impl Default for WorldGeneratorBuilder {
+    fn default() -> Self {
+        Self {
+            chunk_size: CHUNK_SIZE,
+            view_radius: VIEW_RADIUS,
+            prefetch_radius: PREFETCH_RADIUS,
+            budget: BUDGET,
+            max_retries: MAX_RETRIES,
+        }
+    }
+}
+
+impl WorldGeneratorBuilder {
+    /// Height and width of each chunk.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either side is zero, or too large for cell positions to address.
+    #[must_use]
+    pub fn chunk_size(mut self, chunk_size: (usize, usize)) -> Self {
+        assert!(
+            chunk_size.0 > 0 && chunk_size.1 > 0,
+            "Chunk size must be greater than zero"
+        );
+        assert!(
+            i64::try_from(chunk_size.0).is_ok() && i64::try_from(chunk_size.1).is_ok(),
+            "Chunk size must fit in an i64"
+        );
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Distance, in chunks, around each focus point's chunk within which every chunk is needed.
+    #[must_use]
+    pub fn view_radius(mut self, view_radius: usize) -> Self {
+        self.view_radius = view_radius;
+        self
+    }
+
+    /// Width, in chunks, of the ring beyond the view radius generated ahead of need.
+    #[must_use]
+    pub fn prefetch_radius(mut self, prefetch_radius: usize) -> Self {
+        self.prefetch_radius = prefetch_radius;
+        self
+    }
+
+    /// Most chunks started by each call to `tick`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `budget` is zero.
+    #[must_use]
+    pub fn budget(mut self, budget: usize) -> Self {
+        assert!(budget > 0, "Budget must be greater than zero");
+        self.budget = budget;
+        self
+    }
+
+    /// Number of extra attempts, each with a new seed, before a chunk fails.
+    #[must_use]
+    pub fn max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    pub fn build<W: WaveFunction + Send + Sync + 'static>(
+        self,
+        algorithm: W,
+        rules: &Rules,
+        seed: u64,
+    ) -> WorldGenerator<W> {
+        WorldGenerator {
+            algorithm: Arc::new(algorithm),
+            rules: Arc::new(rules.clone()),
+            seed,
+            chunk_size: self.chunk_size,
+            view_radius: self.view_radius,
+            prefetch_radius: self.prefetch_radius,
+            budget: self.budget,
+            max_retries: self.max_retries,
+            focus_points: Vec::new(),
+            chunks: HashMap::new(),
+            store: None,
+            prefetching: Vec::new(),
+            prefetched: None,
+        }
+    }
+}
+
+impl<W: WaveFunction + Send + Sync + 'static> WorldGenerator<W> {
+    /// Set the cell positions, as (row, column), around which the world is generated.
+    pub fn set_focus_points(&mut self, focus_points: &[(i64, i64)]) {
+        self.focus_points = focus_points.to_vec();
+    }
+
+    /// Persist chunks in a store, keyed to this world's seed, chunk size and the hash of its tileset.
+    /// A store holding chunks of another seed, chunk size or tileset is cleared.
+    /// Returns whether the store's existing chunks were kept.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the store cannot be read or reset.
+    pub fn set_store(&mut self, mut store: Box<dyn ChunkStore>, tileset_hash: u64) -> Result<bool> {
+        let kept = store.open(WorldVersion {
+            seed: self.seed,
+            tileset_hash,
+            chunk_size: self.chunk_size,
+        })?;
+        self.store = Some(store);
+        Ok(kept)
+    }
+
+    #[must_use]
+    pub fn focus_points(&self) -> &[(i64, i64)] {
+        &self.focus_points
+    }
+
+    #[must_use]
+    pub fn chunk_size(&self) -> (usize, usize) {
+        self.chunk_size
+    }
+
+    /// Coordinates of the chunk containing a cell position.
+    #[must_use]
+    pub fn chunk_of(&self, pos: (i64, i64)) -> (i64, i64) {
+        (
+            pos.0.div_euclid(signed(self.chunk_size.0)),
+            pos.1.div_euclid(signed(self.chunk_size.1)),
+        )
+    }
+
+    /// A generated chunk, if it has been generated.
+    #[must_use]
+    pub fn chunk(&self, chunk: (i64, i64)) -> Option<&Map> {
+        self.chunks.get(&chunk)
+    }
+
+    /// Number of chunks generated so far.
+    #[must_use]
+    pub fn num_chunks(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// The cell at a position, if its chunk has been generated.
+    #[must_use]
+    pub fn get(&self, pos: (i64, i64)) -> Option<Cell> {
+        let chunk = self.chunk_of(pos);
+        let local = (
+            within(pos.0, self.chunk_size.0),
+            within(pos.1, self.chunk_size.1),
+        );
+        self.chunks.get(&chunk).map(|map| map[local])
+    }
+
+    /// Chunks within the view or prefetch radius of a focus point which have not been generated or started,
+    /// in the order they will be started: nearest a focus point first, and view chunks before prefetch chunks.
+    #[must_use]
+    pub fn pending(&self) -> Vec<(i64, i64)> {
+        let reach = signed(self.view_radius + self.prefetch_radius);
+        let focus_chunks: Vec<(i64, i64)> = self
+            .focus_points
+            .iter()
+            .map(|&pos| self.chunk_of(pos))
+            .collect();
+
+        let mut pending: Vec<(i64, (i64, i64))> = Vec::new();
+        for &(focus_y, focus_x) in &focus_chunks {
+            for chunk_y in focus_y - reach..=focus_y + reach {
+                for chunk_x in focus_x - reach..=focus_x + reach {
+                    let chunk = (chunk_y, chunk_x);
+                    if !self.chunks.contains_key(&chunk) && !self.prefetching.contains(&chunk) {
+                        pending.push((self.priority(&focus_chunks, chunk), chunk));
+                    }
+                }
+            }
+        }
+        pending.sort_unstable();
+        pending.dedup();
+        pending.into_iter().map(|(_, chunk)| chunk).collect()
+    }
+
+    /// Whether every chunk within the view radius of each focus point has been generated.
+    #[must_use]
+    pub fn is_ready(&self) -> bool {
+        let radius = signed(self.view_radius);
+        self.focus_points.iter().all(|&pos| {
+            let (focus_y, focus_x) = self.chunk_of(pos);
+            (focus_y - radius..=focus_y + radius).all(|chunk_y| {
+                (focus_x - radius..=focus_x + radius)
+                    .all(|chunk_x| self.chunks.contains_key(&(chunk_y, chunk_x)))
+            })
+        })
+    }
+
+    /// Collect the prefetch chunks started by the last tick, then start up to the budget of pending chunks,
+    /// returning the chunks generated in order.
+    /// View chunks are generated before returning, and prefetch chunks are left to generate in the background.
+    /// Chunks found in the store are loaded instead, and count towards the budget.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a chunk cannot be generated within its retries, or the store cannot be read or written.
+    pub fn tick(&mut self) -> Result<Vec<(i64, i64)>> {
+        let mut generated = self.finish_prefetch()?;
+        let mut batch: Vec<(i64, i64)> = self.pending().into_iter().take(self.budget).collect();
+
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("world_tick", num_chunks = batch.len()).entered();
+
+        if let Some(store) = &self.store {
+            let mut unstored = Vec::with_capacity(batch.len());
+            for chunk in batch {
+                match store.load(chunk)? {
+                    Some(map) if map.size() == self.chunk_size => {
+                        self.chunks.insert(chunk, map);
+                        generated.push(chunk);
+                    }
+                    Some(_) => bail!(
+                        "Stored chunk ({}, {}) does not match the chunk size",
+                        chunk.0,
+                        chunk.1
+                    ),
+                    None => unstored.push(chunk),
+                }
+            }
+            batch = unstored;
+        }
+
+        let radius = signed(self.view_radius);
+        let focus_chunks: Vec<(i64, i64)> = self
+            .focus_points
+            .iter()
+            .map(|&pos| self.chunk_of(pos))
+            .collect();
+        let (mut batch, prefetch): (Vec<_>, Vec<_>) = batch.into_iter().partition(|&chunk| {
+            focus_chunks
+                .iter()
+                .any(|&focus| ring(chunk, focus) <= radius)
+        });
+
+        while !batch.is_empty() {
+            // Chunks which touch must be generated one after the other, so each sees the other's edge
+            let mut wave: Vec<(i64, i64)> = Vec::new();
+            batch.retain(|&chunk| {
+                if wave.iter().any(|&other| touches(chunk, other)) {
+                    true
+                } else {
+                    wave.push(chunk);
+                    false
+                }
+            });
+
+            let regions: Vec<Map> = wave.iter().map(|&chunk| self.region(chunk)).collect();
+            let (algorithm, rules) = (self.algorithm.as_ref(), self.rules.as_ref());
+            let (seed, max_retries) = (self.seed, self.max_retries);
+            let results: Vec<Result<Map>> = wave
+                .par_iter()
+                .zip(&regions)
+                .map(|(&chunk, region)| {
+                    collapse_chunk(algorithm, rules, region, seed, chunk, max_retries)
+                })
+                .collect();
+            for (chunk, result) in wave.into_iter().zip(results) {
+                self.insert(chunk, result?)?;
+                generated.push(chunk);
+            }
+        }
+
+        self.start_prefetch(&prefetch);
+        Ok(generated)
+    }
+
+    /// Tick until every chunk within the view radius of each focus point has been generated.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a chunk cannot be generated within its retries, or the store cannot be read or written.
+    pub fn generate_view(&mut self) -> Result<()> {
+        while !self.is_ready() {
+            self.tick()?;
+        }
+        Ok(())
+    }
+
+    // Sort key of a chunk: view chunks first, then by squared distance to the nearest focus chunk
+    fn priority(&self, focus_chunks: &[(i64, i64)], chunk: (i64, i64)) -> i64 {
+        let (ring, distance) = focus_chunks
+            .iter()
+            .map(|&focus| {
+                let (dy, dx) = (chunk.0 - focus.0, chunk.1 - focus.1);
+                (ring(chunk, focus), dy * dy + dx * dx)
+            })
+            .min_by_key(|&(_, distance)| distance)
+            .unwrap_or_default();
+        let is_prefetch = i64::from(ring > signed(self.view_radius));
+        is_prefetch * i64::MAX / 2 + distance
+    }
+
+    // Template of a chunk along with a one-cell ring of the chunks generated around it
+    fn region(&self, chunk: (i64, i64)) -> Map {
+        let (height, width) = self.chunk_size;
+        let top = chunk.0 * signed(height) - 1;
+        let left = chunk.1 * signed(width) - 1;
+        Map::new(Array2::from_shape_fn((height + 2, width + 2), |(y, x)| {
+            if (1..=height).contains(&y) && (1..=width).contains(&x) {
+                Cell::Wildcard
+            } else {
+                self.get((top + signed(y), left + signed(x)))
+                    .unwrap_or(Cell::Wildcard)
+            }
+        }))
+    }
+
+    // Keep a generated chunk, saving it to the store if there is one
+    fn insert(&mut self, chunk: (i64, i64), map: Map) -> Result<()> {
+        if let Some(store) = &mut self.store {
+            store.save(chunk, &map)?;
+        }
+        self.chunks.insert(chunk, map);
+        Ok(())
+    }
+
+    // Generate prefetch chunks in the background, skipping those which touch an earlier one, as they would
+    // not see its edge; they are left pending for a later tick
+    fn start_prefetch(&mut self, chunks: &[(i64, i64)]) {
+        let (sender, receiver) = mpsc::channel();
+        for &chunk in chunks {
+            if self.prefetching.iter().any(|&other| touches(chunk, other)) {
+                continue;
+            }
+            let region = self.region(chunk);
+            let (seed, max_retries) = (self.seed, self.max_retries);
+            let (algorithm, rules, sender) = (
+                Arc::clone(&self.algorithm),
+                Arc::clone(&self.rules),
+                sender.clone(),
+            );
+            rayon::spawn(move || {
+                let result = collapse_chunk(
+                    algorithm.as_ref(),
+                    &rules,
+                    &region,
+                    seed,
+                    chunk,
+                    max_retries,
+                );
+                // Nothing is waiting for the chunk if the generator has been dropped
+                let _ = sender.send((chunk, result));
+            });
+            self.prefetching.push(chunk);
+        }
+        self.prefetched = Some(receiver);
+    }
+
+    // Wait for the prefetch chunks started by the last tick, keeping them in the order they were started
+    fn finish_prefetch(&mut self) -> Result<Vec<(i64, i64)>> {
+        let Some(receiver) = self.prefetched.take() else {
+            return Ok(Vec::new());
+        };
+        let chunks = std::mem::take(&mut self.prefetching);
+        let mut results = HashMap::with_capacity(chunks.len());
+        for _ in 0..chunks.len() {
+            let (chunk, result) = receiver.recv()?;
+            results.insert(chunk, result);
+        }
+        for &chunk in &chunks {
+            let map = results
+                .remove(&chunk)
+                .expect("Every prefetch chunk sends one result")?;
+            self.insert(chunk, map)?;
+        }
+        Ok(chunks)
+    }
+}
+
+// Collapse one chunk, constrained by a one-cell ring of the chunks generated around it
+fn collapse_chunk(
+    algorithm: &impl WaveFunction,
+    rules: &Rules,
+    region: &Map,
+    seed: u64,
+    chunk: (i64, i64),
+    max_retries: usize,
+) -> Result<Map> {
+    let (height, width) = (region.size().0 - 2, region.size().1 - 2);
+    let mut last_error = None;
+    for attempt in 0..=max_retries {
+        let mut rng = Pcg64::seed_from_u64(chunk_seed(seed, chunk, attempt));
+        match algorithm.collapse(region, rules, &mut rng) {
+            Ok(collapsed) => {
+                return Ok(Map::new(Array2::from_shape_fn(
+                    (height, width),
+                    |(y, x)| collapsed[(y + 1, x + 1)],
+                )));
+            }
+            Err(e) => {
+                trace_event!(debug, chunk = ?chunk, attempt, error = %e, "world chunk failed");
+                last_error = Some(e);
+            }
+        }
+    }
+
+    // The last failure is kept as the source, so callers can still downcast it to a `WfcError`
+    let message = format!(
+        "Failed to generate world chunk ({}, {}) after {max_retries} retries",
+        chunk.0, chunk.1
+    );
+    Err(match last_error {
+        Some(error) => error.context(message),
+        None => anyhow!(message),
+    })
+}
+
+// Chunk sizes, radii and cell offsets, which the builder keeps small enough to sign
+fn signed(value: usize) -> i64 {
+    i64::try_from(value).expect("Value fits in an i64")
+}
+
+// Position of a cell within its chunk along one axis
+fn within(value: i64, size: usize) -> usize {
+    usize::try_from(value.rem_euclid(signed(size))).expect("Remainder is less than the chunk size")
+}
+
+// Distance between two chunks in whole rings
+fn ring(a: (i64, i64), b: (i64, i64)) -> i64 {
+    (a.0 - b.0).abs().max((a.1 - b.1).abs())
+}
+
+// Whether two chunks share an edge or a corner
+fn touches(a: (i64, i64), b: (i64, i64)) -> bool {
+    (a.0 - b.0).abs() <= 1 && (a.1 - b.1).abs() <= 1
+}
+
+// Seed of an attempt at a chunk, mixed from the world seed so neighbouring chunks are uncorrelated
+fn chunk_seed(seed: u64, chunk: (i64, i64), attempt: usize) -> u64 {
+    let mut hash = seed;
+    for value in [
+        chunk.0.cast_unsigned(),
+        chunk.1.cast_unsigned(),
+        attempt as u64,
+    ] {
+        hash = (hash ^ value).wrapping_add(0x9E37_79B9_7F4A_7C15);
+        hash = (hash ^ (hash >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        hash = (hash ^ (hash >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        hash ^= hash >> 31;
+    }
+    hash
+}
@@ -0,0 +1,29 @@
+use std::fmt::{Display, Formatter};
+
+/// Capabilities of a configured algorithm, as given by `WaveFunction::info`, for tools presenting the choice of
+/// algorithm to their users.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AlgorithmInfo {
+    /// Short name of the algorithm, which is also its name in the default `AlgorithmRegistry` if registered there.
+    pub name: &'static str,
+    /// Whether the algorithm can undo earlier choices after a contradiction, rather than failing or only
+    /// resetting the cells around it.
+    pub supports_backtracking: bool,
+    /// Whether the same template, rules and seed always give the same map.
+    pub deterministic: bool,
+    /// Notes on the expected running time and when the algorithm gives up, including its configured limits.
+    pub complexity: String,
+}
+
+impl Display for AlgorithmInfo {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.name)?;
+        if self.supports_backtracking {
+            write!(f, " [backtracking]")?;
+        }
+        if !self.deterministic {
+            write!(f, " [non-deterministic]")?;
+        }
+        write!(f, ": {}", self.complexity)
+    }
+}
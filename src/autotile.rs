@@ -0,0 +1,85 @@
+use std::sync::OnceLock;
+
+// Neighbour bits, clockwise from north
+const NORTH: u8 = 1 << 0;
+const NORTH_EAST: u8 = 1 << 1;
+const EAST: u8 = 1 << 2;
+const SOUTH_EAST: u8 = 1 << 3;
+const SOUTH: u8 = 1 << 4;
+const SOUTH_WEST: u8 = 1 << 5;
+const WEST: u8 = 1 << 6;
+const NORTH_WEST: u8 = 1 << 7;
+
+/// Standard autotile variant sets, as consumed by engines such as Godot and RPG Maker.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AutotileSet {
+    /// 16 variants chosen by the four edge neighbours.
+    /// The index is the sum of north = 1, east = 2, south = 4 and west = 8 for each matching neighbour.
+    Bitmask16,
+    /// 47 variants chosen by all eight neighbours, where a corner only counts if both of its adjacent edges match.
+    /// The index is the position of the reduced blob bitmask (north = 1, then doubling clockwise to north-west = 128)
+    /// among the 47 valid blob bitmasks in ascending order.
+    Blob47,
+}
+
+impl AutotileSet {
+    #[must_use]
+    pub fn num_variants(&self) -> usize {
+        match self {
+            AutotileSet::Bitmask16 => 16,
+            AutotileSet::Blob47 => 47,
+        }
+    }
+
+    /// Variant index for a cell, given which of its neighbours match, clockwise from north.
+    #[must_use]
+    pub fn index(&self, neighbours: [bool; 8]) -> usize {
+        let mask = neighbours
+            .iter()
+            .enumerate()
+            .filter(|&(_, &matches)| matches)
+            .fold(0u8, |mask, (n, _)| mask | (1 << n));
+        match self {
+            AutotileSet::Bitmask16 => [NORTH, EAST, SOUTH, WEST]
+                .iter()
+                .enumerate()
+                .filter(|&(_, &bit)| mask & bit != 0)
+                .map(|(n, _)| 1 << n)
+                .sum(),
+            AutotileSet::Blob47 => blob_indices()[usize::from(reduce_corners(mask))],
+        }
+    }
+}
+
+// Clear each corner bit unless both of its adjacent edges are set
+fn reduce_corners(mask: u8) -> u8 {
+    let mut reduced = mask;
+    for (corner, a, b) in [
+        (NORTH_EAST, NORTH, EAST),
+        (SOUTH_EAST, SOUTH, EAST),
+        (SOUTH_WEST, SOUTH, WEST),
+        (NORTH_WEST, NORTH, WEST),
+    ] {
+        if mask & a == 0 || mask & b == 0 {
+            reduced &= !corner;
+        }
+    }
+    reduced
+}
+
+// Lookup from reduced blob bitmask to variant index
+fn blob_indices() -> &'static [usize; 256] {
+    static INDICES: OnceLock<[usize; 256]> = OnceLock::new();
+    INDICES.get_or_init(|| {
+        let mut indices = [0; 256];
+        let mut next = 0;
+        for mask in 0..=u8::MAX {
+            if reduce_corners(mask) == mask {
+                indices[usize::from(mask)] = next;
+                next += 1;
+            }
+        }
+        debug_assert_eq!(next, 47, "There must be exactly 47 blob variants");
+        indices
+    })
+}
@@ -3,19 +3,37 @@ use std::fmt::{Display, Formatter};
 
 const CELL_IGNORE: &str = "!";
 const CELL_WILDCARD: &str = "*";
+const CELL_HOLE: &str = "?";
 
 #[derive(Clone, Copy, PartialEq)]
 pub enum Cell {
     Ignore,
     Wildcard,
+    /// A soft hole, which prefers to stay empty but may be filled when leaving it empty would cause a
+    /// contradiction, such as for decorative gaps and optional structures.
+    /// A hole stays empty by taking the rules' hole tile, as set by `Rules::set_hole_tile`. Solvers decide holes
+    /// before other cells and try the hole tile first, so a hole is only filled once the search finds no map
+    /// with it empty; see `Map::filled_holes`.
+    Hole,
     Fixed(usize),
 }
 
 impl Cell {
+    /// Parse a cell as written by `Display`, or `None` if the string is not a cell.
+    #[must_use]
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            CELL_IGNORE => Some(Cell::Ignore),
+            CELL_WILDCARD => Some(Cell::Wildcard),
+            CELL_HOLE => Some(Cell::Hole),
+            _ => s.parse().ok().map(Cell::Fixed),
+        }
+    }
+
     pub fn domain(&self, num_tiles: usize) -> FixedBitSet {
         match self {
             Cell::Ignore => FixedBitSet::with_capacity(num_tiles),
-            Cell::Wildcard => {
+            Cell::Wildcard | Cell::Hole => {
                 let mut bs = FixedBitSet::with_capacity(num_tiles);
                 bs.insert_range(..);
                 bs
@@ -34,6 +52,7 @@ impl Display for Cell {
         match self {
             Cell::Ignore => write!(f, "{}", CELL_IGNORE),
             Cell::Wildcard => write!(f, "{}", CELL_WILDCARD),
+            Cell::Hole => write!(f, "{CELL_HOLE}"),
             Cell::Fixed(index) => write!(f, "{}", index),
         }
     }
@@ -41,16 +60,6 @@ impl Display for Cell {
 
 impl From<&str> for Cell {
     fn from(s: &str) -> Self {
-        match s {
-            "!" => Cell::Ignore,
-            "*" => Cell::Wildcard,
-            _ => {
-                if let Ok(index) = s.parse::<usize>() {
-                    Cell::Fixed(index)
-                } else {
-                    panic!("Invalid cell string: {}", s)
-                }
-            }
-        }
+        Cell::parse(s).unwrap_or_else(|| panic!("Invalid cell string: {s}"))
     }
 }
@@ -0,0 +1,377 @@
+use anyhow::{Context, Result};
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use crate::Map;
+
+const VERSION_FILENAME: &str = "version.txt";
+const CHUNK_PREFIX: &str = "chunk_"; // Start of the name of each chunk file in a directory store
+const CHUNK_EXTENSION: &str = "txt";
+
+/// Identifies the world a store's chunks were generated for. Chunks are only valid for the seed, tileset and
+/// chunk size which generated them, so a store holding another version's chunks is cleared rather than read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WorldVersion {
+    pub seed: u64,
+    /// Hash of the tileset, as given by `Tileset::content_hash`.
+    pub tileset_hash: u64,
+    /// Size of each chunk, as (height, width).
+    pub chunk_size: (usize, usize),
+}
+
+/// Persists the chunks of a `WorldGenerator`, so revisited or reloaded parts of the world are loaded
+/// instead of being regenerated.
+pub trait ChunkStore: Send + Sync {
+    /// Version of the world whose chunks are stored, or `None` if the store is new.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the store cannot be read.
+    fn version(&self) -> Result<Option<WorldVersion>>;
+
+    /// Discard every stored chunk and record the version of the world whose chunks will be stored.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the store cannot be written.
+    fn reset(&mut self, version: WorldVersion) -> Result<()>;
+
+    /// The stored chunk at the given chunk coordinates, if any.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the store cannot be read or the stored chunk is corrupt.
+    fn load(&self, chunk: (i64, i64)) -> Result<Option<Map>>;
+
+    /// Store a chunk at the given chunk coordinates, replacing any stored there before.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the store cannot be written.
+    fn save(&mut self, chunk: (i64, i64), map: &Map) -> Result<()>;
+
+    /// Prepare the store for a world, resetting it unless it already holds chunks of that version.
+    /// Returns whether the stored chunks were kept.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the store cannot be read or reset.
+    fn open(&mut self, version: WorldVersion) -> Result<bool> {
+        if self.version()? == Some(version) {
+            Ok(true)
+        } else {
+            self.reset(version)?;
+            Ok(false)
+        }
+    }
+}
+
+/// Keeps chunks in memory, for worlds which only need to outlive unloading within a session.
+#[derive(Default)]
+pub struct MemoryChunkStore {
+    version: Option<WorldVersion>,
+    chunks: HashMap<(i64, i64), Map>,
+}
+
+impl MemoryChunkStore {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ChunkStore for MemoryChunkStore {
+    fn version(&self) -> Result<Option<WorldVersion>> {
+        Ok(self.version)
+    }
+
+    fn reset(&mut self, version: WorldVersion) -> Result<()> {
+        self.version = Some(version);
+        self.chunks.clear();
+        Ok(())
+    }
+
+    fn load(&self, chunk: (i64, i64)) -> Result<Option<Map>> {
+        Ok(self.chunks.get(&chunk).cloned())
+    }
+
+    fn save(&mut self, chunk: (i64, i64), map: &Map) -> Result<()> {
+        self.chunks.insert(chunk, map.clone());
+        Ok(())
+    }
+}
+
+/// Keeps each chunk in its own file of a directory, in the plain text map format, alongside a file
+/// recording the world version.
+pub struct DirectoryChunkStore {
+    path: PathBuf,
+}
+
+impl DirectoryChunkStore {
+    /// Use a directory as a store, creating it if it does not exist.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the directory cannot be created.
+    pub fn new(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        fs::create_dir_all(&path)
+            .with_context(|| format!("Failed to create chunk directory {}", path.display()))?;
+        Ok(Self { path })
+    }
+
+    fn chunk_path(&self, chunk: (i64, i64)) -> PathBuf {
+        self.path.join(format!(
+            "{}{}_{}.{}",
+            CHUNK_PREFIX, chunk.0, chunk.1, CHUNK_EXTENSION
+        ))
+    }
+}
+
+impl ChunkStore for DirectoryChunkStore {
+    fn version(&self) -> Result<Option<WorldVersion>> {
+        let Ok(contents) = fs::read_to_string(self.path.join(VERSION_FILENAME)) else {
+            return Ok(None);
+        };
+        let mut values = contents.split_whitespace();
+        let mut next = || values.next().and_then(|value| value.parse::<u64>().ok());
+        match (next(), next(), next(), next()) {
+            (Some(seed), Some(tileset_hash), Some(height), Some(width)) => Ok(Some(WorldVersion {
+                seed,
+                tileset_hash,
+                chunk_size: (usize::try_from(height)?, usize::try_from(width)?),
+            })),
+            _ => Ok(None),
+        }
+    }
+
+    fn reset(&mut self, version: WorldVersion) -> Result<()> {
+        for entry in fs::read_dir(&self.path)? {
+            let path = entry?.path();
+            let is_chunk = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(CHUNK_PREFIX))
+                && path.extension().is_some_and(|ext| ext == CHUNK_EXTENSION);
+            if is_chunk {
+                fs::remove_file(&path)
+                    .with_context(|| format!("Failed to remove chunk {}", path.display()))?;
+            }
+        }
+        fs::write(
+            self.path.join(VERSION_FILENAME),
+            format!(
+                "{} {} {} {}\n",
+                version.seed, version.tileset_hash, version.chunk_size.0, version.chunk_size.1
+            ),
+        )?;
+        Ok(())
+    }
+
+    fn load(&self, chunk: (i64, i64)) -> Result<Option<Map>> {
+        let path = self.chunk_path(chunk);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let map_str = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read chunk {}", path.display()))?;
+        stored_chunk(chunk, &map_str).map(Some)
+    }
+
+    fn save(&mut self, chunk: (i64, i64), map: &Map) -> Result<()> {
+        let path = self.chunk_path(chunk);
+        fs::write(&path, map.to_string())
+            .with_context(|| format!("Failed to write chunk {}", path.display()))
+    }
+}
+
+// Parse a stored chunk, failing if it is corrupt
+fn stored_chunk(chunk: (i64, i64), map_str: &str) -> Result<Map> {
+    Map::parse(map_str)
+        .with_context(|| format!("Stored chunk ({}, {}) is corrupt", chunk.0, chunk.1))
+}
+
+// Database key of a chunk: its coordinates as big-endian bytes
+#[cfg(any(feature = "sled", feature = "sqlite"))]
+fn chunk_key(chunk: (i64, i64)) -> [u8; 16] {
+    let mut key = [0; 16];
+    key[..8].copy_from_slice(&chunk.0.to_be_bytes());
+    key[8..].copy_from_slice(&chunk.1.to_be_bytes());
+    key
+}
+
+/// Keeps chunks in a `sled` database, with the world version in a separate tree.
+#[cfg(feature = "sled")]
+pub struct SledChunkStore {
+    chunks: sled::Tree,
+    meta: sled::Tree,
+}
+
+#[cfg(feature = "sled")]
+impl SledChunkStore {
+    /// Use a database as a store, opening its trees of chunks and metadata.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the trees cannot be opened.
+    pub fn new(db: &sled::Db) -> Result<Self> {
+        Ok(Self {
+            chunks: db.open_tree("chunks")?,
+            meta: db.open_tree("meta")?,
+        })
+    }
+}
+
+#[cfg(feature = "sled")]
+impl ChunkStore for SledChunkStore {
+    fn version(&self) -> Result<Option<WorldVersion>> {
+        let Some(bytes) = self.meta.get("version")? else {
+            return Ok(None);
+        };
+        let Ok(bytes) = <[u8; 32]>::try_from(bytes.as_ref()) else {
+            return Ok(None);
+        };
+        let value = |index: usize| -> Result<u64> {
+            Ok(u64::from_be_bytes(
+                bytes[8 * index..8 * (index + 1)].try_into()?,
+            ))
+        };
+        Ok(Some(WorldVersion {
+            seed: value(0)?,
+            tileset_hash: value(1)?,
+            chunk_size: (usize::try_from(value(2)?)?, usize::try_from(value(3)?)?),
+        }))
+    }
+
+    fn reset(&mut self, version: WorldVersion) -> Result<()> {
+        self.chunks.clear()?;
+        let mut bytes = [0; 32];
+        for (index, value) in [
+            version.seed,
+            version.tileset_hash,
+            u64::try_from(version.chunk_size.0)?,
+            u64::try_from(version.chunk_size.1)?,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            bytes[8 * index..8 * (index + 1)].copy_from_slice(&value.to_be_bytes());
+        }
+        self.meta.insert("version", &bytes)?;
+        self.meta.flush()?;
+        Ok(())
+    }
+
+    fn load(&self, chunk: (i64, i64)) -> Result<Option<Map>> {
+        let Some(bytes) = self.chunks.get(chunk_key(chunk))? else {
+            return Ok(None);
+        };
+        stored_chunk(chunk, std::str::from_utf8(&bytes)?).map(Some)
+    }
+
+    fn save(&mut self, chunk: (i64, i64), map: &Map) -> Result<()> {
+        self.chunks
+            .insert(chunk_key(chunk), map.to_string().as_bytes())?;
+        Ok(())
+    }
+}
+
+/// Keeps chunks in a table of an `SQLite` database.
+#[cfg(feature = "sqlite")]
+pub struct SqliteChunkStore {
+    connection: std::sync::Mutex<rusqlite::Connection>,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteChunkStore {
+    /// Use an open connection as a store, creating its tables if they do not exist.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the tables cannot be created.
+    pub fn new(connection: rusqlite::Connection) -> Result<Self> {
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS chunks (key BLOB PRIMARY KEY, map TEXT NOT NULL);
+             CREATE TABLE IF NOT EXISTS world_version (
+                 seed BLOB NOT NULL,
+                 tileset_hash BLOB NOT NULL,
+                 chunk_height INTEGER NOT NULL,
+                 chunk_width INTEGER NOT NULL
+             );",
+        )?;
+        Ok(Self {
+            connection: std::sync::Mutex::new(connection),
+        })
+    }
+
+    fn connection(&self) -> std::sync::MutexGuard<'_, rusqlite::Connection> {
+        self.connection
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl ChunkStore for SqliteChunkStore {
+    fn version(&self) -> Result<Option<WorldVersion>> {
+        use rusqlite::OptionalExtension;
+
+        let version = self
+            .connection()
+            .query_row(
+                "SELECT seed, tileset_hash, chunk_height, chunk_width FROM world_version",
+                [],
+                |row| {
+                    Ok(WorldVersion {
+                        seed: u64::from_be_bytes(row.get(0)?),
+                        tileset_hash: u64::from_be_bytes(row.get(1)?),
+                        chunk_size: (row.get(2)?, row.get(3)?),
+                    })
+                },
+            )
+            .optional()?;
+        Ok(version)
+    }
+
+    fn reset(&mut self, version: WorldVersion) -> Result<()> {
+        let mut connection = self.connection();
+        let transaction = connection.transaction()?;
+        transaction.execute("DELETE FROM chunks", [])?;
+        transaction.execute("DELETE FROM world_version", [])?;
+        transaction.execute(
+            "INSERT INTO world_version (seed, tileset_hash, chunk_height, chunk_width)
+             VALUES (?1, ?2, ?3, ?4)",
+            (
+                version.seed.to_be_bytes(),
+                version.tileset_hash.to_be_bytes(),
+                version.chunk_size.0,
+                version.chunk_size.1,
+            ),
+        )?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn load(&self, chunk: (i64, i64)) -> Result<Option<Map>> {
+        use rusqlite::OptionalExtension;
+
+        let map_str: Option<String> = self
+            .connection()
+            .query_row(
+                "SELECT map FROM chunks WHERE key = ?1",
+                [chunk_key(chunk)],
+                |row| row.get(0),
+            )
+            .optional()?;
+        map_str
+            .map(|map_str| stored_chunk(chunk, &map_str))
+            .transpose()
+    }
+
+    fn save(&mut self, chunk: (i64, i64), map: &Map) -> Result<()> {
+        self.connection().execute(
+            "INSERT OR REPLACE INTO chunks (key, map) VALUES (?1, ?2)",
+            (chunk_key(chunk), map.to_string()),
+        )?;
+        Ok(())
+    }
+}
@@ -0,0 +1,137 @@
+use anyhow::{Result, bail};
+use std::{
+    collections::HashMap,
+    fmt::{Display, Formatter},
+};
+
+use crate::{Cell, Map, Rules};
+
+/// Measures of how alike two maps are, each from zero (nothing in common) to one (identical).
+#[derive(Clone, Copy, Debug)]
+pub struct MapComparison {
+    /// Fraction of cells fixed in both maps which hold the same tile.
+    pub cell_agreement: f64,
+    /// Fraction of cells fixed in both maps whose tiles share a category.
+    pub category_agreement: f64,
+    /// Similarity of the distributions of 2x2 tile patterns, as one minus their total variation distance.
+    pub pattern_similarity_2x2: f64,
+    /// Similarity of the distributions of 3x3 tile patterns, as one minus their total variation distance.
+    pub pattern_similarity_3x3: f64,
+}
+
+impl Display for MapComparison {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        writeln!(f, "Cells        : {:.1}%", 100.0 * self.cell_agreement)?;
+        writeln!(f, "Categories   : {:.1}%", 100.0 * self.category_agreement)?;
+        writeln!(
+            f,
+            "2x2 patterns : {:.1}%",
+            100.0 * self.pattern_similarity_2x2
+        )?;
+        writeln!(
+            f,
+            "3x3 patterns : {:.1}%",
+            100.0 * self.pattern_similarity_3x3
+        )
+    }
+}
+
+/// Compare two maps of the same size, such as outputs generated before and after editing rules or weights.
+/// Cell and category agreement compare the maps position by position, using the categories of `rules`,
+/// while the pattern similarities compare how often each local arrangement of tiles occurs anywhere in them.
+/// Wildcard and ignored cells are left out of every measure.
+///
+/// # Errors
+///
+/// Fails if the maps differ in size.
+pub fn compare(map_a: &Map, map_b: &Map, rules: &Rules) -> Result<MapComparison> {
+    if map_a.size() != map_b.size() {
+        bail!(
+            "Maps must be the same size to compare, found {:?} and {:?}",
+            map_a.size(),
+            map_b.size()
+        );
+    }
+    let (height, width) = map_a.size();
+    let category = |tile: usize| rules.categories().get(tile).copied();
+
+    let mut num_compared: usize = 0;
+    let mut num_same_tile = 0;
+    let mut num_same_category = 0;
+    for y in 0..height {
+        for x in 0..width {
+            if let (Cell::Fixed(a), Cell::Fixed(b)) = (map_a[(y, x)], map_b[(y, x)]) {
+                num_compared += 1;
+                if a == b {
+                    num_same_tile += 1;
+                }
+                if a == b || category(a).is_some() && category(a) == category(b) {
+                    num_same_category += 1;
+                }
+            }
+        }
+    }
+    let fraction = |count: usize| {
+        if num_compared == 0 {
+            1.0
+        } else {
+            count as f64 / num_compared as f64
+        }
+    };
+
+    Ok(MapComparison {
+        cell_agreement: fraction(num_same_tile),
+        category_agreement: fraction(num_same_category),
+        pattern_similarity_2x2: pattern_similarity(map_a, map_b, 2),
+        pattern_similarity_3x3: pattern_similarity(map_a, map_b, 3),
+    })
+}
+
+// One minus the total variation distance between the distributions of fully fixed k x k windows
+fn pattern_similarity(map_a: &Map, map_b: &Map, k: usize) -> f64 {
+    let counts_a = pattern_counts(map_a, k);
+    let counts_b = pattern_counts(map_b, k);
+    let total_a: usize = counts_a.values().sum();
+    let total_b: usize = counts_b.values().sum();
+    match (total_a, total_b) {
+        (0, 0) => return 1.0,
+        (0, _) | (_, 0) => return 0.0,
+        _ => {}
+    }
+
+    let mut distance = 0.0;
+    for (pattern, &count) in &counts_a {
+        let other_count = counts_b.get(pattern).copied().unwrap_or(0);
+        distance += (count as f64 / total_a as f64 - other_count as f64 / total_b as f64).abs();
+    }
+    for (pattern, &count) in &counts_b {
+        if !counts_a.contains_key(pattern) {
+            distance += count as f64 / total_b as f64;
+        }
+    }
+    1.0 - distance / 2.0
+}
+
+// Number of times each k x k arrangement of fixed tiles occurs, skipping windows containing other cells
+fn pattern_counts(map: &Map, k: usize) -> HashMap<Vec<usize>, usize> {
+    let (height, width) = map.size();
+    let mut counts = HashMap::new();
+    if height < k || width < k {
+        return counts;
+    }
+    for y in 0..=height - k {
+        for x in 0..=width - k {
+            let pattern: Option<Vec<usize>> = (y..y + k)
+                .flat_map(|wy| (x..x + k).map(move |wx| (wy, wx)))
+                .map(|pos| match map[pos] {
+                    Cell::Fixed(tile) => Some(tile),
+                    Cell::Wildcard | Cell::Hole | Cell::Ignore => None,
+                })
+                .collect();
+            if let Some(pattern) = pattern {
+                *counts.entry(pattern).or_insert(0) += 1;
+            }
+        }
+    }
+    counts
+}
@@ -0,0 +1,146 @@
+use std::{
+    fmt::{Display, Formatter},
+    str::FromStr,
+};
+
+/// Order of the two values in a position or size given as a plain pair.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum IndexOrder {
+    /// Row first: (y, x) positions and (height, width) sizes, as `Map` indexing and `Map::size` use.
+    #[default]
+    RowMajor,
+    /// Column first: (x, y) positions and (width, height) sizes, as images and screen coordinates usually are.
+    ColumnMajor,
+}
+
+/// Size of a map with named dimensions, to convert to and from plain pairs in an explicit order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MapSize {
+    pub width: usize,
+    pub height: usize,
+}
+
+impl MapSize {
+    #[must_use]
+    pub fn from_height_width((height, width): (usize, usize)) -> Self {
+        Self { width, height }
+    }
+
+    #[must_use]
+    pub fn from_width_height((width, height): (usize, usize)) -> Self {
+        Self { width, height }
+    }
+
+    #[must_use]
+    pub fn from_pair(pair: (usize, usize), order: IndexOrder) -> Self {
+        match order {
+            IndexOrder::RowMajor => Self::from_height_width(pair),
+            IndexOrder::ColumnMajor => Self::from_width_height(pair),
+        }
+    }
+
+    /// The size as `Map::empty` and `Map::size` take and give it.
+    #[must_use]
+    pub fn height_width(&self) -> (usize, usize) {
+        (self.height, self.width)
+    }
+
+    #[must_use]
+    pub fn width_height(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    #[must_use]
+    pub fn to_pair(&self, order: IndexOrder) -> (usize, usize) {
+        match order {
+            IndexOrder::RowMajor => self.height_width(),
+            IndexOrder::ColumnMajor => self.width_height(),
+        }
+    }
+
+    #[must_use]
+    pub fn num_cells(&self) -> usize {
+        self.width * self.height
+    }
+
+    #[must_use]
+    pub fn contains(&self, coord: Coord) -> bool {
+        coord.x < self.width && coord.y < self.height
+    }
+}
+
+/// Written as `WxH`, width first, as sizes usually are on the command line.
+impl Display for MapSize {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+/// Read from `WxH`, width first, as sizes usually are on the command line.
+impl FromStr for MapSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let (width, height) = s
+            .split_once('x')
+            .ok_or_else(|| format!("Expected a size of the form WxH, found {s:?}"))?;
+        let parse = |value: &str, name: &str| {
+            value
+                .trim()
+                .parse()
+                .map_err(|e| format!("Invalid {name} {value:?}: {e}"))
+        };
+        Ok(Self {
+            width: parse(width, "width")?,
+            height: parse(height, "height")?,
+        })
+    }
+}
+
+/// Position of a cell with named axes, to convert to and from plain pairs in an explicit order.
+/// `x` counts columns from the left and `y` rows from the top, and coordinates order row by row, as cells are
+/// stored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Coord {
+    pub y: usize,
+    pub x: usize,
+}
+
+impl Coord {
+    #[must_use]
+    pub fn from_yx((y, x): (usize, usize)) -> Self {
+        Self { y, x }
+    }
+
+    #[must_use]
+    pub fn from_xy((x, y): (usize, usize)) -> Self {
+        Self { y, x }
+    }
+
+    #[must_use]
+    pub fn from_pair(pair: (usize, usize), order: IndexOrder) -> Self {
+        match order {
+            IndexOrder::RowMajor => Self::from_yx(pair),
+            IndexOrder::ColumnMajor => Self::from_xy(pair),
+        }
+    }
+
+    /// The position as `Map` indexing takes it.
+    #[must_use]
+    pub fn yx(&self) -> (usize, usize) {
+        (self.y, self.x)
+    }
+
+    #[must_use]
+    pub fn xy(&self) -> (usize, usize) {
+        (self.x, self.y)
+    }
+
+    #[must_use]
+    pub fn to_pair(&self, order: IndexOrder) -> (usize, usize) {
+        match order {
+            IndexOrder::RowMajor => self.yx(),
+            IndexOrder::ColumnMajor => self.xy(),
+        }
+    }
+}
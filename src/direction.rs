@@ -0,0 +1,140 @@
+/// One of the four directions between edge-adjacent cells of a map.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    North,
+    East,
+    South,
+    West,
+}
+
+/// Every direction, in the order of `Direction::index()`.
+pub const ALL_DIRECTIONS: [Direction; 4] = [
+    Direction::North,
+    Direction::East,
+    Direction::South,
+    Direction::West,
+];
+
+impl Direction {
+    /// Position of the direction in `ALL_DIRECTIONS`.
+    #[must_use]
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
+
+    #[must_use]
+    pub fn opposite(&self) -> Self {
+        ALL_DIRECTIONS[(self.index() + 2) % 4]
+    }
+
+    /// The neighbouring (y, x) position in this direction, or `None` if it falls outside a grid of the given size.
+    /// North is towards row zero.
+    #[must_use]
+    pub fn apply_to(&self, pos: (usize, usize), size: (usize, usize)) -> Option<(usize, usize)> {
+        let (y, x) = pos;
+        match self {
+            Self::North => (y > 0).then(|| (y - 1, x)),
+            Self::East => (x + 1 < size.1).then(|| (y, x + 1)),
+            Self::South => (y + 1 < size.0).then(|| (y + 1, x)),
+            Self::West => (x > 0).then(|| (y, x - 1)),
+        }
+    }
+}
+
+#[cfg(feature = "render")]
+impl From<Direction> for photo::Direction {
+    fn from(direction: Direction) -> Self {
+        match direction {
+            Direction::North => Self::North,
+            Direction::East => Self::East,
+            Direction::South => Self::South,
+            Direction::West => Self::West,
+        }
+    }
+}
+
+/// One of the six directions between face-adjacent cells of a volume: the four directions within a floor, plus
+/// up and down between floors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Direction3 {
+    North,
+    East,
+    South,
+    West,
+    Up,
+    Down,
+}
+
+/// Every volume direction, in the order of `Direction3::index()`.
+pub const ALL_DIRECTIONS_3: [Direction3; 6] = [
+    Direction3::North,
+    Direction3::East,
+    Direction3::South,
+    Direction3::West,
+    Direction3::Up,
+    Direction3::Down,
+];
+
+impl Direction3 {
+    /// Position of the direction in `ALL_DIRECTIONS_3`.
+    #[must_use]
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
+
+    #[must_use]
+    pub fn opposite(&self) -> Self {
+        match self {
+            Self::Up => Self::Down,
+            Self::Down => Self::Up,
+            Self::North => Self::South,
+            Self::East => Self::West,
+            Self::South => Self::North,
+            Self::West => Self::East,
+        }
+    }
+
+    /// The direction within a floor, or `None` for up and down.
+    #[must_use]
+    pub fn horizontal(&self) -> Option<Direction> {
+        match self {
+            Self::North => Some(Direction::North),
+            Self::East => Some(Direction::East),
+            Self::South => Some(Direction::South),
+            Self::West => Some(Direction::West),
+            Self::Up | Self::Down => None,
+        }
+    }
+
+    /// The neighbouring (z, y, x) position in this direction, or `None` if it falls outside a volume of the given
+    /// size. Up is away from floor zero.
+    #[must_use]
+    pub fn apply_to(
+        &self,
+        pos: (usize, usize, usize),
+        size: (usize, usize, usize),
+    ) -> Option<(usize, usize, usize)> {
+        let (z, y, x) = pos;
+        match self {
+            Self::Up => (z + 1 < size.0).then(|| (z + 1, y, x)),
+            Self::Down => (z > 0).then(|| (z - 1, y, x)),
+            _ => {
+                let direction = self.horizontal()?;
+                direction
+                    .apply_to((y, x), (size.1, size.2))
+                    .map(|(y, x)| (z, y, x))
+            }
+        }
+    }
+}
+
+impl From<Direction> for Direction3 {
+    fn from(direction: Direction) -> Self {
+        match direction {
+            Direction::North => Self::North,
+            Direction::East => Self::East,
+            Direction::South => Self::South,
+            Direction::West => Self::West,
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use anyhow::{Result, bail};
+use ndarray::Array2;
+use photo::ImageRGBA;
+use std::{fmt::Write as _, path::Path};
+
+use crate::{Cell, Map, Tileset};
+
+const TRANSPARENT_COLOUR: [u8; 4] = [0, 0, 0, 0];
+
+impl Map {
+    /// Export the map as a Godot 4 scene containing a single `TileMap` node.
+    /// The interiors of the tileset are packed into an atlas image saved alongside the scene,
+    /// which is referenced as `res://<atlas file name>`, so both files should be placed in the project root.
+    /// Ignored and wildcard cells are left empty.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the atlas path has no valid file name, or either file cannot be written.
+    pub fn export_godot(
+        &self,
+        tileset: &Tileset,
+        scene_path: &Path,
+        atlas_path: &Path,
+    ) -> Result<()> {
+        debug_assert!(
+            self.max_index().is_none_or(|index| index < tileset.len()),
+            "Index out of bounds for tileset"
+        );
+        let Some(atlas_name) = atlas_path.file_name().and_then(|name| name.to_str()) else {
+            bail!("Invalid atlas path: {}", atlas_path.display());
+        };
+
+        // Pack the tiles into a roughly square atlas, row by row
+        let num_tiles = tileset.len();
+        let columns = (num_tiles as f64).sqrt().ceil() as usize;
+        let rows = num_tiles.div_ceil(columns);
+        let tile_size = tileset.interior_size();
+        let mut interiors = tileset.interiors().into_iter();
+        let atlas = Array2::from_shape_fn((rows, columns), |_| {
+            interiors
+                .next()
+                .unwrap_or_else(|| ImageRGBA::filled([tile_size, tile_size], TRANSPARENT_COLOUR))
+        });
+        ImageRGBA::from_tiles(&atlas).save(atlas_path)?;
+
+        let mut scene = String::new();
+        writeln!(scene, "[gd_scene load_steps=4 format=3]")?;
+        writeln!(scene)?;
+        writeln!(
+            scene,
+            "[ext_resource type=\"Texture2D\" path=\"res://{atlas_name}\" id=\"1\"]"
+        )?;
+        writeln!(scene)?;
+        writeln!(
+            scene,
+            "[sub_resource type=\"TileSetAtlasSource\" id=\"TileSetAtlasSource_1\"]"
+        )?;
+        writeln!(scene, "texture = ExtResource(\"1\")")?;
+        writeln!(
+            scene,
+            "texture_region_size = Vector2i({tile_size}, {tile_size})"
+        )?;
+        for index in 0..num_tiles {
+            writeln!(scene, "{}:{}/0 = 0", index % columns, index / columns)?;
+        }
+        writeln!(scene)?;
+        writeln!(scene, "[sub_resource type=\"TileSet\" id=\"TileSet_1\"]")?;
+        writeln!(scene, "tile_size = Vector2i({tile_size}, {tile_size})")?;
+        writeln!(scene, "sources/0 = SubResource(\"TileSetAtlasSource_1\")")?;
+        writeln!(scene)?;
+        writeln!(scene, "[node name=\"TileMap\" type=\"TileMap\"]")?;
+        writeln!(scene, "tile_set = SubResource(\"TileSet_1\")")?;
+        writeln!(scene, "format = 2")?;
+
+        // Each placed cell is three packed integers: position, source and atlas column, then atlas row and alternative
+        let mut tile_data = Vec::new();
+        for y in 0..self.height() {
+            for x in 0..self.width() {
+                if let Cell::Fixed(index) = self[(y, x)] {
+                    tile_data.push((y as u32) << 16 | (x as u32 & 0xFFFF));
+                    tile_data.push(((index % columns) as u32) << 16);
+                    tile_data.push((index / columns) as u32);
+                }
+            }
+        }
+        writeln!(
+            scene,
+            "layer_0/tile_data = PackedInt32Array({})",
+            tile_data
+                .iter()
+                .map(|value| value.cast_signed().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )?;
+
+        std::fs::write(scene_path, scene)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,113 @@
+use anyhow::Result;
+use rand::Rng;
+use std::ops::{Index, IndexMut};
+
+use crate::{Cell, Direction, ReviseStrategy, Rules, collapse_graph, collapse_graph_with_strategy};
+
+/// A map over an arbitrary graph of cells, such as the polygons of a navmesh or the rooms of a dungeon.
+/// Each edge is labelled with the direction in which it leaves its first node.
+#[derive(Clone)]
+pub struct GraphMap {
+    nodes: Vec<Cell>,
+    edges: Vec<(usize, usize, Direction)>,
+}
+
+impl GraphMap {
+    #[must_use]
+    pub fn new(nodes: Vec<Cell>) -> Self {
+        debug_assert!(!nodes.is_empty(), "Graph must contain at least one node");
+        Self {
+            nodes,
+            edges: Vec::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn empty(num_nodes: usize) -> Self {
+        Self::new(vec![Cell::Wildcard; num_nodes])
+    }
+
+    /// Declare that node `to` lies in `direction` of node `from`.
+    /// The reverse relation is implied, so each pair of nodes only needs connecting once.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either node is out of bounds, or the nodes are the same.
+    pub fn add_edge(&mut self, from: usize, to: usize, direction: Direction) {
+        assert!(
+            from < self.nodes.len() && to < self.nodes.len(),
+            "Edge node index out of bounds"
+        );
+        assert!(from != to, "Nodes cannot be connected to themselves");
+        self.edges.push((from, to, direction));
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    #[must_use]
+    pub fn nodes(&self) -> &[Cell] {
+        &self.nodes
+    }
+
+    #[must_use]
+    pub fn edges(&self) -> &[(usize, usize, Direction)] {
+        &self.edges
+    }
+
+    /// Nodes connected to the given node, along with the direction in which they lie.
+    pub fn neighbours(&self, node: usize) -> impl Iterator<Item = (usize, Direction)> + '_ {
+        self.edges.iter().filter_map(move |&(from, to, direction)| {
+            if from == node {
+                Some((to, direction))
+            } else if to == node {
+                Some((from, direction.opposite()))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Collapse the graph, propagating constraints along its edges.
+    ///
+    /// # Errors
+    ///
+    /// Fails as `collapse_graph` does.
+    pub fn collapse(&self, rules: &Rules, rng: &mut impl Rng) -> Result<GraphMap> {
+        collapse_graph(self, rules, rng)
+    }
+
+    /// Collapse the graph, checking support during propagation with the given strategy.
+    ///
+    /// # Errors
+    ///
+    /// Fails as `collapse_graph` does.
+    pub fn collapse_with_strategy(
+        &self,
+        rules: &Rules,
+        revise_strategy: ReviseStrategy,
+        rng: &mut impl Rng,
+    ) -> Result<GraphMap> {
+        collapse_graph_with_strategy(self, rules, revise_strategy, rng)
+    }
+}
+
+impl Index<usize> for GraphMap {
+    type Output = Cell;
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.nodes[index]
+    }
+}
+
+impl IndexMut<usize> for GraphMap {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        &mut self.nodes[index]
+    }
+}
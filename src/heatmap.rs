@@ -0,0 +1,106 @@
+use ndarray::{Array3, aview1, s};
+use photo::ImageRGBA;
+
+use crate::{
+    Cell, Map, Rules,
+    map::{HOLE_COLOUR, IGNORE_COLOUR, WILDCARD_COLOUR},
+};
+
+const MAX_LOG_RATIO: f64 = 2.0; // Usage ratios beyond 4x either way are drawn at full strength
+
+/// Region over which tile usage is measured for a heatmap.
+#[derive(Clone, Copy, Debug, Default)]
+pub enum UsageWindow {
+    /// Compare each tile's share of the whole map with its target share.
+    #[default]
+    Global,
+    /// Compare each tile's share of the square of cells within this many steps of a cell with its target share.
+    Local(usize),
+}
+
+impl Map {
+    /// Render each cell coloured by how over or under-represented its tile is, relative to the target
+    /// frequencies of the rules: red where the tile is used more than intended, blue where it is used less,
+    /// and white where it matches, with each cell drawn as a `scale` by `scale` block of pixels.
+    /// Wildcard cells are drawn magenta and ignored cells are transparent.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the scale is zero, or a cell is fixed to a tile beyond the rules.
+    #[must_use]
+    pub fn render_usage_heatmap(
+        &self,
+        rules: &Rules,
+        window: UsageWindow,
+        scale: usize,
+    ) -> ImageRGBA<u8> {
+        assert!(scale > 0, "Scale must be greater than zero");
+        let (height, width) = self.size();
+        let num_tiles = rules.len();
+        let total_frequency: usize = rules.frequencies().iter().sum();
+
+        // Running totals of each tile, and of all fixed cells, over the rectangle above and left of each corner
+        let mut totals = Array3::<usize>::zeros((height + 1, width + 1, num_tiles + 1));
+        for y in 0..height {
+            for x in 0..width {
+                for channel in 0..=num_tiles {
+                    totals[[y + 1, x + 1, channel]] = totals[[y, x + 1, channel]]
+                        + totals[[y + 1, x, channel]]
+                        - totals[[y, x, channel]];
+                }
+                if let Cell::Fixed(tile) = self[(y, x)] {
+                    assert!(tile < num_tiles, "Tile index out of bounds for ruleset");
+                    totals[[y + 1, x + 1, tile]] += 1;
+                    totals[[y + 1, x + 1, num_tiles]] += 1;
+                }
+            }
+        }
+        let count = |channel: usize, rows: (usize, usize), cols: (usize, usize)| {
+            totals[[rows.1, cols.1, channel]] + totals[[rows.0, cols.0, channel]]
+                - totals[[rows.0, cols.1, channel]]
+                - totals[[rows.1, cols.0, channel]]
+        };
+
+        let mut data = Array3::zeros((height * scale, width * scale, 4));
+        for y in 0..height {
+            for x in 0..width {
+                let colour = match self[(y, x)] {
+                    Cell::Ignore => IGNORE_COLOUR,
+                    Cell::Wildcard => WILDCARD_COLOUR,
+                    Cell::Hole => HOLE_COLOUR,
+                    Cell::Fixed(tile) => {
+                        let (rows, cols) = match window {
+                            UsageWindow::Global => ((0, height), (0, width)),
+                            UsageWindow::Local(radius) => (
+                                (y.saturating_sub(radius), (y + radius + 1).min(height)),
+                                (x.saturating_sub(radius), (x + radius + 1).min(width)),
+                            ),
+                        };
+                        let observed =
+                            count(tile, rows, cols) as f64 / count(num_tiles, rows, cols) as f64;
+                        let target = rules.frequencies()[tile] as f64 / total_frequency as f64;
+                        usage_colour(observed / target)
+                    }
+                };
+                data.slice_mut(s![
+                    y * scale..(y + 1) * scale,
+                    x * scale..(x + 1) * scale,
+                    ..
+                ])
+                .assign(&aview1(&colour));
+            }
+        }
+        ImageRGBA::new(data)
+    }
+}
+
+// White for a ratio of one, shading to red above and blue below on a logarithmic scale
+fn usage_colour(ratio: f64) -> [u8; 4] {
+    let strength = (ratio.log2() / MAX_LOG_RATIO).clamp(-1.0, 1.0);
+    let fade = (255.0 * (1.0 - strength.abs())).round() as u8;
+    if strength >= 0.0 {
+        [255, fade, fade, 255]
+    } else {
+        [fade, fade, 255, 255]
+    }
+}
@@ -0,0 +1,103 @@
+use std::fmt::{Display, Formatter};
+
+use crate::{ALL_DIRECTIONS, Direction, Tileset};
+
+/// A likely authoring error in a tileset's images, found by `Tileset::validate_images`.
+#[derive(Clone, Debug)]
+pub enum ImageIssue {
+    /// The tiles look the same once placed, but their borders differ, so they follow different adjacency rules.
+    DuplicateInterior { tiles: Vec<usize> },
+    /// Every pixel of the tile is fully transparent.
+    Transparent { tile: usize },
+    /// The tile's border in a direction matches the opposite border of no tile, so nothing may be placed there.
+    UnmatchedBorder { tile: usize, direction: Direction },
+}
+
+impl ImageIssue {
+    /// Suggested fix for the issue.
+    #[must_use]
+    pub fn suggestion(&self) -> &'static str {
+        match self {
+            ImageIssue::DuplicateInterior { .. } => {
+                "make the borders match if the tiles are meant to be the same, or give them distinct interiors"
+            }
+            ImageIssue::Transparent { .. } => {
+                "remove the tile, or check it was cut from the right part of the source image"
+            }
+            ImageIssue::UnmatchedBorder { .. } => {
+                "check the border for stray pixels, or add a tile which can sit on that side"
+            }
+        }
+    }
+}
+
+impl Display for ImageIssue {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            ImageIssue::DuplicateInterior { tiles } => write!(
+                f,
+                "Tiles {tiles:?} have identical interiors but different borders"
+            )?,
+            ImageIssue::Transparent { tile } => write!(f, "Tile {tile} is fully transparent")?,
+            ImageIssue::UnmatchedBorder { tile, direction } => write!(
+                f,
+                "Tile {tile}'s {direction:?} border matches no tile, so nothing may be placed {direction:?} of it"
+            )?,
+        }
+        write!(f, " ({})", self.suggestion())
+    }
+}
+
+impl Tileset {
+    /// Check the tile images for likely authoring errors: tiles which look the same but have different
+    /// borders, fully transparent tiles, and borders which match no tile.
+    /// Returns every issue found; an empty list means none were.
+    #[must_use]
+    pub fn validate_images(&self) -> Vec<ImageIssue> {
+        let mut issues = Vec::new();
+
+        let interiors = self.interiors();
+        let mut grouped = vec![false; self.len()];
+        for tile in 0..self.len() {
+            if grouped[tile] {
+                continue;
+            }
+            let tiles: Vec<usize> = (tile..self.len())
+                .filter(|&other| interiors[other] == interiors[tile])
+                .collect();
+            for &other in &tiles {
+                grouped[other] = true;
+            }
+            if tiles
+                .iter()
+                .any(|&other| self.tiles()[other] != self.tiles()[tile])
+            {
+                issues.push(ImageIssue::DuplicateInterior { tiles });
+            }
+        }
+
+        for (tile, image) in self.tiles().iter().enumerate() {
+            if image
+                .data
+                .indexed_iter()
+                .all(|((_, _, c), &v)| c != 3 || v == 0)
+            {
+                issues.push(ImageIssue::Transparent { tile });
+            }
+        }
+
+        for (tile, image) in self.tiles().iter().enumerate() {
+            for direction in ALL_DIRECTIONS {
+                let border = image.view_border(direction.into(), self.border_size());
+                let is_matched = self.tiles().iter().any(|other| {
+                    other.view_border(direction.opposite().into(), self.border_size()) == border
+                });
+                if !is_matched {
+                    issues.push(ImageIssue::UnmatchedBorder { tile, direction });
+                }
+            }
+        }
+
+        issues
+    }
+}
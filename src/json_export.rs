@@ -0,0 +1,125 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{Cell, Map, TileMetadata};
+
+const SCHEMA_NAME: &str = "wave-function/map";
+const CELL_IGNORE: i64 = -1;
+const CELL_WILDCARD: i64 = -2;
+const CELL_HOLE: i64 = -3;
+
+/// Version 1 of the JSON map layout.
+/// Fields are never removed or changed in meaning within a version; new optional fields may be appended.
+#[derive(Serialize)]
+struct MapSchemaV1 {
+    /// Always `"wave-function/map"`.
+    schema: &'static str,
+    /// Always `1`.
+    version: u32,
+    width: usize,
+    height: usize,
+    /// Every tile index used by the map, in ascending order.
+    palette: Vec<PaletteEntryV1>,
+    /// Row-major tile indices, starting at the top-left cell.
+    /// Ignored cells are `-1`, uncollapsed wildcard cells are `-2` and unresolved soft holes are `-3`.
+    cells: Vec<i64>,
+    /// How the map was generated, left out when unknown.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    provenance: Option<ProvenanceV1>,
+}
+
+#[derive(Serialize)]
+struct PaletteEntryV1 {
+    /// Index of the tile within its tileset.
+    index: usize,
+    /// Tags attached to the tile, empty when it has no metadata.
+    tags: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct ProvenanceV1 {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    algorithm: String,
+    /// Hashes are 16 hexadecimal digits.
+    options_hash: String,
+    rules_hash: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tileset_hash: Option<String>,
+    crate_version: String,
+    duration_seconds: f64,
+    backtracks: usize,
+    /// Settings particular to the algorithm, by name.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    settings: BTreeMap<String, String>,
+}
+
+impl Map {
+    /// Export the map in version 1 of the versioned JSON layout, for engine-side importers.
+    /// The tags of each tile are taken from `metadata`, indexed by tile as in `Tileset::metadata`; tiles past
+    /// its end are exported without tags.
+    /// ```json
+    /// {
+    ///   "schema": "wave-function/map",
+    ///   "version": 1,
+    ///   "width": 3,
+    ///   "height": 1,
+    ///   "palette": [{ "index": 0, "tags": ["land"] }, { "index": 4, "tags": [] }],
+    ///   "cells": [0, 4, -1]
+    /// }
+    /// ```
+    /// Maps with a provenance also have a `provenance` object recording how they were generated.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a tile index does not fit in a JSON cell.
+    #[must_use]
+    pub fn export_json_schema_v1(&self, metadata: &[TileMetadata]) -> String {
+        let (height, width) = self.size();
+        let mut palette = BTreeSet::new();
+        let mut cells = Vec::with_capacity(height * width);
+        for y in 0..height {
+            for x in 0..width {
+                cells.push(match self[(y, x)] {
+                    Cell::Fixed(index) => {
+                        palette.insert(index);
+                        i64::try_from(index).expect("Tile index does not fit in a JSON cell")
+                    }
+                    Cell::Ignore => CELL_IGNORE,
+                    Cell::Wildcard => CELL_WILDCARD,
+                    Cell::Hole => CELL_HOLE,
+                });
+            }
+        }
+
+        let schema = MapSchemaV1 {
+            schema: SCHEMA_NAME,
+            version: 1,
+            width,
+            height,
+            palette: palette
+                .into_iter()
+                .map(|index| PaletteEntryV1 {
+                    index,
+                    tags: metadata
+                        .get(index)
+                        .map(|metadata| metadata.tags.clone())
+                        .unwrap_or_default(),
+                })
+                .collect(),
+            cells,
+            provenance: self.provenance().map(|provenance| ProvenanceV1 {
+                seed: provenance.seed,
+                algorithm: provenance.algorithm.clone(),
+                options_hash: format!("{:016x}", provenance.options_hash),
+                rules_hash: format!("{:016x}", provenance.rules_hash),
+                tileset_hash: provenance.tileset_hash.map(|hash| format!("{hash:016x}")),
+                crate_version: provenance.crate_version.clone(),
+                duration_seconds: provenance.duration.as_secs_f64(),
+                backtracks: provenance.backtracks,
+                settings: provenance.settings.iter().cloned().collect(),
+            }),
+        };
+        serde_json::to_string_pretty(&schema).expect("Failed to serialise map")
+    }
+}
@@ -0,0 +1,92 @@
+use serde::{Deserialize, Serialize};
+
+/// How the effect of a `Landmark` fades with distance from its position.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Falloff {
+    /// Gaussian falloff with the given standard deviation, in cells.
+    Gaussian { sigma: f64 },
+    /// Linear falloff, reaching nothing at the given radius, in cells.
+    Linear { radius: f64 },
+    /// Full effect within the given radius, in cells, and none beyond it.
+    Constant { radius: f64 },
+}
+
+impl Falloff {
+    /// Strength of the effect at a distance from the landmark, from one at the landmark down to zero.
+    /// A Gaussian or linear falloff whose width is not positive affects only the landmark itself.
+    #[must_use]
+    pub fn strength(&self, distance: f64) -> f64 {
+        match *self {
+            Self::Gaussian { sigma: width } | Self::Linear { radius: width }
+                if width.is_nan() || width <= 0.0 =>
+            {
+                if distance <= 0.0 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Self::Gaussian { sigma } => (-0.5 * (distance / sigma).powi(2)).exp(),
+            Self::Linear { radius } => (1.0 - distance / radius).max(0.0),
+            Self::Constant { radius } => {
+                if distance <= radius {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+/// A point of a map which makes some tiles more, or less, likely to be chosen near it, without fixing any cells.
+/// Each boosted tile's frequency is multiplied by its boost at the landmark itself, and by a smaller factor
+/// further away, as given by the falloff; a boost below one makes a tile less likely instead.
+/// The factors of overlapping landmarks multiply. Landmarks are registered with `Map::add_landmark`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Landmark {
+    /// Position of the landmark, as (row, column).
+    pub pos: (usize, usize),
+    pub falloff: Falloff,
+    /// Multiplier of each boosted tile's frequency at the landmark, as (tile, boost).
+    pub boosts: Vec<(usize, f64)>,
+}
+
+impl Landmark {
+    #[must_use]
+    pub fn new(pos: (usize, usize), falloff: Falloff) -> Self {
+        Self {
+            pos,
+            falloff,
+            boosts: Vec::new(),
+        }
+    }
+
+    /// Multiply the frequency of a tile by `boost` at the landmark.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `boost` is not positive and finite.
+    #[must_use]
+    pub fn boost(mut self, tile: usize, boost: f64) -> Self {
+        assert!(
+            boost.is_finite() && boost > 0.0,
+            "Landmark boost must be positive and finite"
+        );
+        self.boosts.push((tile, boost));
+        self
+    }
+
+    /// Factor by which the frequency of a tile is multiplied at a cell.
+    #[must_use]
+    pub fn multiplier(&self, pos: (usize, usize), tile: usize) -> f64 {
+        let dy = pos.0 as f64 - self.pos.0 as f64;
+        let dx = pos.1 as f64 - self.pos.1 as f64;
+        let strength = self.falloff.strength(dy.hypot(dx));
+        self.boosts
+            .iter()
+            .filter(|&&(boosted, _)| boosted == tile)
+            .map(|&(_, boost)| boost.powf(strength))
+            .product()
+    }
+}
@@ -0,0 +1,133 @@
+use anyhow::Result;
+use ndarray::{Array2, Array3, s};
+use photo::ImageRGBA;
+
+use crate::Tileset;
+
+const LEGEND_PADDING: usize = 1; // Transparent pixels around each thumbnail of the legend sheet
+const LEGEND_CAPTION_SCALE: usize = 1; // Scale of the thumbnails printed by `print_legend`
+
+impl Tileset {
+    /// Reference table of every tile's index, name, tags and frequency, for authoring templates against the tileset.
+    #[must_use]
+    pub fn legend_table(&self) -> String {
+        let rows: Vec<[String; 4]> = self
+            .metadata()
+            .iter()
+            .zip(self.rules().frequencies())
+            .enumerate()
+            .map(|(index, (metadata, frequency))| {
+                [
+                    index.to_string(),
+                    metadata.name.clone().unwrap_or_default(),
+                    metadata.tags.join(", "),
+                    frequency.to_string(),
+                ]
+            })
+            .collect();
+
+        let headers = ["Index", "Name", "Tags", "Frequency"];
+        let widths: Vec<usize> = (0..headers.len())
+            .map(|column| {
+                rows.iter()
+                    .map(|row| row[column].len())
+                    .chain([headers[column].len()])
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        let format_row = |cells: [&str; 4]| {
+            format!(
+                "{:>w0$} | {:<w1$} | {:<w2$} | {:>w3$}\n",
+                cells[0],
+                cells[1],
+                cells[2],
+                cells[3],
+                w0 = widths[0],
+                w1 = widths[1],
+                w2 = widths[2],
+                w3 = widths[3]
+            )
+        };
+
+        let mut table = format_row(headers);
+        table.push_str(
+            &widths
+                .iter()
+                .map(|&width| "-".repeat(width))
+                .collect::<Vec<_>>()
+                .join("-+-"),
+        );
+        table.push('\n');
+        for row in &rows {
+            table.push_str(&format_row([&row[0], &row[1], &row[2], &row[3]]));
+        }
+        table
+    }
+
+    /// Render a reference sheet of every tile's interior, in index order with `columns` tiles per row,
+    /// to be read alongside `legend_table`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are no columns.
+    #[must_use]
+    pub fn render_legend(&self, columns: usize) -> ImageRGBA<u8> {
+        assert!(columns > 0, "Legend must have at least one column");
+        let size = self.interior_size() + 2 * LEGEND_PADDING;
+        let thumbnails: Vec<ImageRGBA<u8>> = self
+            .interiors()
+            .into_iter()
+            .map(|interior| {
+                let mut data = Array3::zeros((size, size, 4));
+                data.slice_mut(s![
+                    LEGEND_PADDING..size - LEGEND_PADDING,
+                    LEGEND_PADDING..size - LEGEND_PADDING,
+                    ..
+                ])
+                .assign(&interior.data);
+                ImageRGBA::new(data)
+            })
+            .collect();
+
+        let rows = thumbnails.len().div_ceil(columns);
+        let blank = ImageRGBA::new(Array3::zeros((size, size, 4)));
+        let grid = Array2::from_shape_fn((rows, columns), |(row, column)| {
+            thumbnails
+                .get(row * columns + column)
+                .unwrap_or(&blank)
+                .clone()
+        });
+        ImageRGBA::from_tiles(&grid)
+    }
+
+    /// Print every tile to the terminal, captioned with its index, name, tags and frequency.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the images cannot be printed to the terminal.
+    pub fn print_legend(&self) -> Result<()> {
+        let interiors = self.interiors();
+        let captions: Vec<(&ImageRGBA<u8>, String)> = interiors
+            .iter()
+            .zip(self.metadata())
+            .zip(self.rules().frequencies())
+            .enumerate()
+            .map(|(index, ((interior, metadata), frequency))| {
+                let name = metadata
+                    .name
+                    .as_ref()
+                    .map(|name| format!(" {name}"))
+                    .unwrap_or_default();
+                let tags = if metadata.tags.is_empty() {
+                    String::new()
+                } else {
+                    format!(" [{}]", metadata.tags.join(", "))
+                };
+                (interior, format!("{index}{name}{tags} ({frequency})"))
+            })
+            .collect();
+        ImageRGBA::print_image_grid_with_caption(&captions, LEGEND_CAPTION_SCALE)?;
+        Ok(())
+    }
+}
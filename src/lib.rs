@@ -1,6 +1,34 @@
 //! # `WaveFunction`
 //!
-//! `WaveFunction` is a library for procedurally generating 2D maps.
+//! `WaveFunction` is a library for procedurally generating 2D maps, and 3D volumes with `Map3`.
+//!
+//! ## Determinism
+//!
+//! Generation is reproducible: the same template, rules, configuration and seed always give the same map.
+//! Parallel features keep to this whatever the number of threads, by giving each independent piece of work
+//! its own generator, seeded before the work is shared out, rather than drawing from one shared generator.
+//! `Map::collapse_seeded` builds the generator from a seed itself, for output which depends on nothing else.
+//! The tests behind the `determinism` feature check this by comparing hashes of serial and parallel output.
+//!
+//! ## Coordinates
+//!
+//! Positions given as plain pairs are (y, x), row then column, and sizes are (height, width), as `ndarray`
+//! indexes its arrays: `Map::empty((height, width))`, `map[(y, x)]` and `Map::size()` all take this order.
+//! Cells are stored and visited row by row, top to bottom and left to right within a row, and this order is
+//! stable: text, serialized and packed maps, and any iteration over cells all follow it.
+//! `MapSize` and `Coord` name their fields instead, and convert to and from pairs in either order with
+//! `IndexOrder`, for code which would otherwise risk swapping them silently.
+//!
+//! ## Features
+//!
+//! The core of cells, rules and solvers is always built and needs no image or terminal dependencies,
+//! so a game runtime can depend on the solver alone. Everything else is opt-in:
+//!
+//! - `io`: RON and JSON map formats.
+//! - `render`: tilesets cut from images, and rendering maps to images and PNG files.
+//! - `progress`: `TerminalProgress`, drawing terminal progress bars while collapsing, which becomes the default
+//!   `ProgressSink`.
+//! - `godot`: export to Godot scenes, which implies `render`.
 
 // #![deny(warnings)]
 // #![deny(missing_docs)]
@@ -13,18 +41,119 @@
 #![allow(clippy::cast_sign_loss)]
 #![allow(unreachable_code)]
 
+mod adjacency_explanation;
 mod algorithm;
+mod algorithm_info;
+mod autotile;
 mod cell;
+mod chunk_store;
+mod comparison;
+mod coord;
+mod direction;
+#[cfg(feature = "godot")]
+mod godot;
+mod graph_map;
+#[cfg(feature = "render")]
+mod heatmap;
+#[cfg(feature = "render")]
+mod image_validation;
+#[cfg(feature = "io")]
+mod json_export;
+mod landmark;
+#[cfg(feature = "render")]
+mod legend;
 mod map;
+mod map3;
+mod map_blend;
+#[cfg(feature = "render")]
+mod map_diff;
+mod map_mirror;
+#[cfg(feature = "io")]
+mod map_ron;
+mod map_summary;
+mod metrics;
+mod min_bodies;
+mod packet;
+mod pathfinding;
+#[cfg(feature = "render")]
+mod png_writer;
+mod prefab;
+mod profile;
+mod provenance;
+mod quota;
+mod registry;
+#[cfg(feature = "render")]
+mod render_mode;
+mod rule_minimization;
 mod rules;
+mod rules3;
+mod soft_holes;
+mod spawn;
+mod symmetry;
+mod template;
+mod tile_metadata;
+#[cfg(feature = "render")]
 mod tileset;
+#[cfg(feature = "render")]
 mod tileset_builder;
+#[cfg(feature = "render")]
+mod tileset_diff;
+#[cfg(feature = "render")]
+mod tileset_view;
+#[cfg(feature = "render")]
+mod transition_synthesis;
+mod visibility;
 mod wave_function;
 
+#[cfg(feature = "render")]
+pub use adjacency_explanation::BorderComparison;
+pub use adjacency_explanation::{AdjacencyExplanation, AdjacencyReason};
 pub use algorithm::*;
+pub use algorithm_info::AlgorithmInfo;
+pub use autotile::AutotileSet;
 pub use cell::Cell;
+#[cfg(feature = "sled")]
+pub use chunk_store::SledChunkStore;
+#[cfg(feature = "sqlite")]
+pub use chunk_store::SqliteChunkStore;
+pub use chunk_store::{ChunkStore, DirectoryChunkStore, MemoryChunkStore, WorldVersion};
+pub use comparison::{MapComparison, compare};
+pub use coord::{Coord, IndexOrder, MapSize};
+pub use direction::{ALL_DIRECTIONS, ALL_DIRECTIONS_3, Direction, Direction3};
+pub use graph_map::GraphMap;
+#[cfg(feature = "render")]
+pub use heatmap::UsageWindow;
+#[cfg(feature = "render")]
+pub use image_validation::ImageIssue;
+pub use landmark::{Falloff, Landmark};
 pub use map::Map;
+#[cfg(feature = "render")]
+pub use map_diff::render_diff;
+pub use map_mirror::MirrorAxis;
+pub use map_summary::MapSummary;
+pub use map3::Map3;
+pub use metrics::{Metric, ObjectiveScore, Ranking, select_best};
+pub use packet::{MAX_PACKET_CELLS, PacketCompression};
+pub use prefab::{Placement, Prefab, PrefabLayout};
+pub use profile::{Profile, Span};
+pub use provenance::Provenance;
+pub use quota::Quota;
+pub use registry::AlgorithmRegistry;
+#[cfg(feature = "render")]
+pub use render_mode::{Blend, RenderMode, ZOrder};
+pub use rule_minimization::RuleMinimization;
 pub use rules::Rules;
+pub use rules3::Rules3;
+pub use spawn::{EntityPlacement, SpawnConstraints};
+pub use symmetry::{Asymmetry, Symmetry};
+pub use template::{Template, Tile, TileGroup};
+pub use tile_metadata::{SpawnEntry, TileMetadata};
+#[cfg(feature = "render")]
 pub use tileset::Tileset;
+#[cfg(feature = "render")]
 pub use tileset_builder::TilesetBuilder;
-pub use wave_function::WaveFunction;
+#[cfg(feature = "render")]
+pub use tileset_diff::{AdjacencyChange, FrequencyChange, TilesetDiff};
+#[cfg(feature = "render")]
+pub use tileset_view::TilesetView;
+pub use wave_function::{Collapse, WaveFunction};
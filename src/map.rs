@@ -1,62 +1,205 @@
-use anyhow::Result;
+use anyhow::{Result, anyhow, bail};
 use fixedbitset::FixedBitSet;
+#[cfg(feature = "render")]
+use ndarray::Array3;
 use ndarray::{Array2, s};
-use photo::{Direction, ImageRGBA};
-use rand::Rng;
+#[cfg(feature = "render")]
+use photo::ImageRGBA;
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64;
+use rayon::prelude::*;
 use std::{
+    collections::{BTreeMap, VecDeque},
     fmt::{Display, Formatter},
     fs::File,
     io::Write,
     ops::{Index, IndexMut},
 };
 
-use crate::{Cell, Rules, Tileset, WaveFunction};
+use crate::{
+    ALL_DIRECTIONS, AutotileSet, Cell, CollapseOptions, CollapseStats, Coord, Direction, Landmark,
+    MapSize, Profile, Provenance, Rules, WaveFunction, WaveFunctionFast,
+};
+#[cfg(feature = "render")]
+use crate::{Blend, RenderMode, Tileset, ZOrder};
 
-const WILDCARD_COLOUR: [u8; 4] = [255, 0, 255, 255];
-const IGNORE_COLOUR: [u8; 4] = [0, 0, 0, 0];
+const PROVENANCE_PREFIX: &str = "# provenance: "; // Start of the comment line recording a map's provenance
+#[cfg(feature = "render")]
+pub(crate) const WILDCARD_COLOUR: [u8; 4] = [255, 0, 255, 255];
+#[cfg(feature = "render")]
+pub(crate) const IGNORE_COLOUR: [u8; 4] = [0, 0, 0, 0];
+#[cfg(feature = "render")]
+pub(crate) const HOLE_COLOUR: [u8; 4] = [255, 0, 255, 96];
 
 #[derive(Clone)]
 pub struct Map {
     cells: Array2<Cell>,
+    // Tiles allowed in particular wildcard cells
+    restrictions: BTreeMap<(usize, usize), Vec<usize>>,
+    // Points biasing tile choice around them
+    landmarks: Vec<Landmark>,
+    // Constraints on whole rows and columns
+    profiles: Vec<Profile>,
+    // How the map was generated, if it was collapsed
+    provenance: Option<Provenance>,
 }
 
 impl Map {
     pub fn new(cells: Array2<Cell>) -> Self {
         debug_assert!(!cells.is_empty(), "Cell map must contain at least one cell");
-        Self { cells }
+        Self {
+            cells,
+            restrictions: BTreeMap::new(),
+            landmarks: Vec::new(),
+            profiles: Vec::new(),
+            provenance: None,
+        }
     }
 
+    /// A map of wildcards of the given (height, width).
     pub fn empty(size: (usize, usize)) -> Self {
         debug_assert!(size.0 > 0, "Map height must be greater than zero");
         debug_assert!(size.1 > 0, "Map width must be greater than zero");
         let cells = Array2::from_elem(size, Cell::Wildcard);
-        Self { cells }
+        Self::new(cells)
+    }
+
+    /// A map of wildcards of the given size, named so width and height cannot be swapped.
+    #[must_use]
+    pub fn with_size(size: MapSize) -> Self {
+        Self::empty(size.height_width())
     }
 
     pub fn from_str(map_str: &str) -> Self {
-        let cells: Vec<Vec<Cell>> = map_str
+        Self::parse(map_str).unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    /// Parse a map as written by `Display` or `save`, failing rather than panicking on malformed text.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the map has no cells, its rows differ in length or a cell is not a tile index, `!`, `*` or
+    /// `?`.
+    pub fn parse(map_str: &str) -> Result<Self> {
+        let provenance = map_str.lines().find_map(|line| {
+            line.trim()
+                .strip_prefix(PROVENANCE_PREFIX)
+                .and_then(Provenance::parse)
+        });
+        let cells = map_str
             .lines()
-            .map(|line| line.trim()) // Remove surrounding whitespace
+            .map(str::trim) // Remove surrounding whitespace
             .filter(|line| !line.is_empty() && !line.starts_with('#')) // Skip blank or commented lines
             .map(|line| {
                 line.split_whitespace()
-                    .map(|cell_str| Cell::from(cell_str))
-                    .collect()
+                    .map(|cell_str| {
+                        Cell::parse(cell_str)
+                            .ok_or_else(|| anyhow!("Invalid cell string: {cell_str}"))
+                    })
+                    .collect::<Result<Vec<_>>>()
             })
-            .collect();
+            .collect::<Result<Vec<_>>>()?;
 
         let height = cells.len();
-        assert!(height > 0, "Map must contain at least one row");
+        if height == 0 {
+            bail!("Map must contain at least one row");
+        }
         let width = cells[0].len();
+        if width == 0 {
+            bail!("Map must contain at least one column");
+        }
+        if cells.iter().any(|row| row.len() != width) {
+            bail!("All rows must have the same length");
+        }
+
+        let mut map = Self::new(Array2::from_shape_vec(
+            (height, width),
+            cells.into_iter().flatten().collect(),
+        )?);
+        map.provenance = provenance;
+        Ok(map)
+    }
+
+    /// Create a map of the given `(height, width)` where only the listed cells are fixed, as `(x, y, tile)`
+    /// triples, and every other cell is a wildcard.
+    #[must_use]
+    pub fn from_sparse(size: (usize, usize), fixed: &[(usize, usize, usize)]) -> Self {
+        let mut map = Self::empty(size);
+        for &(x, y, tile) in fixed {
+            map[(y, x)] = Cell::Fixed(tile);
+        }
+        map
+    }
+
+    /// Parse the sparse template format: a `WIDTHxHEIGHT` line followed by one `x y cell` line per
+    /// non-wildcard cell, where `cell` is a tile index or `!` to ignore the cell.
+    /// Blank lines and lines starting with `#` are skipped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the dimensions are missing, malformed or zero, or a cell line is not `x y cell`.
+    #[must_use]
+    pub fn from_sparse_str(map_str: &str) -> Self {
+        let mut lines = map_str
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+
+        let dimensions = lines
+            .next()
+            .expect("Sparse map must start with its dimensions");
+        let (width, height) = dimensions
+            .split_once('x')
+            .and_then(|(w, h)| Some((w.trim().parse().ok()?, h.trim().parse().ok()?)))
+            .unwrap_or_else(|| panic!("Invalid sparse map dimensions: {dimensions}"));
+        assert!(height > 0, "Map must contain at least one row");
         assert!(width > 0, "Map must contain at least one column");
-        cells.iter().for_each(|row| {
-            assert_eq!(row.len(), width, "All rows must have the same length");
-        });
 
-        Self::new(
-            Array2::from_shape_vec((height, width), cells.into_iter().flatten().collect())
-                .expect("Failed to create cell array"),
-        )
+        let mut map = Self::empty((height, width));
+        for line in lines {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [x, y, cell] = fields[..] else {
+                panic!("Invalid sparse map cell: {line}");
+            };
+            let (Ok(x), Ok(y)) = (x.parse::<usize>(), y.parse::<usize>()) else {
+                panic!("Invalid sparse map coordinates: {line}");
+            };
+            map[(y, x)] = Cell::from(cell);
+        }
+        map
+    }
+
+    /// Load a map in the sparse template format from a file.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file cannot be read.
+    pub fn load_sparse(path: &str) -> std::io::Result<Self> {
+        let map_str = std::fs::read_to_string(path)?;
+        Ok(Self::from_sparse_str(&map_str))
+    }
+
+    /// Write the map in the sparse template format, listing every cell which is not a wildcard.
+    #[must_use]
+    pub fn to_sparse_string(&self) -> String {
+        let (height, width) = self.size();
+        let mut sparse = format!("{width}x{height}\n");
+        sparse.extend(
+            self.cells
+                .indexed_iter()
+                .filter(|&(_, cell)| *cell != Cell::Wildcard)
+                .map(|((y, x), cell)| format!("{x} {y} {cell}\n")),
+        );
+        sparse
+    }
+
+    /// Save the map in the sparse template format to a file.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file cannot be written.
+    pub fn save_sparse(&self, path: &str) -> std::io::Result<()> {
+        std::fs::write(path, self.to_sparse_string())
     }
 
     pub fn load(path: &str) -> std::io::Result<Self> {
@@ -64,8 +207,12 @@ impl Map {
         Ok(Self::from_str(&map_str))
     }
 
+    /// Write the map as text, after a comment line recording its provenance if it has one.
     pub fn save(&self, path: &str) -> std::io::Result<()> {
         let mut file = File::create(path)?;
+        if let Some(provenance) = &self.provenance {
+            writeln!(file, "{PROVENANCE_PREFIX}{provenance}")?;
+        }
         write!(file, "{}", self)?;
         Ok(())
     }
@@ -77,6 +224,7 @@ impl Map {
                 Cell::Fixed(index) => Some(*index),
                 Cell::Ignore => None,
                 Cell::Wildcard => None,
+                Cell::Hole => None,
             })
             .max()
     }
@@ -89,24 +237,395 @@ impl Map {
         self.cells.shape()[1]
     }
 
+    /// The (height, width) of the map.
     pub fn size(&self) -> (usize, usize) {
         self.cells.dim()
     }
 
+    #[must_use]
+    pub fn map_size(&self) -> MapSize {
+        MapSize::from_height_width(self.size())
+    }
+
     pub fn mask(&self) -> Array2<bool> {
         self.cells.mapv(|cell| match cell {
             Cell::Ignore => true,
             Cell::Wildcard => false,
+            Cell::Hole => false,
             Cell::Fixed(_) => false,
         })
     }
 
+    /// Ignore every cell whose part of an image falls below a threshold, such as to generate only inside
+    /// a continent silhouette; all other cells keep their template value.
+    /// The image is stretched over the map, sampling the pixel at the centre of each cell. The alpha
+    /// channel is used if any pixel is transparent, and the luminance otherwise.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the image is empty.
+    #[cfg(feature = "render")]
+    #[must_use]
+    pub fn mask_from_image(&self, image: &ImageRGBA<u8>, threshold: u8) -> Self {
+        let (image_height, image_width, _) = image.data.dim();
+        assert!(
+            image_height > 0 && image_width > 0,
+            "Mask image must not be empty"
+        );
+        let (height, width) = self.size();
+        let use_alpha = image.data.slice(s![.., .., 3]).iter().any(|&a| a < u8::MAX);
+
+        let mut map = self.clone();
+        for ((y, x), cell) in map.cells.indexed_iter_mut() {
+            let row = (2 * y + 1) * image_height / (2 * height);
+            let col = (2 * x + 1) * image_width / (2 * width);
+            let pixel = image.data.slice(s![row, col, ..]);
+            let value = if use_alpha {
+                pixel[3]
+            } else {
+                let luminance = 0.2126 * f64::from(pixel[0])
+                    + 0.7152 * f64::from(pixel[1])
+                    + 0.0722 * f64::from(pixel[2]);
+                luminance.round() as u8
+            };
+            if value < threshold {
+                *cell = Cell::Ignore;
+            }
+        }
+        map
+    }
+
+    /// Turn the hard edge of the ignored area into a ragged one, such as for natural coastlines around islands
+    /// generated inside a mask, by growing the generated area into the ignored cells at random.
+    /// Each ignored cell up to `band_width` steps out becomes a wildcard with a probability falling from
+    /// `band_width / (band_width + 1)` next to the edge to `1 / (band_width + 1)` at the outside of the band,
+    /// but only if the cell it was reached from did too, so the band has no detached specks.
+    #[must_use]
+    pub fn ragged_mask_edges(&self, band_width: usize, rng: &mut impl Rng) -> Self {
+        let size = self.size();
+        let mut map = self.clone();
+        let mut is_visited = self.mask().mapv(|is_ignore| !is_ignore);
+        let mut frontier: VecDeque<((usize, usize), usize)> = self
+            .cells
+            .indexed_iter()
+            .filter(|&(_, &cell)| cell != Cell::Ignore)
+            .map(|(pos, _)| (pos, 0))
+            .collect();
+
+        // Breadth-first, so each cell is reached at its distance in steps from the original edge
+        while let Some((pos, distance)) = frontier.pop_front() {
+            if distance == band_width {
+                continue;
+            }
+            let probability = (band_width - distance) as f64 / (band_width + 1) as f64;
+            for direction in ALL_DIRECTIONS {
+                let Some(next) = direction.apply_to(pos, size) else {
+                    continue;
+                };
+                if is_visited[next] {
+                    continue;
+                }
+                is_visited[next] = true;
+                if rng.random_bool(probability) {
+                    map.cells[next] = Cell::Wildcard;
+                    frontier.push_back((next, distance + 1));
+                }
+            }
+        }
+        map
+    }
+
+    /// Limit a wildcard cell to a set of tiles, which it collapses to one of.
+    /// Restrictions only apply while the cell is a wildcard, and are not written by `save` or the other text formats.
+    pub fn restrict(&mut self, pos: (usize, usize), tiles: &[usize]) {
+        let mut tiles = tiles.to_vec();
+        tiles.sort_unstable();
+        tiles.dedup();
+        self.restrictions.insert(pos, tiles);
+    }
+
+    /// Tiles a wildcard or hole cell is limited to, if it has been restricted.
+    pub fn restriction(&self, pos: (usize, usize)) -> Option<&[usize]> {
+        self.restrictions.get(&pos).map(Vec::as_slice)
+    }
+
+    /// Register a landmark, making its boosted tiles more likely to be chosen near it when collapsing.
+    /// Like restrictions, landmarks are not written by `save` or the other text formats.
+    pub fn add_landmark(&mut self, landmark: Landmark) {
+        debug_assert!(
+            landmark.pos.0 < self.cells.nrows() && landmark.pos.1 < self.cells.ncols(),
+            "Landmark must lie within the map"
+        );
+        self.landmarks.push(landmark);
+    }
+
+    #[must_use]
+    pub fn landmarks(&self) -> &[Landmark] {
+        &self.landmarks
+    }
+
+    /// Register a profile, constraining the tiles of its rows or columns when collapsing.
+    /// Tile limits apply to wildcard and hole cells, on top of any restriction. Like restrictions, profiles
+    /// are not written by `save` or the other text formats.
+    pub fn add_profile(&mut self, profile: Profile) {
+        self.profiles.push(profile);
+    }
+
+    #[must_use]
+    pub fn profiles(&self) -> &[Profile] {
+        &self.profiles
+    }
+
+    /// How the map was generated, if it was collapsed by one of the crate's solvers or loaded from a file
+    /// recording it.
+    #[must_use]
+    pub fn provenance(&self) -> Option<&Provenance> {
+        self.provenance.as_ref()
+    }
+
+    /// Replace the map's provenance, such as to fill in `Provenance::tileset_hash`.
+    pub fn set_provenance(&mut self, provenance: Option<Provenance>) {
+        self.provenance = provenance;
+    }
+
+    // Record the seed a collapsed map was generated from
+    fn with_seed(mut self, seed: u64) -> Self {
+        if let Some(provenance) = &mut self.provenance {
+            provenance.seed = Some(seed);
+        }
+        self
+    }
+
+    /// Factor by which the landmarks and profiles scale the frequency of a tile at a cell.
+    #[must_use]
+    pub fn frequency_multiplier(&self, pos: (usize, usize), tile: usize) -> f64 {
+        let landmarks: f64 = self
+            .landmarks
+            .iter()
+            .map(|landmark| landmark.multiplier(pos, tile))
+            .product();
+        let profiles: f64 = self
+            .profiles
+            .iter()
+            .map(|profile| profile.multiplier(pos, tile))
+            .product();
+        landmarks * profiles
+    }
+
+    // Whether any tile frequency is scaled anywhere on the map
+    pub(crate) fn has_frequency_multipliers(&self) -> bool {
+        !self.landmarks.is_empty()
+            || self
+                .profiles
+                .iter()
+                .any(|profile| !profile.weights.is_empty())
+    }
+
+    /// Initial options for a cell, taking any restriction and profile into account.
+    #[must_use]
+    pub fn cell_domain(&self, pos: (usize, usize), num_tiles: usize) -> FixedBitSet {
+        let mut domain = match (self.cells[pos], self.restrictions.get(&pos)) {
+            (Cell::Wildcard | Cell::Hole, Some(tiles)) => {
+                let mut domain = FixedBitSet::with_capacity(num_tiles);
+                for &tile in tiles.iter().filter(|&&tile| tile < num_tiles) {
+                    domain.insert(tile);
+                }
+                domain
+            }
+            (cell, _) => cell.domain(num_tiles),
+        };
+        if matches!(self.cells[pos], Cell::Wildcard | Cell::Hole) {
+            for profile in self
+                .profiles
+                .iter()
+                .filter(|profile| profile.span.contains(pos))
+            {
+                if let Some(tiles) = &profile.tiles {
+                    let mut allowed = FixedBitSet::with_capacity(num_tiles);
+                    for &tile in tiles.iter().filter(|&&tile| tile < num_tiles) {
+                        allowed.insert(tile);
+                    }
+                    domain.intersect_with(&allowed);
+                }
+            }
+        }
+        domain
+    }
+
+    /// Initial options for every cell, as for `cell_domain`.
+    #[must_use]
     pub fn domains(&self, num_tiles: usize) -> Array2<FixedBitSet> {
-        self.cells.mapv(|cell| cell.domain(num_tiles))
+        Array2::from_shape_fn(self.size(), |pos| self.cell_domain(pos, num_tiles))
+    }
+
+    /// Collapse the map using the default configuration of an algorithm.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the algorithm cannot collapse the map.
+    pub fn collapse<WF: WaveFunction + Default>(
+        &self,
+        rules: &Rules,
+        rng: &mut impl Rng,
+    ) -> Result<Self> {
+        WF::default().collapse(self, rules, rng)
+    }
+
+    /// Collapse the map using the default configuration of an algorithm, drawing from a generator seeded
+    /// with `seed`.
+    /// The generator is always PCG, whose output is fixed by its seed on every platform, so the same seed gives
+    /// the same map on every run. Maps may still differ between machines, as heuristics such as entropy use
+    /// the platform's maths library, whose rounding is not fixed.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the algorithm cannot collapse the map.
+    pub fn collapse_seeded<WF: WaveFunction + Default>(
+        &self,
+        rules: &Rules,
+        seed: u64,
+    ) -> Result<Self> {
+        WF::default()
+            .collapse(self, rules, &mut Pcg64::seed_from_u64(seed))
+            .map(|map| map.with_seed(seed))
+    }
+
+    /// Collapse the map using the default configuration of an algorithm, with its shared settings replaced
+    /// by `options`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the algorithm cannot collapse the map.
+    pub fn collapse_with_options<WF: WaveFunction + Default>(
+        &self,
+        rules: &Rules,
+        options: &CollapseOptions,
+        rng: &mut impl Rng,
+    ) -> Result<Self> {
+        WF::default().collapse_with_options(self, rules, options, rng)
+    }
+
+    /// Collapse the map using the default configuration of an algorithm, also reporting how the collapse
+    /// went.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the algorithm cannot collapse the map.
+    pub fn collapse_with_stats<WF: WaveFunction + Default>(
+        &self,
+        rules: &Rules,
+        rng: &mut impl Rng,
+    ) -> Result<(Self, CollapseStats)> {
+        WF::default().collapse_with_stats(self, rules, rng)
+    }
+
+    /// Collapse the map using a configured algorithm.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the algorithm cannot collapse the map.
+    pub fn collapse_with(
+        &self,
+        algorithm: &impl WaveFunction,
+        rules: &Rules,
+        rng: &mut impl Rng,
+    ) -> Result<Self> {
+        algorithm.collapse(self, rules, rng)
+    }
+
+    /// Collapse the map once for each seed, in parallel.
+    /// Each collapse draws only from a generator seeded with its own seed, so the results are the same as
+    /// collapsing serially, whatever the number of threads or the order they run in.
+    pub fn collapse_batch(
+        &self,
+        algorithm: &(impl WaveFunction + Sync),
+        rules: &Rules,
+        seeds: &[u64],
+    ) -> Vec<Result<Self>> {
+        seeds
+            .par_iter()
+            .map(|&seed| {
+                algorithm
+                    .collapse(self, rules, &mut Pcg64::seed_from_u64(seed))
+                    .map(|map| map.with_seed(seed))
+            })
+            .collect()
+    }
+
+    /// Generate a variation of the map by re-collapsing a random `mutation_rate` fraction of its fixed cells
+    /// while the others keep their tiles, such as to build a family of levels from one liked map.
+    /// The same seed always gives the same variant. A freed cell is often forced back to its old tile by its
+    /// neighbours, so low rates may change very little.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the freed cells cannot be collapsed around the ones which were kept.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the mutation rate is not between zero and one.
+    pub fn variant(&self, seed: u64, mutation_rate: f64, rules: &Rules) -> Result<Self> {
+        assert!(
+            (0.0..=1.0).contains(&mutation_rate),
+            "Mutation rate must be between 0 and 1"
+        );
+        let mut rng = Pcg64::seed_from_u64(seed);
+        let mut template = self.clone();
+        for cell in &mut template.cells {
+            if matches!(cell, Cell::Fixed(_)) && rng.random_bool(mutation_rate) {
+                *cell = Cell::Wildcard;
+            }
+        }
+        WaveFunctionFast::default()
+            .collapse(&template, rules, &mut rng)
+            .map(|map| map.with_seed(seed))
     }
 
-    pub fn collapse<WF: WaveFunction>(&self, rules: &Rules, rng: &mut impl Rng) -> Result<Self> {
-        WF::collapse(self, rules, rng)
+    /// Convert the cells of one category into autotile variant indices.
+    /// Each cell whose tile has the given category in `rules` becomes fixed to the variant matching which of its
+    /// neighbours have tiles of the same category, and every other cell is ignored.
+    /// Cells beyond the edge of the map count as matching if `edges_match` is set.
+    #[must_use]
+    pub fn autotile(
+        &self,
+        rules: &Rules,
+        category: usize,
+        set: AutotileSet,
+        edges_match: bool,
+    ) -> Self {
+        const OFFSETS: [(isize, isize); 8] = [
+            (-1, 0),
+            (-1, 1),
+            (0, 1),
+            (1, 1),
+            (1, 0),
+            (1, -1),
+            (0, -1),
+            (-1, -1),
+        ];
+        let (height, width) = self.size();
+        let categories = rules.categories();
+        let is_category = |pos: (usize, usize)| match self.cells[pos] {
+            Cell::Fixed(tile) => categories.get(tile) == Some(&category),
+            Cell::Ignore | Cell::Wildcard | Cell::Hole => false,
+        };
+
+        let mut result = Self::new(Array2::from_elem((height, width), Cell::Ignore));
+        for y in 0..height {
+            for x in 0..width {
+                if !is_category((y, x)) {
+                    continue;
+                }
+                let neighbours = OFFSETS.map(|(dy, dx)| {
+                    match (y.checked_add_signed(dy), x.checked_add_signed(dx)) {
+                        (Some(ny), Some(nx)) if ny < height && nx < width => is_category((ny, nx)),
+                        _ => edges_match,
+                    }
+                });
+                result.cells[(y, x)] = Cell::Fixed(set.index(neighbours));
+            }
+        }
+        result
     }
 
     /// Create a bordering map chunk with the same dimensions as the original map.
@@ -208,22 +727,245 @@ impl Map {
         }
     }
 
+    /// Build the template of a chunk from every neighbouring chunk that has already been generated, sharing a
+    /// border `border_size` cells deep with each as `set_shared_border` does, so the chunk is collapsed once
+    /// against all of its neighbours. The template takes the size of the neighbours, which must all match,
+    /// and neighbours which overlap in a corner must agree there.
+    ///
+    /// # Errors
+    ///
+    /// Fails if there are no neighbours, they differ in size, or they disagree where they overlap.
+    pub fn constrained_by_neighbors(
+        north: Option<&Self>,
+        east: Option<&Self>,
+        south: Option<&Self>,
+        west: Option<&Self>,
+        border_size: usize,
+    ) -> Result<Self> {
+        let neighbours: Vec<(Direction, &Self)> = [
+            (Direction::North, north),
+            (Direction::East, east),
+            (Direction::South, south),
+            (Direction::West, west),
+        ]
+        .into_iter()
+        .filter_map(|(direction, neighbour)| neighbour.map(|n| (direction, n)))
+        .collect();
+        let Some(&(_, first)) = neighbours.first() else {
+            bail!("At least one neighbour is needed to size the chunk");
+        };
+        let size = first.size();
+        if let Some(&(direction, _)) = neighbours.iter().find(|(_, n)| n.size() != size) {
+            bail!(
+                "Neighbours must all be the same size, but the {direction:?} neighbour is not {size:?}"
+            );
+        }
+
+        let mut template = Self::empty(size);
+        for (direction, neighbour) in neighbours {
+            let mut border = Self::empty(size);
+            border.set_shared_border(neighbour, direction, border_size);
+            for ((pos, &cell), current) in
+                border.cells.indexed_iter().zip(template.cells.iter_mut())
+            {
+                match (*current, cell) {
+                    (_, Cell::Wildcard) => {}
+                    (Cell::Wildcard, _) => *current = cell,
+                    (existing, _) if existing == cell => {}
+                    _ => bail!(
+                        "Neighbours disagree on cell ({}, {}) where their borders overlap",
+                        pos.0,
+                        pos.1
+                    ),
+                }
+            }
+        }
+        Ok(template)
+    }
+}
+
+#[cfg(feature = "render")]
+impl Map {
     pub fn render(&self, tileset: &Tileset) -> ImageRGBA<u8> {
+        self.render_with(tileset, RenderMode::Orthographic)
+    }
+
+    /// Render the map with an alternative palette for the tileset it was generated from.
+    /// The palette is checked against the tileset first, so a mismatched set fails rather than drawing the wrong tiles.
+    ///
+    /// # Errors
+    ///
+    /// Fails as `Tileset::check_palette` does.
+    pub fn render_palette(&self, tileset: &Tileset, palette: &Tileset) -> Result<ImageRGBA<u8>> {
+        tileset.check_palette(palette)?;
+        Ok(self.render(palette))
+    }
+
+    #[must_use]
+    pub fn render_with(&self, tileset: &Tileset, mode: RenderMode) -> ImageRGBA<u8> {
+        match mode {
+            RenderMode::Overlapped { blend } => self.render_overlapped(tileset, blend),
+            _ => self.render_interiors(&tileset.interiors(), tileset, mode),
+        }
+    }
+
+    /// Render the map at a frame of the tileset's animations, which loop independently for each tile.
+    #[must_use]
+    pub fn render_frame(&self, tileset: &Tileset, frame: usize) -> ImageRGBA<u8> {
+        self.render_interiors(
+            &tileset.interiors_at(frame),
+            tileset,
+            RenderMode::Orthographic,
+        )
+    }
+
+    fn render_interiors(
+        &self,
+        interiors: &[ImageRGBA<u8>],
+        tileset: &Tileset,
+        mode: RenderMode,
+    ) -> ImageRGBA<u8> {
         debug_assert!(
             self.max_index().map_or(true, |index| index < tileset.len()),
             "Index out of bounds for tileset"
         );
-        let interiors = tileset.interiors();
         let interior_size = tileset.interior_size();
         let wildcard_img = ImageRGBA::filled([interior_size, interior_size], WILDCARD_COLOUR);
         let ignore_img = ImageRGBA::filled([interior_size, interior_size], IGNORE_COLOUR);
+        let hole_img = ImageRGBA::filled([interior_size, interior_size], HOLE_COLOUR);
         let data = self.cells.mapv(|cell| match cell {
             Cell::Fixed(index) => interiors[index].clone(),
             Cell::Ignore => ignore_img.clone(),
             Cell::Wildcard => wildcard_img.clone(),
+            Cell::Hole => hole_img.clone(),
         });
 
-        ImageRGBA::from_tiles(&data)
+        match mode {
+            RenderMode::Orthographic => ImageRGBA::from_tiles(&data),
+            RenderMode::Isometric { overlap, z_order } => {
+                Self::render_isometric(&data, interior_size, overlap, z_order)
+            }
+            RenderMode::Overlapped { .. } => unreachable!("Overlapped renders use whole tiles"),
+        }
+    }
+
+    // Draw whole tiles on a square grid of interiors, blending each over the tiles drawn before it where they
+    // overlap. Cells without a tile are filled in over their interior only.
+    fn render_overlapped(&self, tileset: &Tileset, blend: Blend) -> ImageRGBA<u8> {
+        debug_assert!(
+            self.max_index().is_none_or(|index| index < tileset.len()),
+            "Index out of bounds for tileset"
+        );
+        let interior_size = tileset.interior_size();
+        let border_size = tileset.border_size();
+        let (height, width) = self.size();
+        let mut data = Array3::zeros((height * interior_size, width * interior_size, 4));
+        let mut covered = Array2::from_elem((height * interior_size, width * interior_size), false);
+
+        for ((y, x), cell) in self.cells.indexed_iter() {
+            let colour = match *cell {
+                Cell::Fixed(index) => {
+                    let image = &tileset.tiles()[index].data;
+                    let (tile_height, tile_width, _) = image.dim();
+                    for row in 0..tile_height {
+                        // Position of the pixel in the render, which falls outside it for borders on the map's edge
+                        let Some(canvas_row) = (y * interior_size + row).checked_sub(border_size)
+                        else {
+                            continue;
+                        };
+                        if canvas_row >= height * interior_size {
+                            continue;
+                        }
+                        for col in 0..tile_width {
+                            let Some(canvas_col) =
+                                (x * interior_size + col).checked_sub(border_size)
+                            else {
+                                continue;
+                            };
+                            if canvas_col >= width * interior_size {
+                                continue;
+                            }
+                            let top = std::array::from_fn(|channel| image[[row, col, channel]]);
+                            let pixel = if covered[(canvas_row, canvas_col)] {
+                                let bottom = std::array::from_fn(|channel| {
+                                    data[[canvas_row, canvas_col, channel]]
+                                });
+                                blend.apply(top, bottom)
+                            } else {
+                                top
+                            };
+                            for channel in 0..4 {
+                                data[[canvas_row, canvas_col, channel]] = pixel[channel];
+                            }
+                            covered[(canvas_row, canvas_col)] = true;
+                        }
+                    }
+                    continue;
+                }
+                Cell::Ignore => IGNORE_COLOUR,
+                Cell::Wildcard => WILDCARD_COLOUR,
+                Cell::Hole => HOLE_COLOUR,
+            };
+            for row in y * interior_size..(y + 1) * interior_size {
+                for col in x * interior_size..(x + 1) * interior_size {
+                    for channel in 0..4 {
+                        data[[row, col, channel]] = colour[channel];
+                    }
+                    covered[(row, col)] = true;
+                }
+            }
+        }
+
+        ImageRGBA::new(data)
+    }
+
+    // Composite tile images onto a diamond grid, skipping fully transparent pixels
+    fn render_isometric(
+        tiles: &Array2<ImageRGBA<u8>>,
+        tile_size: usize,
+        overlap: usize,
+        z_order: ZOrder,
+    ) -> ImageRGBA<u8> {
+        assert!(
+            overlap < tile_size,
+            "Overlap must be smaller than the tile size"
+        );
+        let (height, width) = tiles.dim();
+        let step_x = ((tile_size - overlap) / 2).max(1);
+        let step_y = (step_x / 2).max(1);
+        let diagonals = height + width - 2;
+        let mut data = Array3::zeros((
+            diagonals * step_y + tile_size,
+            diagonals * step_x + tile_size,
+            4,
+        ));
+
+        // Tiles on the same diagonal never overlap, so ordering by diagonal is a valid painter's order
+        let mut order: Vec<(usize, usize)> = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (y, x)))
+            .collect();
+        order.sort_by_key(|&(y, x)| y + x);
+        if z_order == ZOrder::FarthestOnTop {
+            order.reverse();
+        }
+
+        for (y, x) in order {
+            let top = (y + x) * step_y;
+            let left = (x + height - 1 - y) * step_x;
+            let tile = &tiles[(y, x)].data;
+            for row in 0..tile_size {
+                for col in 0..tile_size {
+                    if tile[[row, col, 3]] == 0 {
+                        continue;
+                    }
+                    for channel in 0..4 {
+                        data[[top + row, left + col, channel]] = tile[[row, col, channel]];
+                    }
+                }
+            }
+        }
+
+        ImageRGBA::new(data)
     }
 }
 
@@ -257,6 +999,20 @@ impl IndexMut<(usize, usize)> for Map {
     }
 }
 
+impl Index<Coord> for Map {
+    type Output = Cell;
+
+    fn index(&self, coord: Coord) -> &Self::Output {
+        &self[coord.yx()]
+    }
+}
+
+impl IndexMut<Coord> for Map {
+    fn index_mut(&mut self, coord: Coord) -> &mut Self::Output {
+        &mut self[coord.yx()]
+    }
+}
+
 impl Display for Map {
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
         let print_width = self.max_index().unwrap_or(0).to_string().len();
@@ -0,0 +1,110 @@
+use anyhow::{Result, bail};
+use ndarray::{Array3, Axis};
+use rand::Rng;
+use std::{
+    fmt::{Display, Formatter},
+    ops::{Index, IndexMut},
+};
+
+use crate::{Cell, Map, Rules3, collapse_volume};
+
+/// A volume of cells, indexed by (z, y, x), such as the floors of a building or a voxel dungeon.
+/// Floor zero is the bottom of the volume.
+#[derive(Clone, PartialEq)]
+pub struct Map3 {
+    cells: Array3<Cell>,
+}
+
+impl Map3 {
+    #[must_use]
+    pub fn new(cells: Array3<Cell>) -> Self {
+        debug_assert!(!cells.is_empty(), "Volume must contain at least one cell");
+        Self { cells }
+    }
+
+    #[must_use]
+    pub fn empty(size: (usize, usize, usize)) -> Self {
+        Self::new(Array3::from_elem(size, Cell::Wildcard))
+    }
+
+    /// Stack maps of the same size into a volume, bottom floor first.
+    ///
+    /// # Errors
+    ///
+    /// Fails if there are no floors, or they differ in size.
+    pub fn from_floors(floors: &[Map]) -> Result<Self> {
+        let Some(first) = floors.first() else {
+            bail!("Volume must contain at least one floor");
+        };
+        let (height, width) = first.size();
+        if let Some(z) = floors
+            .iter()
+            .position(|floor| floor.size() != (height, width))
+        {
+            bail!(
+                "Floor {} has size {:?}, but floor 0 has size {:?}",
+                z,
+                floors[z].size(),
+                (height, width)
+            );
+        }
+        Ok(Self::new(Array3::from_shape_fn(
+            (floors.len(), height, width),
+            |(z, y, x)| floors[z][(y, x)],
+        )))
+    }
+
+    /// Size as (depth, height, width).
+    #[must_use]
+    pub fn size(&self) -> (usize, usize, usize) {
+        self.cells.dim()
+    }
+
+    /// Copy of a single floor.
+    #[must_use]
+    pub fn floor(&self, z: usize) -> Map {
+        Map::new(self.cells.index_axis(Axis(0), z).to_owned())
+    }
+
+    /// Every (z, y, x) position, floor by floor in row-major order.
+    pub fn positions(&self) -> impl Iterator<Item = (usize, usize, usize)> + use<> {
+        let (depth, height, width) = self.size();
+        (0..depth)
+            .flat_map(move |z| (0..height).flat_map(move |y| (0..width).map(move |x| (z, y, x))))
+    }
+
+    /// Collapse the volume with `collapse_volume`.
+    ///
+    /// # Errors
+    ///
+    /// Fails as `collapse_volume` does.
+    pub fn collapse(&self, rules: &Rules3, rng: &mut impl Rng) -> Result<Map3> {
+        collapse_volume(self, rules, rng)
+    }
+}
+
+impl Index<(usize, usize, usize)> for Map3 {
+    type Output = Cell;
+    fn index(&self, index: (usize, usize, usize)) -> &Self::Output {
+        &self.cells[index]
+    }
+}
+
+impl IndexMut<(usize, usize, usize)> for Map3 {
+    fn index_mut(&mut self, index: (usize, usize, usize)) -> &mut Self::Output {
+        &mut self.cells[index]
+    }
+}
+
+/// Each floor is printed as a map, bottom floor first, separated by blank lines.
+impl Display for Map3 {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        for z in 0..self.size().0 {
+            if z > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", self.floor(z))?;
+        }
+        Ok(())
+    }
+}
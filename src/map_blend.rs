@@ -0,0 +1,85 @@
+use anyhow::{Result, bail};
+use ndarray::Array2;
+use rand::Rng;
+use std::collections::VecDeque;
+
+use crate::{ALL_DIRECTIONS, Cell, Map, Rules, WaveFunction};
+
+impl Map {
+    /// Combine two maps of the same size, taking cells from `a` where the mask is set and from `b` elsewhere,
+    /// such as to mix the mountains of one generated map with the coast of another.
+    /// The seam would usually break the adjacency rules, so every cell within `band_width` steps of the
+    /// mask's boundary is reset to a wildcard and re-collapsed with the given algorithm, around the cells
+    /// kept from each map. Ignored cells stay ignored.
+    /// Wider bands give the algorithm more room to join the maps, and narrow ones are more likely to fail.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the maps and mask differ in size, the band is empty, or the algorithm cannot collapse the band.
+    pub fn blend(
+        a: &Self,
+        b: &Self,
+        mask: &Array2<bool>,
+        band_width: usize,
+        algorithm: &impl WaveFunction,
+        rules: &Rules,
+        rng: &mut impl Rng,
+    ) -> Result<Self> {
+        if a.size() != b.size() || mask.dim() != a.size() {
+            bail!(
+                "Maps and mask must be the same size, found {:?}, {:?} and {:?}",
+                a.size(),
+                b.size(),
+                mask.dim()
+            );
+        }
+        if band_width == 0 {
+            bail!("Blend band must be at least one cell wide");
+        }
+
+        let band = boundary_band(mask, band_width);
+        let template = Map::new(Array2::from_shape_fn(a.size(), |pos| {
+            let cell = if mask[pos] { a[pos] } else { b[pos] };
+            match cell {
+                Cell::Fixed(_) if band[pos] => Cell::Wildcard,
+                cell => cell,
+            }
+        }));
+        algorithm.collapse(&template, rules, rng)
+    }
+}
+
+// Cells within a number of steps of a cell on the other side of the mask's boundary
+fn boundary_band(mask: &Array2<bool>, band_width: usize) -> Array2<bool> {
+    let size = mask.dim();
+    let mut distances: Array2<Option<usize>> = Array2::from_elem(size, None);
+    let mut frontier = VecDeque::new();
+    for (pos, &is_masked) in mask.indexed_iter() {
+        let is_boundary = ALL_DIRECTIONS.iter().any(|direction| {
+            direction
+                .apply_to(pos, size)
+                .is_some_and(|next| mask[next] != is_masked)
+        });
+        if is_boundary {
+            distances[pos] = Some(0);
+            frontier.push_back(pos);
+        }
+    }
+
+    // Breadth-first, so each cell is reached at its distance in steps from the boundary
+    while let Some(pos) = frontier.pop_front() {
+        let distance = distances[pos].unwrap_or_default();
+        if distance + 1 == band_width {
+            continue;
+        }
+        for direction in ALL_DIRECTIONS {
+            if let Some(next) = direction.apply_to(pos, size)
+                && distances[next].is_none()
+            {
+                distances[next] = Some(distance + 1);
+                frontier.push_back(next);
+            }
+        }
+    }
+    distances.map(Option::is_some)
+}
@@ -0,0 +1,51 @@
+use anyhow::{Result, bail};
+use photo::ImageRGBA;
+
+use crate::{Map, Tileset};
+
+const DIM_FACTOR: f64 = 0.3; // Brightness of cells which are the same in both maps
+const HIGHLIGHT_COLOUR: [u8; 4] = [255, 216, 0, 255]; // Outline around cells which differ
+const HIGHLIGHT_FRACTION: usize = 8; // Outline width as a fraction of the tile interior
+
+/// Render the second of two maps of the same size, such as before and after a re-collapse or a rule edit,
+/// with the cells which are the same in both dimmed and those which differ drawn at full strength inside a
+/// bright outline, so the changes stand out at a glance.
+///
+/// # Errors
+///
+/// Fails if the maps are not the same size.
+pub fn render_diff(map_a: &Map, map_b: &Map, tileset: &Tileset) -> Result<ImageRGBA<u8>> {
+    if map_a.size() != map_b.size() {
+        bail!(
+            "Maps must be the same size to diff, found {:?} and {:?}",
+            map_a.size(),
+            map_b.size()
+        );
+    }
+    let (height, width) = map_b.size();
+    let interior_size = tileset.interior_size();
+    let outline = (interior_size / HIGHLIGHT_FRACTION).max(1);
+
+    let mut image = map_b.render(tileset);
+    for y in 0..height {
+        for x in 0..width {
+            let changed = map_a[(y, x)] != map_b[(y, x)];
+            for row in 0..interior_size {
+                for col in 0..interior_size {
+                    let pixel = (y * interior_size + row, x * interior_size + col);
+                    if !changed {
+                        for channel in 0..3 {
+                            let value = &mut image.data[[pixel.0, pixel.1, channel]];
+                            *value = (f64::from(*value) * DIM_FACTOR).round() as u8;
+                        }
+                    } else if row.min(col).min(interior_size - 1 - row.max(col)) < outline {
+                        for (channel, &value) in HIGHLIGHT_COLOUR.iter().enumerate() {
+                            image.data[[pixel.0, pixel.1, channel]] = value;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Ok(image)
+}
@@ -0,0 +1,62 @@
+use anyhow::{Result, bail};
+
+use crate::{Cell, Map};
+
+/// Line a template is mirrored across.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MirrorAxis {
+    /// The vertical centre line, swapping the left and right halves.
+    Vertical,
+    /// The horizontal centre line, swapping the top and bottom halves.
+    Horizontal,
+}
+
+impl Map {
+    /// Copy every fixed cell of a template to its mirror image across an axis, so only half of a symmetric
+    /// template (such as an arena) needs authoring.
+    /// `tile_transform` gives the mirrored version of each tile, so directional tiles such as a wall facing
+    /// east become their counterparts facing west; `Tileset::symmetry(..).tiles` gives such a table.
+    /// Only wildcard cells are overwritten: ignored cells stay ignored, and a fixed cell may only be mirrored
+    /// onto a cell already fixed to the same mirrored tile, so cells on the axis must be fixed to tiles which
+    /// are their own mirror image.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a fixed tile has no mirrored version, or is mirrored onto a cell fixed to another tile.
+    pub fn mirror_constraints(
+        &self,
+        axis: MirrorAxis,
+        tile_transform: &[Option<usize>],
+    ) -> Result<Self> {
+        let (height, width) = self.size();
+        let mut mirrored = self.clone();
+        for y in 0..height {
+            for x in 0..width {
+                let Cell::Fixed(tile) = self[(y, x)] else {
+                    continue;
+                };
+                let Some(&Some(mirrored_tile)) = tile_transform.get(tile) else {
+                    bail!("Tile {tile} at ({y}, {x}) has no mirrored version");
+                };
+                let target = match axis {
+                    MirrorAxis::Vertical => (y, width - 1 - x),
+                    MirrorAxis::Horizontal => (height - 1 - y, x),
+                };
+                match self[target] {
+                    Cell::Wildcard => mirrored[target] = Cell::Fixed(mirrored_tile),
+                    Cell::Fixed(existing) if existing != mirrored_tile => bail!(
+                        "Cell ({}, {}) is fixed to tile {}, but mirroring ({}, {}) would fix it to tile {}",
+                        target.0,
+                        target.1,
+                        existing,
+                        y,
+                        x,
+                        mirrored_tile
+                    ),
+                    Cell::Fixed(_) | Cell::Hole | Cell::Ignore => {}
+                }
+            }
+        }
+        Ok(mirrored)
+    }
+}
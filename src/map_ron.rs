@@ -0,0 +1,196 @@
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use crate::{Cell, Map, TileMetadata};
+
+const WILDCARD_TOKEN: &str = "*";
+const IGNORE_TOKEN: &str = "!";
+const HOLE_TOKEN: &str = "?";
+
+// Hand-editable form of a map template
+#[derive(Deserialize)]
+struct RonMap {
+    width: usize,
+    height: usize,
+    #[serde(default)]
+    tiles: Vec<String>,
+    #[serde(default)]
+    rows: Vec<String>,
+    #[serde(default)]
+    regions: Vec<RonRegion>,
+}
+
+#[derive(Deserialize)]
+struct RonRegion {
+    #[serde(default)]
+    name: String,
+    cell: String,
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+impl Map {
+    /// Parse a map from the commented RON template format written by `to_ron`.
+    /// Cells are tile names from the `tiles` list, tile indices, `*` for a wildcard, `?` for a soft hole or `!` to
+    /// ignore the cell.
+    /// `rows` may be left out to start from an all-wildcard map, and `regions` then fill rectangles of cells
+    /// in the order listed.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the text is not a valid RON map, the map is empty, a row or region does not fit it, or a cell
+    /// names an unknown tile.
+    pub fn from_ron(ron_str: &str) -> Result<Self> {
+        let document: RonMap = ron::from_str(ron_str).context("Invalid RON map")?;
+        if document.width == 0 || document.height == 0 {
+            bail!("Map must contain at least one row and one column");
+        }
+
+        let names: HashMap<&str, usize> = document
+            .tiles
+            .iter()
+            .enumerate()
+            .map(|(index, name)| (name.as_str(), index))
+            .collect();
+        let parse_cell = |token: &str| -> Result<Cell> {
+            match token {
+                WILDCARD_TOKEN => Ok(Cell::Wildcard),
+                IGNORE_TOKEN => Ok(Cell::Ignore),
+                HOLE_TOKEN => Ok(Cell::Hole),
+                _ => names
+                    .get(token)
+                    .copied()
+                    .or_else(|| token.parse().ok())
+                    .map(Cell::Fixed)
+                    .with_context(|| format!("Unknown tile: {token}")),
+            }
+        };
+
+        let mut map = Self::empty((document.height, document.width));
+        if !document.rows.is_empty() {
+            if document.rows.len() != document.height {
+                bail!(
+                    "Map has {} rows, but its height is {}",
+                    document.rows.len(),
+                    document.height
+                );
+            }
+            for (y, row) in document.rows.iter().enumerate() {
+                let tokens: Vec<&str> = row.split_whitespace().collect();
+                if tokens.len() != document.width {
+                    bail!(
+                        "Row {} has {} cells, but the map width is {}",
+                        y,
+                        tokens.len(),
+                        document.width
+                    );
+                }
+                for (x, token) in tokens.into_iter().enumerate() {
+                    map[(y, x)] = parse_cell(token)?;
+                }
+            }
+        }
+
+        for region in &document.regions {
+            if region.x + region.width > document.width
+                || region.y + region.height > document.height
+            {
+                bail!("Region {} extends outside the map", region.name);
+            }
+            let cell = parse_cell(&region.cell)?;
+            for y in region.y..region.y + region.height {
+                for x in region.x..region.x + region.width {
+                    map[(y, x)] = cell;
+                }
+            }
+        }
+
+        Ok(map)
+    }
+
+    /// Write the map as a commented RON template, designed for editing by hand.
+    /// Tiles are written by their names from `metadata`, falling back to their index for unnamed tiles
+    /// or names which would be ambiguous, and the rows are column aligned so version control diffs stay readable.
+    pub fn to_ron(&self, metadata: &[TileMetadata]) -> String {
+        let (height, width) = self.size();
+        let num_tiles = self
+            .max_index()
+            .map_or(0, |index| index + 1)
+            .max(metadata.len());
+
+        let mut names: Vec<String> = Vec::with_capacity(num_tiles);
+        for index in 0..num_tiles {
+            let name = metadata
+                .get(index)
+                .and_then(|m| m.name.as_deref())
+                .filter(|name| {
+                    !name.is_empty()
+                        && !name.contains(char::is_whitespace)
+                        && *name != WILDCARD_TOKEN
+                        && *name != IGNORE_TOKEN
+                        && *name != HOLE_TOKEN
+                        && name.parse::<usize>().is_err()
+                        && !names.iter().any(|other| other == name)
+                });
+            names.push(name.map_or_else(|| index.to_string(), str::to_string));
+        }
+
+        let token = |cell: &Cell| match cell {
+            Cell::Fixed(index) => names[*index].as_str(),
+            Cell::Wildcard => WILDCARD_TOKEN,
+            Cell::Ignore => IGNORE_TOKEN,
+            Cell::Hole => HOLE_TOKEN,
+        };
+        let column_width = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (y, x)))
+            .map(|pos| token(&self[pos]).len())
+            .max()
+            .unwrap_or(1);
+
+        let mut ron_str = format!(
+            "// {width}x{height} map template.\n\
+             // Cells are tile names, `*` for a wildcard, `?` for a soft hole or `!` to ignore the cell.\n\
+             // Regions fill rectangles of cells after the rows are read, in the order listed.\n\
+             (\n    width: {width},\n    height: {height},\n"
+        );
+        ron_str.push_str("    // Tile names, in tile index order\n    tiles: [\n");
+        ron_str.extend(names.iter().map(|name| format!("        {name:?},\n")));
+        ron_str.push_str("    ],\n");
+        ron_str.push_str("    // Rows, top to bottom\n    rows: [\n");
+        ron_str.extend((0..height).map(|y| {
+            let cells: Vec<String> = (0..width)
+                .map(|x| format!("{:<column_width$}", token(&self[(y, x)])))
+                .collect();
+            format!("        {:?},\n", cells.join(" ").trim_end())
+        }));
+        ron_str.push_str("    ],\n");
+        ron_str.push_str(
+            "    // For example: (name: \"lake\", cell: \"water\", x: 2, y: 1, width: 3, height: 2)\n    regions: [],\n",
+        );
+        ron_str.push_str(")\n");
+        ron_str
+    }
+
+    /// Load a map template in the RON format from a file.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file cannot be read, or as `from_ron` does.
+    pub fn load_ron(path: &str) -> Result<Self> {
+        let ron_str = std::fs::read_to_string(path)?;
+        Self::from_ron(&ron_str)
+    }
+
+    /// Save the map in the RON template format to a file, naming tiles from their metadata.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file cannot be written.
+    pub fn save_ron(&self, path: &str, metadata: &[TileMetadata]) -> Result<()> {
+        std::fs::write(path, self.to_ron(metadata))?;
+        Ok(())
+    }
+}
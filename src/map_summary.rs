@@ -0,0 +1,104 @@
+use std::fmt::{Display, Formatter};
+
+use crate::{Cell, Map};
+
+const HISTOGRAM_WIDTH: usize = 40; // Characters in the longest histogram bar
+
+/// Counts of the cells in a map, for logging a map without printing the whole grid.
+#[derive(Clone, Debug)]
+pub struct MapSummary {
+    pub height: usize,
+    pub width: usize,
+    /// Number of cells fixed to each tile, indexed by tile.
+    pub tile_counts: Vec<usize>,
+    pub num_wildcard: usize,
+    /// Number of soft holes, which may yet be left empty or filled.
+    pub num_hole: usize,
+    pub num_ignore: usize,
+}
+
+impl MapSummary {
+    #[must_use]
+    pub fn num_cells(&self) -> usize {
+        self.height * self.width
+    }
+
+    #[must_use]
+    pub fn num_fixed(&self) -> usize {
+        self.tile_counts.iter().sum()
+    }
+
+    /// Percentage of the cells which are not ignored that have been fixed to a tile.
+    #[must_use]
+    pub fn percent_collapsed(&self) -> f64 {
+        let num_active = self.num_cells() - self.num_ignore;
+        if num_active == 0 {
+            return 100.0;
+        }
+        100.0 * self.num_fixed() as f64 / num_active as f64
+    }
+}
+
+impl Display for MapSummary {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Size      : {}x{} ({} cells)",
+            self.width,
+            self.height,
+            self.num_cells()
+        )?;
+        writeln!(
+            f,
+            "Collapsed : {:.1}% ({} fixed, {} wildcard, {} hole)",
+            self.percent_collapsed(),
+            self.num_fixed(),
+            self.num_wildcard,
+            self.num_hole
+        )?;
+        writeln!(f, "Ignored   : {}", self.num_ignore)?;
+
+        let num_fixed = self.num_fixed();
+        let max_count = self.tile_counts.iter().copied().max().unwrap_or(0);
+        let index_width = self.tile_counts.len().saturating_sub(1).to_string().len();
+        let count_width = max_count.to_string().len();
+        // Only tiles which appear are listed, to keep the histogram short
+        for (tile, &count) in self.tile_counts.iter().enumerate().filter(|&(_, &c)| c > 0) {
+            let bar = (count * HISTOGRAM_WIDTH).div_ceil(max_count.max(1));
+            writeln!(
+                f,
+                "{tile:>index_width$} | {:<HISTOGRAM_WIDTH$} {count:>count_width$} ({:.1}%)",
+                "#".repeat(bar),
+                100.0 * count as f64 / num_fixed.max(1) as f64
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Map {
+    /// Summarise the map's dimensions and cell counts, including a histogram of tile usage.
+    #[must_use]
+    pub fn summary(&self) -> MapSummary {
+        let (height, width) = self.size();
+        let mut summary = MapSummary {
+            height,
+            width,
+            tile_counts: vec![0; self.max_index().map_or(0, |max| max + 1)],
+            num_wildcard: 0,
+            num_hole: 0,
+            num_ignore: 0,
+        };
+        for y in 0..height {
+            for x in 0..width {
+                match self[(y, x)] {
+                    Cell::Fixed(tile) => summary.tile_counts[tile] += 1,
+                    Cell::Wildcard => summary.num_wildcard += 1,
+                    Cell::Hole => summary.num_hole += 1,
+                    Cell::Ignore => summary.num_ignore += 1,
+                }
+            }
+        }
+        summary
+    }
+}
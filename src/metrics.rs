@@ -0,0 +1,177 @@
+use std::{
+    cmp::Ordering,
+    fmt::{Display, Formatter},
+};
+
+use crate::{Cell, Map};
+
+/// A score of a collapsed map, for ranking candidate maps in a generate-and-test workflow with `select_best`.
+#[derive(Clone, Debug)]
+pub enum Metric {
+    /// Fraction of fixed cells holding the given tile.
+    TileFraction(usize),
+    /// Shannon entropy, in nats, of the tiles of the fixed cells, which is higher when more tiles are used
+    /// more evenly.
+    TileDiversity,
+    /// Fraction of the enterable cells which lie in the largest region connected by edge-adjacent moves, with
+    /// movement costs indexed by tile as for `Map::distance_field`, where `None` marks a tile which cannot be
+    /// entered. One for a map whose walkable space is all connected, or which has none.
+    Connectivity(Vec<Option<u32>>),
+    /// A score given by a function of the map.
+    Custom(fn(&Map) -> f64),
+}
+
+impl Metric {
+    #[must_use]
+    pub fn score(&self, map: &Map) -> f64 {
+        match self {
+            Self::TileFraction(tile) => {
+                let counts = tile_counts(map);
+                let total: usize = counts.iter().sum();
+                if total == 0 {
+                    return 0.0;
+                }
+                counts.get(*tile).copied().unwrap_or(0) as f64 / total as f64
+            }
+            Self::TileDiversity => {
+                let counts = tile_counts(map);
+                let total = counts.iter().sum::<usize>() as f64;
+                counts
+                    .iter()
+                    .filter(|&&count| count > 0)
+                    .map(|&count| {
+                        let p = count as f64 / total;
+                        -p * p.ln()
+                    })
+                    .sum()
+            }
+            Self::Connectivity(costs) => connectivity(map, costs),
+            Self::Custom(score) => score(map),
+        }
+    }
+}
+
+/// A metric's score of one map, as part of a `Ranking`.
+#[derive(Clone, Copy, Debug)]
+pub struct ObjectiveScore {
+    /// Score given by the metric.
+    pub value: f64,
+    /// Score rescaled to lie between zero, for the lowest score among the candidates, and one, for the highest.
+    /// Every candidate scores zero if they all have the same score.
+    pub normalized: f64,
+    /// Normalized score multiplied by the objective's weight, which is this objective's part of the total.
+    pub weighted: f64,
+}
+
+/// One of the best maps found by `select_best`.
+#[derive(Clone, Debug)]
+pub struct Ranking {
+    /// Index of the map among the candidates.
+    pub index: usize,
+    /// Sum of the weighted scores, where higher is better.
+    pub score: f64,
+    /// Score of each objective, in the order they were given.
+    pub breakdown: Vec<ObjectiveScore>,
+}
+
+impl Display for Ranking {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "Map {}: {:.3} (", self.index, self.score)?;
+        for (n, objective) in self.breakdown.iter().enumerate() {
+            if n > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{:.3}", objective.weighted)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Rank candidate maps, such as a batch collapsed with different seeds, by a weighted combination of metrics,
+/// and return the best `k` of them, best first.
+/// Each metric's scores are normalized across the candidates before weighting, so metrics on different scales
+/// can be combined; a negative weight penalizes a metric instead. Equal totals keep the candidates' order.
+#[must_use]
+pub fn select_best(maps: &[Map], objectives: &[(Metric, f64)], k: usize) -> Vec<Ranking> {
+    let values: Vec<Vec<f64>> = objectives
+        .iter()
+        .map(|(metric, _)| maps.iter().map(|map| metric.score(map)).collect())
+        .collect();
+
+    let mut rankings: Vec<Ranking> = (0..maps.len())
+        .map(|index| {
+            let breakdown: Vec<ObjectiveScore> = objectives
+                .iter()
+                .zip(&values)
+                .map(|(&(_, weight), scores)| {
+                    let (min, max) = scores
+                        .iter()
+                        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &score| {
+                            (min.min(score), max.max(score))
+                        });
+                    let value = scores[index];
+                    let normalized = if max > min {
+                        (value - min) / (max - min)
+                    } else {
+                        0.0
+                    };
+                    ObjectiveScore {
+                        value,
+                        normalized,
+                        weighted: weight * normalized,
+                    }
+                })
+                .collect();
+            Ranking {
+                index,
+                score: breakdown.iter().map(|objective| objective.weighted).sum(),
+                breakdown,
+            }
+        })
+        .collect();
+
+    rankings.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
+    rankings.truncate(k);
+    rankings
+}
+
+// Number of cells fixed to each tile, indexed by tile
+fn tile_counts(map: &Map) -> Vec<usize> {
+    map.summary().tile_counts
+}
+
+fn connectivity(map: &Map, costs: &[Option<u32>]) -> f64 {
+    let (height, width) = map.size();
+    let is_enterable = |pos: (usize, usize)| match map[pos] {
+        Cell::Fixed(tile) => costs.get(tile).copied().flatten().is_some(),
+        _ => false,
+    };
+
+    let mut reached = vec![vec![false; width]; height];
+    let mut num_enterable: usize = 0;
+    let mut largest: usize = 0;
+    for pos in (0..height).flat_map(|y| (0..width).map(move |x| (y, x))) {
+        if !is_enterable(pos) {
+            continue;
+        }
+        num_enterable += 1;
+        if reached[pos.0][pos.1] {
+            continue;
+        }
+        let distances = map.distance_field(&[pos], costs);
+        let mut size = 0;
+        for (other, distance) in distances.indexed_iter() {
+            if distance.is_some() {
+                reached[other.0][other.1] = true;
+                size += 1;
+            }
+        }
+        largest = largest.max(size);
+    }
+
+    if num_enterable == 0 {
+        1.0
+    } else {
+        largest as f64 / num_enterable as f64
+    }
+}
@@ -0,0 +1,207 @@
+use anyhow::{Result, bail};
+use ndarray::Array2;
+use rand::Rng;
+
+use crate::{
+    Boundary, Cell, CollapseOptions, Map, Rules, WaveFunction, algorithm::calculate_neighbours,
+};
+
+const MAX_BODY_PASSES: usize = 16; // Re-collapses attempted before giving up on undersized bodies
+
+impl Map {
+    /// Connected bodies of tiles smaller than their category's minimum body size, as set by
+    /// `Rules::set_min_body_size`, each listed as its cells in row-major order.
+    /// Bodies connect across the edges of the map if the boundary wraps them.
+    #[must_use]
+    pub fn small_bodies(&self, rules: &Rules, boundary: Boundary) -> Vec<Vec<(usize, usize)>> {
+        let (height, width) = self.size();
+        let neighbors = calculate_neighbours(height, width, &self.mask(), boundary);
+        let category = |pos: (usize, usize)| match self[pos] {
+            Cell::Fixed(tile) if tile < rules.len() => rules
+                .min_body_size(tile)
+                .map(|size| (rules.categories()[tile], size)),
+            _ => None,
+        };
+
+        let mut visited = Array2::from_elem((height, width), false);
+        let mut bodies = Vec::new();
+        for pos in (0..height).flat_map(|y| (0..width).map(move |x| (y, x))) {
+            let Some((body_category, min_size)) = category(pos) else {
+                continue;
+            };
+            if visited[pos] {
+                continue;
+            }
+
+            // Flood fill the body through neighbours of the same category
+            visited[pos] = true;
+            let mut body = vec![pos];
+            let mut next = 0;
+            while next < body.len() {
+                let cell = body[next];
+                next += 1;
+                for other in neighbors[cell].iter().map(|neighbor| neighbor.pos) {
+                    if !visited[other] && category(other).is_some_and(|(c, _)| c == body_category) {
+                        visited[other] = true;
+                        body.push(other);
+                    }
+                }
+            }
+
+            if body.len() < min_size {
+                body.sort_unstable();
+                bodies.push(body);
+            }
+        }
+        bodies
+    }
+
+    /// Remove bodies smaller than their category's minimum size from this map, collapsed from a template.
+    /// Each pass re-collapses every undersized body and its neighbours with the algorithm, with the body's
+    /// category excluded from those cells, until no undersized body remains. Undersized bodies are therefore
+    /// filled in rather than grown, so a category kept rare by its frequency may lose most of its bodies.
+    /// Cells fixed by the template are never changed, so bodies made entirely of them are left alone.
+    /// Only the cells around each body are collapsed again, unless the rules or map constrain cells further
+    /// away, by quotas, run limits, line uniqueness, buffer zones, landmarks or profile weights, in which
+    /// case the whole map is.
+    /// The crate's solvers apply this after collapsing, so it only needs calling for maps produced elsewhere.
+    /// Re-collapses use the options, whose boundary also decides which cells neighbour each other.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a re-collapse fails, or undersized bodies remain after the last pass.
+    pub fn enforce_min_bodies(
+        &self,
+        template: &Map,
+        rules: &Rules,
+        algorithm: &impl WaveFunction,
+        options: &CollapseOptions,
+        rng: &mut impl Rng,
+    ) -> Result<Self> {
+        debug_assert!(
+            self.size() == template.size(),
+            "Map must be the same size as its template"
+        );
+        let num_tiles = rules.len();
+        let (height, width) = template.size();
+        let neighbors = calculate_neighbours(height, width, &template.mask(), options.boundary);
+        let is_open = |pos: (usize, usize)| matches!(template[pos], Cell::Wildcard | Cell::Hole);
+
+        // Re-collapses must not recurse into this pass
+        let mut inner_rules = rules.clone();
+        for tile in 0..num_tiles {
+            inner_rules.set_min_body_size(rules.categories()[tile], 0);
+        }
+
+        let mut map = self.clone();
+        for _ in 0..MAX_BODY_PASSES {
+            let bodies: Vec<_> = map
+                .small_bodies(rules, options.boundary)
+                .into_iter()
+                .filter(|body| body.iter().any(|&pos| is_open(pos)))
+                .collect();
+            if bodies.is_empty() {
+                return Ok(map);
+            }
+
+            for body in &bodies {
+                let Cell::Fixed(tile) = map[body[0]] else {
+                    unreachable!("Bodies are made of fixed cells");
+                };
+                let category = rules.categories()[tile];
+                // Neighbours are reopened too, so the surroundings can adapt, but may not start a new body
+                let mut cells = body.clone();
+                for &pos in body {
+                    cells.extend(neighbors[pos].iter().map(|neighbor| neighbor.pos));
+                }
+                cells.retain(|&pos| is_open(pos));
+                cells.sort_unstable();
+                cells.dedup();
+
+                let mut reopened = map.clone();
+                for &pos in &cells {
+                    let options: Vec<usize> = template
+                        .cell_domain(pos, num_tiles)
+                        .ones()
+                        .filter(|&option| rules.categories()[option] != category)
+                        .collect();
+                    reopened[pos] = Cell::Wildcard;
+                    reopened.restrict(pos, &options);
+                }
+                map = reopened.collapse_reopened(&cells, algorithm, &inner_rules, options, rng)?;
+            }
+        }
+
+        let remaining = map
+            .small_bodies(rules, options.boundary)
+            .into_iter()
+            .filter(|body| body.iter().any(|&pos| is_open(pos)))
+            .count();
+        if remaining > 0 {
+            bail!(
+                "{remaining} bodies remain below their minimum size after {MAX_BODY_PASSES} passes"
+            );
+        }
+        Ok(map)
+    }
+
+    // Collapse the reopened cells of a map within the smallest region holding them and a ring of their
+    // neighbours, or over the whole map if any constraint reaches beyond that ring
+    fn collapse_reopened(
+        &self,
+        cells: &[(usize, usize)],
+        algorithm: &impl WaveFunction,
+        rules: &Rules,
+        options: &CollapseOptions,
+        rng: &mut impl Rng,
+    ) -> Result<Self> {
+        let (height, width) = self.size();
+        let on_edge =
+            |&(y, x): &(usize, usize)| y == 0 || x == 0 || y + 1 == height || x + 1 == width;
+        let reaches_far = rules.has_quotas()
+            || rules.has_run_limits()
+            || rules.has_line_uniqueness()
+            || rules.has_buffer_zones()
+            || self.has_frequency_multipliers()
+            || (options.boundary == Boundary::Wrap && cells.iter().any(on_edge));
+        if reaches_far {
+            return algorithm.collapse_with_options(self, rules, options, rng);
+        }
+
+        let top = cells
+            .iter()
+            .map(|pos| pos.0)
+            .min()
+            .unwrap_or(0)
+            .saturating_sub(1);
+        let left = cells
+            .iter()
+            .map(|pos| pos.1)
+            .min()
+            .unwrap_or(0)
+            .saturating_sub(1);
+        let bottom = (cells.iter().map(|pos| pos.0).max().unwrap_or(0) + 2).min(height);
+        let right = (cells.iter().map(|pos| pos.1).max().unwrap_or(0) + 2).min(width);
+        let mut region = Map::new(Array2::from_shape_fn(
+            (bottom - top, right - left),
+            |(y, x)| self[(top + y, left + x)],
+        ));
+        for &(y, x) in cells {
+            if let Some(tiles) = self.restriction((y, x)) {
+                region.restrict((y - top, x - left), tiles);
+            }
+        }
+
+        // The region's edges are either the map's own or fixed cells, so nothing wraps around them
+        let region_options = CollapseOptions {
+            boundary: Boundary::Open,
+            ..options.clone()
+        };
+        let collapsed = algorithm.collapse_with_options(&region, rules, &region_options, rng)?;
+        let mut map = self.clone();
+        for &(y, x) in cells {
+            map[(y, x)] = collapsed[(y - top, x - left)];
+        }
+        Ok(map)
+    }
+}
@@ -0,0 +1,271 @@
+use anyhow::{Result, anyhow, bail};
+use ndarray::Array2;
+use std::{io::Read, iter};
+
+use crate::{Cell, Map};
+
+const PACKET_MAGIC: &[u8; 3] = b"WFP";
+const PACKET_VERSION: u8 = 2;
+const FLAG_DELTA: u8 = 0b01; // Cells are stored relative to a base map the receiver already holds
+const FLAG_ZSTD: u8 = 0b10; // The body after the header is zstd compressed
+const UNCHANGED: u64 = 0; // Code of a delta cell which matches the base map
+const MAX_VARINT_BYTES: usize = 10; // Longest encoding of a 64 bit integer
+
+/// Largest number of cells `Map::from_packet` accepts, to bound what a malicious packet can make it allocate.
+pub const MAX_PACKET_CELLS: usize = 1 << 24;
+
+/// Compression applied to the body of an encoded packet.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PacketCompression {
+    #[default]
+    None,
+    /// Zstandard at the given level, which pays off for large or noisy regions.
+    #[cfg(feature = "zstd")]
+    Zstd(i32),
+}
+
+impl Map {
+    /// Encode the map as a compact packet, such as for sending a generated region from a server to clients.
+    /// Cells are run-length encoded in row-major order as variable-length integers, so uniform areas cost
+    /// a couple of bytes. Tile restrictions are not included.
+    pub fn to_packet(&self, compression: PacketCompression) -> Vec<u8> {
+        let codes = cells(self).map(cell_code);
+        encode_packet(self.size(), codes, 0, compression)
+    }
+
+    /// Encode the map as a packet of its differences from a base map of the same size, which the receiver
+    /// must already hold, such as a client's copy of a region before it was regenerated or edited.
+    /// Unchanged cells form long runs, so small edits make very small packets.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the base map is not the same size as this one.
+    pub fn to_delta_packet(&self, base: &Map, compression: PacketCompression) -> Result<Vec<u8>> {
+        if base.size() != self.size() {
+            bail!(
+                "Delta base must be the same size as the map, found {:?} and {:?}",
+                base.size(),
+                self.size()
+            );
+        }
+        let codes = cells(self).zip(cells(base)).map(|(cell, base_cell)| {
+            if cell == base_cell {
+                UNCHANGED
+            } else {
+                cell_code(cell) + 1
+            }
+        });
+        Ok(encode_packet(self.size(), codes, FLAG_DELTA, compression))
+    }
+
+    /// Decode a packet made by `to_packet`, or by `to_delta_packet` given the same base map.
+    /// Packets come from the network, so anything malformed gives an error rather than a panic, and packets of
+    /// more than `MAX_PACKET_CELLS` cells are refused before anything is allocated for them.
+    /// Packets of version 1, from before hole cells, still decode.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the packet is truncated, corrupt, of an unknown version or too large, or is a delta packet
+    /// without the base map it was encoded against.
+    pub fn from_packet(packet: &[u8], base: Option<&Map>) -> Result<Self> {
+        Self::from_packet_with_limit(packet, base, MAX_PACKET_CELLS)
+    }
+
+    /// Decode a packet as `from_packet` does, refusing packets of more than `max_cells` cells.
+    ///
+    /// # Errors
+    ///
+    /// Fails as `from_packet` does.
+    pub fn from_packet_with_limit(
+        packet: &[u8],
+        base: Option<&Map>,
+        max_cells: usize,
+    ) -> Result<Self> {
+        if packet.len() < PACKET_MAGIC.len() + 2 || &packet[..PACKET_MAGIC.len()] != PACKET_MAGIC {
+            bail!("Not a map packet");
+        }
+        let version = packet[PACKET_MAGIC.len()];
+        if !(1..=PACKET_VERSION).contains(&version) {
+            bail!("Unsupported packet version {version} (expected 1 to {PACKET_VERSION})");
+        }
+        let flags = packet[PACKET_MAGIC.len() + 1];
+        // A valid body holds the size and at most one run per cell, so reading more than that cannot succeed
+        let max_body = max_cells
+            .checked_mul(2 * MAX_VARINT_BYTES)
+            .and_then(|bytes| bytes.checked_add(2 * MAX_VARINT_BYTES + 1))
+            .unwrap_or(usize::MAX) as u64;
+        let mut body = decompress(&packet[PACKET_MAGIC.len() + 2..], flags)?.take(max_body);
+
+        let height = read_length(&mut body)?;
+        let width = read_length(&mut body)?;
+        if height == 0 || width == 0 {
+            bail!("Packet map must contain at least one cell");
+        }
+        let num_cells = height
+            .checked_mul(width)
+            .filter(|&num_cells| num_cells <= max_cells)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Packet map of {height}x{width} cells is larger than the limit of {max_cells}"
+                )
+            })?;
+        let is_delta = flags & FLAG_DELTA != 0;
+        let base: Option<Vec<Cell>> = match (is_delta, base) {
+            (true, Some(base)) if base.size() == (height, width) => Some(cells(base).collect()),
+            (true, Some(base)) => bail!(
+                "Delta base must be {:?}, found {:?}",
+                (height, width),
+                base.size()
+            ),
+            (true, None) => bail!("Delta packet requires the base map it was encoded against"),
+            (false, _) => None,
+        };
+
+        let mut cells = Vec::with_capacity(num_cells);
+        while cells.len() < num_cells {
+            let run = read_length(&mut body)?;
+            let code = read_varint(&mut body)?;
+            if run == 0
+                || cells
+                    .len()
+                    .checked_add(run)
+                    .is_none_or(|end| end > num_cells)
+            {
+                bail!("Packet run of {run} cells does not fit the map");
+            }
+            let cell = match &base {
+                Some(base) if code == UNCHANGED => {
+                    let start = cells.len();
+                    cells.extend_from_slice(&base[start..start + run]);
+                    continue;
+                }
+                Some(_) => code_cell(code - 1, version)?,
+                None => code_cell(code, version)?,
+            };
+            cells.extend(iter::repeat_n(cell, run));
+        }
+        let mut trailing = [0];
+        if body.read(&mut trailing)? != 0 {
+            bail!("Packet has unexpected trailing bytes");
+        }
+        Ok(Map::new(Array2::from_shape_vec((height, width), cells)?))
+    }
+}
+
+// Every cell of a map in row-major order
+fn cells(map: &Map) -> impl Iterator<Item = Cell> + '_ {
+    let (height, width) = map.size();
+    (0..height).flat_map(move |y| (0..width).map(move |x| map[(y, x)]))
+}
+
+// Header, then the size and (run, code) pairs as varints, compressed as requested
+fn encode_packet(
+    size: (usize, usize),
+    codes: impl Iterator<Item = u64>,
+    flags: u8,
+    compression: PacketCompression,
+) -> Vec<u8> {
+    let mut body = Vec::new();
+    write_varint(&mut body, size.0 as u64);
+    write_varint(&mut body, size.1 as u64);
+    let mut run: Option<(u64, u64)> = None;
+    for code in codes {
+        run = match run {
+            Some((length, current)) if current == code => Some((length + 1, current)),
+            Some((length, current)) => {
+                write_varint(&mut body, length);
+                write_varint(&mut body, current);
+                Some((1, code))
+            }
+            None => Some((1, code)),
+        };
+    }
+    if let Some((length, current)) = run {
+        write_varint(&mut body, length);
+        write_varint(&mut body, current);
+    }
+
+    let mut packet = PACKET_MAGIC.to_vec();
+    packet.push(PACKET_VERSION);
+    match compression {
+        PacketCompression::None => {
+            packet.push(flags);
+            packet.extend(body);
+        }
+        #[cfg(feature = "zstd")]
+        PacketCompression::Zstd(level) => {
+            packet.push(flags | FLAG_ZSTD);
+            packet
+                .extend(zstd::encode_all(&body[..], level).expect("Writing to memory cannot fail"));
+        }
+    }
+    packet
+}
+
+// Reader of the body of a packet, decompressing it as it is read if it was compressed, so a small packet
+// claiming to expand enormously is only decompressed as far as its cells are read
+fn decompress(body: &[u8], flags: u8) -> Result<Box<dyn Read + '_>> {
+    if flags & FLAG_ZSTD == 0 {
+        return Ok(Box::new(body));
+    }
+    #[cfg(feature = "zstd")]
+    {
+        Ok(Box::new(zstd::stream::read::Decoder::new(body)?))
+    }
+    #[cfg(not(feature = "zstd"))]
+    {
+        bail!("Packet is zstd compressed, which requires the zstd feature")
+    }
+}
+
+// Ignored and wildcard cells take the two smallest codes, followed by the tiles
+fn cell_code(cell: Cell) -> u64 {
+    match cell {
+        Cell::Ignore => 0,
+        Cell::Wildcard => 1,
+        Cell::Hole => 2,
+        Cell::Fixed(tile) => tile as u64 + 3,
+    }
+}
+
+// Version 1 packets predate hole cells, so their tiles start one code earlier
+fn code_cell(code: u64, version: u8) -> Result<Cell> {
+    let first_tile = if version == 1 { 2 } else { 3 };
+    Ok(match code {
+        0 => Cell::Ignore,
+        1 => Cell::Wildcard,
+        2 if version > 1 => Cell::Hole,
+        tile => Cell::Fixed(usize::try_from(tile - first_tile)?),
+    })
+}
+
+// LEB128: seven bits per byte, least significant first, with the high bit set on all but the last byte
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push((value as u8 & 0x7F) | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn read_varint(bytes: &mut impl Read) -> Result<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0];
+        if bytes.read(&mut byte)? == 0 {
+            bail!("Packet ended unexpectedly");
+        }
+        let byte = byte[0];
+        value |= u64::from(byte & 0x7F) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    bail!("Packet contains an overlong integer")
+}
+
+// A size or run length, which must fit in memory
+fn read_length(bytes: &mut impl Read) -> Result<usize> {
+    let value = read_varint(bytes)?;
+    usize::try_from(value).map_err(|_| anyhow!("Packet length {value} is too large"))
+}
@@ -0,0 +1,115 @@
+use ndarray::Array2;
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use crate::{ALL_DIRECTIONS, Cell, Map};
+
+impl Map {
+    /// Cheapest cost of reaching every cell from the nearest of the sources, moving between edge-adjacent cells.
+    /// Entering a cell costs the movement cost of its tile, indexed by tile, where `None` marks a tile which
+    /// cannot be entered. Wildcard and ignored cells cannot be entered either. Unreachable cells are `None`.
+    #[must_use]
+    pub fn distance_field(
+        &self,
+        sources: &[(usize, usize)],
+        costs: &[Option<u32>],
+    ) -> Array2<Option<u32>> {
+        let mut distances = Array2::from_elem(self.size(), None);
+        let mut queue = BinaryHeap::new();
+        for &source in sources {
+            distances[source] = Some(0);
+            queue.push(Reverse((0, source)));
+        }
+
+        while let Some(Reverse((distance, pos))) = queue.pop() {
+            if distances[pos].is_some_and(|d| d < distance) {
+                continue;
+            }
+            for (next, cost) in self.steps(pos, costs) {
+                let next_distance = distance + cost;
+                if distances[next].is_none_or(|d| next_distance < d) {
+                    distances[next] = Some(next_distance);
+                    queue.push(Reverse((next_distance, next)));
+                }
+            }
+        }
+        distances
+    }
+
+    /// Cheapest path between two cells using A*, with costs as for `distance_field`.
+    /// Returns the cells of the path, including both ends, and its total cost, or `None` if the goal is unreachable.
+    #[must_use]
+    pub fn find_path(
+        &self,
+        start: (usize, usize),
+        goal: (usize, usize),
+        costs: &[Option<u32>],
+    ) -> Option<(Vec<(usize, usize)>, u32)> {
+        // Every step costs at least the cheapest tile, which keeps the estimate admissible
+        let min_cost = costs.iter().flatten().copied().min().unwrap_or(0);
+        let estimate = |pos: (usize, usize)| {
+            (pos.0.abs_diff(goal.0) + pos.1.abs_diff(goal.1)) as u32 * min_cost
+        };
+
+        let mut distances = Array2::from_elem(self.size(), None);
+        let mut previous: Array2<Option<(usize, usize)>> = Array2::from_elem(self.size(), None);
+        let mut queue = BinaryHeap::new();
+        distances[start] = Some(0);
+        queue.push(Reverse((estimate(start), 0, start)));
+
+        while let Some(Reverse((_, distance, pos))) = queue.pop() {
+            if pos == goal {
+                let mut path = vec![goal];
+                let mut current = goal;
+                while let Some(prev) = previous[current] {
+                    path.push(prev);
+                    current = prev;
+                }
+                path.reverse();
+                return Some((path, distance));
+            }
+            if distances[pos].is_some_and(|d| d < distance) {
+                continue;
+            }
+            for (next, cost) in self.steps(pos, costs) {
+                let next_distance = distance + cost;
+                if distances[next].is_none_or(|d| next_distance < d) {
+                    distances[next] = Some(next_distance);
+                    previous[next] = Some(pos);
+                    queue.push(Reverse((
+                        next_distance + estimate(next),
+                        next_distance,
+                        next,
+                    )));
+                }
+            }
+        }
+        None
+    }
+
+    /// Whether the goal can be reached from the start, with costs as for `distance_field`.
+    /// Useful for rejecting generated maps whose key locations are cut off from each other.
+    #[must_use]
+    pub fn is_reachable(
+        &self,
+        start: (usize, usize),
+        goal: (usize, usize),
+        costs: &[Option<u32>],
+    ) -> bool {
+        self.find_path(start, goal, costs).is_some()
+    }
+
+    // Neighbouring cells which can be entered from a cell, with the cost of entering them
+    fn steps<'a>(
+        &'a self,
+        pos: (usize, usize),
+        costs: &'a [Option<u32>],
+    ) -> impl Iterator<Item = ((usize, usize), u32)> + 'a {
+        ALL_DIRECTIONS.iter().filter_map(move |direction| {
+            let next = direction.apply_to(pos, self.size())?;
+            match self[next] {
+                Cell::Fixed(tile) => costs[tile].map(|cost| (next, cost)),
+                Cell::Wildcard | Cell::Hole | Cell::Ignore => None,
+            }
+        })
+    }
+}
@@ -0,0 +1,65 @@
+use anyhow::{Context, Result};
+use ndarray::s;
+use photo::ImageRGBA;
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use crate::{
+    Cell, Map, Tileset,
+    map::{HOLE_COLOUR, IGNORE_COLOUR, WILDCARD_COLOUR},
+};
+
+impl Map {
+    /// Render the map straight to a PNG file, one row of tiles at a time, producing the same image as `render`.
+    /// Only a single row of tiles is held in memory, so maps far too large to render as one image can still be written.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file cannot be created or the image cannot be encoded.
+    pub fn render_to_png(&self, tileset: &Tileset, path: &Path) -> Result<()> {
+        debug_assert!(
+            self.max_index().is_none_or(|index| index < tileset.len()),
+            "Index out of bounds for tileset"
+        );
+        let (height, width) = self.size();
+        let interior_size = tileset.interior_size();
+        let interiors = tileset.interiors();
+        let wildcard_img = ImageRGBA::filled([interior_size, interior_size], WILDCARD_COLOUR);
+        let ignore_img = ImageRGBA::filled([interior_size, interior_size], IGNORE_COLOUR);
+        let hole_img = ImageRGBA::filled([interior_size, interior_size], HOLE_COLOUR);
+
+        let file =
+            File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+        let mut encoder = png::Encoder::new(
+            BufWriter::new(file),
+            (width * interior_size) as u32,
+            (height * interior_size) as u32,
+        );
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        let mut stream = writer.stream_writer()?;
+
+        let mut band = Vec::with_capacity(interior_size * width * interior_size * 4);
+        for y in 0..height {
+            band.clear();
+            for pixel_row in 0..interior_size {
+                for x in 0..width {
+                    let tile = match self[(y, x)] {
+                        Cell::Fixed(index) => &interiors[index],
+                        Cell::Wildcard => &wildcard_img,
+                        Cell::Ignore => &ignore_img,
+                        Cell::Hole => &hole_img,
+                    };
+                    band.extend(tile.data.slice(s![pixel_row, .., ..]).iter());
+                }
+            }
+            stream.write_all(&band)?;
+        }
+        stream.finish()?;
+        Ok(())
+    }
+}
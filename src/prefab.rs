@@ -0,0 +1,276 @@
+use anyhow::{Context, Result, bail};
+use rand::Rng;
+
+use crate::{Cell, Collapser, Map, Rules};
+
+/// A hand-made pattern of cells, such as a building or room, to be stamped onto a template before collapse.
+/// Wildcard cells of the pattern leave the template untouched.
+#[derive(Clone)]
+pub struct Prefab {
+    pub name: String,
+    pub cells: Map,
+    pub tags: Vec<String>,
+}
+
+impl Prefab {
+    #[must_use]
+    pub fn new(name: &str, cells: Map) -> Self {
+        Self {
+            name: name.to_string(),
+            cells,
+            tags: Vec::new(),
+        }
+    }
+
+    /// Add a tag, for use with `PrefabLayout::exclude`.
+    #[must_use]
+    pub fn with_tag(mut self, tag: &str) -> Self {
+        self.tags.push(tag.to_string());
+        self
+    }
+
+    #[must_use]
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
+    }
+}
+
+/// A prefab placed with its top-left cell at `position`, given as `(y, x)`.
+#[derive(Clone, Copy, Debug)]
+pub struct Placement {
+    pub prefab: usize,
+    pub position: (usize, usize),
+}
+
+/// Prefabs placed on a template of a given size, kept apart by exclusion zones.
+pub struct PrefabLayout {
+    size: (usize, usize),
+    prefabs: Vec<Prefab>,
+    placements: Vec<Placement>,
+    // No two prefabs sharing the tag may be placed within the distance of each other
+    exclusions: Vec<(String, usize)>,
+}
+
+impl PrefabLayout {
+    /// Create an empty layout for a template of the given `(height, width)`.
+    #[must_use]
+    pub fn new(size: (usize, usize)) -> Self {
+        Self {
+            size,
+            prefabs: Vec::new(),
+            placements: Vec::new(),
+            exclusions: Vec::new(),
+        }
+    }
+
+    /// Register a prefab, returning its index for placing.
+    pub fn add_prefab(&mut self, prefab: Prefab) -> usize {
+        self.prefabs.push(prefab);
+        self.prefabs.len() - 1
+    }
+
+    /// Keep any two prefabs tagged `tag` at least `distance` cells apart, counting the cells between their
+    /// footprints along the row or column in which they are furthest apart.
+    pub fn exclude(&mut self, tag: &str, distance: usize) {
+        self.exclusions.push((tag.to_string(), distance));
+    }
+
+    #[must_use]
+    pub fn prefabs(&self) -> &[Prefab] {
+        &self.prefabs
+    }
+
+    #[must_use]
+    pub fn placements(&self) -> &[Placement] {
+        &self.placements
+    }
+
+    /// Place a prefab at the given `(y, x)` position.
+    ///
+    /// # Errors
+    ///
+    /// Fails if it would fall outside the template, overlap another prefab or break an exclusion zone.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the prefab index is out of bounds.
+    pub fn place(&mut self, prefab: usize, position: (usize, usize)) -> Result<()> {
+        let placement = Placement { prefab, position };
+        self.check(placement)?;
+        self.placements.push(placement);
+        Ok(())
+    }
+
+    /// Place a prefab at a random position which respects the exclusion zones, giving up after
+    /// `max_attempts` positions have been rejected.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the prefab is larger than the template, or no room is found for it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the prefab index is out of bounds.
+    pub fn place_randomly(
+        &mut self,
+        prefab: usize,
+        max_attempts: usize,
+        rng: &mut impl Rng,
+    ) -> Result<(usize, usize)> {
+        assert!(prefab < self.prefabs.len(), "Prefab index out of bounds");
+        let (height, width) = self.prefabs[prefab].cells.size();
+        if height > self.size.0 || width > self.size.1 {
+            bail!(
+                "Prefab `{}` does not fit in the template",
+                self.prefabs[prefab].name
+            );
+        }
+
+        for _ in 0..max_attempts {
+            let position = (
+                rng.random_range(0..=self.size.0 - height),
+                rng.random_range(0..=self.size.1 - width),
+            );
+            if self.place(prefab, position).is_ok() {
+                return Ok(position);
+            }
+        }
+        bail!(
+            "No room found for prefab `{}` after {} attempts",
+            self.prefabs[prefab].name,
+            max_attempts
+        )
+    }
+
+    fn check(&self, placement: Placement) -> Result<()> {
+        assert!(
+            placement.prefab < self.prefabs.len(),
+            "Prefab index out of bounds"
+        );
+        let prefab = &self.prefabs[placement.prefab];
+        let (top, left) = placement.position;
+        if top + prefab.cells.height() > self.size.0 || left + prefab.cells.width() > self.size.1 {
+            bail!(
+                "Prefab `{}` at ({}, {}) extends beyond the template",
+                prefab.name,
+                top,
+                left
+            );
+        }
+
+        for other in &self.placements {
+            let other_prefab = &self.prefabs[other.prefab];
+            let gap = self.gap(placement, *other);
+            if gap.is_none() {
+                bail!(
+                    "Prefab `{}` at ({}, {}) overlaps `{}` at ({}, {})",
+                    prefab.name,
+                    top,
+                    left,
+                    other_prefab.name,
+                    other.position.0,
+                    other.position.1
+                );
+            }
+            for (tag, distance) in &self.exclusions {
+                if prefab.has_tag(tag) && other_prefab.has_tag(tag) && gap < Some(*distance) {
+                    bail!(
+                        "Prefab `{}` at ({}, {}) is within {} cells of `{}` at ({}, {}), which shares tag `{}`",
+                        prefab.name,
+                        top,
+                        left,
+                        distance,
+                        other_prefab.name,
+                        other.position.0,
+                        other.position.1,
+                        tag
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Cells between the footprints of two placements, or None if they overlap
+    fn gap(&self, a: Placement, b: Placement) -> Option<usize> {
+        let span = |placement: Placement| {
+            let (height, width) = self.prefabs[placement.prefab].cells.size();
+            let (top, left) = placement.position;
+            ((top, top + height), (left, left + width))
+        };
+        let (a_rows, a_cols) = span(a);
+        let (b_rows, b_cols) = span(b);
+        let between = |a: (usize, usize), b: (usize, usize)| {
+            if a.1 <= b.0 {
+                Some(b.0 - a.1)
+            } else if b.1 <= a.0 {
+                Some(a.0 - b.1)
+            } else {
+                None
+            }
+        };
+        match (between(a_rows, b_rows), between(a_cols, b_cols)) {
+            (None, None) => None,
+            (rows, cols) => rows.max(cols),
+        }
+    }
+
+    /// Stamp every placed prefab onto a template, leaving the template's own cells under wildcard prefab cells.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a prefab would fix a cell the template has already fixed to a different tile.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the template is not the size of the layout.
+    pub fn apply(&self, template: &Map) -> Result<Map> {
+        assert_eq!(
+            template.size(),
+            self.size,
+            "Template must match the size of the layout"
+        );
+        let mut result = template.clone();
+        for placement in &self.placements {
+            let prefab = &self.prefabs[placement.prefab];
+            let (top, left) = placement.position;
+            for y in 0..prefab.cells.height() {
+                for x in 0..prefab.cells.width() {
+                    let pos = (top + y, left + x);
+                    match (prefab.cells[(y, x)], template[pos]) {
+                        (Cell::Wildcard, _) => {}
+                        (cell, Cell::Wildcard) => result[pos] = cell,
+                        (cell, existing) if cell == existing => {}
+                        (_, existing) => bail!(
+                            "Prefab `{}` conflicts with the template at ({}, {}), which is `{}`",
+                            prefab.name,
+                            pos.0,
+                            pos.1,
+                            existing
+                        ),
+                    }
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Stamp the prefabs onto a template and check that the result is consistent with the rules, by running
+    /// the initial constraint propagation, so impossible layouts are caught before collapse begins.
+    /// Propagation catches any prefab cells which cannot sit next to each other or their surroundings, though
+    /// a consistent layout can still occasionally fail to collapse. Returns the stamped template.
+    ///
+    /// # Errors
+    ///
+    /// Fails as `apply` does, or if propagating the stamped template leaves a cell with no options.
+    ///
+    /// # Panics
+    ///
+    /// Panics as `apply` does.
+    pub fn validate(&self, template: &Map, rules: &Rules) -> Result<Map> {
+        let stamped = self.apply(template)?;
+        Collapser::new(&stamped, rules)
+            .context("Prefab placements cannot be satisfied by the rules")?;
+        Ok(stamped)
+    }
+}
@@ -0,0 +1,86 @@
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// Rows or columns of a map covered by a `Profile`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Span {
+    Rows(Range<usize>),
+    Columns(Range<usize>),
+}
+
+impl Span {
+    #[must_use]
+    pub fn contains(&self, pos: (usize, usize)) -> bool {
+        match self {
+            Self::Rows(rows) => rows.contains(&pos.0),
+            Self::Columns(columns) => columns.contains(&pos.1),
+        }
+    }
+}
+
+/// Constraints on whole rows or columns of a map, such as the top rows only holding sky tiles, or a road
+/// being more likely down the middle column, registered with `Map::add_profile`.
+/// A profile may limit the tiles its open cells can take, and scale the frequencies of tiles chosen there.
+/// Overlapping profiles combine: their tile limits intersect and their weights multiply.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    pub span: Span,
+    /// Tiles the open cells of the span are limited to, if any.
+    pub tiles: Option<Vec<usize>>,
+    /// Multiplier of each weighted tile's frequency within the span, as (tile, weight).
+    pub weights: Vec<(usize, f64)>,
+}
+
+impl Profile {
+    #[must_use]
+    pub fn rows(rows: Range<usize>) -> Self {
+        Self::new(Span::Rows(rows))
+    }
+
+    #[must_use]
+    pub fn columns(columns: Range<usize>) -> Self {
+        Self::new(Span::Columns(columns))
+    }
+
+    fn new(span: Span) -> Self {
+        Self {
+            span,
+            tiles: None,
+            weights: Vec::new(),
+        }
+    }
+
+    /// Limit the open cells of the span to the given tiles, as `Map::restrict` does for single cells.
+    #[must_use]
+    pub fn only(mut self, tiles: &[usize]) -> Self {
+        let mut tiles = tiles.to_vec();
+        tiles.sort_unstable();
+        tiles.dedup();
+        self.tiles = Some(tiles);
+        self
+    }
+
+    /// Multiply the frequency of a tile by `weight` within the span.
+    #[must_use]
+    pub fn weight(mut self, tile: usize, weight: f64) -> Self {
+        debug_assert!(
+            weight.is_finite() && weight > 0.0,
+            "Profile weight must be positive and finite"
+        );
+        self.weights.push((tile, weight));
+        self
+    }
+
+    /// Factor by which the frequency of a tile is multiplied at a cell.
+    #[must_use]
+    pub fn multiplier(&self, pos: (usize, usize), tile: usize) -> f64 {
+        if !self.span.contains(pos) {
+            return 1.0;
+        }
+        self.weights
+            .iter()
+            .filter(|&&(weighted, _)| weighted == tile)
+            .map(|&(_, weight)| weight)
+            .product()
+    }
+}
@@ -0,0 +1,131 @@
+use std::{
+    fmt::{Display, Formatter},
+    time::Duration,
+};
+
+use crate::{CollapseOptions, Rules, algorithm::hash_settings};
+
+const CRATE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// How a collapsed map was generated, so a saved map can be traced back to the inputs which made it.
+/// The crate's solvers attach this to every map they collapse, available from `Map::provenance`, and `save`
+/// writes it as a comment line which `load` reads back.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Provenance {
+    /// Seed of the generator, when the map was collapsed from one, as by `Map::collapse_seeded`.
+    pub seed: Option<u64>,
+    /// Name of the algorithm, as in its `AlgorithmInfo`.
+    pub algorithm: String,
+    /// Hash of the collapse options, as given by `CollapseOptions::content_hash`, extended with the settings
+    /// particular to the algorithm, as listed by `WaveFunction::settings`.
+    pub options_hash: u64,
+    /// Settings particular to the algorithm, and to any algorithm wrapping it, as (name, value) pairs.
+    pub settings: Vec<(String, String)>,
+    /// Hash of the rules, as given by `Rules::content_hash`.
+    pub rules_hash: u64,
+    /// Hash of the tileset, as given by `Tileset::content_hash`. Solvers only see the rules, so this is left
+    /// for the caller to fill in.
+    pub tileset_hash: Option<u64>,
+    /// Version of this crate.
+    pub crate_version: String,
+    /// Time taken to collapse the map.
+    pub duration: Duration,
+    /// Number of contradictions recovered from, by backtracking or resetting cells.
+    pub backtracks: usize,
+}
+
+impl Provenance {
+    pub(crate) fn new(
+        algorithm: &str,
+        settings: &[(&'static str, String)],
+        options: &CollapseOptions,
+        rules: &Rules,
+        duration: Duration,
+        backtracks: usize,
+    ) -> Self {
+        let provenance = Self {
+            seed: None,
+            algorithm: algorithm.to_string(),
+            options_hash: options.content_hash(),
+            settings: Vec::new(),
+            rules_hash: rules.content_hash(),
+            tileset_hash: None,
+            crate_version: CRATE_VERSION.to_string(),
+            duration,
+            backtracks,
+        };
+        provenance.with_settings(settings)
+    }
+
+    // Add the settings of an algorithm, either the one which collapsed the map or one wrapping it
+    pub(crate) fn with_settings(mut self, settings: &[(&'static str, String)]) -> Self {
+        self.options_hash = hash_settings(self.options_hash, settings);
+        self.settings.extend(
+            settings
+                .iter()
+                .map(|(name, value)| ((*name).to_string(), value.clone())),
+        );
+        self
+    }
+
+    // Read provenance from the fields written by `Display`, or None if any are missing or malformed
+    pub(crate) fn parse(fields: &str) -> Option<Self> {
+        let mut provenance = Self {
+            seed: None,
+            algorithm: String::new(),
+            options_hash: 0,
+            settings: Vec::new(),
+            rules_hash: 0,
+            tileset_hash: None,
+            crate_version: String::new(),
+            duration: Duration::ZERO,
+            backtracks: 0,
+        };
+        let hex = |value: &str| u64::from_str_radix(value, 16).ok();
+        for field in fields.split_whitespace() {
+            let (key, value) = field.split_once('=')?;
+            match key {
+                "seed" => provenance.seed = Some(value.parse().ok()?),
+                "algorithm" => provenance.algorithm = value.to_string(),
+                "options" => provenance.options_hash = hex(value)?,
+                "rules" => provenance.rules_hash = hex(value)?,
+                "tileset" => provenance.tileset_hash = Some(hex(value)?),
+                "version" => provenance.crate_version = value.to_string(),
+                "duration" => provenance.duration = Duration::from_secs_f64(value.parse().ok()?),
+                "backtracks" => provenance.backtracks = value.parse().ok()?,
+                _ => provenance
+                    .settings
+                    .push((key.to_string(), value.to_string())),
+            }
+        }
+        (!provenance.algorithm.is_empty()).then_some(provenance)
+    }
+}
+
+/// Space separated `key=value` fields, with hashes in hexadecimal and the duration in seconds.
+/// The algorithm's settings follow the hashes, each as a field of its own.
+impl Display for Provenance {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        if let Some(seed) = self.seed {
+            write!(f, "seed={seed} ")?;
+        }
+        write!(
+            f,
+            "algorithm={} options={:016x} rules={:016x} ",
+            self.algorithm, self.options_hash, self.rules_hash
+        )?;
+        if let Some(tileset_hash) = self.tileset_hash {
+            write!(f, "tileset={tileset_hash:016x} ")?;
+        }
+        for (name, value) in &self.settings {
+            write!(f, "{name}={value} ")?;
+        }
+        write!(
+            f,
+            "version={} duration={} backtracks={}",
+            self.crate_version,
+            self.duration.as_secs_f64(),
+            self.backtracks
+        )
+    }
+}
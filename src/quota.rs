@@ -0,0 +1,38 @@
+/// Bounds on how many cells of a map a category's tiles may fill, set with `Rules::set_quota`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Quota {
+    /// Between `min` and `max` cells, inclusive.
+    Count { min: usize, max: usize },
+    /// Between `min` and `max` of the map's open cells, as fractions from zero to one.
+    Proportion { min: f64, max: f64 },
+}
+
+impl Quota {
+    /// Fewest cells which may hold the category's tiles.
+    #[must_use]
+    pub fn at_least(min: usize) -> Self {
+        Self::Count {
+            min,
+            max: usize::MAX,
+        }
+    }
+
+    /// Most cells which may hold the category's tiles.
+    #[must_use]
+    pub fn at_most(max: usize) -> Self {
+        Self::Count { min: 0, max }
+    }
+
+    /// Smallest and largest number of cells allowed in a map of `num_cells` open cells.
+    /// Proportions are rounded inwards, so the bounds may cross when no whole number of cells meets them.
+    #[must_use]
+    pub fn bounds(&self, num_cells: usize) -> (usize, usize) {
+        match *self {
+            Self::Count { min, max } => (min, max),
+            Self::Proportion { min, max } => (
+                (min * num_cells as f64).ceil() as usize,
+                (max * num_cells as f64).floor() as usize,
+            ),
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    AlgorithmInfo, Collapse, WaveFunctionBacktracking, WaveFunctionFast, WaveFunctionLocalReset,
+    WaveFunctionPortfolio, WaveFunctionWithRestarts,
+};
+
+type Factory = Box<dyn Fn() -> Box<dyn Collapse> + Send + Sync>;
+
+/// Maps algorithm names to constructors, so algorithms can be selected from strings at runtime.
+pub struct AlgorithmRegistry {
+    factories: BTreeMap<String, Factory>,
+}
+
+impl AlgorithmRegistry {
+    /// Create a registry with no algorithms registered.
+    #[must_use]
+    pub fn empty() -> Self {
+        Self {
+            factories: BTreeMap::new(),
+        }
+    }
+
+    /// Register an algorithm under a name, replacing any algorithm already registered with that name.
+    pub fn register(
+        &mut self,
+        name: &str,
+        factory: impl Fn() -> Box<dyn Collapse> + Send + Sync + 'static,
+    ) {
+        self.factories.insert(name.to_string(), Box::new(factory));
+    }
+
+    /// Construct the algorithm registered under a name.
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<Box<dyn Collapse>> {
+        self.factories.get(name).map(|factory| factory())
+    }
+
+    /// Name and capabilities of the algorithm registered under a name.
+    #[must_use]
+    pub fn info(&self, name: &str) -> Option<AlgorithmInfo> {
+        self.factories.get(name).map(|factory| factory().info_dyn())
+    }
+
+    /// Registered algorithm names with their capabilities, in alphabetical order of name.
+    pub fn infos(&self) -> impl Iterator<Item = (&str, AlgorithmInfo)> {
+        self.factories
+            .iter()
+            .map(|(name, factory)| (name.as_str(), factory().info_dyn()))
+    }
+
+    #[must_use]
+    pub fn contains(&self, name: &str) -> bool {
+        self.factories.contains_key(name)
+    }
+
+    /// Registered algorithm names, in alphabetical order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.factories.keys().map(String::as_str)
+    }
+}
+
+impl Default for AlgorithmRegistry {
+    /// Create a registry containing the crate's algorithms in their default configurations.
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.register("fast", || Box::new(WaveFunctionFast::default()));
+        registry.register("backtracking", || {
+            Box::new(WaveFunctionBacktracking::default())
+        });
+        registry.register(
+            "local-reset",
+            || Box::new(WaveFunctionLocalReset::default()),
+        );
+        registry.register("portfolio", || Box::new(WaveFunctionPortfolio::default()));
+        registry.register("restarts", || Box::new(WaveFunctionWithRestarts::default()));
+        registry
+    }
+}
@@ -0,0 +1,90 @@
+/// How a map is projected when rendered to an image.
+#[derive(Clone, Copy, Debug, Default)]
+pub enum RenderMode {
+    /// Tiles are laid out on a square grid, edge to edge.
+    #[default]
+    Orthographic,
+    /// Tiles are placed on a diamond grid, with each row of the map running down and to the right
+    /// and each column running down and to the left.
+    /// Tile images are drawn unchanged, so they should already be isometric sprites whose footprint
+    /// is a diamond half as tall as it is wide, sitting at the bottom of the image.
+    Isometric {
+        /// Pixels by which horizontally neighbouring footprints overlap, to hide seams in the artwork.
+        overlap: usize,
+        /// Which tiles are drawn on top where their images overlap.
+        z_order: ZOrder,
+    },
+    /// Tiles are laid out on a square grid as for `Orthographic`, but drawn whole rather than as interiors, so
+    /// each tile's borders overlap its neighbours' interiors and are combined with them by `blend`, for art
+    /// styles which shade across tile edges.
+    /// Tiles are drawn row by row, each blended over the tiles drawn before it, and borders falling outside
+    /// the map are clipped.
+    Overlapped {
+        /// How overlapping pixels are combined.
+        blend: Blend,
+    },
+}
+
+/// Drawing order of overlapping tiles in an isometric render.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ZOrder {
+    /// Tiles nearer the bottom of the image are drawn over those behind them, as for a scene viewed from above.
+    #[default]
+    NearestOnTop,
+    /// Tiles nearer the top of the image are drawn over those in front of them, as for a ceiling viewed from below.
+    FarthestOnTop,
+}
+
+/// How a tile's pixel is combined with the pixel already drawn beneath it where tiles overlap.
+#[derive(Clone, Copy, Debug, Default)]
+pub enum Blend {
+    /// The tile is alpha composited over what is beneath it.
+    #[default]
+    Over,
+    /// The mean of the two pixels, so neither tile takes precedence.
+    Average,
+    /// The colours are multiplied, weighted by the tile's alpha, darkening where borders overlap.
+    Multiply,
+    /// A function of the tile's pixel and the pixel beneath it, in that order, giving the blended pixel.
+    Custom(fn(top: [u8; 4], bottom: [u8; 4]) -> [u8; 4]),
+}
+
+impl Blend {
+    /// Combine a tile's pixel with the pixel beneath it.
+    #[must_use]
+    pub fn apply(&self, top: [u8; 4], bottom: [u8; 4]) -> [u8; 4] {
+        let unit = |value: u8| f64::from(value) / 255.0;
+        let byte = |value: f64| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+        match self {
+            Self::Over => {
+                let top_alpha = unit(top[3]);
+                let bottom_alpha = unit(bottom[3]) * (1.0 - top_alpha);
+                let alpha = top_alpha + bottom_alpha;
+                if alpha == 0.0 {
+                    return [0; 4];
+                }
+                let mut pixel = [0; 4];
+                for channel in 0..3 {
+                    pixel[channel] = byte(
+                        (unit(top[channel]) * top_alpha + unit(bottom[channel]) * bottom_alpha)
+                            / alpha,
+                    );
+                }
+                pixel[3] = byte(alpha);
+                pixel
+            }
+            Self::Average => std::array::from_fn(|channel| top[channel].midpoint(bottom[channel])),
+            Self::Multiply => {
+                let top_alpha = unit(top[3]);
+                let mut pixel = bottom;
+                for channel in 0..3 {
+                    let product = unit(top[channel]) * unit(bottom[channel]);
+                    pixel[channel] =
+                        byte(product * top_alpha + unit(bottom[channel]) * (1.0 - top_alpha));
+                }
+                pixel
+            }
+            Self::Custom(blend) => blend(top, bottom),
+        }
+    }
+}
@@ -0,0 +1,27 @@
+/// What `Rules::minimize` removed and found.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RuleMinimization {
+    /// Tiles which no map can hold, since their category's quota allows no cells. All of their adjacencies
+    /// were removed.
+    pub unusable_tiles: Vec<usize>,
+    /// Tiles left without any neighbour in some direction, which can only be placed where that side faces
+    /// the edge of the map or an ignored cell. Their adjacencies were kept.
+    pub edge_tiles: Vec<usize>,
+    /// Number of (tile, direction, neighbour) adjacencies removed.
+    pub num_removed: usize,
+    /// Groups of tiles which could be swapped for one another everywhere without changing the rules, so
+    /// they are interchangeable during propagation. Such duplicates are typical of rotated or mirrored copies of
+    /// symmetric tiles, and each group could be merged into a single tile with their summed frequency.
+    pub duplicates: Vec<Vec<usize>>,
+}
+
+impl RuleMinimization {
+    /// Whether the rules were already minimal, and free of edge tiles and duplicates.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.unusable_tiles.is_empty()
+            && self.edge_tiles.is_empty()
+            && self.num_removed == 0
+            && self.duplicates.is_empty()
+    }
+}
@@ -1,19 +1,42 @@
+use anyhow::{Context, Result, bail};
 use fixedbitset::FixedBitSet;
 use ndarray::Array3;
-use photo::Direction;
-use std::ops::Index;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::Index,
+    sync::{Arc, OnceLock},
+};
 
+use crate::{
+    ALL_DIRECTIONS, Asymmetry, Cell, Direction, Map, Quota, RuleMinimization, Symmetry,
+    adjacency_explanation::AdjacencySource,
+};
+
+// Below this fraction of allowed (tile, tile, direction) combinations the rules are treated as sparse
+const SPARSE_DENSITY: f64 = 0.1;
+pub(crate) const FNV_OFFSET: u64 = 0xCBF2_9CE4_8422_2325; // Initial state of content hashes
+const FNV_PRIME: u64 = 0x0100_0000_01B3; // Multiplier of content hashes
+
+#[derive(Clone)]
 pub struct Rules {
-    masks: Vec<[FixedBitSet; 4]>, // [N, E, S, W]
+    adjacency_lists: Vec<[Vec<usize>; 4]>,  // [N, E, S, W], sorted
+    masks: Vec<OnceLock<[FixedBitSet; 4]>>, // [N, E, S, W], built on first use
     frequencies: Vec<usize>,
+    max_runs: Vec<Option<usize>>, // Longest straight line of each tile, if limited
+    categories: Vec<usize>,       // Category of each tile, such as water, beach or grass
+    thicknesses: BTreeMap<usize, usize>, // Minimum thickness of boundary categories
+    line_unique: BTreeSet<usize>, // Categories whose tiles appear at most once per row and column
+    min_bodies: BTreeMap<usize, usize>, // Smallest connected body allowed of each category, if limited
+    quotas: BTreeMap<usize, Quota>, // Bounds on the number of cells of each category, if limited
+    buffers: Vec<Vec<(usize, FixedBitSet)>>, // Tiles kept out of each distance of a tile, derived from the above
+    adjacency_weights: BTreeMap<(usize, usize, usize), usize>, // Weight of (tile, direction index, neighbour), if not 1
+    hole_tile: Option<usize>, // Tile standing for empty space, taken by soft holes left unfilled
+    source: Option<AdjacencySource>, // What the adjacencies were derived from, if known, to explain them by
+    sparse: bool,
 }
 
 impl Rules {
     pub fn new(adjacency_matrix: Array3<bool>, frequencies: Vec<usize>) -> Self {
-        assert!(
-            frequencies.iter().all(|&f| f > 0),
-            "Frequencies must be positive"
-        );
         let num_tiles = frequencies.len();
         assert!(
             num_tiles > 0,
@@ -30,65 +53,1140 @@ impl Rules {
             "Adjacency matrix must be shape [n, n, 2]"
         );
 
-        let mut masks = Vec::with_capacity(num_tiles);
+        let mut adjacency_lists = Vec::with_capacity(num_tiles);
         for j in 0..num_tiles {
-            let mut dirs = [
-                FixedBitSet::with_capacity(num_tiles),
-                FixedBitSet::with_capacity(num_tiles),
-                FixedBitSet::with_capacity(num_tiles),
-                FixedBitSet::with_capacity(num_tiles),
-            ];
+            let mut dirs: [Vec<usize>; 4] = Default::default();
             for i in 0..num_tiles {
                 if adjacency_matrix[[j, i, 1]] {
-                    dirs[Direction::North.index()].insert(i);
+                    dirs[Direction::North.index()].push(i);
                 }
                 if adjacency_matrix[[j, i, 0]] {
-                    dirs[Direction::East.index()].insert(i);
+                    dirs[Direction::East.index()].push(i);
                 }
                 if adjacency_matrix[[i, j, 1]] {
-                    dirs[Direction::South.index()].insert(i);
+                    dirs[Direction::South.index()].push(i);
                 }
                 if adjacency_matrix[[i, j, 0]] {
-                    dirs[Direction::West.index()].insert(i);
+                    dirs[Direction::West.index()].push(i);
                 }
             }
-            masks.push(dirs);
+            adjacency_lists.push(dirs);
         }
-        Rules { masks, frequencies }
+        Self::from_adjacency_lists(adjacency_lists, frequencies)
+    }
+
+    fn from_adjacency_lists(
+        adjacency_lists: Vec<[Vec<usize>; 4]>,
+        frequencies: Vec<usize>,
+    ) -> Self {
+        assert!(
+            frequencies.iter().all(|&f| f > 0),
+            "Frequencies must be positive"
+        );
+        debug_assert!(
+            adjacency_lists
+                .iter()
+                .flatten()
+                .all(|list| list.windows(2).all(|w| w[0] < w[1])),
+            "Adjacency lists must be sorted and free of duplicates"
+        );
+
+        let num_tiles = adjacency_lists.len();
+        let num_allowed: usize = adjacency_lists.iter().flatten().map(Vec::len).sum();
+        let density = num_allowed as f64 / (4 * num_tiles * num_tiles) as f64;
+
+        Rules {
+            masks: (0..num_tiles).map(|_| OnceLock::new()).collect(),
+            adjacency_lists,
+            frequencies,
+            max_runs: vec![None; num_tiles],
+            categories: vec![0; num_tiles],
+            thicknesses: BTreeMap::new(),
+            line_unique: BTreeSet::new(),
+            min_bodies: BTreeMap::new(),
+            quotas: BTreeMap::new(),
+            buffers: vec![Vec::new(); num_tiles],
+            adjacency_weights: BTreeMap::new(),
+            hole_tile: None,
+            source: None,
+            sparse: density < SPARSE_DENSITY,
+        }
+    }
+
+    /// Load adjacency rules from CSV files.
+    /// A single file is read as a long-form edge list of `from,to,direction` rows, where `direction`
+    /// is one of `north`, `east`, `south` or `west` (or their initials) and `to` may be placed in that
+    /// direction of `from`.
+    /// Four files are read as square `0`/`1` matrices for the north, east, south and west directions,
+    /// where a `1` in row `from` and column `to` has the same meaning.
+    /// Every adjacency implies its reverse, so each pair only needs listing once.
+    /// Blank lines, lines starting with `#` and a `from,to,direction` header are skipped.
+    ///
+    /// # Errors
+    ///
+    /// Fails if there are no tiles, any frequency is zero, the number of paths is neither one nor four, or a file
+    /// cannot be read or holds a malformed line or an out of range tile index.
+    pub fn from_csv(paths: &[&str], frequencies: Vec<usize>) -> Result<Self> {
+        let num_tiles = frequencies.len();
+        if num_tiles == 0 {
+            bail!("There must be at least one tile in the ruleset");
+        }
+        if frequencies.contains(&0) {
+            bail!("Frequencies must be positive");
+        }
+
+        let mut adjacency_lists: Vec<[Vec<usize>; 4]> = vec![Default::default(); num_tiles];
+        let mut allow = |from: usize, to: usize, direction: Direction| -> Result<()> {
+            if from >= num_tiles || to >= num_tiles {
+                bail!("Tile index out of bounds for {num_tiles} tiles: {from} -> {to}");
+            }
+            adjacency_lists[from][direction.index()].push(to);
+            adjacency_lists[to][direction.opposite().index()].push(from);
+            Ok(())
+        };
+
+        match paths {
+            [path] => {
+                for (line_num, line) in csv_lines(path)? {
+                    let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+                    if fields.first() == Some(&"from") {
+                        continue;
+                    }
+                    let [from, to, direction] = fields[..] else {
+                        bail!("{path}:{line_num}: expected `from,to,direction`");
+                    };
+                    let from = from
+                        .parse()
+                        .with_context(|| format!("{path}:{line_num}: invalid tile index"))?;
+                    let to = to
+                        .parse()
+                        .with_context(|| format!("{path}:{line_num}: invalid tile index"))?;
+                    let direction = parse_direction(direction)
+                        .with_context(|| format!("{path}:{line_num}: invalid direction"))?;
+                    allow(from, to, direction).with_context(|| format!("{path}:{line_num}"))?;
+                }
+            }
+            [_, _, _, _] => {
+                for (&path, direction) in paths.iter().zip(ALL_DIRECTIONS) {
+                    let mut from = 0;
+                    for (line_num, line) in csv_lines(path)? {
+                        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+                        if fields.len() != num_tiles {
+                            bail!(
+                                "{}:{}: expected {} columns, found {}",
+                                path,
+                                line_num,
+                                num_tiles,
+                                fields.len()
+                            );
+                        }
+                        for (to, field) in fields.iter().enumerate() {
+                            match *field {
+                                "1" => allow(from, to, direction)
+                                    .with_context(|| format!("{path}:{line_num}"))?,
+                                "0" | "" => {}
+                                _ => {
+                                    bail!("{path}:{line_num}: expected `0` or `1`, found `{field}`")
+                                }
+                            }
+                        }
+                        from += 1;
+                    }
+                    if from != num_tiles {
+                        bail!("{path}: expected {num_tiles} rows, found {from}");
+                    }
+                }
+            }
+            _ => bail!(
+                "Expected one edge list or four direction matrices, found {} files",
+                paths.len()
+            ),
+        }
+
+        for dirs in &mut adjacency_lists {
+            for list in dirs {
+                list.sort_unstable();
+                list.dedup();
+            }
+        }
+        Ok(Self::from_adjacency_lists(adjacency_lists, frequencies))
+    }
+
+    /// Build rules from edge sockets, as used by tilesets without overlapping borders.
+    /// Each tile has a socket label on each side, in the order of `ALL_DIRECTIONS`, and a tile may be placed in
+    /// a direction of another when the other's socket on that side equals its own socket on the opposite side.
+    ///
+    /// # Errors
+    ///
+    /// Fails if there are no tiles, any frequency is zero, or the number of socket lists differs from the number
+    /// of tiles.
+    pub fn from_sockets(sockets: &[[&str; 4]], frequencies: Vec<usize>) -> Result<Self> {
+        let num_tiles = frequencies.len();
+        if num_tiles == 0 {
+            bail!("There must be at least one tile in the ruleset");
+        }
+        if frequencies.contains(&0) {
+            bail!("Frequencies must be positive");
+        }
+        if sockets.len() != num_tiles {
+            bail!(
+                "Expected sockets for {} tiles, found {}",
+                num_tiles,
+                sockets.len()
+            );
+        }
+
+        let adjacency_lists = sockets
+            .iter()
+            .map(|tile_sockets| {
+                ALL_DIRECTIONS.map(|direction| {
+                    (0..num_tiles)
+                        .filter(|&other| {
+                            sockets[other][direction.opposite().index()]
+                                == tile_sockets[direction.index()]
+                        })
+                        .collect()
+                })
+            })
+            .collect();
+        let mut rules = Self::from_adjacency_lists(adjacency_lists, frequencies);
+        rules.source = Some(AdjacencySource::Sockets(Arc::new(
+            sockets
+                .iter()
+                .map(|tile_sockets| tile_sockets.map(str::to_string))
+                .collect(),
+        )));
+        Ok(rules)
+    }
+
+    /// Learn rules from example maps of `num_tiles` tiles.
+    /// Every pair of tiles found side by side is allowed in that direction, each tile's frequency is the number of
+    /// times it appears, and each adjacency is weighted by how often the neighbour appears in that direction of the
+    /// tile. Tiles which never appear are given a frequency of one and no adjacencies.
+    ///
+    /// # Errors
+    ///
+    /// Fails if there are no tiles, or an example uses a tile beyond them.
+    pub fn from_examples(examples: &[Map], num_tiles: usize) -> Result<Self> {
+        if num_tiles == 0 {
+            bail!("There must be at least one tile in the ruleset");
+        }
+
+        let mut frequencies = vec![0; num_tiles];
+        let mut counts: BTreeMap<(usize, usize, usize), usize> = BTreeMap::new();
+        for (n, example) in examples.iter().enumerate() {
+            let size = example.size();
+            for y in 0..size.0 {
+                for x in 0..size.1 {
+                    let Cell::Fixed(tile) = example[(y, x)] else {
+                        continue;
+                    };
+                    if tile >= num_tiles {
+                        bail!("Example {n} uses tile {tile}, but there are only {num_tiles} tiles");
+                    }
+                    frequencies[tile] += 1;
+                    for direction in ALL_DIRECTIONS {
+                        let Some(pos) = direction.apply_to((y, x), size) else {
+                            continue;
+                        };
+                        if let Cell::Fixed(other) = example[pos]
+                            && other < num_tiles
+                        {
+                            *counts.entry((tile, direction.index(), other)).or_default() += 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut adjacency_lists: Vec<[Vec<usize>; 4]> = vec![Default::default(); num_tiles];
+        for &(tile, dir_index, other) in counts.keys() {
+            adjacency_lists[tile][dir_index].push(other);
+        }
+        for frequency in &mut frequencies {
+            *frequency = (*frequency).max(1);
+        }
+        let mut rules = Self::from_adjacency_lists(adjacency_lists, frequencies);
+        rules.adjacency_weights = counts;
+        Ok(rules)
     }
 
     pub fn len(&self) -> usize {
-        self.masks.len()
+        self.adjacency_lists.len()
     }
 
-    pub fn masks(&self) -> &Vec<[FixedBitSet; 4]> {
-        &self.masks
+    /// Whether so few adjacencies are allowed that scanning adjacency lists beats scanning bitsets.
+    #[must_use]
+    pub fn is_sparse(&self) -> bool {
+        self.sparse
+    }
+
+    /// Tiles allowed in each direction of a tile, as bitsets indexed by `Direction::index()`.
+    /// The masks are built the first time they are requested.
+    #[must_use]
+    pub fn mask(&self, tile: usize) -> &[FixedBitSet; 4] {
+        self.masks[tile].get_or_init(|| {
+            let num_tiles = self.len();
+            let mut dirs = [
+                FixedBitSet::with_capacity(num_tiles),
+                FixedBitSet::with_capacity(num_tiles),
+                FixedBitSet::with_capacity(num_tiles),
+                FixedBitSet::with_capacity(num_tiles),
+            ];
+            for (mask, list) in dirs.iter_mut().zip(&self.adjacency_lists[tile]) {
+                for &other in list {
+                    mask.insert(other);
+                }
+            }
+            dirs
+        })
+    }
+
+    /// Masks of every tile, indexed by tile and then by `Direction::index()`.
+    /// Builds the mask of every tile, which `Rules::mask` avoids for tiles that are never asked about.
+    #[deprecated(
+        note = "Masks are built on first use, so ask for each tile with `Rules::mask` instead"
+    )]
+    #[must_use]
+    pub fn masks(&self) -> Vec<&[FixedBitSet; 4]> {
+        (0..self.len()).map(|tile| self.mask(tile)).collect()
+    }
+
+    // What the adjacencies were derived from, if known
+    pub(crate) fn adjacency_source(&self) -> Option<&AdjacencySource> {
+        self.source.as_ref()
+    }
+
+    #[cfg(feature = "render")]
+    pub(crate) fn set_adjacency_source(&mut self, source: Option<AdjacencySource>) {
+        self.source = source;
+    }
+
+    /// Tiles which may be placed in a direction of a tile, as a bitset.
+    #[must_use]
+    pub fn allowed(&self, tile: usize, direction: Direction) -> &FixedBitSet {
+        &self.mask(tile)[direction.index()]
+    }
+
+    /// Whether `other` may be placed in a direction of `tile`.
+    #[must_use]
+    pub fn is_allowed(&self, tile: usize, other: usize, direction: Direction) -> bool {
+        self.allows(tile, direction.index(), other)
+    }
+
+    /// Tiles allowed in each direction of a tile, as sorted lists indexed by `Direction::index()`.
+    #[must_use]
+    pub fn adjacency_list(&self, tile: usize) -> &[Vec<usize>; 4] {
+        &self.adjacency_lists[tile]
+    }
+
+    // Check whether `other` may be placed in direction `dir_index` of `tile`, using the cheaper representation
+    fn allows(&self, tile: usize, dir_index: usize, other: usize) -> bool {
+        if self.sparse {
+            self.adjacency_lists[tile][dir_index]
+                .binary_search(&other)
+                .is_ok()
+        } else {
+            self.mask(tile)[dir_index].contains(other)
+        }
     }
 
     pub fn frequencies(&self) -> &[usize] {
         &self.frequencies
     }
 
+    /// Hash of the adjacencies, frequencies and every other constraint of the rules, which changes whenever an
+    /// edit to them would change what is generated. The hash is stable between runs and platforms.
+    #[must_use]
+    pub fn content_hash(&self) -> u64 {
+        let mut hash = FNV_OFFSET;
+        let mut write = |bytes: &[u8]| fnv_write(&mut hash, bytes);
+        for tile in 0..self.len() {
+            for list in &self.adjacency_lists[tile] {
+                write(&(list.len() as u64).to_le_bytes());
+                for &other in list {
+                    write(&(other as u64).to_le_bytes());
+                }
+            }
+            write(&(self.frequencies[tile] as u64).to_le_bytes());
+        }
+        // The remaining constraints are small, and their debug output is stable
+        write(
+            format!(
+                "{:?}{:?}{:?}{:?}{:?}{:?}{:?}{:?}",
+                self.max_runs,
+                self.categories,
+                self.thicknesses,
+                self.line_unique,
+                self.min_bodies,
+                self.quotas,
+                self.adjacency_weights,
+                self.hole_tile
+            )
+            .as_bytes(),
+        );
+        hash
+    }
+
+    /// Replace the frequency of every tile.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is not exactly one frequency per tile, or any frequency is zero.
+    pub fn set_frequencies(&mut self, frequencies: Vec<usize>) {
+        assert_eq!(
+            frequencies.len(),
+            self.len(),
+            "Frequencies must match number of tiles"
+        );
+        assert!(
+            frequencies.iter().all(|&f| f > 0),
+            "Frequencies must be positive"
+        );
+        self.frequencies = frequencies;
+    }
+
     pub fn max_frequency(&self) -> Option<usize> {
         self.frequencies.iter().copied().max()
     }
 
+    /// Frequencies normalised to sum to one, giving the probability of choosing each tile from a full domain.
+    #[must_use]
+    pub fn probabilities(&self) -> Vec<f64> {
+        let total = self.frequencies.iter().sum::<usize>() as f64;
+        self.frequencies
+            .iter()
+            .map(|&frequency| frequency as f64 / total)
+            .collect()
+    }
+
+    /// Natural logarithm of the probability of each tile.
+    pub fn log_probabilities(&self) -> Vec<f64> {
+        self.probabilities().into_iter().map(f64::ln).collect()
+    }
+
+    /// Shannon entropy, in nats, of choosing a tile from a domain weighted by frequency.
+    /// Zero for a domain of one tile, and for an empty domain.
+    #[must_use]
+    pub fn entropy_of(&self, domain: &FixedBitSet) -> f64 {
+        weighted_entropy(domain, &self.frequencies)
+    }
+
+    /// How strongly `other` is favoured in a direction of `tile`: zero if the adjacency is not allowed, and one
+    /// unless a weight has been set or learned from examples.
+    #[must_use]
+    pub fn adjacency_weight(&self, tile: usize, direction: Direction, other: usize) -> usize {
+        let dir_index = direction.index();
+        if !self.allows(tile, dir_index, other) {
+            return 0;
+        }
+        self.adjacency_weights
+            .get(&(tile, dir_index, other))
+            .copied()
+            .unwrap_or(1)
+    }
+
+    /// Weight an allowed adjacency, such as to favour a neighbour in one direction over the others.
+    /// When a cell is collapsed, each tile's frequency is multiplied by the weights of its adjacencies with
+    /// the neighbours already fixed to a single tile.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the rules do not allow the adjacency, or the weight is zero.
+    pub fn set_adjacency_weight(
+        &mut self,
+        tile: usize,
+        direction: Direction,
+        other: usize,
+        weight: usize,
+    ) {
+        assert!(
+            self.allows(tile, direction.index(), other),
+            "Only allowed adjacencies can be weighted"
+        );
+        assert!(weight > 0, "Adjacency weights must be positive");
+        self.adjacency_weights
+            .insert((tile, direction.index(), other), weight);
+    }
+
+    /// Whether any adjacency has a weight other than one.
+    #[must_use]
+    pub fn has_adjacency_weights(&self) -> bool {
+        self.adjacency_weights.values().any(|&weight| weight != 1)
+    }
+
+    /// Add a tile allowing `neighbours` in each direction, indexed by `Direction::index()`, and return its index.
+    /// Each adjacency is mirrored onto the neighbour, and the tile may list its own new index to sit beside itself.
+    /// The tile has category zero and no run limit.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the frequency is zero, or a neighbour is beyond the new tile.
+    pub fn add_tile(&mut self, neighbours: [Vec<usize>; 4], frequency: usize) -> usize {
+        assert!(frequency > 0, "Frequencies must be positive");
+        let tile = self.len();
+        assert!(
+            neighbours.iter().flatten().all(|&other| other <= tile),
+            "Tile index out of bounds for ruleset"
+        );
+
+        let mut dirs = neighbours;
+        for (direction, list) in ALL_DIRECTIONS.iter().zip(&mut dirs) {
+            list.sort_unstable();
+            list.dedup();
+            for &other in list.iter().filter(|&&other| other != tile) {
+                self.adjacency_lists[other][direction.opposite().index()].push(tile);
+            }
+        }
+        self.adjacency_lists.push(dirs);
+        self.frequencies.push(frequency);
+        self.max_runs.push(None);
+        self.categories.push(0);
+        self.source = None;
+        self.masks = (0..self.len()).map(|_| OnceLock::new()).collect();
+
+        let num_allowed: usize = self.adjacency_lists.iter().flatten().map(Vec::len).sum();
+        self.sparse = (num_allowed as f64 / (4 * self.len() * self.len()) as f64) < SPARSE_DENSITY;
+        self.derive_buffers();
+        tile
+    }
+
+    /// Remove the adjacencies which no valid map can contain, and report tiles which duplicate each other's
+    /// rules.
+    /// An adjacency is only removed if it is provably unusable, which is when one of its tiles can never be
+    /// placed because its category's quota allows no cells at all. Every map which the rules accepted before
+    /// is still accepted, on any boundary and around any ignored cells.
+    /// Tiles left without a neighbour in some direction are reported rather than removed, since they can
+    /// still be placed where that side faces the edge of the map or an ignored cell.
+    /// Tile indices and frequencies are unchanged, so templates and tilesets stay valid.
+    pub fn minimize(&mut self) -> RuleMinimization {
+        let num_tiles = self.len();
+        let is_usable: Vec<bool> = (0..num_tiles)
+            .map(|tile| {
+                self.quota(self.categories[tile])
+                    .is_none_or(|quota| quota.bounds(usize::MAX).1 > 0)
+            })
+            .collect();
+        let unusable_tiles: Vec<usize> = (0..num_tiles).filter(|&tile| !is_usable[tile]).collect();
+
+        let mut num_removed = 0;
+        for (tile, dirs) in self.adjacency_lists.iter_mut().enumerate() {
+            for list in dirs {
+                let before = list.len();
+                list.retain(|&other| is_usable[tile] && is_usable[other]);
+                num_removed += before - list.len();
+            }
+        }
+        if num_removed > 0 {
+            self.adjacency_weights
+                .retain(|&(tile, _, other), _| is_usable[tile] && is_usable[other]);
+            self.masks = (0..num_tiles).map(|_| OnceLock::new()).collect();
+            let num_allowed: usize = self.adjacency_lists.iter().flatten().map(Vec::len).sum();
+            self.sparse =
+                (num_allowed as f64 / (4 * num_tiles * num_tiles) as f64) < SPARSE_DENSITY;
+            self.derive_buffers();
+        }
+
+        let edge_tiles = (0..num_tiles)
+            .filter(|&tile| is_usable[tile] && self.adjacency_lists[tile].iter().any(Vec::is_empty))
+            .collect();
+
+        let mut duplicates: Vec<Vec<usize>> = Vec::new();
+        for tile in (0..num_tiles).filter(|&tile| is_usable[tile]) {
+            match duplicates
+                .iter_mut()
+                .find(|group| self.is_duplicate(group[0], tile))
+            {
+                Some(group) => group.push(tile),
+                None => duplicates.push(vec![tile]),
+            }
+        }
+        duplicates.retain(|group| group.len() > 1);
+
+        RuleMinimization {
+            unusable_tiles,
+            edge_tiles,
+            num_removed,
+            duplicates,
+        }
+    }
+
+    // Whether swapping two tiles everywhere leaves the rules unchanged
+    fn is_duplicate(&self, a: usize, b: usize) -> bool {
+        let swap = |tile: usize| match tile {
+            t if t == a => b,
+            t if t == b => a,
+            t => t,
+        };
+        self.max_runs[a] == self.max_runs[b]
+            && self.categories[a] == self.categories[b]
+            && self.hole_tile.is_none_or(|hole| hole != a && hole != b)
+            && ALL_DIRECTIONS.iter().all(|&direction| {
+                let dir_index = direction.index();
+                let mut swapped: Vec<usize> = self.adjacency_lists[a][dir_index]
+                    .iter()
+                    .map(|&t| swap(t))
+                    .collect();
+                swapped.sort_unstable();
+                swapped == self.adjacency_lists[b][dir_index]
+                    && self.adjacency_lists[a][dir_index].iter().all(|&other| {
+                        self.adjacency_weight(a, direction, other)
+                            == self.adjacency_weight(b, direction, swap(other))
+                    })
+            })
+    }
+
+    /// Most copies of a tile which may appear consecutively along a row or column, if limited.
+    #[must_use]
+    pub fn max_run(&self, tile: usize) -> Option<usize> {
+        self.max_runs[tile]
+    }
+
+    /// Limit how many copies of a tile may appear consecutively along a row or column,
+    /// such as keeping fences or walls from running unnaturally far in a straight line.
+    /// The limit is enforced during propagation, from runs of cells which have already been fixed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the tile is out of bounds, or the limit is zero.
+    pub fn set_max_run(&mut self, tile: usize, max_run: Option<usize>) {
+        assert!(tile < self.len(), "Tile index out of bounds for ruleset");
+        assert!(max_run != Some(0), "Maximum run length must be positive");
+        self.max_runs[tile] = max_run;
+    }
+
+    /// Whether any tile has a maximum run length.
+    pub fn has_run_limits(&self) -> bool {
+        self.max_runs.iter().any(Option::is_some)
+    }
+
+    /// Tile taken by soft holes which are left empty, if set.
+    #[must_use]
+    pub fn hole_tile(&self) -> Option<usize> {
+        self.hole_tile
+    }
+
+    /// Set the tile standing for empty space, such as a blank tile whose adjacencies say what may border a gap.
+    /// Each `Cell::Hole` tries this tile before any other, and is only filled with another tile, as a wildcard
+    /// would be, when the solver backtracks out of leaving it empty or propagation rules the tile out.
+    /// Other cells may take the hole tile like any other, according to its frequency.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the tile is out of bounds.
+    pub fn set_hole_tile(&mut self, tile: Option<usize>) {
+        assert!(
+            tile.is_none_or(|tile| tile < self.len()),
+            "Tile index out of bounds for ruleset"
+        );
+        self.hole_tile = tile;
+    }
+
+    /// Category of each tile. All tiles share category zero unless set otherwise.
+    #[must_use]
+    pub fn categories(&self) -> &[usize] {
+        &self.categories
+    }
+
+    /// Assign each tile a category, such as water, beach or grass, for use with `set_min_thickness`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is not exactly one category per tile.
+    pub fn set_categories(&mut self, categories: Vec<usize>) {
+        assert_eq!(
+            categories.len(),
+            self.len(),
+            "Categories must match number of tiles"
+        );
+        self.categories = categories;
+        self.derive_buffers();
+    }
+
+    /// Require any boundary formed by a category to be at least `thickness` tiles thick,
+    /// such as a beach at least two tiles wide between water and grass.
+    /// The boundary categories are found from the category transition graph: each pair of categories which
+    /// only meet through this one must be kept more than `thickness` tiles apart (counting steps between
+    /// neighbouring cells), which is enforced during propagation around cells which have been fixed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the thickness is zero.
+    pub fn set_min_thickness(&mut self, category: usize, thickness: usize) {
+        assert!(thickness > 0, "Minimum thickness must be positive");
+        self.thicknesses.insert(category, thickness);
+        self.derive_buffers();
+    }
+
+    /// Allow each tile of a category at most once in any row or column, such as landmarks which should not
+    /// repeat along a line, or the symbols of a Latin square in a puzzle layout.
+    /// Different tiles of the category may still share a line. The constraint is enforced during propagation,
+    /// by removing each fixed tile from the rest of its row and column.
+    pub fn set_line_unique(&mut self, category: usize, unique: bool) {
+        if unique {
+            self.line_unique.insert(category);
+        } else {
+            self.line_unique.remove(&category);
+        }
+    }
+
+    /// Whether a tile may appear at most once in any row or column, as set for its category.
+    #[must_use]
+    pub fn is_line_unique(&self, tile: usize) -> bool {
+        self.line_unique.contains(&self.categories[tile])
+    }
+
+    /// Whether any category is limited to one of each tile per row and column.
+    #[must_use]
+    pub fn has_line_uniqueness(&self) -> bool {
+        !self.line_unique.is_empty()
+    }
+
+    /// Require every connected body of a category's tiles to span at least `size` cells, such as lakes
+    /// without single-tile puddles. Cells belong to the same body when they are neighbours in one of the four
+    /// directions, across the edges on wrapped maps, and both hold tiles of the category. A size of one or less
+    /// removes the limit.
+    /// The constraint is enforced after collapsing by re-collapsing the cells around each smaller body with
+    /// the category's tiles excluded from it; see `Map::enforce_min_bodies`.
+    pub fn set_min_body_size(&mut self, category: usize, size: usize) {
+        if size > 1 {
+            self.min_bodies.insert(category, size);
+        } else {
+            self.min_bodies.remove(&category);
+        }
+    }
+
+    /// Smallest connected body allowed of a tile's category, if limited.
+    #[must_use]
+    pub fn min_body_size(&self, tile: usize) -> Option<usize> {
+        self.min_bodies.get(&self.categories[tile]).copied()
+    }
+
+    /// Whether any category has a minimum body size.
+    #[must_use]
+    pub fn has_min_body_sizes(&self) -> bool {
+        !self.min_bodies.is_empty()
+    }
+
+    /// Limit how many cells of a map a category's tiles may fill, such as at least one exit, or no more than a
+    /// tenth of the map covered in water. `None` removes the limit.
+    /// The quota is enforced during propagation: once a category reaches its maximum its tiles are removed from
+    /// every other cell, and once only its minimum number of cells can still take its tiles those cells are
+    /// limited to them. The solvers check quotas can be met before collapsing; see `check_quotas`.
+    pub fn set_quota(&mut self, category: usize, quota: Option<Quota>) {
+        match quota {
+            Some(quota) => self.quotas.insert(category, quota),
+            None => self.quotas.remove(&category),
+        };
+    }
+
+    /// Bounds on the number of cells of a category, if limited.
+    #[must_use]
+    pub fn quota(&self, category: usize) -> Option<Quota> {
+        self.quotas.get(&category).copied()
+    }
+
+    /// Every category with a quota, with its quota, in order of category.
+    pub fn quotas(&self) -> impl Iterator<Item = (usize, Quota)> + '_ {
+        self.quotas
+            .iter()
+            .map(|(&category, &quota)| (category, quota))
+    }
+
+    /// Whether any category has a quota.
+    #[must_use]
+    pub fn has_quotas(&self) -> bool {
+        !self.quotas.is_empty()
+    }
+
+    /// Tiles of a category.
+    #[must_use]
+    pub fn category_tiles(&self, category: usize) -> FixedBitSet {
+        let mut tiles = FixedBitSet::with_capacity(self.len());
+        for (tile, &tile_category) in self.categories.iter().enumerate() {
+            if tile_category == category {
+                tiles.insert(tile);
+            }
+        }
+        tiles
+    }
+
+    /// Pairs of distinct categories whose tiles may be placed next to each other, smallest first.
+    #[must_use]
+    pub fn category_transitions(&self) -> BTreeSet<(usize, usize)> {
+        let mut transitions = BTreeSet::new();
+        for (from, dirs) in self.adjacency_lists.iter().enumerate() {
+            for &to in dirs.iter().flatten() {
+                let (a, b) = (self.categories[from], self.categories[to]);
+                if a != b {
+                    transitions.insert((a.min(b), a.max(b)));
+                }
+            }
+        }
+        transitions
+    }
+
+    /// Tiles which may not appear within each distance of a tile, as derived from the boundary thicknesses.
+    #[must_use]
+    pub fn buffer_zones(&self, tile: usize) -> &[(usize, FixedBitSet)] {
+        &self.buffers[tile]
+    }
+
+    /// Whether any tile keeps other tiles out of a buffer zone around it.
+    #[must_use]
+    pub fn has_buffer_zones(&self) -> bool {
+        self.buffers.iter().any(|zones| !zones.is_empty())
+    }
+
+    // Turn the boundary thicknesses into buffer zones between the categories on either side
+    fn derive_buffers(&mut self) {
+        let num_tiles = self.len();
+        let transitions = self.category_transitions();
+        let touches = |a: usize, b: usize| transitions.contains(&(a.min(b), a.max(b)));
+
+        // Distance within which each category must not meet each other category
+        let mut separations: BTreeMap<(usize, usize), usize> = BTreeMap::new();
+        for (&boundary, &thickness) in &self.thicknesses {
+            let sides: Vec<usize> = transitions
+                .iter()
+                .filter_map(|&(a, b)| match (a == boundary, b == boundary) {
+                    (true, false) => Some(b),
+                    (false, true) => Some(a),
+                    _ => None,
+                })
+                .collect();
+            for &a in &sides {
+                for &b in &sides {
+                    if a != b && !touches(a, b) {
+                        let distance = separations.entry((a, b)).or_default();
+                        *distance = (*distance).max(thickness);
+                    }
+                }
+            }
+        }
+
+        self.buffers = (0..num_tiles)
+            .map(|tile| {
+                let mut zones: BTreeMap<usize, FixedBitSet> = BTreeMap::new();
+                for (&(a, b), &distance) in &separations {
+                    if a == self.categories[tile] {
+                        let zone = zones
+                            .entry(distance)
+                            .or_insert_with(|| FixedBitSet::with_capacity(num_tiles));
+                        for other in 0..num_tiles {
+                            if self.categories[other] == b {
+                                zone.insert(other);
+                            }
+                        }
+                    }
+                }
+                zones.into_iter().collect()
+            })
+            .collect();
+    }
+
     pub fn adjacency_matrix(&self) -> Array3<bool> {
         let num_tiles = self.len();
         let mut matrix = Array3::from_elem((num_tiles, num_tiles, 2), false);
         for i in 0..num_tiles {
-            for j in 0..num_tiles {
-                matrix[[i, j, 0]] = self.masks[i][Direction::East.index()].contains(j);
-                matrix[[i, j, 1]] = self.masks[j][Direction::North.index()].contains(i);
+            for &j in &self.adjacency_lists[i][Direction::East.index()] {
+                matrix[[i, j, 0]] = true;
+            }
+            for &j in &self.adjacency_lists[i][Direction::South.index()] {
+                matrix[[i, j, 1]] = true;
             }
         }
         matrix
     }
+
+    /// Keep only the adjacencies permitted by both rule sets.
+    /// Each tile keeps the lower of its two frequencies and the tighter of its two run limits, and each
+    /// adjacency the lower of its two weights.
+    /// Categories, boundary thicknesses, line uniqueness, body sizes, quotas and the hole tile are taken from `self`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the rule sets have different numbers of tiles.
+    #[must_use]
+    pub fn intersect(&self, other: &Self) -> Self {
+        assert_eq!(
+            self.len(),
+            other.len(),
+            "Rules must share the same tile universe"
+        );
+        let adjacency_lists = (0..self.len())
+            .map(|tile| {
+                let mut dirs = self.adjacency_lists[tile].clone();
+                for (dir_index, list) in dirs.iter_mut().enumerate() {
+                    list.retain(|&o| other.allows(tile, dir_index, o));
+                }
+                dirs
+            })
+            .collect();
+        let frequencies = self
+            .frequencies
+            .iter()
+            .zip(&other.frequencies)
+            .map(|(&a, &b)| a.min(b))
+            .collect();
+        let mut rules = Self::from_adjacency_lists(adjacency_lists, frequencies);
+        rules.max_runs = self
+            .max_runs
+            .iter()
+            .zip(&other.max_runs)
+            .map(|(&a, &b)| match (a, b) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                _ => a.or(b),
+            })
+            .collect();
+        rules.adjacency_weights = self.combine_weights(other, &rules, usize::min);
+        rules.categories.clone_from(&self.categories);
+        rules.thicknesses.clone_from(&self.thicknesses);
+        rules.line_unique.clone_from(&self.line_unique);
+        rules.min_bodies.clone_from(&self.min_bodies);
+        rules.quotas.clone_from(&self.quotas);
+        rules.hole_tile = self.hole_tile;
+        rules.derive_buffers();
+        rules
+    }
+
+    /// Keep the adjacencies permitted by either rule set.
+    /// Each tile keeps the higher of its two frequencies and the looser of its two run limits, and each
+    /// adjacency the higher of its weights in the rule sets allowing it.
+    /// Categories, boundary thicknesses, line uniqueness, body sizes, quotas and the hole tile are taken from `self`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the rule sets have different numbers of tiles.
+    #[must_use]
+    pub fn union(&self, other: &Self) -> Self {
+        assert_eq!(
+            self.len(),
+            other.len(),
+            "Rules must share the same tile universe"
+        );
+        let adjacency_lists = self
+            .adjacency_lists
+            .iter()
+            .zip(&other.adjacency_lists)
+            .map(|(self_dirs, other_dirs)| {
+                let mut dirs = self_dirs.clone();
+                for (list, other_list) in dirs.iter_mut().zip(other_dirs) {
+                    list.extend(other_list);
+                    list.sort_unstable();
+                    list.dedup();
+                }
+                dirs
+            })
+            .collect();
+        let frequencies = self
+            .frequencies
+            .iter()
+            .zip(&other.frequencies)
+            .map(|(&a, &b)| a.max(b))
+            .collect();
+        let mut rules = Self::from_adjacency_lists(adjacency_lists, frequencies);
+        rules.max_runs = self
+            .max_runs
+            .iter()
+            .zip(&other.max_runs)
+            .map(|(&a, &b)| a.zip(b).map(|(a, b)| a.max(b)))
+            .collect();
+        rules.adjacency_weights = self.combine_weights(other, &rules, usize::max);
+        rules.categories.clone_from(&self.categories);
+        rules.thicknesses.clone_from(&self.thicknesses);
+        rules.line_unique.clone_from(&self.line_unique);
+        rules.min_bodies.clone_from(&self.min_bodies);
+        rules.quotas.clone_from(&self.quotas);
+        rules.hole_tile = self.hole_tile;
+        rules.derive_buffers();
+        rules
+    }
+
+    /// Restrict the rules to a subset of tiles.
+    /// Tile `tiles[n]` of the original rules becomes tile `n` of the returned rules.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tiles` is empty or holds a tile which is not part of the rules.
+    #[must_use]
+    pub fn restrict_to(&self, tiles: &[usize]) -> Self {
+        assert!(!tiles.is_empty(), "Tile subset must not be empty");
+        assert!(
+            tiles.iter().all(|&tile| tile < self.len()),
+            "Tile index out of bounds for ruleset"
+        );
+        debug_assert!(
+            tiles
+                .iter()
+                .enumerate()
+                .all(|(n, tile)| !tiles[..n].contains(tile)),
+            "Tile subset must not contain duplicates"
+        );
+
+        let mut new_indices = vec![None; self.len()];
+        for (new_index, &old_index) in tiles.iter().enumerate() {
+            new_indices[old_index] = Some(new_index);
+        }
+
+        let adjacency_lists = tiles
+            .iter()
+            .map(|&old_i| {
+                let mut dirs: [Vec<usize>; 4] = Default::default();
+                for (list, old_list) in dirs.iter_mut().zip(&self.adjacency_lists[old_i]) {
+                    list.extend(old_list.iter().filter_map(|&old_j| new_indices[old_j]));
+                    list.sort_unstable();
+                }
+                dirs
+            })
+            .collect();
+        let frequencies = tiles.iter().map(|&tile| self.frequencies[tile]).collect();
+        let mut rules = Self::from_adjacency_lists(adjacency_lists, frequencies);
+        rules.max_runs = tiles.iter().map(|&tile| self.max_runs[tile]).collect();
+        rules.adjacency_weights = self
+            .adjacency_weights
+            .iter()
+            .filter_map(|(&(tile, dir_index, other), &weight)| {
+                Some(((new_indices[tile]?, dir_index, new_indices[other]?), weight))
+            })
+            .collect();
+        rules.categories = tiles.iter().map(|&tile| self.categories[tile]).collect();
+        rules.thicknesses.clone_from(&self.thicknesses);
+        rules.line_unique.clone_from(&self.line_unique);
+        rules.min_bodies.clone_from(&self.min_bodies);
+        rules.quotas.clone_from(&self.quotas);
+        rules.hole_tile = self.hole_tile.and_then(|tile| new_indices[tile]);
+        rules.source = self.source.as_ref().map(|source| source.restrict_to(tiles));
+        rules.derive_buffers();
+        rules
+    }
+
+    // Weights of the adjacencies allowed by the combined rules, merging those allowed by both rule sets
+    fn combine_weights(
+        &self,
+        other: &Self,
+        combined: &Self,
+        merge: fn(usize, usize) -> usize,
+    ) -> BTreeMap<(usize, usize, usize), usize> {
+        self.adjacency_weights
+            .keys()
+            .chain(other.adjacency_weights.keys())
+            .filter(|&&(tile, dir_index, neighbour)| combined.allows(tile, dir_index, neighbour))
+            .map(|&(tile, dir_index, neighbour)| {
+                let direction = ALL_DIRECTIONS[dir_index];
+                let weights =
+                    [self, other].map(|rules| rules.adjacency_weight(tile, direction, neighbour));
+                let weight = match weights {
+                    [0, weight] | [weight, 0] => weight,
+                    [a, b] => merge(a, b),
+                };
+                ((tile, dir_index, neighbour), weight)
+            })
+            .collect()
+    }
+
+    /// Verify the adjacency rules are invariant under each of the given symmetries.
+    /// Returns every rule which does not survive a transformation; an empty list means the rules are symmetric.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a symmetry does not map the same number of tiles as the rules have.
+    #[must_use]
+    pub fn check_symmetry(&self, symmetries: &[Symmetry]) -> Vec<Asymmetry> {
+        let mut asymmetries = Vec::new();
+        for (n, symmetry) in symmetries.iter().enumerate() {
+            assert_eq!(
+                symmetry.tiles.len(),
+                self.len(),
+                "Symmetry must map every tile in the ruleset"
+            );
+
+            for (tile, transformed) in symmetry.tiles.iter().enumerate() {
+                if transformed.is_none() {
+                    asymmetries.push(Asymmetry::MissingTile { symmetry: n, tile });
+                }
+            }
+
+            for from in 0..self.len() {
+                let Some(transformed_from) = symmetry.tiles[from] else {
+                    continue;
+                };
+                for direction in ALL_DIRECTIONS {
+                    let transformed_dir_index = symmetry.direction(direction).index();
+                    for &to in &self.adjacency_lists[from][direction.index()] {
+                        let Some(transformed_to) = symmetry.tiles[to] else {
+                            continue;
+                        };
+                        if !self.allows(transformed_from, transformed_dir_index, transformed_to) {
+                            asymmetries.push(Asymmetry::MissingAdjacency {
+                                symmetry: n,
+                                from,
+                                to,
+                                direction,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        asymmetries
+    }
+}
+
+// Read the non-blank, non-comment lines of a CSV file, with their one-based line numbers
+fn csv_lines(path: &str) -> Result<Vec<(usize, String)>> {
+    let contents =
+        std::fs::read_to_string(path).with_context(|| format!("Failed to read {path}"))?;
+    Ok(contents
+        .lines()
+        .enumerate()
+        .map(|(n, line)| (n + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(n, line)| (n, line.to_string()))
+        .collect())
+}
+
+fn parse_direction(name: &str) -> Result<Direction> {
+    Ok(match name.to_ascii_lowercase().as_str() {
+        "north" | "n" => Direction::North,
+        "east" | "e" => Direction::East,
+        "south" | "s" => Direction::South,
+        "west" | "w" => Direction::West,
+        _ => bail!("Unknown direction `{name}`"),
+    })
 }
 
 impl Index<usize> for Rules {
     type Output = [FixedBitSet; 4];
     fn index(&self, idx: usize) -> &Self::Output {
-        &self.masks[idx]
+        self.mask(idx)
+    }
+}
+
+impl Index<(usize, Direction)> for Rules {
+    type Output = FixedBitSet;
+    fn index(&self, (tile, direction): (usize, Direction)) -> &Self::Output {
+        self.allowed(tile, direction)
+    }
+}
+
+// Shannon entropy, in nats, of choosing a tile from a domain weighted by the given frequencies
+pub(crate) fn weighted_entropy(domain: &FixedBitSet, frequencies: &[usize]) -> f64 {
+    let (total, weighted_logs) = domain
+        .ones()
+        .fold((0.0, 0.0), |(total, weighted_logs), tile| {
+            let weight = frequencies[tile] as f64;
+            (total + weight, weighted_logs + weight * weight.ln())
+        });
+    if total == 0.0 {
+        return 0.0;
+    }
+    (total.ln() - weighted_logs / total).max(0.0)
+}
+
+// Fold bytes into an FNV-1a hash
+pub(crate) fn fnv_write(hash: &mut u64, bytes: &[u8]) {
+    for &byte in bytes {
+        *hash = (*hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME);
     }
 }
@@ -0,0 +1,145 @@
+use anyhow::{Result, bail};
+use fixedbitset::FixedBitSet;
+use ndarray::Array2;
+
+use crate::{ALL_DIRECTIONS_3, Cell, Direction, Direction3, Map, Map3, Rules};
+
+/// Rules for collapsing volumes: the adjacencies and frequencies of a `Rules` within each floor,
+/// plus which tiles may be stacked on top of each other.
+#[derive(Clone)]
+pub struct Rules3 {
+    layer: Rules,
+    above: Vec<FixedBitSet>, // Tiles allowed directly above each tile
+    below: Vec<FixedBitSet>, // Tiles allowed directly below each tile
+}
+
+impl Rules3 {
+    /// Combine rules within a floor with a vertical adjacency matrix,
+    /// where `vertical[[i, j]]` means tile j may sit directly above tile i.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the vertical adjacency matrix is not square in the number of tiles.
+    #[must_use]
+    pub fn new(layer: Rules, vertical: &Array2<bool>) -> Self {
+        let num_tiles = layer.len();
+        assert_eq!(
+            vertical.dim(),
+            (num_tiles, num_tiles),
+            "Vertical adjacency matrix must be shape [n, n]"
+        );
+
+        let mut above = vec![FixedBitSet::with_capacity(num_tiles); num_tiles];
+        let mut below = vec![FixedBitSet::with_capacity(num_tiles); num_tiles];
+        for ((i, j), &allowed) in vertical.indexed_iter() {
+            if allowed {
+                above[i].insert(j);
+                below[j].insert(i);
+            }
+        }
+        Self {
+            layer,
+            above,
+            below,
+        }
+    }
+
+    /// Learn rules from example volumes of `num_tiles` tiles.
+    /// The rules within a floor are learnt from every floor of every example as by `Rules::from_examples`,
+    /// and every pair of tiles found stacked on top of each other is allowed vertically.
+    ///
+    /// # Errors
+    ///
+    /// Fails as `Rules::from_examples` does, or if an example uses a tile beyond them.
+    pub fn from_examples(examples: &[Map3], num_tiles: usize) -> Result<Self> {
+        let floors: Vec<Map> = examples
+            .iter()
+            .flat_map(|example| (0..example.size().0).map(|z| example.floor(z)))
+            .collect();
+        let layer = Rules::from_examples(&floors, num_tiles)?;
+
+        let mut vertical = Array2::from_elem((num_tiles, num_tiles), false);
+        for (n, example) in examples.iter().enumerate() {
+            let (depth, height, width) = example.size();
+            for z in 1..depth {
+                for y in 0..height {
+                    for x in 0..width {
+                        if let (Cell::Fixed(lower), Cell::Fixed(upper)) =
+                            (example[(z - 1, y, x)], example[(z, y, x)])
+                        {
+                            if lower >= num_tiles || upper >= num_tiles {
+                                bail!(
+                                    "Example {} uses tile {}, but there are only {} tiles",
+                                    n,
+                                    lower.max(upper),
+                                    num_tiles
+                                );
+                            }
+                            vertical[[lower, upper]] = true;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(Self::new(layer, &vertical))
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.layer.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Rules within a single floor.
+    #[must_use]
+    pub fn layer(&self) -> &Rules {
+        &self.layer
+    }
+
+    #[must_use]
+    pub fn frequencies(&self) -> &[usize] {
+        self.layer.frequencies()
+    }
+
+    /// Tiles which may be placed in a direction of a tile, as a bitset.
+    #[must_use]
+    pub fn allowed(&self, tile: usize, direction: Direction3) -> &FixedBitSet {
+        match direction {
+            Direction3::Up => &self.above[tile],
+            Direction3::Down => &self.below[tile],
+            Direction3::North => self.layer.allowed(tile, Direction::North),
+            Direction3::East => self.layer.allowed(tile, Direction::East),
+            Direction3::South => self.layer.allowed(tile, Direction::South),
+            Direction3::West => self.layer.allowed(tile, Direction::West),
+        }
+    }
+
+    /// Whether `other` may be placed in a direction of `tile`.
+    #[must_use]
+    pub fn is_allowed(&self, tile: usize, other: usize, direction: Direction3) -> bool {
+        self.allowed(tile, direction).contains(other)
+    }
+
+    /// Check that every cell of a collapsed volume is allowed next to each of its neighbours.
+    #[must_use]
+    pub fn is_valid(&self, map: &Map3) -> bool {
+        let size = map.size();
+        map.positions().all(|pos| {
+            let Cell::Fixed(tile) = map[pos] else {
+                return true;
+            };
+            ALL_DIRECTIONS_3.iter().all(|direction| {
+                match direction.apply_to(pos, size).map(|other| map[other]) {
+                    Some(Cell::Fixed(other)) => {
+                        other < self.len() && self.is_allowed(tile, other, *direction)
+                    }
+                    _ => true,
+                }
+            })
+        })
+    }
+}
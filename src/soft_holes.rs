@@ -0,0 +1,23 @@
+use crate::{Cell, Map, Rules};
+
+impl Map {
+    /// Soft holes of a template which this map, collapsed from it, filled rather than leaving empty with the
+    /// rules' hole tile. Each filled hole is a penalty against the layout, for comparing candidate maps.
+    #[must_use]
+    pub fn filled_holes(&self, template: &Map, rules: &Rules) -> Vec<(usize, usize)> {
+        debug_assert!(
+            self.size() == template.size(),
+            "Map must be the same size as its template"
+        );
+        let (height, width) = template.size();
+        (0..height)
+            .flat_map(|y| (0..width).map(move |x| (y, x)))
+            .filter(|&pos| {
+                template[pos] == Cell::Hole
+                    && rules
+                        .hole_tile()
+                        .is_none_or(|hole| self[pos] != Cell::Fixed(hole))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,142 @@
+use rand::{Rng, seq::SliceRandom};
+use std::collections::HashMap;
+
+use crate::{Cell, Map, TileMetadata};
+
+/// Requirements for the cells chosen by `Map::suggest_spawns`.
+#[derive(Clone, Debug, Default)]
+pub struct SpawnConstraints {
+    /// Tags which a spawn's tile must all carry.
+    pub tags: Vec<String>,
+    /// Smallest path cost allowed between any two spawns.
+    pub min_distance: u32,
+}
+
+impl SpawnConstraints {
+    #[must_use]
+    pub fn new(min_distance: u32) -> Self {
+        Self {
+            tags: Vec::new(),
+            min_distance,
+        }
+    }
+
+    /// Require spawn tiles to carry a tag.
+    #[must_use]
+    pub fn with_tag(mut self, tag: &str) -> Self {
+        self.tags.push(tag.to_string());
+        self
+    }
+}
+
+/// An entity placed on a map by `Map::roll_spawns`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EntityPlacement {
+    pub entity: String,
+    pub pos: (usize, usize),
+}
+
+impl Map {
+    /// Roll the spawn tables of the tiles to place entities on the map, a common step after generation.
+    /// Cells are visited in a random order, so entities limited to a maximum count are spread over the whole
+    /// map. Each cell tries the entries of its tile's spawn table in order and takes at most one entity,
+    /// skipping entries which have reached their maximum count or would be closer than their minimum spacing
+    /// to another copy of the entity.
+    /// `metadata` gives the spawn table of each tile, indexed by tile.
+    pub fn roll_spawns(
+        &self,
+        metadata: &[TileMetadata],
+        rng: &mut impl Rng,
+    ) -> Vec<EntityPlacement> {
+        let mut cells: Vec<((usize, usize), usize)> = (0..self.height())
+            .flat_map(|y| (0..self.width()).map(move |x| (y, x)))
+            .filter_map(|pos| match self[pos] {
+                Cell::Fixed(tile) if !metadata[tile].spawns.is_empty() => Some((pos, tile)),
+                _ => None,
+            })
+            .collect();
+        cells.shuffle(rng);
+
+        let mut placed: HashMap<&str, Vec<(usize, usize)>> = HashMap::new();
+        let mut placements = Vec::new();
+        for (pos, tile) in cells {
+            for entry in &metadata[tile].spawns {
+                let copies = placed.entry(&entry.entity).or_default();
+                let is_allowed = entry.max_count.is_none_or(|max| copies.len() < max)
+                    && copies.iter().all(|other| {
+                        pos.0.abs_diff(other.0) + pos.1.abs_diff(other.1) >= entry.min_spacing
+                    });
+                if is_allowed && rng.random_bool(entry.probability) {
+                    copies.push(pos);
+                    placements.push(EntityPlacement {
+                        entity: entry.entity.clone(),
+                        pos,
+                    });
+                    break;
+                }
+            }
+        }
+        placements
+    }
+
+    /// Choose up to `n` well-spaced spawn points on walkable cells whose tiles carry all the required tags.
+    /// Points are picked one at a time, each as far as possible along walkable paths from those already
+    /// chosen, so all spawns are reachable from each other. Fewer than `n` are returned if no remaining
+    /// cell is at least the minimum distance from every spawn.
+    /// `metadata` gives the tags and movement costs of each tile, indexed by tile.
+    #[must_use]
+    pub fn suggest_spawns(
+        &self,
+        n: usize,
+        constraints: &SpawnConstraints,
+        metadata: &[TileMetadata],
+    ) -> Vec<(usize, usize)> {
+        let costs: Vec<Option<u32>> = metadata.iter().map(|m| m.movement_cost).collect();
+        let is_candidate = |cell: Cell| match cell {
+            Cell::Fixed(tile) => {
+                metadata[tile].is_walkable()
+                    && constraints
+                        .tags
+                        .iter()
+                        .all(|tag| metadata[tile].has_tag(tag))
+            }
+            Cell::Wildcard | Cell::Hole | Cell::Ignore => false,
+        };
+        let candidates: Vec<(usize, usize)> = (0..self.height())
+            .flat_map(|y| (0..self.width()).map(move |x| (y, x)))
+            .filter(|&pos| is_candidate(self[pos]))
+            .collect();
+        let Some(&first_candidate) = candidates.first() else {
+            return Vec::new();
+        };
+        if n == 0 {
+            return Vec::new();
+        }
+
+        // Start from the far end of the walkable region containing the first candidate, so spawns spread across it
+        let distances = self.distance_field(&[first_candidate], &costs);
+        let start = candidates
+            .iter()
+            .copied()
+            .filter(|&pos| distances[pos].is_some())
+            .max_by_key(|&pos| distances[pos])
+            .unwrap_or(first_candidate);
+
+        let mut spawns = vec![start];
+        while spawns.len() < n {
+            let distances = self.distance_field(&spawns, &costs);
+            let Some((best, distance)) = candidates
+                .iter()
+                .filter_map(|&pos| distances[pos].map(|d| (pos, d)))
+                .max_by_key(|&(_, d)| d)
+            else {
+                break;
+            };
+            if distance < constraints.min_distance.max(1) {
+                break;
+            }
+            spawns.push(best);
+        }
+        spawns
+    }
+}
@@ -0,0 +1,59 @@
+use std::fmt::{Display, Formatter};
+
+use crate::Direction;
+
+/// Describes how a tileset transformation maps tiles and directions.
+#[derive(Clone, Debug)]
+pub struct Symmetry {
+    /// Index of the transformed version of each tile, or `None` if the tileset lacks it.
+    pub tiles: Vec<Option<usize>>,
+    /// The direction each direction is mapped to, indexed by `Direction::index()`.
+    /// For example a clockwise quarter turn maps North to East.
+    pub directions: [Direction; 4],
+}
+
+impl Symmetry {
+    #[must_use]
+    pub fn new(tiles: Vec<Option<usize>>, directions: [Direction; 4]) -> Self {
+        Self { tiles, directions }
+    }
+
+    #[must_use]
+    pub fn direction(&self, direction: Direction) -> Direction {
+        self.directions[direction.index()]
+    }
+}
+
+/// An adjacency rule which is not preserved by a tileset symmetry.
+#[derive(Clone, Debug)]
+pub enum Asymmetry {
+    /// The transformed version of a tile is not part of the tileset.
+    MissingTile { symmetry: usize, tile: usize },
+    /// Tile `to` may be placed in `direction` of tile `from`, but the transformed pair may not.
+    MissingAdjacency {
+        symmetry: usize,
+        from: usize,
+        to: usize,
+        direction: Direction,
+    },
+}
+
+impl Display for Asymmetry {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Asymmetry::MissingTile { symmetry, tile } => write!(
+                f,
+                "Symmetry {symmetry}: tile {tile} has no transformed counterpart (is a rotated or mirrored tile missing?)"
+            ),
+            Asymmetry::MissingAdjacency {
+                symmetry,
+                from,
+                to,
+                direction,
+            } => write!(
+                f,
+                "Symmetry {symmetry}: tile {to} may be placed {direction:?} of tile {from}, but the transformed pair is not allowed"
+            ),
+        }
+    }
+}
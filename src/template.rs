@@ -0,0 +1,227 @@
+use anyhow::{Result, bail};
+use ndarray::Array2;
+use std::ops::Range;
+
+use crate::{Cell, Map, TileMetadata};
+
+/// A single tile, by index or by its metadata name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Tile {
+    Index(usize),
+    Named(String),
+}
+
+impl Tile {
+    #[must_use]
+    pub fn index(index: usize) -> Self {
+        Self::Index(index)
+    }
+
+    #[must_use]
+    pub fn named(name: &str) -> Self {
+        Self::Named(name.to_string())
+    }
+
+    fn resolve(&self, metadata: &[TileMetadata]) -> Result<usize> {
+        match self {
+            Self::Index(index) => Ok(*index),
+            Self::Named(name) => match metadata
+                .iter()
+                .position(|m| m.name.as_deref() == Some(name.as_str()))
+            {
+                Some(index) => Ok(index),
+                None => bail!("No tile is named {name}"),
+            },
+        }
+    }
+}
+
+/// A set of tiles which a cell may collapse to.
+/// Named `TileGroup` rather than `TileSet` to keep it distinct from `Tileset`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TileGroup {
+    Tiles(Vec<Tile>),
+    Tagged(String),
+}
+
+impl TileGroup {
+    #[must_use]
+    pub fn of(tiles: Vec<Tile>) -> Self {
+        Self::Tiles(tiles)
+    }
+
+    /// Every tile carrying the given tag.
+    #[must_use]
+    pub fn tagged(tag: &str) -> Self {
+        Self::Tagged(tag.to_string())
+    }
+
+    fn resolve(&self, metadata: &[TileMetadata]) -> Result<Vec<usize>> {
+        let tiles = match self {
+            Self::Tiles(tiles) => tiles
+                .iter()
+                .map(|tile| tile.resolve(metadata))
+                .collect::<Result<Vec<_>>>()?,
+            Self::Tagged(tag) => (0..metadata.len())
+                .filter(|&index| metadata[index].has_tag(tag))
+                .collect(),
+        };
+        if tiles.is_empty() {
+            match self {
+                Self::Tiles(_) => bail!("Tile group is empty"),
+                Self::Tagged(tag) => bail!("No tiles are tagged {tag}"),
+            }
+        }
+        Ok(tiles)
+    }
+}
+
+impl From<Tile> for TileGroup {
+    fn from(tile: Tile) -> Self {
+        Self::Tiles(vec![tile])
+    }
+}
+
+// What a template operation paints into its cells
+#[derive(Clone, Debug)]
+enum Paint {
+    Tiles(TileGroup),
+    Wildcard,
+    Ignore,
+}
+
+/// Builder for `Map` templates, as an alternative to writing template strings by hand.
+/// Operations are applied in order, so later ones paint over earlier ones, and cells left unpainted are
+/// filled by `wildcard_rest` (the default) or `ignore_rest`.
+/// Tiles are resolved by name and tag against tileset metadata when the template is built; a cell painted
+/// with a group of several tiles is left as a wildcard restricted to those tiles.
+#[derive(Clone, Debug)]
+pub struct Template {
+    height: usize,
+    width: usize,
+    operations: Vec<(Range<usize>, Range<usize>, Paint)>,
+    rest: Paint,
+}
+
+impl Template {
+    /// Start a template of the given size with every cell a wildcard.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either dimension is zero.
+    #[must_use]
+    pub fn new(width: usize, height: usize) -> Self {
+        assert!(width > 0, "Template width must be greater than zero");
+        assert!(height > 0, "Template height must be greater than zero");
+        Self {
+            height,
+            width,
+            operations: Vec::new(),
+            rest: Paint::Wildcard,
+        }
+    }
+
+    fn paint(mut self, rows: Range<usize>, cols: Range<usize>, paint: Paint) -> Self {
+        assert!(
+            rows.end <= self.height && cols.end <= self.width,
+            "Template region must lie within the map"
+        );
+        self.operations.push((rows, cols, paint));
+        self
+    }
+
+    /// Paint the outermost ring of cells.
+    #[must_use]
+    pub fn border(self, tiles: impl Into<TileGroup>) -> Self {
+        self.border_width(1, tiles)
+    }
+
+    /// Paint a ring of cells of the given thickness around the edge of the map.
+    #[must_use]
+    pub fn border_width(self, thickness: usize, tiles: impl Into<TileGroup>) -> Self {
+        let (height, width) = (self.height, self.width);
+        let (rows, cols) = (thickness.min(height), thickness.min(width));
+        let tiles = tiles.into();
+        self.paint(0..rows, 0..width, Paint::Tiles(tiles.clone()))
+            .paint(height - rows..height, 0..width, Paint::Tiles(tiles.clone()))
+            .paint(0..height, 0..cols, Paint::Tiles(tiles.clone()))
+            .paint(0..height, width - cols..width, Paint::Tiles(tiles))
+    }
+
+    /// Paint a rectangle of cells.
+    #[must_use]
+    pub fn rect(self, rows: Range<usize>, cols: Range<usize>, tiles: impl Into<TileGroup>) -> Self {
+        self.paint(rows, cols, Paint::Tiles(tiles.into()))
+    }
+
+    /// Return a rectangle of cells to wildcards.
+    #[must_use]
+    pub fn wildcard_rect(self, rows: Range<usize>, cols: Range<usize>) -> Self {
+        self.paint(rows, cols, Paint::Wildcard)
+    }
+
+    /// Ignore a rectangle of cells.
+    #[must_use]
+    pub fn ignore_rect(self, rows: Range<usize>, cols: Range<usize>) -> Self {
+        self.paint(rows, cols, Paint::Ignore)
+    }
+
+    /// Leave every unpainted cell as a wildcard.
+    #[must_use]
+    pub fn wildcard_rest(mut self) -> Self {
+        self.rest = Paint::Wildcard;
+        self
+    }
+
+    /// Ignore every unpainted cell.
+    #[must_use]
+    pub fn ignore_rest(mut self) -> Self {
+        self.rest = Paint::Ignore;
+        self
+    }
+
+    /// Produce the template map, resolving tile names and tags against the tileset metadata.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a tile name or tag matches no tile, or a group of tiles is empty.
+    pub fn build(&self, metadata: &[TileMetadata]) -> Result<Map> {
+        let mut painted: Array2<Option<usize>> = Array2::from_elem((self.height, self.width), None);
+        for (index, (rows, cols, _)) in self.operations.iter().enumerate() {
+            for y in rows.clone() {
+                for x in cols.clone() {
+                    painted[(y, x)] = Some(index);
+                }
+            }
+        }
+
+        let resolved = self
+            .operations
+            .iter()
+            .map(|(_, _, paint)| Self::resolve(paint, metadata))
+            .collect::<Result<Vec<_>>>()?;
+        let rest = Self::resolve(&self.rest, metadata)?;
+
+        let mut map = Map::empty((self.height, self.width));
+        for (pos, index) in painted.indexed_iter() {
+            let (cell, tiles) = index.map_or(&rest, |index| &resolved[index]);
+            map[pos] = *cell;
+            if let Some(tiles) = tiles {
+                map.restrict(pos, tiles);
+            }
+        }
+        Ok(map)
+    }
+
+    // The cell a paint becomes, with its restriction if it allows several tiles
+    fn resolve(paint: &Paint, metadata: &[TileMetadata]) -> Result<(Cell, Option<Vec<usize>>)> {
+        Ok(match paint {
+            Paint::Wildcard => (Cell::Wildcard, None),
+            Paint::Ignore => (Cell::Ignore, None),
+            Paint::Tiles(group) => match group.resolve(metadata)?[..] {
+                [tile] => (Cell::Fixed(tile), None),
+                ref tiles => (Cell::Wildcard, Some(tiles.to_vec())),
+            },
+        })
+    }
+}
@@ -0,0 +1,81 @@
+/// Descriptive data attached to a tile, used by the tools which work with a map after generation.
+#[derive(Clone, Debug)]
+pub struct TileMetadata {
+    pub name: Option<String>,
+    pub tags: Vec<String>,
+    /// Cost of moving onto the tile, or `None` if it cannot be entered.
+    pub movement_cost: Option<u32>,
+    /// Entities which may be placed on the tile by `Map::roll_spawns`, tried in order.
+    pub spawns: Vec<SpawnEntry>,
+}
+
+impl Default for TileMetadata {
+    fn default() -> Self {
+        Self {
+            name: None,
+            tags: Vec::new(),
+            movement_cost: Some(1),
+            spawns: Vec::new(),
+        }
+    }
+}
+
+impl TileMetadata {
+    #[must_use]
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
+    }
+
+    #[must_use]
+    pub fn is_walkable(&self) -> bool {
+        self.movement_cost.is_some()
+    }
+}
+
+/// An entity which may be placed on a tile after generation, such as a tree on grass or a chest in a room.
+#[derive(Clone, Debug)]
+pub struct SpawnEntry {
+    /// Identifier of the entity, as understood by the game.
+    pub entity: String,
+    /// Chance of placing the entity on each cell of the tile, from zero to one.
+    pub probability: f64,
+    /// Most copies of the entity placed across the whole map, if limited.
+    pub max_count: Option<usize>,
+    /// Fewest steps allowed between two copies of the entity, counted along rows and columns.
+    pub min_spacing: usize,
+}
+
+impl SpawnEntry {
+    /// An entity placed with the given chance on each cell of its tile, with no limit on its count or spacing.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the probability is not between zero and one.
+    #[must_use]
+    pub fn new(entity: &str, probability: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&probability),
+            "Spawn probability must be in [0, 1]"
+        );
+        Self {
+            entity: entity.to_string(),
+            probability,
+            max_count: None,
+            min_spacing: 0,
+        }
+    }
+
+    /// Limit how many copies of the entity are placed across the map.
+    #[must_use]
+    pub fn with_max_count(mut self, max_count: usize) -> Self {
+        self.max_count = Some(max_count);
+        self
+    }
+
+    /// Keep copies of the entity at least this many steps apart.
+    #[must_use]
+    pub fn with_min_spacing(mut self, min_spacing: usize) -> Self {
+        self.min_spacing = min_spacing;
+        self
+    }
+}
@@ -1,19 +1,27 @@
-use anyhow::Result;
+use anyhow::{Result, bail};
 use ndarray::Array3;
-use photo::ImageRGBA;
-use std::{env, io::Write, path::Path};
+use photo::{ImageRGBA, Transformation};
+use std::{env, io::Write, path::Path, sync::Arc};
 
-use crate::Rules;
+use crate::{
+    ALL_DIRECTIONS, Direction, Rules, Symmetry, TileMetadata, TilesetDiff, TilesetView,
+    adjacency_explanation::AdjacencySource,
+    rules::{FNV_OFFSET, fnv_write},
+};
 
 const TILESET_FILENAME: &str = "tiles.txt";
 const ADJACENCY_INVALID_SYMBOL: &str = "0";
 const ADJACENCY_VALID_SYMBOL: &str = "1";
+const FRAME_SEPARATOR: char = ','; // Separates the image paths of an animated tile's frames
 
 pub struct Tileset {
     interior_size: usize,
     border_size: usize,
     tiles: Vec<ImageRGBA<u8>>,
+    // Animation frames of each tile after its first, which is the tile image itself
+    frames: Vec<Vec<ImageRGBA<u8>>>,
     rules: Rules,
+    metadata: Vec<TileMetadata>,
 }
 
 impl Tileset {
@@ -24,24 +32,44 @@ impl Tileset {
         rules: Rules,
     ) -> Self {
         debug_assert!(interior_size > 0, "Interior size must be greater than 0");
-        debug_assert!(border_size > 0, "Border size must be greater than 0");
         debug_assert!(!tiles.is_empty(), "Tileset must contain at least one tile");
         debug_assert!(
             tiles.len() == rules.len(),
             "Number of tiles must match number of rules"
         );
 
-        Self {
+        let mut tileset = Self {
             interior_size,
             border_size,
+            metadata: vec![TileMetadata::default(); tiles.len()],
+            frames: vec![Vec::new(); tiles.len()],
             tiles,
             rules,
+        };
+        tileset.explain_by_borders();
+        tileset
+    }
+
+    // Keep the tile borders with the rules, so their adjacencies can be explained, unless the tiles are
+    // borderless or the rules were built from sockets
+    fn explain_by_borders(&mut self) {
+        if self.is_borderless()
+            || matches!(
+                self.rules.adjacency_source(),
+                Some(AdjacencySource::Sockets(_))
+            )
+        {
+            return;
         }
+        let borders = (0..self.len())
+            .map(|tile| ALL_DIRECTIONS.map(|direction| self.border(tile, direction)))
+            .collect();
+        self.rules
+            .set_adjacency_source(Some(AdjacencySource::Borders(Arc::new(borders))));
     }
 
     pub fn from_str(interior_size: usize, border_size: usize, data: &str) -> Self {
         debug_assert!(interior_size > 0, "Interior size must be greater than 0");
-        debug_assert!(border_size > 0, "Border size must be greater than 0");
 
         // Read line by line, ignoring empty lines and comments
         let lines = data
@@ -54,6 +82,7 @@ impl Tileset {
 
         let num_tiles = lines.len();
         let mut tiles = Vec::with_capacity(num_tiles);
+        let mut frames = Vec::with_capacity(num_tiles);
         let mut frequencies = Vec::with_capacity(num_tiles);
         let mut adjacency_matrix = Array3::from_elem((num_tiles, num_tiles, 2), false);
 
@@ -63,9 +92,14 @@ impl Tileset {
                 panic!("Invalid line format: {}", line);
             }
 
-            let tile = ImageRGBA::<u8>::load(parts[0]).expect("Failed to load tile image");
+            // Animated tiles list the image of each frame, separated by commas
+            let mut images = parts[0]
+                .split(FRAME_SEPARATOR)
+                .map(|path| ImageRGBA::<u8>::load(path).expect("Failed to load tile image"));
+            let tile = images.next().expect("Missing tile image");
             let frequency = parts[1].parse::<usize>().expect("Invalid frequency");
             tiles.push(tile);
+            frames.push(images.collect());
             frequencies.push(frequency);
 
             // Parse the adjacency matrix
@@ -77,12 +111,16 @@ impl Tileset {
             }
         }
 
-        Self {
+        let mut tileset = Self::new(
             interior_size,
             border_size,
             tiles,
-            rules: Rules::new(adjacency_matrix, frequencies),
+            Rules::new(adjacency_matrix, frequencies),
+        );
+        for (index, tile_frames) in frames.into_iter().enumerate() {
+            tileset.set_frames(index, tile_frames);
         }
+        tileset
     }
 
     pub fn load(interior_size: usize, border_size: usize, path: &Path) -> Self {
@@ -119,17 +157,30 @@ impl Tileset {
         let frequencies_path = path.join(TILESET_FILENAME);
         let mut frequencies_file = std::fs::File::create(frequencies_path)?;
         for (i, (tile, frequency)) in self.tiles.iter().zip(self.rules.frequencies()).enumerate() {
-            let tile_filename = format!("{i:0index_print_width$}.png");
-            let tile_path = path.join(&tile_filename);
-            tile.save(&tile_path)?;
+            let mut image_paths = Vec::with_capacity(1 + self.frames[i].len());
+            for (frame, image) in std::iter::once(tile).chain(&self.frames[i]).enumerate() {
+                let tile_filename = if frame == 0 {
+                    format!("{i:0index_print_width$}.png")
+                } else {
+                    format!("{i:0index_print_width$}_{frame}.png")
+                };
+                let tile_path = path.join(&tile_filename);
+                image.save(&tile_path)?;
 
-            let abs_tile_path = tile_path.canonicalize()?;
-            let relative_tile_path = abs_tile_path.strip_prefix(&cwd).unwrap_or(&abs_tile_path);
+                let abs_tile_path = tile_path.canonicalize()?;
+                let relative_tile_path = abs_tile_path.strip_prefix(&cwd).unwrap_or(&abs_tile_path);
+                image_paths.push(
+                    Path::new(".")
+                        .join(relative_tile_path)
+                        .display()
+                        .to_string(),
+                );
+            }
 
             write!(
                 frequencies_file,
                 "{}    {frequency:frequency_print_width$}    ",
-                Path::new(".").join(relative_tile_path).display()
+                image_paths.join(&FRAME_SEPARATOR.to_string())
             )?;
 
             for j in 0..self.len() {
@@ -161,10 +212,22 @@ impl Tileset {
         self.border_size
     }
 
+    /// Whether the tiles have no overlapping borders, so their adjacencies were supplied explicitly rather than
+    /// derived from their images, and each tile is rendered whole.
+    #[must_use]
+    pub fn is_borderless(&self) -> bool {
+        self.border_size == 0
+    }
+
     pub fn len(&self) -> usize {
         self.tiles.len()
     }
 
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+    }
+
     pub fn tiles(&self) -> &[ImageRGBA<u8>] {
         &self.tiles
     }
@@ -173,10 +236,254 @@ impl Tileset {
         &self.rules
     }
 
+    /// Name, tags and movement cost of each tile, indexed by tile.
+    #[must_use]
+    pub fn metadata(&self) -> &[TileMetadata] {
+        &self.metadata
+    }
+
+    pub fn set_metadata(&mut self, index: usize, metadata: TileMetadata) {
+        self.metadata[index] = metadata;
+    }
+
+    /// Indices of every tile carrying the given tag.
+    #[must_use]
+    pub fn tagged(&self, tag: &str) -> Vec<usize> {
+        (0..self.len())
+            .filter(|&index| self.metadata[index].has_tag(tag))
+            .collect()
+    }
+
+    /// Cost of moving onto each tile, for use with the pathfinding utilities of `Map`.
+    #[must_use]
+    pub fn movement_costs(&self) -> Vec<Option<u32>> {
+        self.metadata.iter().map(|m| m.movement_cost).collect()
+    }
+
+    /// Part of each tile image shown when rendered, in tile order.
+    #[must_use]
     pub fn interiors(&self) -> Vec<ImageRGBA<u8>> {
-        self.tiles
-            .iter()
-            .map(|tile| tile.interior(self.border_size))
+        self.tiles.iter().map(|tile| self.interior(tile)).collect()
+    }
+
+    // Part of a tile image shown when rendered, which is the whole image for borderless tilesets
+    fn interior(&self, image: &ImageRGBA<u8>) -> ImageRGBA<u8> {
+        if self.is_borderless() {
+            image.clone()
+        } else {
+            image.interior(self.border_size)
+        }
+    }
+
+    /// Border strip of a tile on one side, `border_size` pixels deep.
+    /// A tile may sit in `direction` of another when this border of the other matches its own border in the
+    /// opposite direction.
+    /// Borderless tilesets have empty borders.
+    #[must_use]
+    pub fn border(&self, tile: usize, direction: Direction) -> ImageRGBA<u8> {
+        ImageRGBA::new(
+            self.tiles[tile]
+                .view_border(direction.into(), self.border_size)
+                .to_owned(),
+        )
+    }
+
+    /// Hash of a tile's border on one side, which depends only on its pixels, so borders can be matched by
+    /// hash: the tile `other` may sit east of `tile` only if `border_hash(tile, Direction::East)` equals
+    /// `border_hash(other, Direction::West)`. The hash is stable between runs and platforms.
+    #[must_use]
+    pub fn border_hash(&self, tile: usize, direction: Direction) -> u64 {
+        let border = self.tiles[tile].view_border(direction.into(), self.border_size);
+        let mut hash = FNV_OFFSET;
+        for &dim in border.shape() {
+            fnv_write(&mut hash, &(dim as u64).to_le_bytes());
+        }
+        fnv_write(&mut hash, &border.iter().copied().collect::<Vec<u8>>());
+        hash
+    }
+
+    /// Hash of a tile's image, which depends only on its pixels and is stable between runs and platforms.
+    #[must_use]
+    pub fn tile_hash(&self, tile: usize) -> u64 {
+        let image = &self.tiles[tile].data;
+        let mut hash = FNV_OFFSET;
+        for &dim in image.shape() {
+            fnv_write(&mut hash, &(dim as u64).to_le_bytes());
+        }
+        fnv_write(&mut hash, &image.iter().copied().collect::<Vec<u8>>());
+        hash
+    }
+
+    /// Number of animation frames of a tile, which is one for a still tile.
+    #[must_use]
+    pub fn num_frames(&self, index: usize) -> usize {
+        1 + self.frames[index].len()
+    }
+
+    /// Image of a tile at an animation frame. Each tile loops through its own frames, so tiles with different
+    /// frame counts can be animated together.
+    #[must_use]
+    pub fn frame(&self, index: usize, frame: usize) -> &ImageRGBA<u8> {
+        match frame % self.num_frames(index) {
+            0 => &self.tiles[index],
+            n => &self.frames[index][n - 1],
+        }
+    }
+
+    /// Give a tile animation frames to follow its tile image, such as for flowing water or lava.
+    /// Adjacency rules come from the tile image alone, so every frame should share its borders.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the tile is out of bounds, or a frame differs in size from the tile image.
+    pub fn set_frames(&mut self, index: usize, frames: Vec<ImageRGBA<u8>>) {
+        assert!(
+            frames
+                .iter()
+                .all(|frame| frame.data.dim() == self.tiles[index].data.dim()),
+            "Animation frames must be the same size as their tile"
+        );
+        self.frames[index] = frames;
+    }
+
+    /// Interiors of every tile at an animation frame.
+    #[must_use]
+    pub fn interiors_at(&self, frame: usize) -> Vec<ImageRGBA<u8>> {
+        (0..self.len())
+            .map(|index| self.interior(self.frame(index, frame)))
             .collect()
     }
+
+    /// Build the symmetry describing how a transformation maps this tileset's tiles onto each other.
+    /// `directions` gives the direction each direction is mapped to by the transformation.
+    #[must_use]
+    pub fn symmetry(&self, transformation: Transformation, directions: [Direction; 4]) -> Symmetry {
+        let tiles = self
+            .tiles
+            .iter()
+            .map(|tile| {
+                let transformed = tile.transform(transformation);
+                self.tiles.iter().position(|other| other == &transformed)
+            })
+            .collect();
+        Symmetry::new(tiles, directions)
+    }
+
+    /// Check that another tileset can stand in for this one at render time, as an alternative palette
+    /// (for example a winter or night version) drawing the same logical tiles.
+    /// The palette must have the same number of tiles and the same interior and border sizes.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the palette differs in its number of tiles, or its interior or border size.
+    pub fn check_palette(&self, palette: &Tileset) -> Result<()> {
+        if palette.len() != self.len() {
+            bail!(
+                "Palette has {} tiles, but the tileset has {}",
+                palette.len(),
+                self.len()
+            );
+        }
+        if palette.interior_size != self.interior_size || palette.border_size != self.border_size {
+            bail!(
+                "Palette tiles have interior size {} and border size {}, but the tileset's have {} and {}",
+                palette.interior_size,
+                palette.border_size,
+                self.interior_size,
+                self.border_size
+            );
+        }
+        Ok(())
+    }
+
+    /// Add a tile, deriving its adjacencies by comparing its borders with those of every tile (itself included),
+    /// and return its index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the tile differs in size from the tileset's tiles, or the frequency is zero.
+    pub fn add_tile(&mut self, tile: ImageRGBA<u8>, frequency: usize) -> usize {
+        assert_eq!(
+            tile.data.dim(),
+            self.tiles[0].data.dim(),
+            "Tile must be the same size as the tileset's tiles"
+        );
+        let border = |image: &ImageRGBA<u8>, direction: Direction| {
+            image
+                .view_border(direction.into(), self.border_size)
+                .to_owned()
+        };
+        let index = self.len();
+        let mut neighbours: [Vec<usize>; 4] = Default::default();
+        for direction in ALL_DIRECTIONS {
+            let facing = border(&tile, direction);
+            for (other_index, other) in self.tiles.iter().chain([&tile]).enumerate() {
+                if facing == border(other, direction.opposite()) {
+                    neighbours[direction.index()].push(other_index);
+                }
+            }
+        }
+        let added = self.rules.add_tile(neighbours, frequency);
+        debug_assert_eq!(added, index);
+
+        self.tiles.push(tile);
+        self.frames.push(Vec::new());
+        self.metadata.push(TileMetadata::default());
+        self.explain_by_borders();
+        index
+    }
+
+    /// Hash of the tile images, adjacency rules and frequencies, which changes whenever an edit to the tileset
+    /// would change what is generated. The hash is stable between runs and platforms, so it can version saved
+    /// worlds.
+    #[must_use]
+    pub fn content_hash(&self) -> u64 {
+        let mut hash = FNV_OFFSET;
+        let mut write = |bytes: &[u8]| fnv_write(&mut hash, bytes);
+        write(&(self.interior_size as u64).to_le_bytes());
+        write(&(self.border_size as u64).to_le_bytes());
+        for tile in &self.tiles {
+            write(&tile.data.iter().copied().collect::<Vec<u8>>());
+        }
+        for tile in 0..self.len() {
+            for list in self.rules.adjacency_list(tile) {
+                write(&(list.len() as u64).to_le_bytes());
+                for &other in list {
+                    write(&(other as u64).to_le_bytes());
+                }
+            }
+            write(&(self.rules.frequencies()[tile] as u64).to_le_bytes());
+        }
+        hash
+    }
+
+    /// Differences from this tileset to a newer version of it: added, removed and edited tiles, and changes to
+    /// the frequencies and adjacencies of the tiles in both.
+    #[must_use]
+    pub fn diff(&self, other: &Tileset) -> TilesetDiff {
+        TilesetDiff::new(self, other)
+    }
+
+    /// Create a view exposing only the given tiles, with rules remapped to the subset.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no tiles are given or any is not part of the tileset.
+    #[must_use]
+    pub fn subset(&self, indices: &[usize]) -> TilesetView<'_> {
+        TilesetView::new(self, indices)
+    }
+
+    /// Create a view exposing every tile carrying any of the given tags, in master tileset order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no tile carries any of the tags.
+    #[must_use]
+    pub fn subset_tagged(&self, tags: &[&str]) -> TilesetView<'_> {
+        let indices = (0..self.len())
+            .filter(|&index| tags.iter().any(|tag| self.metadata[index].has_tag(tag)))
+            .collect::<Vec<_>>();
+        TilesetView::new(self, &indices)
+    }
 }
@@ -3,6 +3,10 @@ use photo::{Direction, ImageRGBA, Transformation};
 
 use crate::{Rules, Tileset};
 
+/// Collects tiles cut from source images into a `Tileset`.
+/// With a border size of zero the tiles have no overlapping borders to derive adjacencies from, so the
+/// tileset must be built with explicit rules by `build_with_rules`, such as rules from `Rules::from_sockets`,
+/// `Rules::from_csv` or `Rules::from_examples`.
 pub struct TilesetBuilder {
     interior_size: usize,
     border_size: usize,
@@ -13,7 +17,6 @@ pub struct TilesetBuilder {
 impl TilesetBuilder {
     pub fn new(interior_size: usize, border_size: usize) -> Self {
         debug_assert!(interior_size > 0, "Interior size must be greater than 0");
-        debug_assert!(border_size > 0, "Border size must be greater than 0");
         Self {
             interior_size,
             border_size,
@@ -46,6 +49,11 @@ impl TilesetBuilder {
         self.tiles.len()
     }
 
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+    }
+
     fn adjacency_matrix(&self) -> Array3<bool> {
         debug_assert!(
             !self.tiles.is_empty(),
@@ -99,7 +107,31 @@ impl TilesetBuilder {
             !self.tiles.is_empty(),
             "TilesetBuilder must contain at least one tile before it can be built"
         );
+        assert!(
+            self.border_size > 0,
+            "Borderless tilesets must be built with explicit rules, using build_with_rules"
+        );
         let rules = Rules::new(self.adjacency_matrix(), self.frequencies);
         Tileset::new(self.interior_size, self.border_size, self.tiles, rules)
     }
+
+    /// Build the tileset with the given rules in place of adjacencies derived from the tile borders.
+    /// The frequencies counted while adding tiles are available from `frequencies` for building the rules.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no tiles have been added.
+    #[must_use]
+    pub fn build_with_rules(self, rules: Rules) -> Tileset {
+        assert!(
+            !self.tiles.is_empty(),
+            "TilesetBuilder must contain at least one tile before it can be built"
+        );
+        assert_eq!(
+            rules.len(),
+            self.tiles.len(),
+            "Rules must have one tile for each tile of the builder"
+        );
+        Tileset::new(self.interior_size, self.border_size, self.tiles, rules)
+    }
 }
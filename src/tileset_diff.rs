@@ -0,0 +1,163 @@
+use std::fmt::{Display, Formatter};
+
+use crate::{ALL_DIRECTIONS, Direction, Tileset};
+
+/// A tile whose frequency differs between two tilesets.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FrequencyChange {
+    /// Index of the tile in the old and new tilesets.
+    pub tile: (usize, usize),
+    pub old: usize,
+    pub new: usize,
+}
+
+/// An adjacency which one tileset allows and the other does not.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AdjacencyChange {
+    /// Index of the tile in the old and new tilesets.
+    pub tile: (usize, usize),
+    pub direction: Direction,
+    /// Index of the neighbouring tile in the old and new tilesets.
+    pub other: (usize, usize),
+    /// Whether the new tileset allows the neighbour in this direction of the tile, where the old did not.
+    pub allowed: bool,
+}
+
+/// Differences between two versions of a tileset, as given by `Tileset::diff`, for reviewing a tileset update
+/// and understanding why generated output shifted.
+/// Tiles are matched by image, so reordered tiles are not reported as added or removed. A tile whose image
+/// changed but whose index did not is reported as changed, and still compared for frequency and adjacency.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TilesetDiff {
+    /// Tiles of the new tileset with no counterpart in the old one, by index in the new tileset.
+    pub added: Vec<usize>,
+    /// Tiles of the old tileset with no counterpart in the new one, by index in the old tileset.
+    pub removed: Vec<usize>,
+    /// Tiles whose image differs while their index is the same in both tilesets.
+    pub changed: Vec<usize>,
+    /// Tiles with the same image at a different index, as (old, new) indices.
+    pub moved: Vec<(usize, usize)>,
+    pub frequency_changes: Vec<FrequencyChange>,
+    pub adjacency_changes: Vec<AdjacencyChange>,
+}
+
+impl TilesetDiff {
+    pub(crate) fn new(old: &Tileset, new: &Tileset) -> Self {
+        let old_hashes: Vec<u64> = (0..old.len()).map(|tile| old.tile_hash(tile)).collect();
+        let new_hashes: Vec<u64> = (0..new.len()).map(|tile| new.tile_hash(tile)).collect();
+
+        // Pair tiles by image, preferring tiles which kept their index, then pair edits in place
+        let mut old_match: Vec<Option<usize>> = vec![None; old.len()];
+        let mut new_match: Vec<Option<usize>> = vec![None; new.len()];
+        for tile in 0..old.len().min(new.len()) {
+            if old_hashes[tile] == new_hashes[tile] {
+                old_match[tile] = Some(tile);
+                new_match[tile] = Some(tile);
+            }
+        }
+        let mut diff = Self::default();
+        for j in 0..new.len() {
+            if new_match[j].is_some() {
+                continue;
+            }
+            if let Some(i) =
+                (0..old.len()).find(|&i| old_match[i].is_none() && old_hashes[i] == new_hashes[j])
+            {
+                old_match[i] = Some(j);
+                new_match[j] = Some(i);
+                diff.moved.push((i, j));
+            }
+        }
+        for tile in 0..old.len().min(new.len()) {
+            if old_match[tile].is_none() && new_match[tile].is_none() {
+                old_match[tile] = Some(tile);
+                new_match[tile] = Some(tile);
+                diff.changed.push(tile);
+            }
+        }
+        diff.removed = (0..old.len()).filter(|&i| old_match[i].is_none()).collect();
+        diff.added = (0..new.len()).filter(|&j| new_match[j].is_none()).collect();
+
+        let pairs: Vec<(usize, usize)> = old_match
+            .iter()
+            .enumerate()
+            .filter_map(|(i, j)| j.map(|j| (i, j)))
+            .collect();
+        let (old_rules, new_rules) = (old.rules(), new.rules());
+        for &(i, j) in &pairs {
+            let (old_frequency, new_frequency) =
+                (old_rules.frequencies()[i], new_rules.frequencies()[j]);
+            if old_frequency != new_frequency {
+                diff.frequency_changes.push(FrequencyChange {
+                    tile: (i, j),
+                    old: old_frequency,
+                    new: new_frequency,
+                });
+            }
+            for direction in ALL_DIRECTIONS {
+                for &(k, l) in &pairs {
+                    let allowed = new_rules.is_allowed(j, l, direction);
+                    if old_rules.is_allowed(i, k, direction) != allowed {
+                        diff.adjacency_changes.push(AdjacencyChange {
+                            tile: (i, j),
+                            direction,
+                            other: (k, l),
+                            allowed,
+                        });
+                    }
+                }
+            }
+        }
+        diff
+    }
+
+    /// Whether the tilesets have the same tiles, frequencies and adjacencies, in the same order.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.changed.is_empty()
+            && self.moved.is_empty()
+            && self.frequency_changes.is_empty()
+            && self.adjacency_changes.is_empty()
+    }
+}
+
+/// One line per difference, numbering tiles by their index in the new tileset, apart from removed tiles.
+impl Display for TilesetDiff {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "No changes");
+        }
+        for tile in &self.added {
+            writeln!(f, "+ tile {tile}")?;
+        }
+        for tile in &self.removed {
+            writeln!(f, "- tile {tile}")?;
+        }
+        for tile in &self.changed {
+            writeln!(f, "~ tile {tile}: image changed")?;
+        }
+        for (old, new) in &self.moved {
+            writeln!(f, "~ tile {old} -> {new}: moved")?;
+        }
+        for change in &self.frequency_changes {
+            writeln!(
+                f,
+                "~ tile {}: frequency {} -> {}",
+                change.tile.1, change.old, change.new
+            )?;
+        }
+        for change in &self.adjacency_changes {
+            writeln!(
+                f,
+                "{} tile {} {:?} of tile {}",
+                if change.allowed { '+' } else { '-' },
+                change.other.1,
+                change.direction,
+                change.tile.1
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,127 @@
+use photo::ImageRGBA;
+
+use crate::{Cell, Map, Rules, TileMetadata, Tileset};
+
+/// A filtered view of a `Tileset` exposing a subset of its tiles.
+/// Tile `n` of the view corresponds to tile `indices[n]` of the master tileset.
+pub struct TilesetView<'a> {
+    tileset: &'a Tileset,
+    indices: Vec<usize>,
+    rules: Rules,
+}
+
+impl<'a> TilesetView<'a> {
+    /// # Panics
+    ///
+    /// Panics if no tiles are given or any is not part of the tileset.
+    #[must_use]
+    pub fn new(tileset: &'a Tileset, indices: &[usize]) -> Self {
+        let rules = tileset.rules().restrict_to(indices);
+        Self {
+            tileset,
+            indices: indices.to_vec(),
+            rules,
+        }
+    }
+
+    #[must_use]
+    pub fn tileset(&self) -> &Tileset {
+        self.tileset
+    }
+
+    #[must_use]
+    pub fn indices(&self) -> &[usize] {
+        &self.indices
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    #[must_use]
+    pub fn rules(&self) -> &Rules {
+        &self.rules
+    }
+
+    #[must_use]
+    pub fn tiles(&self) -> Vec<&ImageRGBA<u8>> {
+        self.indices
+            .iter()
+            .map(|&index| &self.tileset.tiles()[index])
+            .collect()
+    }
+
+    /// Metadata of each tile in the view, taken from the master tileset.
+    #[must_use]
+    pub fn metadata(&self) -> Vec<&TileMetadata> {
+        self.indices
+            .iter()
+            .map(|&index| &self.tileset.metadata()[index])
+            .collect()
+    }
+
+    /// Cost of moving onto each tile of the view, for use with the pathfinding utilities of `Map`.
+    #[must_use]
+    pub fn movement_costs(&self) -> Vec<Option<u32>> {
+        self.metadata().iter().map(|m| m.movement_cost).collect()
+    }
+
+    /// Convert a view tile index into the corresponding master tileset index.
+    #[must_use]
+    pub fn to_master(&self, index: usize) -> usize {
+        self.indices[index]
+    }
+
+    /// Convert a master tileset index into the corresponding view tile index, if it is part of the view.
+    #[must_use]
+    pub fn from_master(&self, index: usize) -> Option<usize> {
+        self.indices.iter().position(|&i| i == index)
+    }
+
+    /// Remap a map indexed by this view so it can be used with the master tileset.
+    #[must_use]
+    pub fn to_master_map(&self, map: &Map) -> Map {
+        let mut result = map.clone();
+        for y in 0..map.height() {
+            for x in 0..map.width() {
+                if let Cell::Fixed(index) = map[(y, x)] {
+                    result[(y, x)] = Cell::Fixed(self.to_master(index));
+                }
+            }
+        }
+        result
+    }
+
+    /// Remap a template authored with master tileset indices so it can be collapsed with this view's rules.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the template fixes a tile which is not part of the view.
+    #[must_use]
+    pub fn from_master_map(&self, map: &Map) -> Map {
+        let mut result = map.clone();
+        for y in 0..map.height() {
+            for x in 0..map.width() {
+                if let Cell::Fixed(index) = map[(y, x)] {
+                    let view_index = self.from_master(index).unwrap_or_else(|| {
+                        panic!("Tile {index} at ({y}, {x}) is not part of the view")
+                    });
+                    result[(y, x)] = Cell::Fixed(view_index);
+                }
+            }
+        }
+        result
+    }
+
+    /// Render a map indexed by this view using the master tileset images.
+    #[must_use]
+    pub fn render(&self, map: &Map) -> ImageRGBA<u8> {
+        self.to_master_map(map).render(self.tileset)
+    }
+}
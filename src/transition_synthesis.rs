@@ -0,0 +1,110 @@
+use ndarray::{Array3, s};
+use photo::ImageRGBA;
+
+use crate::{BorderComparison, Direction, TileMetadata, Tileset};
+
+const TRANSITION_TAG: &str = "transition"; // Tag given to every synthesized transition tile
+
+impl Tileset {
+    /// Close gaps in an incomplete tileset by synthesizing a transition tile for each pair of tiles whose facing
+    /// borders nearly match, differing in at most `max_mismatch` (a fraction from zero to one) of their pixels.
+    /// Each transition takes the first tile's border on one side and the second's on the other, blending
+    /// between the two tiles across its middle, so it can sit between them. Its adjacencies are then derived
+    /// from its borders like any other tile's.
+    /// Pairs the rules already allow, or which an existing tile already bridges, are skipped.
+    /// Returns the indices of the added tiles, which are tagged `transition`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the maximum mismatch is not between zero and one.
+    pub fn synthesize_transitions(&mut self, max_mismatch: f64) -> Vec<usize> {
+        assert!(
+            (0.0..=1.0).contains(&max_mismatch),
+            "Maximum mismatch must be in [0, 1]"
+        );
+        let num_tiles = self.len();
+        let mut added = Vec::new();
+        for tile in 0..num_tiles {
+            for other in 0..num_tiles {
+                for direction in [Direction::East, Direction::North] {
+                    let comparison = BorderComparison::new(
+                        &self.border(tile, direction),
+                        &self.border(other, direction.opposite()),
+                    );
+                    if self.rules().is_allowed(tile, other, direction)
+                        || comparison.borders_match
+                        || comparison.num_mismatched as f64 / comparison.num_pixels as f64
+                            > max_mismatch
+                        || self.is_bridged(tile, direction, other)
+                    {
+                        continue;
+                    }
+
+                    let transition = self.blend_transition(tile, direction, other);
+                    if self.tiles().contains(&transition) {
+                        continue;
+                    }
+                    let frequency =
+                        self.rules().frequencies()[tile].min(self.rules().frequencies()[other]);
+                    let index = self.add_tile(transition, frequency);
+                    let metadata = self.transition_metadata(tile, other);
+                    self.set_metadata(index, metadata);
+                    added.push(index);
+                }
+            }
+        }
+        added
+    }
+
+    // Whether some tile may already be placed between the two tiles
+    fn is_bridged(&self, tile: usize, direction: Direction, other: usize) -> bool {
+        let rules = self.rules();
+        (0..self.len()).any(|middle| {
+            rules.is_allowed(tile, middle, direction) && rules.is_allowed(middle, other, direction)
+        })
+    }
+
+    // Tile with the border of `tile` facing back towards it and the border of `other` facing towards `other`,
+    // blended linearly between the two tiles in between
+    fn blend_transition(&self, tile: usize, direction: Direction, other: usize) -> ImageRGBA<u8> {
+        let from = &self.tiles()[tile].data;
+        let to = &self.tiles()[other].data;
+        let (height, width, channels) = from.dim();
+        let n = self.border_size();
+
+        let mut data = Array3::from_shape_fn((height, width, channels), |(y, x, c)| {
+            let t = match direction {
+                Direction::East => x as f64 / (width - 1) as f64,
+                _ => 1.0 - y as f64 / (height - 1) as f64,
+            };
+            (f64::from(from[[y, x, c]]) * (1.0 - t) + f64::from(to[[y, x, c]]) * t).round() as u8
+        });
+        if matches!(direction, Direction::East) {
+            data.slice_mut(s![.., ..n, ..])
+                .assign(&from.slice(s![.., width - n.., ..]));
+            data.slice_mut(s![.., width - n.., ..])
+                .assign(&to.slice(s![.., ..n, ..]));
+        } else {
+            data.slice_mut(s![height - n.., .., ..])
+                .assign(&from.slice(s![..n, .., ..]));
+            data.slice_mut(s![..n, .., ..])
+                .assign(&to.slice(s![height - n.., .., ..]));
+        }
+        ImageRGBA::new(data)
+    }
+
+    // Name the transition after the tiles it joins, and make it as hard to cross as the harder of the two
+    fn transition_metadata(&self, tile: usize, other: usize) -> TileMetadata {
+        let (a, b) = (&self.metadata()[tile], &self.metadata()[other]);
+        TileMetadata {
+            name: a
+                .name
+                .as_ref()
+                .zip(b.name.as_ref())
+                .map(|(a, b)| format!("{a}_to_{b}")),
+            tags: vec![TRANSITION_TAG.to_string()],
+            movement_cost: a.movement_cost.zip(b.movement_cost).map(|(a, b)| a.max(b)),
+            spawns: Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use ndarray::Array2;
+
+use crate::{Cell, Map};
+
+// Transforms from the first octant into each of the eight, as (xx, xy, yx, yy)
+const OCTANTS: [(isize, isize, isize, isize); 8] = [
+    (1, 0, 0, 1),
+    (0, 1, 1, 0),
+    (0, -1, 1, 0),
+    (-1, 0, 0, 1),
+    (-1, 0, 0, -1),
+    (0, -1, -1, 0),
+    (0, 1, -1, 0),
+    (1, 0, 0, -1),
+];
+
+impl Map {
+    /// Cells visible from an origin within a radius, using recursive shadowcasting.
+    /// Cells fixed to one of the `blocking` tiles, and ignored cells, block sight but are themselves visible.
+    #[must_use]
+    pub fn visible_from(
+        &self,
+        origin: (usize, usize),
+        radius: usize,
+        blocking: &[usize],
+    ) -> Array2<bool> {
+        let mut caster = Shadowcaster {
+            map: self,
+            blocking,
+            origin,
+            radius,
+            visible: Array2::from_elem(self.size(), false),
+        };
+        caster.visible[origin] = true;
+        for octant in OCTANTS {
+            caster.cast(1, 1.0, 0.0, octant);
+        }
+        caster.visible
+    }
+
+    /// Number of cells visible within a radius of each cell, as a measure of openness: low values mark
+    /// enclosed spaces and chokepoints, high values open ground with long sightlines.
+    /// Blocking and ignored cells score zero.
+    #[must_use]
+    pub fn visibility(&self, radius: usize, blocking: &[usize]) -> Array2<usize> {
+        let mut counts = Array2::zeros(self.size());
+        for ((y, x), count) in counts.indexed_iter_mut() {
+            if !is_blocking(self[(y, x)], blocking) {
+                *count = self
+                    .visible_from((y, x), radius, blocking)
+                    .iter()
+                    .filter(|&&v| v)
+                    .count();
+            }
+        }
+        counts
+    }
+}
+
+fn is_blocking(cell: Cell, blocking: &[usize]) -> bool {
+    match cell {
+        Cell::Ignore => true,
+        Cell::Wildcard | Cell::Hole => false,
+        Cell::Fixed(tile) => blocking.contains(&tile),
+    }
+}
+
+struct Shadowcaster<'m> {
+    map: &'m Map,
+    blocking: &'m [usize],
+    origin: (usize, usize),
+    radius: usize,
+    visible: Array2<bool>,
+}
+
+impl Shadowcaster<'_> {
+    // Scan the rows of one octant outwards from `row`, between the start and end slopes, recursing past blockers
+    fn cast(
+        &mut self,
+        row: usize,
+        mut start: f64,
+        end: f64,
+        (xx, xy, yx, yy): (isize, isize, isize, isize),
+    ) {
+        if start < end {
+            return;
+        }
+        let (height, width) = self.map.size();
+        let radius_squared = (self.radius * self.radius).cast_signed();
+        let mut next_start = start;
+        for distance in row..=self.radius {
+            let dy = -distance.cast_signed();
+            let mut blocked = false;
+            for dx in dy..=0 {
+                let left_slope = (dx as f64 - 0.5) / (dy as f64 + 0.5);
+                let right_slope = (dx as f64 + 0.5) / (dy as f64 - 0.5);
+                if start < right_slope {
+                    continue;
+                }
+                if end > left_slope {
+                    break;
+                }
+
+                let (Some(y), Some(x)) = (
+                    self.origin.0.checked_add_signed(dx * yx + dy * yy),
+                    self.origin.1.checked_add_signed(dx * xx + dy * xy),
+                ) else {
+                    continue;
+                };
+                if y >= height || x >= width {
+                    continue;
+                }
+                let pos = (y, x);
+                if dx * dx + dy * dy <= radius_squared {
+                    self.visible[pos] = true;
+                }
+
+                let blocks = is_blocking(self.map[pos], self.blocking);
+                if blocked {
+                    if blocks {
+                        next_start = right_slope;
+                    } else {
+                        blocked = false;
+                        start = next_start;
+                    }
+                } else if blocks && distance < self.radius {
+                    blocked = true;
+                    self.cast(distance + 1, start, left_slope, (xx, xy, yx, yy));
+                    next_start = right_slope;
+                }
+            }
+            if blocked {
+                break;
+            }
+        }
+    }
+}
@@ -1,8 +1,91 @@
 use anyhow::Result;
-use rand::Rng;
+use rand::{Rng, RngCore};
+use std::time::Instant;
 
-use crate::{Map, Rules};
+use crate::{AlgorithmInfo, Boundary, CollapseOptions, CollapseStats, Map, Rules};
 
 pub trait WaveFunction {
-    fn collapse(map: &Map, rules: &Rules, rng: &mut impl Rng) -> Result<Map>;
+    /// Collapse the undecided cells of a map, keeping its fixed and ignored cells.
+    ///
+    /// # Errors
+    ///
+    /// Fails with a `WfcError` if the collapse meets a contradiction it cannot get past, or if the template or
+    /// rules cannot be used.
+    fn collapse(&self, map: &Map, rules: &Rules, rng: &mut impl Rng) -> Result<Map>;
+
+    /// Collapse with the given options in place of the shared settings the algorithm was configured with.
+    /// Algorithms without those settings collapse as configured.
+    ///
+    /// # Errors
+    ///
+    /// Fails as `collapse` does.
+    fn collapse_with_options(
+        &self,
+        map: &Map,
+        rules: &Rules,
+        options: &CollapseOptions,
+        rng: &mut impl Rng,
+    ) -> Result<Map> {
+        let _ = options;
+        self.collapse(map, rules, rng)
+    }
+
+    /// Collapse as `collapse` does, also reporting how the collapse went.
+    /// Algorithms which do not measure their search only report the elapsed time.
+    ///
+    /// # Errors
+    ///
+    /// Fails as `collapse` does.
+    fn collapse_with_stats(
+        &self,
+        map: &Map,
+        rules: &Rules,
+        rng: &mut impl Rng,
+    ) -> Result<(Map, CollapseStats)> {
+        let start = Instant::now();
+        let result = self.collapse(map, rules, rng)?;
+        let stats = CollapseStats {
+            elapsed: start.elapsed(),
+            ..CollapseStats::default()
+        };
+        Ok((result, stats))
+    }
+
+    /// Name and capabilities of the algorithm as configured.
+    fn info(&self) -> AlgorithmInfo;
+
+    /// How the algorithm as configured constrains cells on the edges of the map, so that algorithms which
+    /// rework its output keep to the same adjacencies.
+    fn boundary(&self) -> Boundary {
+        Boundary::Open
+    }
+
+    /// Settings of the algorithm as configured which change what it generates, beyond the `CollapseOptions`
+    /// shared by every algorithm, as (name, value) pairs. Provenance hashes them together with the options.
+    /// Algorithms wrapping others only list their own settings.
+    fn settings(&self) -> Vec<(&'static str, String)> {
+        Vec::new()
+    }
+}
+
+/// Object-safe counterpart of `WaveFunction`, allowing algorithms to be chosen at runtime.
+pub trait Collapse {
+    /// Collapse a map as `WaveFunction::collapse` does.
+    ///
+    /// # Errors
+    ///
+    /// Fails as `WaveFunction::collapse` does.
+    fn collapse_dyn(&self, map: &Map, rules: &Rules, rng: &mut dyn RngCore) -> Result<Map>;
+
+    fn info_dyn(&self) -> AlgorithmInfo;
+}
+
+impl<WF: WaveFunction> Collapse for WF {
+    fn collapse_dyn(&self, map: &Map, rules: &Rules, mut rng: &mut dyn RngCore) -> Result<Map> {
+        self.collapse(map, rules, &mut rng)
+    }
+
+    fn info_dyn(&self) -> AlgorithmInfo {
+        self.info()
+    }
 }
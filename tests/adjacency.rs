@@ -0,0 +1,338 @@
+//! Checks that collapsed maps keep to their rules' adjacency on unusual neighbourhoods, where cells neighbour
+//! themselves or the same cell in more than one direction.
+
+use fixedbitset::FixedBitSet;
+use ndarray::{Array2, Array3};
+use rand::SeedableRng;
+use rand_pcg::Pcg64;
+use wave_function::{
+    ALL_DIRECTIONS, Boundary, Cell, Collapser, Direction, GraphMap, Map, Quota, ReviseStrategy,
+    Rules, WaveFunction, WaveFunctionBacktracking, WaveFunctionFast, WaveFunctionLocalReset,
+    WaveFunctionMinCost, check_quotas, check_quotas_with_boundary, precheck,
+    precheck_with_boundary, propagate, propagate_with_boundary,
+};
+
+const SEEDS: u64 = 20;
+const STRATEGIES: [ReviseStrategy; 3] = [
+    ReviseStrategy::Scan,
+    ReviseStrategy::LastSupport,
+    ReviseStrategy::SupportCount,
+];
+
+// Three tiles where only the first may sit beside itself and the last may not sit above or below itself, so a
+// wrapped map one cell wide can only hold the first tile, and one cell high only the first two
+fn rules() -> Rules {
+    let mut adjacency = Array3::from_elem((3, 3, 2), false);
+    for (a, b) in [(0, 0), (0, 1), (1, 0), (1, 2), (2, 1)] {
+        adjacency[[a, b, 0]] = true;
+    }
+    for a in 0..3 {
+        for b in 0..3 {
+            adjacency[[a, b, 1]] = (a, b) != (2, 2);
+        }
+    }
+    Rules::new(adjacency, vec![1, 1, 1])
+}
+
+// Three tiles with one-way adjacencies, on which narrow wrapped maps once came out breaking the rules
+fn one_way_rules() -> Rules {
+    let mut adjacency = Array3::from_elem((3, 3, 2), false);
+    for index in [
+        [0, 0, 0],
+        [0, 2, 1],
+        [1, 0, 0],
+        [1, 0, 1],
+        [1, 1, 0],
+        [2, 0, 0],
+        [2, 2, 0],
+    ] {
+        adjacency[index] = true;
+    }
+    Rules::new(adjacency, vec![1, 1, 1])
+}
+
+// Position of a cell's neighbour in a direction, wrapping around the edges
+fn wrapped(pos: (usize, usize), dir: Direction, (height, width): (usize, usize)) -> (usize, usize) {
+    let (dy, dx) = match dir {
+        Direction::North => (height - 1, 0),
+        Direction::East => (0, 1),
+        Direction::South => (1, 0),
+        Direction::West => (0, width - 1),
+    };
+    ((pos.0 + dy) % height, (pos.1 + dx) % width)
+}
+
+fn tile(map: &Map, pos: (usize, usize)) -> usize {
+    match map[pos] {
+        Cell::Fixed(tile) => tile,
+        _ => panic!("Cell ({}, {}) was not collapsed", pos.0, pos.1),
+    }
+}
+
+// Whether every cell's tile allows the tile of each neighbour, wrapping around the edges
+fn obeys_wrapped(map: &Map, rules: &Rules) -> bool {
+    let (height, width) = map.size();
+    (0..height).all(|y| {
+        (0..width).all(|x| {
+            ALL_DIRECTIONS.iter().all(|&dir| {
+                let neighbour = wrapped((y, x), dir, map.size());
+                rules.is_allowed(tile(map, (y, x)), tile(map, neighbour), dir)
+            })
+        })
+    })
+}
+
+// Collapse wrapped maps one cell across, which must succeed under `rules` and may fail under
+// `one_way_rules`, but must never give a map breaking either
+fn check_wrapped(algorithm: &impl WaveFunction, name: &str) {
+    for (rules, must_succeed) in [(rules(), true), (one_way_rules(), false)] {
+        for size in [(1, 1), (1, 5), (5, 1), (2, 1), (1, 2)] {
+            for seed in 0..SEEDS {
+                let result =
+                    algorithm.collapse(&Map::empty(size), &rules, &mut Pcg64::seed_from_u64(seed));
+                match result {
+                    Ok(map) => assert!(
+                        obeys_wrapped(&map, &rules),
+                        "{name} broke the rules on {size:?} with seed {seed}:\n{map}"
+                    ),
+                    Err(e) => assert!(
+                        !must_succeed,
+                        "{name} failed on {size:?} with seed {seed}: {e}"
+                    ),
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn fast_keeps_rules_on_narrow_wrapped_maps() {
+    for strategy in STRATEGIES {
+        check_wrapped(
+            &WaveFunctionFast::builder()
+                .boundary(Boundary::Wrap)
+                .revise_strategy(strategy)
+                .build(),
+            &format!("fast with {strategy:?}"),
+        );
+    }
+}
+
+#[test]
+fn backtracking_keeps_rules_on_narrow_wrapped_maps() {
+    for strategy in STRATEGIES {
+        for max_nogood_size in [0, 8] {
+            check_wrapped(
+                &WaveFunctionBacktracking::builder()
+                    .boundary(Boundary::Wrap)
+                    .revise_strategy(strategy)
+                    .max_nogood_size(max_nogood_size)
+                    .build(),
+                &format!("backtracking with {strategy:?} and nogoods of up to {max_nogood_size}"),
+            );
+        }
+    }
+}
+
+#[test]
+fn local_reset_keeps_rules_on_narrow_wrapped_maps() {
+    for strategy in STRATEGIES {
+        check_wrapped(
+            &WaveFunctionLocalReset::builder()
+                .boundary(Boundary::Wrap)
+                .revise_strategy(strategy)
+                .build(),
+            &format!("local reset with {strategy:?}"),
+        );
+    }
+}
+
+#[test]
+fn collapser_keeps_rules_on_wrapped_maps() {
+    let rules = rules();
+    for size in [(1, 1), (1, 5), (5, 1), (2, 1), (1, 2), (4, 6)] {
+        for seed in 0..SEEDS {
+            let mut collapser =
+                Collapser::with_boundary(&Map::empty(size), &rules, Boundary::Wrap).unwrap();
+            collapser.seed(seed);
+            collapser.run().unwrap();
+            let map = collapser.to_map();
+            assert!(
+                obeys_wrapped(&map, &rules),
+                "Collapser broke the rules on {size:?} with seed {seed}:\n{map}"
+            );
+        }
+    }
+}
+
+// Local search may only move cells to tiles which keep to the adjacencies across the edges, and counts the cost
+// of pairs across them
+#[test]
+fn min_cost_keeps_rules_across_wrapped_edges() {
+    let rules = rules();
+    // Pairs with the last tile are cheapest, which only the seams keep it out of on narrow maps
+    let costs = Array3::from_shape_fn(
+        (3, 3, 2),
+        |(a, b, _)| if a == 2 || b == 2 { 0.0 } else { 1.0 },
+    );
+    let algorithm = WaveFunctionMinCost::builder().build(
+        WaveFunctionBacktracking::builder()
+            .boundary(Boundary::Wrap)
+            .build(),
+        costs.clone(),
+    );
+    for size in [(1, 5), (5, 1), (2, 3), (4, 6)] {
+        for seed in 0..SEEDS {
+            let map = algorithm
+                .collapse(&Map::empty(size), &rules, &mut Pcg64::seed_from_u64(seed))
+                .unwrap();
+            assert!(
+                obeys_wrapped(&map, &rules),
+                "Min cost broke the rules on {size:?} with seed {seed}:\n{map}"
+            );
+
+            let (height, width) = size;
+            let mut expected = 0.0;
+            for pos in (0..height).flat_map(|y| (0..width).map(move |x| (y, x))) {
+                let east = wrapped(pos, Direction::East, size);
+                let north = wrapped(pos, Direction::North, size);
+                expected += costs[[tile(&map, pos), tile(&map, east), 0]];
+                expected += costs[[tile(&map, pos), tile(&map, north), 1]];
+            }
+            assert!((algorithm.total_cost(&map) - expected).abs() < 1e-9);
+        }
+    }
+}
+
+// A column one cell wide can only hold the first tile when wrapped, so fixing another is a conflict only then
+#[test]
+fn prechecks_and_propagation_see_wrapped_conflicts() {
+    let mut rules = rules();
+    let mut template = Map::empty((3, 1));
+    template[(0, 0)] = Cell::Fixed(1);
+    assert!(precheck(&template, &rules).is_ok());
+    assert!(precheck_with_boundary(&template, &rules, Boundary::Wrap).is_err());
+
+    let all_tiles = || {
+        let mut domain = FixedBitSet::with_capacity(3);
+        domain.insert_range(..);
+        Array2::from_elem((3, 1), domain)
+    };
+    let mut domains = all_tiles();
+    assert_eq!(
+        propagate(&mut domains, &rules, None).unwrap().num_removed,
+        0
+    );
+    let report = propagate_with_boundary(&mut domains, &rules, None, Boundary::Wrap).unwrap();
+    assert_eq!(report.num_removed, 6);
+    assert!(domains.iter().all(|domain| domain.ones().eq([0])));
+
+    // The second tile can only be reached on the open map
+    rules.set_categories(vec![0, 1, 2]);
+    rules.set_quota(1, Some(Quota::at_least(1)));
+    let template = Map::empty((3, 1));
+    assert!(check_quotas(&template, &rules).is_ok());
+    assert!(check_quotas_with_boundary(&template, &rules, Boundary::Wrap).is_err());
+}
+
+// Two tiles, which may sit anywhere, in two categories, the second of which must form bodies of three cells
+fn body_rules() -> Rules {
+    let mut rules = Rules::new(Array3::from_elem((2, 2, 2), true), vec![1, 1]);
+    rules.set_categories(vec![0, 1]);
+    rules.set_min_body_size(1, 3);
+    rules
+}
+
+#[test]
+fn min_bodies_join_across_wrapped_edges() {
+    let rules = body_rules();
+    let map = Map::new(Array2::from_shape_fn((1, 6), |(_, x)| {
+        Cell::Fixed(usize::from(x < 2 || x == 5))
+    }));
+    assert_eq!(map.small_bodies(&rules, Boundary::Open).len(), 2);
+    assert!(map.small_bodies(&rules, Boundary::Wrap).is_empty());
+
+    let algorithm = WaveFunctionFast::builder().boundary(Boundary::Wrap).build();
+    for seed in 0..SEEDS {
+        let map = algorithm
+            .collapse(&Map::empty((4, 5)), &rules, &mut Pcg64::seed_from_u64(seed))
+            .unwrap();
+        assert!(
+            map.small_bodies(&rules, Boundary::Wrap).is_empty(),
+            "Seed {seed} left a small body:\n{map}"
+        );
+    }
+}
+
+// Five tiles which must differ from their neighbours, where the first may not have the second to its north
+fn graph_rules() -> Rules {
+    let mut adjacency = Array3::from_elem((5, 5, 2), false);
+    for a in 0..5 {
+        for b in 0..5 {
+            adjacency[[a, b, 0]] = a != b;
+            adjacency[[a, b, 1]] = a != b && (a, b) != (0, 1);
+        }
+    }
+    Rules::new(adjacency, vec![1; 5])
+}
+
+// A hub with eleven neighbours all to its north, which are chained east to west with some links repeated or
+// skipping ahead in the same direction, so cells have more than eight neighbours and several in one direction
+fn graph() -> GraphMap {
+    let mut graph = GraphMap::empty(12);
+    for node in 1..12 {
+        graph.add_edge(0, node, Direction::North);
+    }
+    for node in 1..11 {
+        graph.add_edge(node, node + 1, Direction::East);
+    }
+    graph.add_edge(1, 2, Direction::East);
+    graph.add_edge(1, 4, Direction::East);
+    graph.add_edge(6, 9, Direction::East);
+    graph.add_edge(11, 3, Direction::South);
+    graph
+}
+
+// Collapses without backtracking can meet contradictions, but every strategy reaches the same fixed point
+// after each decision, so all must succeed or fail together and agree on the tiles
+#[test]
+fn every_revise_strategy_keeps_rules_on_graphs() {
+    let rules = graph_rules();
+    let graph = graph();
+    let mut num_collapsed = 0;
+    for seed in 0..SEEDS {
+        let results = STRATEGIES.map(|strategy| {
+            graph
+                .collapse_with_strategy(&rules, strategy, &mut Pcg64::seed_from_u64(seed))
+                .ok()
+                .map(|collapsed| {
+                    (0..collapsed.len())
+                        .map(|node| match collapsed[node] {
+                            Cell::Fixed(tile) => tile,
+                            _ => panic!("Node {node} was not collapsed"),
+                        })
+                        .collect::<Vec<_>>()
+                })
+        });
+        for (strategy, result) in STRATEGIES.iter().zip(&results) {
+            assert_eq!(
+                result, &results[0],
+                "{strategy:?} and {:?} disagree with seed {seed}",
+                STRATEGIES[0]
+            );
+        }
+        let Some(tiles) = &results[0] else {
+            continue;
+        };
+        num_collapsed += 1;
+        for &(from, to, dir) in graph.edges() {
+            assert!(
+                rules.is_allowed(tiles[from], tiles[to], dir),
+                "Seed {seed} put tile {} {dir:?} of tile {}",
+                tiles[to],
+                tiles[from]
+            );
+        }
+    }
+    assert!(num_collapsed > 0, "Every collapse of the graph failed");
+}
@@ -0,0 +1,99 @@
+//! Checks that rules explain their adjacencies by the sockets or tile borders they were derived from, and
+//! keep those explanations when restricted to a subset of their tiles.
+
+use ndarray::Array3;
+use wave_function::{AdjacencyReason, Direction, Rules};
+
+// Grass, a shore with grass to the west and water to the east, and water
+fn socket_rules() -> Rules {
+    Rules::from_sockets(
+        &[
+            ["g", "g", "g", "g"],
+            ["s", "w", "s", "g"],
+            ["w", "w", "w", "w"],
+        ],
+        vec![1; 3],
+    )
+    .unwrap()
+}
+
+fn sockets(rules: &Rules, tile: usize, other: usize, direction: Direction) -> (String, String) {
+    match rules.explain_adjacency(tile, other, direction).reason {
+        AdjacencyReason::Sockets {
+            socket,
+            other_socket,
+        } => (socket, other_socket),
+        _ => panic!("Adjacency was not explained by sockets"),
+    }
+}
+
+#[test]
+fn socket_rules_name_their_sockets() {
+    let rules = socket_rules();
+    assert!(rules.why_allowed(0, 1, Direction::East).is_some());
+    assert!(rules.why_forbidden(0, 1, Direction::East).is_none());
+    assert_eq!(
+        sockets(&rules, 0, 1, Direction::East),
+        ("g".to_string(), "g".to_string())
+    );
+
+    let forbidden = rules.why_forbidden(0, 2, Direction::East).unwrap();
+    assert!(!forbidden.allowed);
+    assert_eq!(
+        sockets(&rules, 0, 2, Direction::East),
+        ("g".to_string(), "w".to_string())
+    );
+
+    // The shore and the water become tiles 0 and 1
+    let restricted = rules.restrict_to(&[1, 2]);
+    assert!(restricted.why_allowed(0, 1, Direction::East).is_some());
+    assert_eq!(
+        sockets(&restricted, 0, 1, Direction::East),
+        ("w".to_string(), "w".to_string())
+    );
+}
+
+#[test]
+fn given_rules_have_nothing_to_explain_by() {
+    let rules = Rules::new(Array3::from_elem((2, 2, 2), true), vec![1, 1]);
+    let explanation = rules.explain_adjacency(0, 1, Direction::North);
+    assert!(explanation.allowed);
+    assert!(matches!(explanation.reason, AdjacencyReason::Given));
+}
+
+#[cfg(feature = "render")]
+#[test]
+fn tileset_rules_compare_borders() {
+    use photo::ImageRGBA;
+    use wave_function::Tileset;
+
+    // Three by three tiles with one pixel borders: the first is black, the second black but for a white
+    // eastern column
+    let mut white_east = Array3::zeros((3, 3, 4));
+    white_east.slice_mut(ndarray::s![.., 2, ..]).fill(u8::MAX);
+    let tiles = vec![
+        ImageRGBA::new(Array3::zeros((3, 3, 4))),
+        ImageRGBA::new(white_east),
+    ];
+    let mut adjacency = Array3::from_elem((2, 2, 2), true);
+    adjacency[[1, 0, 0]] = false;
+    let tileset = Tileset::new(1, 1, tiles, Rules::new(adjacency, vec![1, 1]));
+
+    let explanation = tileset
+        .rules()
+        .why_forbidden(1, 0, Direction::East)
+        .unwrap();
+    let AdjacencyReason::Borders(comparison) = explanation.reason else {
+        panic!("Adjacency was not explained by borders");
+    };
+    assert!(!comparison.borders_match);
+    assert_eq!(comparison.num_pixels, 3);
+    assert_eq!(comparison.num_mismatched, 3);
+    assert_eq!(comparison.diff.data.dim(), (3, 1, 4));
+
+    let explanation = tileset.rules().why_allowed(0, 1, Direction::East).unwrap();
+    let AdjacencyReason::Borders(comparison) = explanation.reason else {
+        panic!("Adjacency was not explained by borders");
+    };
+    assert!(comparison.borders_match);
+}
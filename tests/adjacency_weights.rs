@@ -0,0 +1,73 @@
+//! Checks that adjacency weights shift which tiles are chosen beside fixed neighbours.
+
+use ndarray::Array3;
+use rand::SeedableRng;
+use rand_pcg::Pcg64;
+use wave_function::{
+    Cell, Collapser, Direction, Map, Rules, WaveFunction, WaveFunctionBacktracking,
+    WaveFunctionFast,
+};
+
+const SEEDS: u64 = 200;
+const LEFT: (usize, usize) = (0, 0);
+const RIGHT: (usize, usize) = (0, 1);
+
+// Two tiles which may sit anywhere, with the second favoured nine to one east of the first
+fn rules(weighted: bool) -> Rules {
+    let mut rules = Rules::new(Array3::from_elem((2, 2, 2), true), vec![1, 1]);
+    if weighted {
+        rules.set_adjacency_weight(1, Direction::West, 0, 9);
+    }
+    rules
+}
+
+// A cell with its west neighbour fixed to the first tile
+fn template() -> Map {
+    let mut map = Map::empty((1, 2));
+    map[LEFT] = Cell::Fixed(0);
+    map
+}
+
+// How many of the seeds give the second tile east of the first
+fn count_favoured(algorithm: &impl WaveFunction, rules: &Rules) -> u64 {
+    (0..SEEDS)
+        .filter(|&seed| {
+            let map = algorithm
+                .collapse(&template(), rules, &mut Pcg64::seed_from_u64(seed))
+                .unwrap();
+            map[RIGHT] == Cell::Fixed(1)
+        })
+        .count() as u64
+}
+
+#[test]
+fn weights_favour_neighbours() {
+    for weighted in [false, true] {
+        let rules = rules(weighted);
+        let counts = [
+            count_favoured(&WaveFunctionFast::default(), &rules),
+            count_favoured(&WaveFunctionBacktracking::default(), &rules),
+        ];
+        for count in counts {
+            if weighted {
+                assert!(count > SEEDS * 4 / 5, "{count} of {SEEDS}");
+            } else {
+                assert!(count.abs_diff(SEEDS / 2) < SEEDS / 5, "{count} of {SEEDS}");
+            }
+        }
+    }
+}
+
+#[test]
+fn collapsers_weigh_neighbours() {
+    let rules = rules(true);
+    let count = (0..SEEDS)
+        .filter(|&seed| {
+            let mut collapser = Collapser::new(&template(), &rules).unwrap();
+            collapser.seed(seed);
+            collapser.run().unwrap();
+            collapser.domain(RIGHT).contains(1)
+        })
+        .count() as u64;
+    assert!(count > SEEDS * 4 / 5, "{count} of {SEEDS}");
+}
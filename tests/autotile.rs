@@ -0,0 +1,61 @@
+//! Checks that autotiling matches neighbours by the category of their tiles rather than by the tiles
+//! themselves, in both the 16-tile and 47-tile sets.
+
+use ndarray::{Array2, Array3};
+use wave_function::{AutotileSet, Cell, Map, Rules};
+
+const GRASS: usize = 0;
+const WATER: usize = 1;
+
+// Two grass tiles followed by two water tiles
+fn rules() -> Rules {
+    let mut rules = Rules::new(Array3::from_elem((4, 4, 2), true), vec![1; 4]);
+    rules.set_categories(vec![GRASS, GRASS, WATER, WATER]);
+    rules
+}
+
+fn map(tiles: [[usize; 3]; 3]) -> Map {
+    Map::new(Array2::from_shape_fn((3, 3), |(y, x)| {
+        Cell::Fixed(tiles[y][x])
+    }))
+}
+
+fn variant(map: &Map, pos: (usize, usize)) -> Option<usize> {
+    match map[pos] {
+        Cell::Fixed(variant) => Some(variant),
+        _ => None,
+    }
+}
+
+#[test]
+fn neighbours_match_by_category() {
+    // A plus of water, mixing both water tiles, on both grass tiles
+    let map = map([[0, 2, 1], [3, 2, 3], [1, 3, 0]]);
+    let water = map.autotile(&rules(), WATER, AutotileSet::Bitmask16, false);
+    assert_eq!(variant(&water, (1, 1)), Some(15));
+    assert_eq!(variant(&water, (0, 1)), Some(4));
+    assert_eq!(variant(&water, (1, 2)), Some(8));
+    assert_eq!(variant(&water, (2, 1)), Some(1));
+    assert_eq!(variant(&water, (1, 0)), Some(2));
+    for corner in [(0, 0), (0, 2), (2, 0), (2, 2)] {
+        assert_eq!(variant(&water, corner), None);
+    }
+
+    // The grass corners touch no other grass
+    let grass = map.autotile(&rules(), GRASS, AutotileSet::Bitmask16, false);
+    assert_eq!(variant(&grass, (0, 0)), Some(0));
+    assert_eq!(variant(&grass, (1, 1)), None);
+}
+
+#[test]
+fn blobs_count_corners_between_matching_edges() {
+    let map = map([[2, 3, 2], [3, 2, 3], [2, 3, 0]]);
+    let water = map.autotile(&rules(), WATER, AutotileSet::Blob47, true);
+    // Every neighbour of the centre is water except the south-east corner
+    let all_but_south_east =
+        AutotileSet::Blob47.index([true, true, true, false, true, true, true, true]);
+    assert_eq!(variant(&water, (1, 1)), Some(all_but_south_east));
+    // Cells beyond the edge match, so the north-west corner sees water all round
+    assert_eq!(variant(&water, (0, 0)), Some(46));
+    assert_eq!(variant(&water, (2, 2)), None);
+}
@@ -0,0 +1,154 @@
+//! Checks that backtracking undoes its decisions by replaying the trail of domain changes, so each undone
+//! decision leaves the cells with exactly the options they had before it, including when the oldest decisions
+//! have been forgotten to bound the depth of the search.
+
+use ndarray::Array3;
+use rand::SeedableRng;
+use rand_pcg::Pcg64;
+use wave_function::{
+    Cell, Direction, Heuristic, Map, Rules, WaveFunction, WaveFunctionBacktracking, WfcError,
+};
+
+const SEEDS: u64 = 5;
+const GADGET: usize = 3; // Width of the two by three block of gadget tiles on the left of the map
+const WIDTH: usize = GADGET + 3;
+const GADGET_TILES: [usize; 4] = [0, 1, 2, 3];
+const FREE_TILES: [usize; 2] = [4, 5];
+
+// Four gadget tiles with one-way adjacencies, under which the first in the top left corner of a two by three
+// block propagates without contradiction, though no block can be filled around it, and two free tiles which
+// may sit next to anything. The first gadget tile is by far the most common, so it is tried first.
+fn rules() -> Rules {
+    let mut adjacency = Array3::from_elem((6, 6, 2), true);
+    for tile in GADGET_TILES {
+        for other in GADGET_TILES {
+            for dir in 0..2 {
+                adjacency[[tile, other, dir]] = false;
+            }
+        }
+    }
+    for index in [
+        [0, 0, 1],
+        [0, 2, 0],
+        [0, 2, 1],
+        [0, 3, 0],
+        [1, 1, 0],
+        [1, 3, 1],
+        [2, 1, 1],
+        [2, 3, 0],
+        [3, 0, 0],
+        [3, 0, 1],
+        [3, 1, 0],
+        [3, 1, 1],
+        [3, 2, 0],
+    ] {
+        adjacency[index] = true;
+    }
+    Rules::new(adjacency, vec![100, 1, 1, 1, 1, 1])
+}
+
+// The gadget's corner first, then the free cells, then the rest of the gadget, so the decision dooming the
+// gadget is only found out once every free cell has been decided on top of it
+fn cost(pos: (usize, usize), _entropy: usize) -> f64 {
+    let order = if pos == (0, 0) {
+        0
+    } else if pos.1 >= GADGET {
+        1 + pos.1 * 2 + pos.0
+    } else {
+        100 + pos.1 * 2 + pos.0
+    };
+    order as f64
+}
+
+// Gadget tiles on the left and free tiles on the right, with one free cell fixed
+fn template() -> Map {
+    let mut template = Map::empty((2, WIDTH));
+    for y in 0..2 {
+        for x in 0..WIDTH {
+            let tiles: &[usize] = if x < GADGET {
+                &GADGET_TILES
+            } else {
+                &FREE_TILES
+            };
+            template.restrict((y, x), tiles);
+        }
+    }
+    template[(1, WIDTH - 1)] = Cell::Fixed(FREE_TILES[1]);
+    template
+}
+
+// Search without learning, so every dead end is found again and undone
+fn search(max_backtrack_depth: usize) -> WaveFunctionBacktracking {
+    WaveFunctionBacktracking::builder()
+        .heuristic(Heuristic::Custom(cost))
+        .max_nogood_size(0)
+        .max_backtrack_depth(max_backtrack_depth)
+        .build()
+}
+
+fn tile(map: &Map, pos: (usize, usize)) -> usize {
+    match map[pos] {
+        Cell::Fixed(tile) => tile,
+        _ => panic!("Cell {pos:?} was not collapsed"),
+    }
+}
+
+// Whether the map obeys the rules and keeps every cell within the options the template gave it
+fn obeys(map: &Map, rules: &Rules) -> bool {
+    let template = template();
+    (0..2).all(|y| {
+        (0..WIDTH).all(|x| {
+            let pos = (y, x);
+            template.restriction(pos).unwrap().contains(&tile(map, pos))
+                && (template[pos] != Cell::Fixed(FREE_TILES[1]) || tile(map, pos) == FREE_TILES[1])
+                && (x + 1 == WIDTH
+                    || rules.is_allowed(tile(map, pos), tile(map, (y, x + 1)), Direction::East))
+                && (y == 1
+                    || rules.is_allowed(tile(map, (y + 1, x)), tile(map, pos), Direction::North))
+        })
+    })
+}
+
+#[test]
+fn undone_decisions_restore_their_options() {
+    let rules = rules();
+    for seed in 0..SEEDS {
+        let (map, stats) = search(100)
+            .collapse_with_stats(&template(), &rules, &mut Pcg64::seed_from_u64(seed))
+            .unwrap();
+        assert!(obeys(&map, &rules), "seed {seed}: {map}");
+        // Every combination of the undecided free cells is tried before the corner is undone
+        assert!(
+            stats.backtracks >= 1 << (2 * (WIDTH - GADGET) - 1),
+            "seed {seed}: {} backtracks",
+            stats.backtracks
+        );
+    }
+}
+
+#[test]
+fn forgotten_decisions_are_never_undone() {
+    let rules = rules();
+    // The corner, then the undecided free cells, then the first gadget cell, whose contradiction dooms them all
+    let num_decisions = 1 + (2 * (WIDTH - GADGET) - 1) + 1;
+    for seed in 0..SEEDS {
+        for depth in 1..=2 * num_decisions {
+            let result =
+                search(depth).collapse(&template(), &rules, &mut Pcg64::seed_from_u64(seed));
+            if depth >= num_decisions {
+                let map = result.unwrap();
+                assert!(obeys(&map, &rules), "seed {seed}, depth {depth}: {map}");
+            } else {
+                // Too shallow to reach back to the corner, so the search runs out of decisions to undo
+                // rather than changing the options of the cells the forgotten decisions fixed
+                let error = result.err().unwrap();
+                assert!(
+                    error
+                        .downcast_ref::<WfcError>()
+                        .is_some_and(WfcError::is_contradiction),
+                    "seed {seed}, depth {depth}: {error}"
+                );
+            }
+        }
+    }
+}
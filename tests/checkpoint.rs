@@ -0,0 +1,103 @@
+//! Checks that a resumed collapser carries on exactly where its checkpoint left off, and that checkpoints
+//! which do not match the rules or have been corrupted are refused rather than resumed.
+
+use ndarray::Array3;
+use std::{fs, path::PathBuf};
+use wave_function::{Cell, Collapser, Map, Rules, WfcError};
+
+const SIZE: (usize, usize) = (8, 8);
+
+fn rules(forbidden: usize) -> Rules {
+    let mut adjacency = Array3::from_elem((3, 3, 2), true);
+    for dir in 0..2 {
+        adjacency[[forbidden, forbidden, dir]] = false;
+    }
+    Rules::new(adjacency, vec![1, 1, 1])
+}
+
+fn path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("wave_function_checkpoint_{name}.bin"))
+}
+
+fn cells(map: &Map) -> Vec<Cell> {
+    (0..SIZE.0)
+        .flat_map(|y| (0..SIZE.1).map(move |x| map[(y, x)]))
+        .collect()
+}
+
+// Checkpoint a collapser part-way through, returning it with the path of its checkpoint
+fn checkpointed<'a>(name: &str, rules: &'a Rules) -> (Collapser<'a>, PathBuf) {
+    let mut collapser = Collapser::new(&Map::empty(SIZE), rules).unwrap();
+    collapser.seed(0);
+    for _ in 0..10 {
+        collapser.step().unwrap();
+    }
+    let path = path(name);
+    collapser.checkpoint(&path).unwrap();
+    (collapser, path)
+}
+
+// Resume from a checkpoint after changing its bytes
+fn resume_edited(name: &str, edit: impl FnOnce(&mut Vec<u8>)) -> anyhow::Error {
+    let rules = rules(2);
+    let (_, path) = checkpointed(name, &rules);
+    let mut bytes = fs::read(&path).unwrap();
+    edit(&mut bytes);
+    fs::write(&path, bytes).unwrap();
+    let error = Collapser::resume(&path, &rules).err().unwrap();
+    fs::remove_file(path).unwrap();
+    error
+}
+
+#[test]
+fn resumed_collapses_match() {
+    let rules = rules(2);
+    let (mut collapser, path) = checkpointed("match", &rules);
+    let mut resumed = Collapser::resume(&path, &rules).unwrap();
+    fs::remove_file(path).unwrap();
+    assert_eq!(resumed.trail(), collapser.trail());
+
+    collapser.run().unwrap();
+    resumed.run().unwrap();
+    assert!(cells(&resumed.to_map()) == cells(&collapser.to_map()));
+}
+
+#[test]
+fn other_rules_are_refused() {
+    let (_, path) = checkpointed("rules", &rules(2));
+    let error = Collapser::resume(&path, &rules(1)).err().unwrap();
+    fs::remove_file(path).unwrap();
+    assert!(
+        matches!(
+            error.downcast_ref::<WfcError>(),
+            Some(WfcError::InvalidRules(_))
+        ),
+        "{error:#}"
+    );
+}
+
+#[test]
+fn corrupt_checkpoints_are_refused() {
+    // The saved size follows the version
+    let error = resume_edited("size", |bytes| bytes[4] += 1);
+    assert!(
+        error.to_string().contains("Checkpoint is corrupt"),
+        "{error:#}"
+    );
+
+    // The template is saved as text, and wildcards are written `*`
+    let error = resume_edited("template", |bytes| {
+        let row = bytes
+            .windows(3)
+            .position(|window| window == b"* *")
+            .unwrap();
+        bytes[row] = b'x';
+    });
+    assert!(
+        error.to_string().contains("Checkpoint is corrupt"),
+        "{error:#}"
+    );
+
+    let error = resume_edited("truncated", |bytes| bytes.truncate(bytes.len() / 2));
+    assert!(!error.to_string().is_empty());
+}
@@ -0,0 +1,112 @@
+//! Checks that chunk stores keep chunks only for the world version which generated them, and that corrupt
+//! chunks fail to load rather than panicking.
+
+use ndarray::Array3;
+use std::{fs, path::PathBuf};
+use wave_function::{
+    Cell, ChunkStore, DirectoryChunkStore, MemoryChunkStore, Rules, WaveFunctionFast,
+    WorldGenerator, WorldVersion,
+};
+
+const TILESET_HASH: u64 = 0x1234;
+
+fn rules() -> Rules {
+    let mut adjacency = Array3::from_elem((3, 3, 2), true);
+    for dir in 0..2 {
+        adjacency[[2, 2, dir]] = false;
+    }
+    Rules::new(adjacency, vec![1, 1, 1])
+}
+
+fn world(rules: &Rules, chunk_size: (usize, usize)) -> WorldGenerator<WaveFunctionFast> {
+    let mut world = WorldGenerator::builder()
+        .chunk_size(chunk_size)
+        .view_radius(0)
+        .prefetch_radius(0)
+        .build(WaveFunctionFast::default(), rules, 7);
+    world.set_focus_points(&[(0, 0)]);
+    world
+}
+
+fn directory(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("wave_function_chunk_store_{name}"));
+    let _ = fs::remove_dir_all(&path);
+    path
+}
+
+fn cells(world: &WorldGenerator<WaveFunctionFast>) -> Vec<Option<Cell>> {
+    (0..4)
+        .flat_map(|y| (0..4).map(move |x| world.get((y, x))))
+        .collect()
+}
+
+#[test]
+fn stores_are_kept_for_the_same_world() {
+    let rules = rules();
+    let path = directory("kept");
+    let mut generated = world(&rules, (4, 4));
+    let store = DirectoryChunkStore::new(&path).unwrap();
+    assert!(!generated.set_store(Box::new(store), TILESET_HASH).unwrap());
+    generated.tick().unwrap();
+    assert!(cells(&generated).iter().all(Option::is_some));
+
+    let mut loaded = world(&rules, (4, 4));
+    let store = DirectoryChunkStore::new(&path).unwrap();
+    assert!(loaded.set_store(Box::new(store), TILESET_HASH).unwrap());
+    loaded.tick().unwrap();
+    assert!(cells(&loaded) == cells(&generated));
+
+    // Chunks of another size belong to another world
+    let mut resized = world(&rules, (5, 5));
+    let store = DirectoryChunkStore::new(&path).unwrap();
+    assert!(!resized.set_store(Box::new(store), TILESET_HASH).unwrap());
+    fs::remove_dir_all(path).unwrap();
+}
+
+#[test]
+fn versions_record_the_chunk_size() {
+    let version = WorldVersion {
+        seed: 7,
+        tileset_hash: TILESET_HASH,
+        chunk_size: (4, 6),
+    };
+    let path = directory("version");
+    let mut store = DirectoryChunkStore::new(&path).unwrap();
+    assert!(!store.open(version).unwrap());
+    assert_eq!(store.version().unwrap(), Some(version));
+    assert!(store.open(version).unwrap());
+    fs::remove_dir_all(path).unwrap();
+
+    let mut store = MemoryChunkStore::new();
+    assert!(!store.open(version).unwrap());
+    assert_eq!(store.version().unwrap(), Some(version));
+
+    #[cfg(feature = "sqlite")]
+    {
+        let connection = rusqlite::Connection::open_in_memory().unwrap();
+        let mut store = wave_function::SqliteChunkStore::new(connection).unwrap();
+        assert!(!store.open(version).unwrap());
+        assert_eq!(store.version().unwrap(), Some(version));
+    }
+    #[cfg(feature = "sled")]
+    {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let mut store = wave_function::SledChunkStore::new(&db).unwrap();
+        assert!(!store.open(version).unwrap());
+        assert_eq!(store.version().unwrap(), Some(version));
+    }
+}
+
+#[test]
+fn corrupt_chunks_fail_to_load() {
+    let path = directory("corrupt");
+    let store = DirectoryChunkStore::new(&path).unwrap();
+    fs::write(path.join("chunk_0_0.txt"), "0 1\n2 x\n").unwrap();
+    fs::write(path.join("chunk_0_1.txt"), "0 1\n2\n").unwrap();
+    for chunk in [(0, 0), (0, 1)] {
+        let error = store.load(chunk).err().unwrap();
+        assert!(error.to_string().contains("is corrupt"), "{error:#}");
+    }
+    assert!(store.load((1, 0)).unwrap().is_none());
+    fs::remove_dir_all(path).unwrap();
+}
@@ -0,0 +1,107 @@
+//! Checks that chunks generated in parallel fit together across their shared edges, keep the template's
+//! cells, and give the same map whatever the number of threads.
+
+use ndarray::Array3;
+use rand::SeedableRng;
+use rand_pcg::Pcg64;
+use wave_function::{Cell, ChunkedGenerator, Direction, Map, Rules, WaveFunctionFast};
+
+const SEEDS: u64 = 5;
+const SIZE: (usize, usize) = (30, 36);
+const CHUNK_SIZE: (usize, usize) = (8, 8); // Leaves the chunks on the far edges cut short
+const THREAD_COUNTS: [usize; 3] = [1, 2, 4];
+
+// Three tiles where the first two may not touch, so chunks must agree along their shared edges
+fn rules() -> Rules {
+    let mut adjacency = Array3::from_elem((3, 3, 2), true);
+    for dir in 0..2 {
+        adjacency[[0, 1, dir]] = false;
+        adjacency[[1, 0, dir]] = false;
+    }
+    Rules::new(adjacency, vec![3, 2, 1])
+}
+
+fn generate(template: &Map, rules: &Rules, seed: u64) -> Map {
+    ChunkedGenerator::builder()
+        .chunk_size(CHUNK_SIZE)
+        .build()
+        .generate_parallel(
+            template,
+            &WaveFunctionFast::default(),
+            rules,
+            &mut Pcg64::seed_from_u64(seed),
+        )
+        .unwrap()
+}
+
+// Whether every open cell is collapsed and allows its eastern and southern neighbours
+fn fits(map: &Map, rules: &Rules) -> bool {
+    let (height, width) = map.size();
+    let tile = |pos| match map[pos] {
+        Cell::Fixed(tile) => Some(tile),
+        _ => None,
+    };
+    let allows = |here, pos, direction| {
+        tile(pos).is_none_or(|other| rules.is_allowed(here, other, direction))
+    };
+    (0..height).all(|y| {
+        (0..width).all(|x| match map[(y, x)] {
+            Cell::Ignore => true,
+            Cell::Fixed(here) => {
+                (x + 1 == width || allows(here, (y, x + 1), Direction::East))
+                    && (y + 1 == height || allows(here, (y + 1, x), Direction::South))
+            }
+            _ => false,
+        })
+    })
+}
+
+#[test]
+fn parallel_chunks_fit_together() {
+    let rules = rules();
+    for seed in 0..SEEDS {
+        let map = generate(&Map::empty(SIZE), &rules, seed);
+        assert!(fits(&map, &rules), "seed {seed}: {map}");
+    }
+}
+
+#[test]
+fn parallel_chunks_keep_the_template() {
+    let rules = rules();
+    let mut template = Map::empty(SIZE);
+    // Cells on the corners and edges of chunks, where neighbouring chunks meet
+    let fixed = [((7, 7), 0), ((8, 8), 1), ((0, 15), 2), ((29, 35), 1)];
+    for (pos, tile) in fixed {
+        template[pos] = Cell::Fixed(tile);
+    }
+    for x in 10..20 {
+        template[(16, x)] = Cell::Ignore;
+    }
+    for seed in 0..SEEDS {
+        let map = generate(&template, &rules, seed);
+        assert!(fits(&map, &rules), "seed {seed}: {map}");
+        for (pos, tile) in fixed {
+            assert!(map[pos] == Cell::Fixed(tile), "seed {seed}: {map}");
+        }
+        assert!((10..20).all(|x| map[(16, x)] == Cell::Ignore));
+    }
+}
+
+#[test]
+fn parallel_chunks_do_not_depend_on_the_number_of_threads() {
+    let rules = rules();
+    let template = Map::empty(SIZE);
+    for seed in 0..SEEDS {
+        let maps: Vec<String> = THREAD_COUNTS
+            .iter()
+            .map(|&num_threads| {
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(num_threads)
+                    .build()
+                    .unwrap()
+                    .install(|| generate(&template, &rules, seed).to_string())
+            })
+            .collect();
+        assert!(maps.iter().all(|map| *map == maps[0]), "seed {seed}");
+    }
+}
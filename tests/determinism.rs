@@ -0,0 +1,161 @@
+//! Checks of the determinism contract: a fixed seed gives identical output, serially or in parallel,
+//! whatever the number of threads.
+//! Run with `cargo test --features determinism`.
+#![cfg(feature = "determinism")]
+
+use ndarray::Array3;
+use rand::SeedableRng;
+use rand_pcg::Pcg64;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use wave_function::{
+    ChunkedGenerator, Collapser, Map, Rules, WaveFunction, WaveFunctionBacktracking,
+    WaveFunctionFast, WaveFunctionLocalReset,
+};
+
+const SEEDS: [u64; 8] = [0, 1, 2, 3, 5, 8, 13, 21];
+const THREAD_COUNTS: [usize; 3] = [1, 2, 4];
+
+// Three tiles where the first two may not touch, so collapses involve real propagation
+fn rules() -> Rules {
+    let mut adjacency = Array3::from_elem((3, 3, 2), true);
+    for dir in 0..2 {
+        adjacency[[0, 1, dir]] = false;
+        adjacency[[1, 0, dir]] = false;
+    }
+    Rules::new(adjacency, vec![3, 2, 1])
+}
+
+fn hash(map: &Map) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    map.to_string().hash(&mut hasher);
+    hasher.finish()
+}
+
+fn serial_hashes(algorithm: &impl WaveFunction, template: &Map, rules: &Rules) -> Vec<u64> {
+    SEEDS
+        .iter()
+        .map(|&seed| {
+            let map = algorithm
+                .collapse(template, rules, &mut Pcg64::seed_from_u64(seed))
+                .expect("Serial collapse failed");
+            hash(&map)
+        })
+        .collect()
+}
+
+fn parallel_hashes(
+    algorithm: &(impl WaveFunction + Sync),
+    template: &Map,
+    rules: &Rules,
+    num_threads: usize,
+) -> Vec<u64> {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(num_threads)
+        .build()
+        .expect("Failed to build thread pool");
+    pool.install(|| template.collapse_batch(algorithm, rules, &SEEDS))
+        .iter()
+        .map(|result| hash(result.as_ref().expect("Parallel collapse failed")))
+        .collect()
+}
+
+fn check_algorithm(algorithm: &(impl WaveFunction + Sync)) {
+    let rules = rules();
+    let template = Map::empty((16, 16));
+    let serial = serial_hashes(algorithm, &template, &rules);
+    assert_eq!(
+        serial,
+        serial_hashes(algorithm, &template, &rules),
+        "Serial output changed between runs"
+    );
+    for num_threads in THREAD_COUNTS {
+        assert_eq!(
+            serial,
+            parallel_hashes(algorithm, &template, &rules, num_threads),
+            "Parallel output on {} threads differs from serial output",
+            num_threads
+        );
+    }
+}
+
+#[test]
+fn fast_is_deterministic() {
+    check_algorithm(&WaveFunctionFast::default());
+}
+
+#[test]
+fn backtracking_is_deterministic() {
+    check_algorithm(&WaveFunctionBacktracking::default());
+}
+
+#[test]
+fn local_reset_is_deterministic() {
+    check_algorithm(&WaveFunctionLocalReset::default());
+}
+
+#[test]
+fn collapse_seeded_matches_seeded_generator() {
+    let rules = rules();
+    let template = Map::empty((16, 16));
+    let seeded: Vec<u64> = SEEDS
+        .iter()
+        .map(|&seed| {
+            let map = template
+                .collapse_seeded::<WaveFunctionBacktracking>(&rules, seed)
+                .expect("Seeded collapse failed");
+            hash(&map)
+        })
+        .collect();
+    assert_eq!(
+        seeded,
+        serial_hashes(&WaveFunctionBacktracking::default(), &template, &rules),
+        "Seeded collapse differs from collapsing with a generator from the same seed"
+    );
+}
+
+#[test]
+fn collapser_is_deterministic() {
+    let rules = rules();
+    let template = Map::empty((16, 16));
+    let run = |seed: u64| {
+        let mut collapser = Collapser::new(&template, &rules).expect("Invalid template");
+        collapser.seed(seed);
+        collapser.run().expect("Collapse failed");
+        hash(&collapser.to_map())
+    };
+    for seed in SEEDS {
+        assert_eq!(
+            run(seed),
+            run(seed),
+            "Collapser output changed for seed {}",
+            seed
+        );
+    }
+}
+
+#[test]
+fn chunked_generator_is_deterministic() {
+    let rules = rules();
+    let template = Map::empty((40, 40));
+    let generator = ChunkedGenerator::builder().chunk_size((16, 16)).build();
+    let algorithm = WaveFunctionFast::default();
+    let run = |seed: u64| {
+        let map = generator
+            .generate(
+                &template,
+                &algorithm,
+                &rules,
+                &mut Pcg64::seed_from_u64(seed),
+            )
+            .expect("Chunked generation failed");
+        hash(&map)
+    };
+    for seed in SEEDS {
+        assert_eq!(
+            run(seed),
+            run(seed),
+            "Chunked output changed for seed {}",
+            seed
+        );
+    }
+}
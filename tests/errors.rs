@@ -0,0 +1,121 @@
+//! Checks that collapses which fail on a contradiction say so with a `WfcError`, including when the failure
+//! comes from inside a chunked or world generator, or a volume.
+
+use ndarray::{Array2, Array3};
+use rand::SeedableRng;
+use rand_pcg::Pcg64;
+#[allow(deprecated)]
+use wave_function::Contradiction;
+use wave_function::{
+    ChunkedGenerator, Map, Map3, Rules, Rules3, WaveFunction, WaveFunctionFast, WfcError,
+    WorldGenerator, collapse_volume,
+};
+
+// The permutations of three items
+const PERMUTATIONS: [[usize; 3]; 6] = [
+    [0, 1, 2],
+    [0, 2, 1],
+    [1, 0, 2],
+    [1, 2, 0],
+    [2, 0, 1],
+    [2, 1, 0],
+];
+
+// Permutation applying `first` and then `second`
+fn compose(first: usize, second: usize) -> usize {
+    let [a, b] = [PERMUTATIONS[first], PERMUTATIONS[second]];
+    let composed = [b[a[0]], b[a[1]], b[a[2]]];
+    PERMUTATIONS.iter().position(|&p| p == composed).unwrap()
+}
+
+// Tiles standing for the permutations, where each tile fixes the tile east of it and the one south of it by
+// two permutations which do not commute. Every tile has neighbours in every direction, so propagation finds
+// nothing wrong until a cell is collapsed, but no two by two block can be filled.
+fn rules() -> Rules {
+    let (east, south) = (1, 2);
+    let mut adjacency = Array3::from_elem((6, 6, 2), false);
+    for tile in 0..6 {
+        adjacency[[tile, compose(tile, east), 0]] = true;
+        adjacency[[compose(tile, south), tile, 1]] = true;
+    }
+    Rules::new(adjacency, vec![1; 6])
+}
+
+fn is_contradiction(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<WfcError>()
+        .is_some_and(WfcError::is_contradiction)
+}
+
+#[test]
+fn solvers_fail_with_contradictions() {
+    let error = WaveFunctionFast::default()
+        .collapse(&Map::empty((4, 4)), &rules(), &mut Pcg64::seed_from_u64(0))
+        .err()
+        .unwrap();
+    assert!(is_contradiction(&error), "{error:#}");
+}
+
+#[test]
+fn chunk_failures_keep_their_contradictions() {
+    let generator = ChunkedGenerator::builder()
+        .chunk_size((4, 4))
+        .max_retries(1)
+        .max_expansions(1)
+        .build();
+    let error = generator
+        .generate(
+            &Map::empty((8, 8)),
+            &WaveFunctionFast::default(),
+            &rules(),
+            &mut Pcg64::seed_from_u64(0),
+        )
+        .err()
+        .unwrap();
+    assert!(is_contradiction(&error), "{error:#}");
+}
+
+#[test]
+fn world_failures_keep_their_contradictions() {
+    let rules = rules();
+    let mut world = WorldGenerator::builder()
+        .chunk_size((4, 4))
+        .max_retries(1)
+        .build(WaveFunctionFast::default(), &rules, 0);
+    world.set_focus_points(&[(0, 0)]);
+    let error = world.generate_view().unwrap_err();
+    assert!(is_contradiction(&error), "{error:#}");
+}
+
+#[test]
+fn volumes_fail_with_contradictions() {
+    let rules = Rules3::new(rules(), &Array2::from_elem((6, 6), true));
+    let error = collapse_volume(
+        &Map3::empty((2, 4, 4)),
+        &rules,
+        &mut Pcg64::seed_from_u64(0),
+    )
+    .err()
+    .unwrap();
+    assert!(
+        matches!(
+            error.downcast_ref::<WfcError>(),
+            Some(WfcError::VolumeContradiction { .. })
+        ),
+        "{error:#}"
+    );
+}
+
+#[test]
+#[allow(deprecated)]
+fn old_contradictions_convert() {
+    let error = WfcError::from(Contradiction { cell: (1, 2) });
+    assert!(error.is_contradiction());
+    assert_eq!(
+        error,
+        WfcError::Contradiction {
+            cell: (1, 2),
+            direction: None
+        }
+    );
+}
@@ -0,0 +1,352 @@
+//! Golden tests: small maps are generated from the bundled tilesets with fixed seeds and compared with the
+//! index and image files checked in under `tests/golden`, so a change to the propagation engine which alters
+//! what is generated cannot pass unnoticed.
+//! The rules derived from each tileset are checked in beside them as edge lists, so the maps are generated and
+//! their index files compared exactly without the `render` feature.
+//! With it, the rules are also derived again from the tileset images and must match the edge lists, and the
+//! rendered maps are compared with the images with a small perceptual tolerance, so changes to rendering which
+//! are invisible to the eye do not fail.
+//! After an intended change to the output, regenerate the golden files with
+//! `GOLDEN_UPDATE=1 cargo test --features render --test golden` and review the differences before committing them.
+
+#[cfg(feature = "render")]
+use ndarray::{Array3, s};
+#[cfg(feature = "render")]
+use photo::ImageRGBA;
+use rand::SeedableRng;
+use rand_pcg::Pcg64;
+#[cfg(feature = "render")]
+use std::fs::File;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+#[cfg(feature = "render")]
+use wave_function::Tileset;
+use wave_function::{AlgorithmRegistry, Direction, Map, Rules};
+
+#[cfg(feature = "render")]
+const INTERIOR_SIZE: usize = 3;
+#[cfg(feature = "render")]
+const BORDER_SIZE: usize = 1;
+const MAP_SIZE: (usize, usize) = (12, 12);
+const UPDATE_VAR: &str = "GOLDEN_UPDATE"; // Set to regenerate the golden files instead of checking them
+#[cfg(feature = "render")]
+const PIXEL_TOLERANCE: u8 = 8; // Largest channel difference at which two pixels still look the same
+#[cfg(feature = "render")]
+const MAX_DIFFERING: f64 = 0.01; // Largest fraction of pixels which may look different
+const FREQUENCIES_PREFIX: &str = "# frequencies: "; // Starts the line of a rules file listing tile frequencies
+
+struct Case {
+    name: &'static str,
+    tileset: &'static str,
+    algorithm: &'static str,
+    seed: u64,
+}
+
+const CASES: [Case; 6] = [
+    Case {
+        name: "simple_fast",
+        tileset: "simple.png",
+        algorithm: "fast",
+        seed: 1,
+    },
+    Case {
+        name: "simple_backtracking",
+        tileset: "simple.png",
+        algorithm: "backtracking",
+        seed: 2,
+    },
+    Case {
+        name: "terrain_local_reset",
+        tileset: "terrain.png",
+        algorithm: "local-reset",
+        seed: 3,
+    },
+    Case {
+        name: "pattern_backtracking",
+        tileset: "pattern.png",
+        algorithm: "backtracking",
+        seed: 4,
+    },
+    Case {
+        name: "complex_backtracking",
+        tileset: "complex.png",
+        algorithm: "backtracking",
+        seed: 5,
+    },
+    Case {
+        name: "complex_local_reset",
+        tileset: "complex.png",
+        algorithm: "local-reset",
+        seed: 6,
+    },
+];
+
+fn root() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+}
+
+fn golden_dir() -> PathBuf {
+    root().join("tests").join("golden")
+}
+
+// Path of the rules derived from a bundled tileset image
+fn rules_path(tileset: &str) -> PathBuf {
+    let stem = Path::new(tileset)
+        .file_stem()
+        .expect("Tileset must have a file name");
+    golden_dir().join(stem).with_extension("csv")
+}
+
+// Rules as an edge list readable by `Rules::from_csv`, headed by a comment listing the tile frequencies
+fn rules_csv(rules: &Rules) -> String {
+    let frequencies: Vec<String> = rules.frequencies().iter().map(usize::to_string).collect();
+    let mut csv = format!(
+        "{FREQUENCIES_PREFIX}{}\nfrom,to,direction\n",
+        frequencies.join(",")
+    );
+    for tile in 0..rules.len() {
+        for other in 0..rules.len() {
+            for (direction, name) in [(Direction::North, "north"), (Direction::East, "east")] {
+                if rules.is_allowed(tile, other, direction) {
+                    csv.push_str(&format!("{tile},{other},{name}\n"));
+                }
+            }
+        }
+    }
+    csv
+}
+
+fn read_rules(tileset: &str) -> Result<Rules, String> {
+    let path = rules_path(tileset);
+    let csv = fs::read_to_string(&path).map_err(|_| format!("missing {}", path.display()))?;
+    let frequencies = csv
+        .lines()
+        .find_map(|line| line.strip_prefix(FREQUENCIES_PREFIX))
+        .ok_or_else(|| format!("{} lists no frequencies", path.display()))?
+        .split(',')
+        .map(|frequency| {
+            frequency
+                .parse()
+                .map_err(|e| format!("{}: {e}", path.display()))
+        })
+        .collect::<Result<Vec<usize>, _>>()?;
+    Rules::from_csv(&[path.to_str().expect("Path must be UTF-8")], frequencies)
+        .map_err(|e| format!("{}: {e:#}", path.display()))
+}
+
+// Decode an 8-bit RGBA PNG
+#[cfg(feature = "render")]
+fn read_png(path: &Path) -> ImageRGBA<u8> {
+    let file = File::open(path).unwrap_or_else(|_| panic!("Failed to open {}", path.display()));
+    let mut reader = png::Decoder::new(file)
+        .read_info()
+        .unwrap_or_else(|_| panic!("Failed to read {}", path.display()));
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader
+        .next_frame(&mut buffer)
+        .expect("Failed to decode PNG");
+    assert_eq!(
+        (info.color_type, info.bit_depth),
+        (png::ColorType::Rgba, png::BitDepth::Eight),
+        "{} must be an 8-bit RGBA image",
+        path.display()
+    );
+    buffer.truncate(info.buffer_size());
+    let shape = (info.height as usize, info.width as usize, 4);
+    ImageRGBA::new(Array3::from_shape_vec(shape, buffer).expect("PNG size mismatch"))
+}
+
+// Cut a bundled example image into overlapping tiles, which share their borders with their neighbours
+// in the image, and derive the rules from which borders match
+#[cfg(feature = "render")]
+fn load_tileset(filename: &str) -> Tileset {
+    let image = read_png(&root().join("input").join(filename));
+    let (height, width, _) = image.data.dim();
+    let tile_size = INTERIOR_SIZE + 2 * BORDER_SIZE;
+    let stride = INTERIOR_SIZE + BORDER_SIZE;
+
+    let mut tiles: Vec<ImageRGBA<u8>> = Vec::new();
+    let mut frequencies = Vec::new();
+    for y in (0..=height - tile_size).step_by(stride) {
+        for x in (0..=width - tile_size).step_by(stride) {
+            let tile = ImageRGBA::new(
+                image
+                    .data
+                    .slice(s![y..y + tile_size, x..x + tile_size, ..])
+                    .to_owned(),
+            );
+            match tiles.iter().position(|other| other == &tile) {
+                Some(index) => frequencies[index] += 1,
+                None => {
+                    tiles.push(tile);
+                    frequencies.push(1);
+                }
+            }
+        }
+    }
+
+    let n = BORDER_SIZE;
+    let mut adjacency = Array3::from_elem((tiles.len(), tiles.len(), 2), false);
+    for (i, a) in tiles.iter().enumerate() {
+        for (j, b) in tiles.iter().enumerate() {
+            adjacency[[i, j, 0]] =
+                a.data.slice(s![.., tile_size - n.., ..]) == b.data.slice(s![.., ..n, ..]);
+            adjacency[[i, j, 1]] =
+                a.data.slice(s![..n, .., ..]) == b.data.slice(s![tile_size - n.., .., ..]);
+        }
+    }
+    Tileset::new(
+        INTERIOR_SIZE,
+        BORDER_SIZE,
+        tiles,
+        Rules::new(adjacency, frequencies),
+    )
+}
+
+// Fraction of pixels whose channels differ by more than the tolerance
+#[cfg(feature = "render")]
+fn differing_fraction(a: &ImageRGBA<u8>, b: &ImageRGBA<u8>) -> f64 {
+    let (height, width, _) = a.data.dim();
+    let mut num_differing = 0;
+    for y in 0..height {
+        for x in 0..width {
+            let differs =
+                (0..4).any(|c| a.data[[y, x, c]].abs_diff(b.data[[y, x, c]]) > PIXEL_TOLERANCE);
+            if differs {
+                num_differing += 1;
+            }
+        }
+    }
+    f64::from(num_differing) / (height * width) as f64
+}
+
+// Write the golden files of a case from its tileset image
+#[cfg(feature = "render")]
+fn update_case(case: &Case, registry: &AlgorithmRegistry) -> Result<(), String> {
+    let tileset = load_tileset(case.tileset);
+    let map = collapse(case, registry, tileset.rules())?;
+    fs::create_dir_all(golden_dir()).expect("Failed to create golden directory");
+    fs::write(rules_path(case.tileset), rules_csv(tileset.rules()))
+        .expect("Failed to write golden rules");
+    fs::write(
+        golden_dir().join(format!("{}.txt", case.name)),
+        map.to_string(),
+    )
+    .expect("Failed to write golden index");
+    map.render_to_png(&tileset, &golden_dir().join(format!("{}.png", case.name)))
+        .expect("Failed to write golden image");
+    Ok(())
+}
+
+#[cfg(not(feature = "render"))]
+fn update_case(_case: &Case, _registry: &AlgorithmRegistry) -> Result<(), String> {
+    panic!(
+        "Regenerating the golden files needs the `render` feature, to derive the rules from the tilesets"
+    )
+}
+
+fn collapse(case: &Case, registry: &AlgorithmRegistry, rules: &Rules) -> Result<Map, String> {
+    let algorithm = registry
+        .get(case.algorithm)
+        .unwrap_or_else(|| panic!("Unknown algorithm: {}", case.algorithm));
+    algorithm
+        .collapse_dyn(
+            &Map::empty(MAP_SIZE),
+            rules,
+            &mut Pcg64::seed_from_u64(case.seed),
+        )
+        .map_err(|e| format!("{}: collapse failed: {}", case.name, e))
+}
+
+fn check_case(case: &Case, registry: &AlgorithmRegistry) -> Result<(), String> {
+    let rules = read_rules(case.tileset).map_err(|e| format!("{}: {e}", case.name))?;
+    let map = collapse(case, registry, &rules)?;
+
+    let index_path = golden_dir().join(format!("{}.txt", case.name));
+    let golden_index = fs::read_to_string(&index_path)
+        .map_err(|_| format!("{}: missing {}", case.name, index_path.display()))?;
+    let golden = Map::parse(&golden_index)
+        .map_err(|e| format!("{}: {}: {e:#}", case.name, index_path.display()))?;
+    if golden.size() != map.size() {
+        return Err(format!(
+            "{}: map is {:?}, golden is {:?}",
+            case.name,
+            map.size(),
+            golden.size()
+        ));
+    }
+    let (height, width) = map.size();
+    let num_changed = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (y, x)))
+        .filter(|&pos| map[pos] != golden[pos])
+        .count();
+    if num_changed > 0 {
+        return Err(format!(
+            "{}: {} of {} cells differ from the golden index",
+            case.name,
+            num_changed,
+            height * width
+        ));
+    }
+
+    #[cfg(feature = "render")]
+    check_image(case, &rules, &map)?;
+    Ok(())
+}
+
+// Check that the tileset image still gives the golden rules, and that the map renders like the golden image
+#[cfg(feature = "render")]
+fn check_image(case: &Case, rules: &Rules, map: &Map) -> Result<(), String> {
+    let tileset = load_tileset(case.tileset);
+    if rules_csv(tileset.rules()) != rules_csv(rules) {
+        return Err(format!(
+            "{}: rules derived from {} differ from the golden rules",
+            case.name, case.tileset
+        ));
+    }
+
+    let image_path = golden_dir().join(format!("{}.png", case.name));
+    let image = map.render(&tileset);
+    let golden_image = read_png(&image_path);
+    if image.data.dim() != golden_image.data.dim() {
+        return Err(format!(
+            "{}: image is {:?}, golden is {:?}",
+            case.name,
+            image.data.dim(),
+            golden_image.data.dim()
+        ));
+    }
+    let fraction = differing_fraction(&image, &golden_image);
+    if fraction > MAX_DIFFERING {
+        return Err(format!(
+            "{}: {:.1}% of pixels differ visibly from the golden image",
+            case.name,
+            fraction * 100.0
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn generated_maps_match_golden_files() {
+    let update = std::env::var_os(UPDATE_VAR).is_some();
+    let registry = AlgorithmRegistry::default();
+    let failures: Vec<String> = CASES
+        .iter()
+        .filter_map(|case| {
+            if update {
+                update_case(case, &registry).err()
+            } else {
+                check_case(case, &registry).err()
+            }
+        })
+        .collect();
+    assert!(
+        failures.is_empty(),
+        "Output differs from the golden files (set {} to regenerate them if the change is intended):\n{}",
+        UPDATE_VAR,
+        failures.join("\n")
+    );
+}
@@ -0,0 +1,47 @@
+//! Checks that maps exported as JSON keep their cells and carry the tags of the tiles they use.
+//! Run with `cargo test --features io`.
+#![cfg(feature = "io")]
+
+use ndarray::Array2;
+use serde_json::{Value, json};
+use wave_function::{Cell, Map, TileMetadata};
+
+fn tagged(tags: &[&str]) -> TileMetadata {
+    TileMetadata {
+        tags: tags.iter().map(ToString::to_string).collect(),
+        ..TileMetadata::default()
+    }
+}
+
+#[test]
+fn exports_cells_and_tags() {
+    let map = Map::new(
+        Array2::from_shape_vec(
+            (2, 3),
+            vec![
+                Cell::Fixed(0),
+                Cell::Fixed(2),
+                Cell::Ignore,
+                Cell::Wildcard,
+                Cell::Hole,
+                Cell::Fixed(3),
+            ],
+        )
+        .unwrap(),
+    );
+    let metadata = [tagged(&["land"]), tagged(&[]), tagged(&["sea", "deep"])];
+    let exported: Value = serde_json::from_str(&map.export_json_schema_v1(&metadata)).unwrap();
+    assert_eq!(exported["width"], 3);
+    assert_eq!(exported["height"], 2);
+    assert_eq!(exported["cells"], json!([0, 2, -1, -2, -3, 3]));
+    // Tile 3 has no metadata, so it is exported without tags
+    assert_eq!(
+        exported["palette"],
+        json!([
+            { "index": 0, "tags": ["land"] },
+            { "index": 2, "tags": ["sea", "deep"] },
+            { "index": 3, "tags": [] },
+        ])
+    );
+    assert!(exported.get("provenance").is_none());
+}
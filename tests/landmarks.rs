@@ -0,0 +1,84 @@
+//! Checks that landmarks make their boosted tiles more common near them, and that every falloff gives a
+//! finite strength, even with no width.
+
+use ndarray::Array3;
+use rand::SeedableRng;
+use rand_pcg::Pcg64;
+use wave_function::{Cell, Falloff, Landmark, Map, Rules, WaveFunction, WaveFunctionFast};
+
+const SIZE: (usize, usize) = (12, 12);
+const CENTRE: (usize, usize) = (6, 6);
+const BOOSTED: usize = 1;
+const SEEDS: u64 = 10;
+
+fn rules() -> Rules {
+    Rules::new(Array3::from_elem((2, 2, 2), true), vec![1, 1])
+}
+
+// Number of boosted tiles within two cells of the centre, over several seeds
+fn num_boosted_near_centre(template: &Map) -> usize {
+    let rules = rules();
+    (0..SEEDS)
+        .map(|seed| {
+            let map = WaveFunctionFast::default()
+                .collapse(template, &rules, &mut Pcg64::seed_from_u64(seed))
+                .unwrap();
+            (CENTRE.0 - 2..=CENTRE.0 + 2)
+                .flat_map(|y| (CENTRE.1 - 2..=CENTRE.1 + 2).map(move |x| (y, x)))
+                .filter(|&pos| map[pos] == Cell::Fixed(BOOSTED))
+                .count()
+        })
+        .sum()
+}
+
+#[test]
+fn landmarks_boost_tiles_near_them() {
+    let plain = Map::empty(SIZE);
+    let mut boosted = Map::empty(SIZE);
+    boosted
+        .add_landmark(Landmark::new(CENTRE, Falloff::Gaussian { sigma: 3.0 }).boost(BOOSTED, 50.0));
+    let mut suppressed = Map::empty(SIZE);
+    suppressed.add_landmark(
+        Landmark::new(CENTRE, Falloff::Constant { radius: 3.0 }).boost(BOOSTED, 0.02),
+    );
+    let (plain, boosted, suppressed) = (
+        num_boosted_near_centre(&plain),
+        num_boosted_near_centre(&boosted),
+        num_boosted_near_centre(&suppressed),
+    );
+    assert!(
+        boosted > plain && plain > suppressed,
+        "{boosted}, {plain}, {suppressed}"
+    );
+}
+
+#[test]
+fn falloffs_without_width_affect_only_the_landmark() {
+    for falloff in [
+        Falloff::Gaussian { sigma: 0.0 },
+        Falloff::Linear { radius: 0.0 },
+        Falloff::Constant { radius: 0.0 },
+        Falloff::Gaussian { sigma: -1.0 },
+        Falloff::Linear { radius: f64::NAN },
+    ] {
+        assert!(
+            (falloff.strength(0.0) - 1.0).abs() < f64::EPSILON,
+            "{falloff:?}"
+        );
+        for distance in [0.5, 1.0, 10.0] {
+            assert!(
+                falloff.strength(distance).abs() < f64::EPSILON,
+                "{falloff:?}"
+            );
+        }
+    }
+    let landmark = Landmark::new(CENTRE, Falloff::Gaussian { sigma: 0.0 }).boost(BOOSTED, 4.0);
+    assert!((landmark.multiplier(CENTRE, BOOSTED) - 4.0).abs() < f64::EPSILON);
+    assert!((landmark.multiplier((0, 0), BOOSTED) - 1.0).abs() < f64::EPSILON);
+}
+
+#[test]
+#[should_panic(expected = "Landmark boost must be positive and finite")]
+fn boosts_must_be_positive() {
+    let _ = Landmark::new(CENTRE, Falloff::Linear { radius: 2.0 }).boost(BOOSTED, 0.0);
+}
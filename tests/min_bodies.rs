@@ -0,0 +1,129 @@
+//! Checks that undersized bodies are removed by re-collapsing only the cells around them, and that bodies
+//! fixed by the template are left alone.
+
+use ndarray::{Array2, Array3};
+use rand::SeedableRng;
+use rand_pcg::Pcg64;
+use wave_function::{Boundary, Cell, CollapseOptions, Map, Rules, WaveFunction, WaveFunctionFast};
+
+const SIZE: (usize, usize) = (10, 10);
+const GROUND: usize = 0;
+const WATER: usize = 1;
+const MIN_LAKE: usize = 4;
+
+// Ground and water which may sit anywhere, with lakes of at least four cells
+fn rules() -> Rules {
+    let mut rules = Rules::new(Array3::from_elem((2, 2, 2), true), vec![1, 1]);
+    rules.set_categories(vec![0, 1]);
+    rules.set_min_body_size(1, MIN_LAKE);
+    rules
+}
+
+// Ground everywhere except for the given water cells
+fn map(water: &[(usize, usize)]) -> Map {
+    let mut map = Map::new(Array2::from_elem(SIZE, Cell::Fixed(GROUND)));
+    for &pos in water {
+        map[pos] = Cell::Fixed(WATER);
+    }
+    map
+}
+
+fn positions() -> impl Iterator<Item = (usize, usize)> {
+    (0..SIZE.0).flat_map(|y| (0..SIZE.1).map(move |x| (y, x)))
+}
+
+fn same(map: &Map, other: &Map) -> bool {
+    positions().all(|pos| map[pos] == other[pos])
+}
+
+#[test]
+fn collapsed_maps_have_no_small_bodies() {
+    let rules = rules();
+    for seed in 0..5 {
+        let map = WaveFunctionFast::default()
+            .collapse(&Map::empty(SIZE), &rules, &mut Pcg64::seed_from_u64(seed))
+            .unwrap();
+        assert!(
+            map.small_bodies(&rules, Boundary::Open).is_empty(),
+            "seed {seed}"
+        );
+    }
+}
+
+#[test]
+fn only_cells_around_small_bodies_change() {
+    let rules = rules();
+    let puddle = [(2, 2), (2, 3)];
+    let lake = [(7, 6), (7, 7), (8, 6), (8, 7)];
+    let original = map(&[&puddle[..], &lake[..]].concat());
+    assert_eq!(
+        original.small_bodies(&rules, Boundary::Open),
+        vec![puddle.to_vec()]
+    );
+
+    for seed in 0..5 {
+        let map = original
+            .enforce_min_bodies(
+                &Map::empty(SIZE),
+                &rules,
+                &WaveFunctionFast::default(),
+                &CollapseOptions::default(),
+                &mut Pcg64::seed_from_u64(seed),
+            )
+            .unwrap();
+        assert!(
+            map.small_bodies(&rules, Boundary::Open).is_empty(),
+            "seed {seed}"
+        );
+        for pos in puddle {
+            assert!(map[pos] == Cell::Fixed(GROUND), "seed {seed}: {map}");
+        }
+        // Only the puddle and its neighbours are reopened
+        for pos in positions().filter(|&(y, x)| !(1..=3).contains(&y) || !(1..=4).contains(&x)) {
+            assert!(map[pos] == original[pos], "seed {seed}: {map}");
+        }
+    }
+}
+
+#[test]
+fn fixed_bodies_are_kept() {
+    let rules = rules();
+    let puddle = [(5, 5)];
+    let original = map(&puddle);
+    let mut template = Map::empty(SIZE);
+    template[puddle[0]] = Cell::Fixed(WATER);
+    let map = original
+        .enforce_min_bodies(
+            &template,
+            &rules,
+            &WaveFunctionFast::default(),
+            &CollapseOptions::default(),
+            &mut Pcg64::seed_from_u64(0),
+        )
+        .unwrap();
+    assert!(same(&map, &original), "{map}");
+}
+
+#[test]
+fn bodies_join_across_wrapped_edges() {
+    let rules = rules();
+    let lake = [(4, 0), (4, 1), (4, 8), (4, 9)];
+    let original = map(&lake);
+    assert!(original.small_bodies(&rules, Boundary::Wrap).is_empty());
+    assert_eq!(original.small_bodies(&rules, Boundary::Open).len(), 2);
+
+    let options = CollapseOptions {
+        boundary: Boundary::Wrap,
+        ..CollapseOptions::default()
+    };
+    let map = original
+        .enforce_min_bodies(
+            &Map::empty(SIZE),
+            &rules,
+            &WaveFunctionFast::default(),
+            &options,
+            &mut Pcg64::seed_from_u64(0),
+        )
+        .unwrap();
+    assert!(same(&map, &original), "{map}");
+}
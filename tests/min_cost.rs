@@ -0,0 +1,96 @@
+//! Checks that the min-cost local search lowers the cost of a map without breaking minimum body sizes or
+//! moving holes off the hole tile.
+
+use ndarray::{Array2, Array3};
+use rand::SeedableRng;
+use rand_pcg::Pcg64;
+use wave_function::{
+    Boundary, Cell, Map, Rules, WaveFunction, WaveFunctionFast, WaveFunctionMinCost,
+};
+
+const SIZE: (usize, usize) = (10, 10);
+const WATER: usize = 1;
+const SEEDS: u64 = 5;
+
+// Ground and water which may sit anywhere
+fn rules() -> Rules {
+    let mut rules = Rules::new(Array3::from_elem((2, 2, 2), true), vec![1, 1]);
+    rules.set_categories(vec![0, 1]);
+    rules
+}
+
+// Every adjacency with water costs one, so the search removes water where it can
+fn search() -> WaveFunctionMinCost<WaveFunctionFast> {
+    let costs = Array3::from_shape_fn((2, 2, 2), |(tile, other, _)| {
+        if tile == WATER || other == WATER {
+            1.0
+        } else {
+            0.0
+        }
+    });
+    WaveFunctionMinCost::builder().build(WaveFunctionFast::default(), costs)
+}
+
+fn num_water(map: &Map) -> usize {
+    Array2::from_shape_fn(SIZE, |pos| map[pos] == Cell::Fixed(WATER))
+        .iter()
+        .filter(|&&is_water| is_water)
+        .count()
+}
+
+#[test]
+fn searches_lower_the_cost() {
+    let rules = rules();
+    let search = search();
+    for seed in 0..SEEDS {
+        let collapsed = WaveFunctionFast::default()
+            .collapse(&Map::empty(SIZE), &rules, &mut Pcg64::seed_from_u64(seed))
+            .unwrap();
+        let searched = search
+            .collapse(&Map::empty(SIZE), &rules, &mut Pcg64::seed_from_u64(seed))
+            .unwrap();
+        assert!(
+            search.total_cost(&searched) < search.total_cost(&collapsed),
+            "seed {seed}"
+        );
+    }
+}
+
+#[test]
+fn searches_keep_min_body_sizes() {
+    let mut rules = rules();
+    rules.set_min_body_size(1, 4);
+    let search = search();
+    let mut num_lakes = 0;
+    for seed in 0..SEEDS {
+        let map = search
+            .collapse(&Map::empty(SIZE), &rules, &mut Pcg64::seed_from_u64(seed))
+            .unwrap();
+        assert!(
+            map.small_bodies(&rules, Boundary::Open).is_empty(),
+            "seed {seed}: {map}"
+        );
+        num_lakes += num_water(&map);
+    }
+    // Shrinking a lake is always cheaper, so the search must have been held back
+    assert!(num_lakes > 0);
+}
+
+#[test]
+fn searches_keep_holes() {
+    let mut rules = rules();
+    rules.set_hole_tile(Some(WATER));
+    let mut template = Map::empty(SIZE);
+    let holes = [(1, 1), (4, 6), (8, 3)];
+    for pos in holes {
+        template[pos] = Cell::Hole;
+    }
+    for seed in 0..SEEDS {
+        let map = search()
+            .collapse(&template, &rules, &mut Pcg64::seed_from_u64(seed))
+            .unwrap();
+        for pos in holes {
+            assert!(map[pos] == Cell::Fixed(WATER), "seed {seed}: {map}");
+        }
+    }
+}
@@ -0,0 +1,112 @@
+//! Checks that minimizing rules only removes adjacencies no map can use, so the minimized rules accept exactly
+//! the maps the original rules did.
+
+use ndarray::{Array2, Array3};
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64;
+use wave_function::{
+    Boundary, Cell, Map, Quota, Rules, check_quotas_with_boundary, propagate_with_boundary,
+};
+
+const NUM_TILES: usize = 4;
+const EDGE_TILE: usize = 1; // Has no tile it may sit below, so only fits on the top edge or under an ignored cell
+const UNUSABLE_TILE: usize = 3; // In a category whose quota allows no cells
+
+// Random adjacencies between four tiles, with one edge tile and one tile which may never be placed
+fn rules(seed: u64) -> Rules {
+    let mut rng = Pcg64::seed_from_u64(seed);
+    let mut adjacency = Array3::from_shape_fn((NUM_TILES, NUM_TILES, 2), |_| rng.random_bool(0.6));
+    for other in 0..NUM_TILES {
+        adjacency[[EDGE_TILE, other, 1]] = false;
+    }
+    let mut rules = Rules::new(adjacency, vec![1, 2, 3, 4]);
+    rules.set_categories(vec![0, 0, 0, 1]);
+    rules.set_quota(1, Some(Quota::at_most(0)));
+    rules
+}
+
+// Every map of the given size made of the tiles and ignored cells
+fn maps((height, width): (usize, usize)) -> impl Iterator<Item = Map> {
+    let num_values = NUM_TILES + 1;
+    (0..num_values.pow((height * width) as u32)).map(move |mut code| {
+        Map::new(Array2::from_shape_fn((height, width), |_| {
+            let value = code % num_values;
+            code /= num_values;
+            if value == NUM_TILES {
+                Cell::Ignore
+            } else {
+                Cell::Fixed(value)
+            }
+        }))
+    })
+}
+
+// Whether the rules allow a map, as solvers check its fixed cells before collapsing it
+fn accepts(rules: &Rules, map: &Map, boundary: Boundary) -> bool {
+    check_quotas_with_boundary(map, rules, boundary).is_ok()
+        && propagate_with_boundary(&mut map.domains(rules.len()), rules, None, boundary).is_ok()
+}
+
+#[test]
+fn minimized_rules_accept_the_same_maps() {
+    for seed in 0..2 {
+        let rules = rules(seed);
+        let mut minimized = rules.clone();
+        let report = minimized.minimize();
+        assert_eq!(report.unusable_tiles, vec![UNUSABLE_TILE], "seed {seed}");
+        assert!(report.edge_tiles.contains(&EDGE_TILE), "seed {seed}");
+        assert!(report.num_removed > 0, "seed {seed}");
+
+        for size in [(2, 3), (1, 4)] {
+            for boundary in [Boundary::Open, Boundary::Wrap] {
+                for map in maps(size) {
+                    assert_eq!(
+                        accepts(&rules, &map, boundary),
+                        accepts(&minimized, &map, boundary),
+                        "seed {seed}, {boundary:?}: {map}"
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn edge_tiles_keep_their_adjacencies() {
+    let rules = rules(0);
+    let mut minimized = rules.clone();
+    minimized.minimize();
+    for tile in 0..UNUSABLE_TILE {
+        for (list, minimized_list) in rules
+            .adjacency_list(tile)
+            .iter()
+            .zip(minimized.adjacency_list(tile))
+        {
+            let kept: Vec<usize> = list
+                .iter()
+                .copied()
+                .filter(|&other| other != UNUSABLE_TILE)
+                .collect();
+            assert_eq!(&kept, minimized_list);
+        }
+    }
+    assert!(
+        minimized
+            .adjacency_list(UNUSABLE_TILE)
+            .iter()
+            .all(Vec::is_empty)
+    );
+}
+
+#[test]
+fn duplicates_are_reported() {
+    let mut adjacency = Array3::from_elem((3, 3, 2), true);
+    for dir in 0..2 {
+        adjacency[[0, 0, dir]] = false;
+    }
+    let mut rules = Rules::new(adjacency, vec![1, 1, 1]);
+    assert_eq!(rules.clone().minimize().duplicates, vec![vec![1, 2]]);
+    // The hole tile is chosen for holes before any other, so it is never interchangeable
+    rules.set_hole_tile(Some(2));
+    assert!(rules.minimize().is_empty());
+}
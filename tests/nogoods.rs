@@ -0,0 +1,109 @@
+//! Checks that backtracking learns which combinations of decisions lead to a dead end, so it does not walk
+//! into the same one again by another route, and that learning never changes which maps are valid.
+
+use ndarray::Array3;
+use rand::SeedableRng;
+use rand_pcg::Pcg64;
+use wave_function::{
+    Cell, Direction, Heuristic, Map, Rules, WaveFunction, WaveFunctionBacktracking,
+};
+
+const SEEDS: u64 = 5;
+const GADGET: usize = 3; // Width of the two by three block of gadget tiles on the left of the map
+const WIDTH: usize = GADGET + 2;
+
+// Four gadget tiles with one-way adjacencies, under which the first in the top left corner of a two by three
+// block propagates without contradiction, though no block can be filled around it, and two free tiles which
+// may sit next to anything. The first gadget tile is by far the most common, so it is tried first.
+fn rules() -> Rules {
+    let mut adjacency = Array3::from_elem((6, 6, 2), true);
+    for tile in 0..4 {
+        for other in 0..4 {
+            for dir in 0..2 {
+                adjacency[[tile, other, dir]] = false;
+            }
+        }
+    }
+    for index in [
+        [0, 0, 1],
+        [0, 2, 0],
+        [0, 2, 1],
+        [0, 3, 0],
+        [1, 1, 0],
+        [1, 3, 1],
+        [2, 1, 1],
+        [2, 3, 0],
+        [3, 0, 0],
+        [3, 0, 1],
+        [3, 1, 0],
+        [3, 1, 1],
+        [3, 2, 0],
+    ] {
+        adjacency[index] = true;
+    }
+    Rules::new(adjacency, vec![100, 1, 1, 1, 1, 1])
+}
+
+// The gadget's corner first, then the free cells, then the rest of the gadget, so every combination of free
+// tiles is decided between the choice which dooms the gadget and the dead end it leads to
+fn cost(pos: (usize, usize), _entropy: usize) -> f64 {
+    let order = if pos == (0, 0) {
+        0
+    } else if pos.1 >= GADGET {
+        1 + pos.1 * 2 + pos.0
+    } else {
+        100 + pos.1 * 2 + pos.0
+    };
+    order as f64
+}
+
+fn template() -> Map {
+    let mut template = Map::empty((2, WIDTH));
+    for y in 0..2 {
+        for x in 0..WIDTH {
+            let tiles: &[usize] = if x < GADGET { &[0, 1, 2, 3] } else { &[4, 5] };
+            template.restrict((y, x), tiles);
+        }
+    }
+    template
+}
+
+fn obeys(map: &Map, rules: &Rules) -> bool {
+    let tile = |pos| match map[pos] {
+        Cell::Fixed(tile) => tile,
+        _ => panic!("Cell {pos:?} was not collapsed"),
+    };
+    (0..2).all(|y| {
+        (0..WIDTH).all(|x| {
+            (x + 1 == WIDTH || rules.is_allowed(tile((y, x)), tile((y, x + 1)), Direction::East))
+                && (y == 1 || rules.is_allowed(tile((y + 1, x)), tile((y, x)), Direction::North))
+        })
+    })
+}
+
+#[test]
+fn learning_skips_known_dead_ends() {
+    let rules = rules();
+    let template = template();
+    let search = |max_nogood_size| {
+        WaveFunctionBacktracking::builder()
+            .heuristic(Heuristic::Custom(cost))
+            .max_nogood_size(max_nogood_size)
+            .build()
+    };
+    for seed in 0..SEEDS {
+        let [(learned, learning), (unlearned, forgetting)] = [16, 0].map(|max_nogood_size| {
+            search(max_nogood_size)
+                .collapse_with_stats(&template, &rules, &mut Pcg64::seed_from_u64(seed))
+                .unwrap()
+        });
+        assert!(obeys(&learned, &rules), "seed {seed}: {learned}");
+        assert!(obeys(&unlearned, &rules), "seed {seed}: {unlearned}");
+        assert!(
+            learning.backtracks < forgetting.backtracks,
+            "seed {seed}: {} backtracks learning, {} without",
+            learning.backtracks,
+            forgetting.backtracks
+        );
+    }
+}
@@ -0,0 +1,130 @@
+//! Checks that map packets survive a round trip, and that truncated, oversized and corrupt packets, which may
+//! arrive from anywhere over a network, give errors rather than panics or huge allocations.
+
+use ndarray::Array2;
+use wave_function::{Cell, MAX_PACKET_CELLS, Map, PacketCompression};
+
+// A small map using every kind of cell, with runs long enough to be run-length encoded
+fn map() -> Map {
+    Map::new(Array2::from_shape_fn((6, 9), |(y, x)| match (y + x) % 7 {
+        0 => Cell::Ignore,
+        1 => Cell::Wildcard,
+        2 => Cell::Hole,
+        _ => Cell::Fixed(y / 2 + 300 * (x / 4)),
+    }))
+}
+
+fn same_cells(a: &Map, b: &Map) -> bool {
+    let (height, width) = a.size();
+    a.size() == b.size() && (0..height).all(|y| (0..width).all(|x| a[(y, x)] == b[(y, x)]))
+}
+
+fn compressions() -> Vec<PacketCompression> {
+    vec![
+        PacketCompression::None,
+        #[cfg(feature = "zstd")]
+        PacketCompression::Zstd(3),
+    ]
+}
+
+// Header and body of an uncompressed full packet of the current version
+fn raw_packet(body: &[u8]) -> Vec<u8> {
+    [b"WFP".as_slice(), &[2, 0], body].concat()
+}
+
+#[test]
+fn packets_round_trip() {
+    let map = map();
+    let mut edited = map.clone();
+    edited[(3, 4)] = Cell::Fixed(7);
+    for compression in compressions() {
+        let decoded = Map::from_packet(&map.to_packet(compression), None).unwrap();
+        assert!(same_cells(&decoded, &map), "{compression:?}");
+
+        let delta = edited.to_delta_packet(&map, compression).unwrap();
+        let decoded = Map::from_packet(&delta, Some(&map)).unwrap();
+        assert!(same_cells(&decoded, &edited), "{compression:?}");
+        assert!(Map::from_packet(&delta, None).is_err());
+        assert!(Map::from_packet(&delta, Some(&Map::empty((2, 2)))).is_err());
+    }
+}
+
+#[test]
+fn truncated_packets_fail() {
+    for compression in compressions() {
+        let packet = map().to_packet(compression);
+        for length in 0..packet.len() {
+            assert!(
+                Map::from_packet(&packet[..length], None).is_err(),
+                "{compression:?} packet cut to {length} bytes decoded"
+            );
+        }
+    }
+}
+
+#[test]
+fn corrupt_packets_fail_without_panicking() {
+    for compression in compressions() {
+        let packet = map().to_packet(compression);
+        for index in 0..packet.len() {
+            for flip in [0x01, 0x80, 0xFF] {
+                let mut corrupt = packet.clone();
+                corrupt[index] ^= flip;
+                // Some corruptions still decode to a valid map, but none may panic
+                let _ = Map::from_packet(&corrupt, None);
+            }
+        }
+    }
+    let mut trailing = map().to_packet(PacketCompression::None);
+    trailing.push(0);
+    assert!(Map::from_packet(&trailing, None).is_err());
+    // A run past the end of the map, and one so long that adding it to the cells so far would overflow
+    assert!(Map::from_packet(&raw_packet(&[1, 2, 3, 5]), None).is_err());
+    let overflowing = [[1, 2, 1, 5].as_slice(), &[0xFF; 9], &[0x01, 5]].concat();
+    assert!(Map::from_packet(&raw_packet(&overflowing), None).is_err());
+    assert!(Map::from_packet(b"WFP\x09\x00\x01\x01\x01\x00", None).is_err());
+}
+
+#[test]
+fn oversized_packets_fail_before_allocating() {
+    // Dimensions whose product overflows, and a tiny packet claiming one run over the largest allowed map
+    let huge = [[0xFF; 9].as_slice(), &[0x01], &[0xFF; 9], &[0x01]].concat();
+    assert!(Map::from_packet(&raw_packet(&huge), None).is_err());
+    let side = 1 << 13;
+    let mut too_many = Vec::new();
+    for value in [side, 2 * side + 1, 1, 0] {
+        let mut value: usize = value;
+        while value >= 0x80 {
+            too_many.push((value as u8 & 0x7F) | 0x80);
+            value >>= 7;
+        }
+        too_many.push(value as u8);
+    }
+    assert!(side * (2 * side + 1) > MAX_PACKET_CELLS);
+    assert!(Map::from_packet(&raw_packet(&too_many), None).is_err());
+
+    let packet = map().to_packet(PacketCompression::None);
+    assert!(Map::from_packet_with_limit(&packet, None, 6 * 9 - 1).is_err());
+    assert!(Map::from_packet_with_limit(&packet, None, 6 * 9).is_ok());
+}
+
+// A packet whose body expands far beyond anything a valid packet could hold stops decompressing at the
+// trailing bytes rather than expanding in full
+#[cfg(feature = "zstd")]
+#[test]
+fn compression_bombs_fail() {
+    let mut body = vec![1, 1, 1, 3];
+    body.resize(64 << 20, 0);
+    let compressed = zstd::encode_all(&body[..], 19).unwrap();
+    let packet = [b"WFP".as_slice(), &[2, 0b10], &compressed].concat();
+    assert!(Map::from_packet(&packet, None).is_err());
+}
+
+#[test]
+fn version_one_packets_decode_without_holes() {
+    let packet = b"WFP\x01\x00\x01\x03\x01\x02\x01\x00\x01\x05";
+    let map = Map::from_packet(packet, None).unwrap();
+    assert!(map[(0, 0)] == Cell::Fixed(0));
+    assert!(map[(0, 1)] == Cell::Ignore);
+    assert!(map[(0, 2)] == Cell::Fixed(3));
+}
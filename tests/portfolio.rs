@@ -0,0 +1,115 @@
+//! Checks that the portfolio finds valid maps under each of its search settings, and that it gives up with
+//! an error, rather than running forever, when there is no map to find.
+
+use ndarray::Array3;
+use rand::SeedableRng;
+use rand_pcg::Pcg64;
+use wave_function::{
+    Boundary, Cell, CollapseOptions, Consistency, Map, ReviseStrategy, Rules, WaveFunction,
+    WaveFunctionPortfolio,
+};
+
+const SEEDS: u64 = 5;
+const SIZE: (usize, usize) = (8, 8);
+
+// Three tiles which may go anywhere, except that the last may not sit next to itself
+fn rules() -> Rules {
+    let mut adjacency = Array3::from_elem((3, 3, 2), true);
+    for dir in 0..2 {
+        adjacency[[2, 2, dir]] = false;
+    }
+    Rules::new(adjacency, vec![1, 1, 1])
+}
+
+fn obeys(map: &Map) -> bool {
+    let (height, width) = map.size();
+    let tile = |pos| match map[pos] {
+        Cell::Fixed(tile) => tile,
+        _ => panic!("Cell {pos:?} was not collapsed"),
+    };
+    (0..height).all(|y| {
+        (0..width).all(|x| {
+            let is_last = tile((y, x)) == 2;
+            !(is_last && x + 1 < width && tile((y, x + 1)) == 2)
+                && !(is_last && y + 1 < height && tile((y + 1, x)) == 2)
+        })
+    })
+}
+
+#[test]
+fn portfolios_find_valid_maps() {
+    let rules = rules();
+    for consistency in [Consistency::default(), Consistency::ForwardChecking] {
+        for revise_strategy in [ReviseStrategy::default(), ReviseStrategy::LastSupport] {
+            let portfolio = WaveFunctionPortfolio::builder()
+                .threads(3)
+                .consistency(consistency)
+                .revise_strategy(revise_strategy)
+                .build();
+            for seed in 0..SEEDS {
+                let map = portfolio
+                    .collapse(&Map::empty(SIZE), &rules, &mut Pcg64::seed_from_u64(seed))
+                    .unwrap();
+                assert!(
+                    obeys(&map),
+                    "{consistency:?}, {revise_strategy:?}, seed {seed}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn portfolios_record_their_settings() {
+    let portfolio = WaveFunctionPortfolio::builder()
+        .threads(2)
+        .consistency(Consistency::ForwardChecking)
+        .revise_strategy(ReviseStrategy::LastSupport)
+        .build();
+    let map = portfolio
+        .collapse(&Map::empty(SIZE), &rules(), &mut Pcg64::seed_from_u64(0))
+        .unwrap();
+    let settings = &map.provenance().unwrap().settings;
+    for (name, value) in [
+        ("threads", "2"),
+        ("consistency", "ForwardChecking"),
+        ("revise_strategy", "LastSupport"),
+    ] {
+        assert!(
+            settings.contains(&(name.to_string(), value.to_string())),
+            "{settings:?}"
+        );
+    }
+}
+
+// Two tiles which may not sit next to themselves, which cannot fill a wrapped map of odd width, though
+// propagation alone never finds a contradiction
+fn two_colouring() -> Rules {
+    let mut adjacency = Array3::from_elem((2, 2, 2), true);
+    for tile in 0..2 {
+        for dir in 0..2 {
+            adjacency[[tile, tile, dir]] = false;
+        }
+    }
+    Rules::new(adjacency, vec![1, 1])
+}
+
+#[test]
+fn portfolios_fail_without_a_solution() {
+    let options = CollapseOptions {
+        boundary: Boundary::Wrap,
+        ..CollapseOptions::default()
+    };
+    for seed in 0..SEEDS {
+        let result = WaveFunctionPortfolio::builder()
+            .threads(2)
+            .build()
+            .collapse_with_options(
+                &Map::empty((3, 3)),
+                &two_colouring(),
+                &options,
+                &mut Pcg64::seed_from_u64(seed),
+            );
+        assert!(result.is_err(), "seed {seed}");
+    }
+}
@@ -0,0 +1,67 @@
+//! Checks that a collapser finishes high-priority regions before it touches the rest of the map, and that
+//! regions reaching outside the map are refused.
+
+use ndarray::Array3;
+use wave_function::{Cell, Collapser, Map, Rules};
+
+const SIZE: (usize, usize) = (10, 10);
+
+fn rules() -> Rules {
+    let mut adjacency = Array3::from_elem((3, 3, 2), true);
+    for dir in 0..2 {
+        adjacency[[2, 2, dir]] = false;
+    }
+    Rules::new(adjacency, vec![1, 1, 1])
+}
+
+fn is_fixed(map: &Map, pos: (usize, usize)) -> bool {
+    matches!(map[pos], Cell::Fixed(_))
+}
+
+#[test]
+fn regions_are_collapsed_first() {
+    let rules = rules();
+    for seed in 0..5 {
+        let mut collapser = Collapser::new(&Map::empty(SIZE), &rules).unwrap();
+        collapser.seed(seed);
+        collapser.set_region_priority(2..5, 3..7, 2).unwrap();
+        collapser.set_region_priority(7..10, 0..2, 1).unwrap();
+        assert!(!collapser.is_region_complete(2));
+
+        collapser.run_region(2).unwrap();
+        assert!(collapser.is_region_complete(2));
+        let map = collapser.to_map();
+        for y in 2..5 {
+            for x in 3..7 {
+                assert!(is_fixed(&map, (y, x)), "seed {seed}: {map}");
+            }
+        }
+        // Nothing outside the region has been decided, though propagation may have fixed some cells
+        assert!(
+            collapser
+                .trail()
+                .iter()
+                .all(|&((y, x), _)| (2..5).contains(&y) && (3..7).contains(&x))
+        );
+
+        collapser.run_region(1).unwrap();
+        assert!(collapser.is_region_complete(1));
+        assert!(!collapser.is_complete());
+        collapser.run().unwrap();
+        assert!(collapser.is_region_complete(0));
+    }
+}
+
+#[test]
+fn regions_must_lie_within_the_map() {
+    let rules = rules();
+    let mut collapser = Collapser::new(&Map::empty(SIZE), &rules).unwrap();
+    assert!(collapser.set_region_priority(8..11, 0..2, 1).is_err());
+    assert!(collapser.set_region_priority(0..2, 5..12, 1).is_err());
+    assert!(collapser.priorities().iter().all(|&priority| priority == 0));
+    collapser.set_region_priority(8..10, 0..10, 1).unwrap();
+    assert_eq!(
+        collapser.priorities().iter().filter(|&&p| p == 1).count(),
+        20
+    );
+}
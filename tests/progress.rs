@@ -0,0 +1,121 @@
+//! Checks that a progress sink shared by collapses running in parallel can tell their reports apart.
+
+use ndarray::Array3;
+use rand::SeedableRng;
+use rand_pcg::Pcg64;
+use std::{
+    collections::BTreeMap,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+};
+use wave_function::{
+    AlgorithmInfo, CollapseOptions, LogProgress, Map, ProgressHandle, ProgressSink, Rules,
+    WaveFunction, WaveFunctionBacktracking, WaveFunctionFast,
+};
+
+const NUM_THREADS: u64 = 4;
+const SIZE: (usize, usize) = (12, 12);
+
+#[derive(Debug, PartialEq)]
+enum Report {
+    Start(usize),
+    Update(usize),
+    Finish,
+}
+
+// Sink recording every report by the handle it was made with
+#[derive(Default)]
+struct Recorder {
+    reports: Mutex<BTreeMap<ProgressHandle, Vec<Report>>>,
+    next_handle: AtomicUsize,
+}
+
+impl ProgressSink for Recorder {
+    fn start(&self, _info: &AlgorithmInfo, total: usize) -> ProgressHandle {
+        let handle = ProgressHandle(self.next_handle.fetch_add(1, Ordering::Relaxed));
+        self.reports
+            .lock()
+            .unwrap()
+            .insert(handle, vec![Report::Start(total)]);
+        handle
+    }
+
+    fn update(&self, handle: ProgressHandle, collapsed: usize) {
+        let mut reports = self.reports.lock().unwrap();
+        reports
+            .get_mut(&handle)
+            .unwrap()
+            .push(Report::Update(collapsed));
+    }
+
+    fn finish(&self, handle: ProgressHandle) {
+        let mut reports = self.reports.lock().unwrap();
+        reports.get_mut(&handle).unwrap().push(Report::Finish);
+    }
+}
+
+fn rules() -> Rules {
+    let mut adjacency = Array3::from_elem((3, 3, 2), true);
+    for dir in 0..2 {
+        adjacency[[2, 2, dir]] = false;
+    }
+    Rules::new(adjacency, vec![1, 1, 1])
+}
+
+// Collapse a map on each of several threads at once, all reporting to the same sink
+fn collapse_in_parallel(algorithm: &(impl WaveFunction + Sync), sink: Arc<dyn ProgressSink>) {
+    let rules = rules();
+    let options = CollapseOptions {
+        progress: sink,
+        ..CollapseOptions::default()
+    };
+    thread::scope(|scope| {
+        for seed in 0..NUM_THREADS {
+            let (rules, options) = (&rules, &options);
+            scope.spawn(move || {
+                algorithm
+                    .collapse_with_options(
+                        &Map::empty(SIZE),
+                        rules,
+                        options,
+                        &mut Pcg64::seed_from_u64(seed),
+                    )
+                    .unwrap();
+            });
+        }
+    });
+}
+
+#[test]
+fn parallel_collapses_report_separately() {
+    let recorder = Arc::new(Recorder::default());
+    collapse_in_parallel(&WaveFunctionFast::default(), recorder.clone());
+
+    let reports = recorder.reports.lock().unwrap();
+    assert_eq!(reports.len(), NUM_THREADS as usize);
+    for collapse in reports.values() {
+        let Some(Report::Start(total)) = collapse.first() else {
+            panic!("Collapse did not start first: {collapse:?}");
+        };
+        assert_eq!(collapse.last(), Some(&Report::Finish));
+        let mut last = 0;
+        for report in &collapse[1..collapse.len() - 1] {
+            let Report::Update(collapsed) = *report else {
+                panic!("Collapse reported out of order: {collapse:?}");
+            };
+            assert!(last <= collapsed && collapsed <= *total, "{collapse:?}");
+            last = collapsed;
+        }
+    }
+}
+
+#[test]
+fn log_sinks_are_shared_between_threads() {
+    collapse_in_parallel(
+        &WaveFunctionBacktracking::default(),
+        Arc::new(LogProgress::default()),
+    );
+}
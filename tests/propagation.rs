@@ -0,0 +1,57 @@
+//! Checks that propagation revises cells which are already fixed, so fixed cells which break the rules are
+//! reported as contradictions rather than collapsing around them.
+
+use ndarray::{Array2, Array3};
+use rand::SeedableRng;
+use rand_pcg::Pcg64;
+use wave_function::{Cell, Collapser, Map, Rules, WaveFunction, WaveFunctionFast};
+
+// Two tiles which may only sit beside themselves
+fn rules() -> Rules {
+    let mut adjacency = Array3::from_elem((2, 2, 2), false);
+    for tile in 0..2 {
+        for dir in 0..2 {
+            adjacency[[tile, tile, dir]] = true;
+        }
+    }
+    Rules::new(adjacency, vec![1, 1])
+}
+
+// Tiles fixed side by side, and one above the other, which neither may be
+fn templates() -> [Map; 2] {
+    let mut beside = Map::new(Array2::from_elem((3, 4), Cell::Wildcard));
+    beside[(1, 1)] = Cell::Fixed(0);
+    beside[(1, 2)] = Cell::Fixed(1);
+    let mut above = Map::new(Array2::from_elem((4, 3), Cell::Wildcard));
+    above[(1, 1)] = Cell::Fixed(0);
+    above[(2, 1)] = Cell::Fixed(1);
+    [beside, above]
+}
+
+#[test]
+fn fixed_cells_breaking_the_rules_are_contradictions() {
+    let rules = rules();
+    for template in templates() {
+        assert!(Collapser::new(&template, &rules).is_err());
+        assert!(
+            WaveFunctionFast::default()
+                .collapse(&template, &rules, &mut Pcg64::seed_from_u64(0))
+                .is_err()
+        );
+    }
+}
+
+#[test]
+fn fixed_cells_keeping_the_rules_collapse() {
+    let rules = rules();
+    let mut template = Map::new(Array2::from_elem((3, 4), Cell::Wildcard));
+    template[(1, 1)] = Cell::Fixed(1);
+    let map = WaveFunctionFast::default()
+        .collapse(&template, &rules, &mut Pcg64::seed_from_u64(0))
+        .unwrap();
+    for y in 0..3 {
+        for x in 0..4 {
+            assert!(map[(y, x)] == Cell::Fixed(1));
+        }
+    }
+}
@@ -0,0 +1,113 @@
+//! Checks that the options hash recorded in provenance changes with every setting which changes what an
+//! algorithm generates, and that those settings are recorded with it.
+
+use ndarray::Array3;
+use rand::SeedableRng;
+use rand_pcg::Pcg64;
+use wave_function::{
+    Consistency, Map, ReviseStrategy, Rules, WaveFunction, WaveFunctionBacktracking,
+    WaveFunctionFast, WaveFunctionLocalReset, WaveFunctionMinCost,
+};
+
+fn rules() -> Rules {
+    let mut adjacency = Array3::from_elem((3, 3, 2), true);
+    for dir in 0..2 {
+        adjacency[[2, 2, dir]] = false;
+    }
+    Rules::new(adjacency, vec![1, 1, 1])
+}
+
+fn options_hash(algorithm: &impl WaveFunction) -> u64 {
+    let map = algorithm
+        .collapse(&Map::empty((6, 6)), &rules(), &mut Pcg64::seed_from_u64(0))
+        .unwrap();
+    map.provenance().unwrap().options_hash
+}
+
+#[test]
+fn hashes_follow_algorithm_settings() {
+    let hashes = [
+        options_hash(&WaveFunctionFast::default()),
+        options_hash(
+            &WaveFunctionFast::builder()
+                .consistency(Consistency::ForwardChecking)
+                .build(),
+        ),
+        options_hash(
+            &WaveFunctionFast::builder()
+                .revise_strategy(ReviseStrategy::LastSupport)
+                .build(),
+        ),
+        options_hash(&WaveFunctionBacktracking::default()),
+        options_hash(
+            &WaveFunctionBacktracking::builder()
+                .max_nogood_size(4)
+                .build(),
+        ),
+        options_hash(&WaveFunctionLocalReset::default()),
+        options_hash(&WaveFunctionLocalReset::builder().radius(5).build()),
+        options_hash(&WaveFunctionLocalReset::builder().max_resets(3).build()),
+    ];
+    for (i, hash) in hashes.iter().enumerate() {
+        assert!(!hashes[..i].contains(hash), "{i}: {hashes:x?}");
+    }
+    // Settings left at their defaults hash the same
+    assert_eq!(
+        hashes[0],
+        options_hash(
+            &WaveFunctionFast::builder()
+                .consistency(Consistency::default())
+                .build()
+        )
+    );
+}
+
+#[test]
+fn hashes_follow_min_cost_settings() {
+    let costs = Array3::from_elem((3, 3, 2), 1.0);
+    let search = |max_sweeps, costs| {
+        WaveFunctionMinCost::builder()
+            .max_sweeps(max_sweeps)
+            .build(WaveFunctionFast::default(), costs)
+    };
+    let hashes = [
+        options_hash(&WaveFunctionFast::default()),
+        options_hash(&search(4, costs.clone())),
+        options_hash(&search(8, costs.clone())),
+        options_hash(&search(4, costs.mapv(|cost| cost * 2.0))),
+    ];
+    for (i, hash) in hashes.iter().enumerate() {
+        assert!(!hashes[..i].contains(hash), "{i}: {hashes:x?}");
+    }
+}
+
+#[test]
+fn settings_are_recorded_and_saved() {
+    let algorithm = WaveFunctionBacktracking::builder()
+        .consistency(Consistency::ForwardChecking)
+        .max_nogood_size(4)
+        .build();
+    let map = algorithm
+        .collapse(&Map::empty((6, 6)), &rules(), &mut Pcg64::seed_from_u64(0))
+        .unwrap();
+    let provenance = map.provenance().unwrap();
+    let setting = |name: &str| {
+        provenance
+            .settings
+            .iter()
+            .find(|(setting, _)| setting == name)
+            .map(|(_, value)| value.as_str())
+    };
+    assert_eq!(setting("consistency"), Some("ForwardChecking"));
+    assert_eq!(setting("revise_strategy"), Some("Scan"));
+    assert_eq!(setting("max_nogood_size"), Some("4"));
+
+    let path = std::env::temp_dir().join("wave_function_provenance_settings.txt");
+    let path = path.to_str().unwrap();
+    map.save(path).unwrap();
+    let loaded = Map::load(path).unwrap();
+    std::fs::remove_file(path).unwrap();
+    let loaded = loaded.provenance().unwrap();
+    assert_eq!(loaded.settings, provenance.settings);
+    assert_eq!(loaded.options_hash, provenance.options_hash);
+}
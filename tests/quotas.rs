@@ -0,0 +1,134 @@
+//! Checks that collapsed maps keep within the quotas of their categories, and that quotas which cannot be met
+//! are reported before collapsing, whether by counting alone or because too few cells can reach a category.
+
+use ndarray::{Array2, Array3};
+use rand::SeedableRng;
+use rand_pcg::Pcg64;
+use wave_function::{
+    Cell, Map, Quota, Rules, WaveFunction, WaveFunctionBacktracking, WaveFunctionFast, WfcError,
+    check_quotas,
+};
+
+const SIZE: (usize, usize) = (10, 10);
+const GROUND: usize = 0;
+const WATER: usize = 1;
+const EXIT: usize = 2;
+
+// Ground, water and exits, each its own category, which may sit anywhere
+fn rules() -> Rules {
+    let mut rules = Rules::new(Array3::from_elem((3, 3, 2), true), vec![4, 4, 1]);
+    rules.set_categories(vec![GROUND, WATER, EXIT]);
+    rules.set_quota(EXIT, Some(Quota::Count { min: 1, max: 1 }));
+    rules.set_quota(WATER, Some(Quota::Proportion { min: 0.1, max: 0.2 }));
+    rules
+}
+
+fn count(map: &Map, tile: usize) -> usize {
+    let (height, width) = map.size();
+    (0..height)
+        .flat_map(|y| (0..width).map(move |x| (y, x)))
+        .filter(|&pos| map[pos] == Cell::Fixed(tile))
+        .count()
+}
+
+// The reason given for quotas which cannot be met, failing if the collapse was refused for another reason
+fn reason(template: &Map, rules: &Rules) -> String {
+    let error = check_quotas(template, rules).unwrap_err();
+    let collapse_error = WaveFunctionFast::default()
+        .collapse(template, rules, &mut Pcg64::seed_from_u64(0))
+        .err()
+        .unwrap();
+    assert_eq!(collapse_error.to_string(), error.to_string());
+    match error.downcast_ref::<WfcError>() {
+        Some(WfcError::UnsatisfiableQuotas(reason)) => reason.clone(),
+        _ => panic!("Expected unsatisfiable quotas, found: {error}"),
+    }
+}
+
+#[test]
+fn collapsed_maps_keep_within_quotas() {
+    let rules = rules();
+    let template = Map::empty(SIZE);
+    check_quotas(&template, &rules).unwrap();
+    let algorithms: [Box<dyn Fn(u64) -> Map>; 2] = [
+        Box::new(|seed| {
+            WaveFunctionFast::default()
+                .collapse(&template, &rules, &mut Pcg64::seed_from_u64(seed))
+                .unwrap()
+        }),
+        Box::new(|seed| {
+            WaveFunctionBacktracking::default()
+                .collapse(&template, &rules, &mut Pcg64::seed_from_u64(seed))
+                .unwrap()
+        }),
+    ];
+    for collapse in &algorithms {
+        for seed in 0..5 {
+            let map = collapse(seed);
+            assert_eq!(count(&map, EXIT), 1, "seed {seed}: {map}");
+            assert!(
+                (10..=20).contains(&count(&map, WATER)),
+                "seed {seed}: {map}"
+            );
+        }
+    }
+}
+
+#[test]
+fn counting_bounds_are_checked() {
+    let template = Map::empty(SIZE);
+
+    let mut crowded = rules();
+    crowded.set_quota(GROUND, Some(Quota::at_least(95)));
+    assert!(reason(&template, &crowded).contains("need at least 106 cells together"));
+
+    // A tenth of nine cells is not a whole number of cells
+    let mut uneven = rules();
+    uneven.set_quota(WATER, Some(Quota::Proportion { min: 0.1, max: 0.1 }));
+    assert!(
+        reason(&Map::empty((3, 3)), &uneven)
+            .contains("needs at least 1 cells but allows at most 0")
+    );
+
+    let mut template = Map::empty(SIZE);
+    template[(0, 0)] = Cell::Fixed(EXIT);
+    template[(9, 9)] = Cell::Fixed(EXIT);
+    assert!(reason(&template, &rules()).contains("fixes 2 cells of category 2"));
+
+    // Every cell is limited to water or exits, which may fill at most 21 cells together
+    let mut template = Map::empty(SIZE);
+    for y in 0..SIZE.0 {
+        for x in 0..SIZE.1 {
+            template.restrict((y, x), &[WATER, EXIT]);
+        }
+    }
+    assert!(reason(&template, &rules()).contains("can only take tiles with quotas"));
+}
+
+#[test]
+fn categories_must_be_reachable() {
+    // Exits may not sit next to ground
+    let mut adjacency = Array3::from_elem((3, 3, 2), true);
+    for dir in 0..2 {
+        adjacency[[GROUND, EXIT, dir]] = false;
+        adjacency[[EXIT, GROUND, dir]] = false;
+    }
+    let mut rules = Rules::new(adjacency, vec![4, 4, 1]);
+    rules.set_categories(vec![GROUND, WATER, EXIT]);
+    rules.set_quota(EXIT, Some(Quota::at_least(1)));
+
+    // Ground everywhere but the middle cell, which can then only be ground or water
+    let mut template = Map::new(Array2::from_elem((3, 3), Cell::Fixed(GROUND)));
+    template[(1, 1)] = Cell::Wildcard;
+    assert!(reason(&template, &rules).contains("only 0 cells can take tiles of category 2"));
+
+    // With water around it, the middle cell can hold the exit
+    for pos in [(0, 1), (1, 0), (1, 2), (2, 1)] {
+        template[pos] = Cell::Fixed(WATER);
+    }
+    check_quotas(&template, &rules).unwrap();
+    let map = WaveFunctionFast::default()
+        .collapse(&template, &rules, &mut Pcg64::seed_from_u64(0))
+        .unwrap();
+    assert!(map[(1, 1)] == Cell::Fixed(EXIT));
+}
@@ -0,0 +1,134 @@
+//! Checks that collapses with restarts retry after contradictions with fresh seeds, fall back once the
+//! restarts run out, and return any other error at once.
+
+use anyhow::Result;
+use ndarray::Array3;
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use wave_function::{
+    AlgorithmInfo, Cell, Map, Rules, WaveFunction, WaveFunctionFast, WaveFunctionWithRestarts,
+    WfcError,
+};
+
+const SIZE: (usize, usize) = (6, 6);
+
+fn rules() -> Rules {
+    let mut adjacency = Array3::from_elem((3, 3, 2), true);
+    for dir in 0..2 {
+        adjacency[[2, 2, dir]] = false;
+    }
+    Rules::new(adjacency, vec![1, 1, 1])
+}
+
+// Fails with the given error for its first few collapses, then collapses with `WaveFunctionFast`
+struct FailsFirst {
+    failures: usize,
+    error: WfcError,
+    calls: AtomicUsize,
+}
+
+impl FailsFirst {
+    fn new(failures: usize, error: WfcError) -> Self {
+        Self {
+            failures,
+            error,
+            calls: AtomicUsize::new(0),
+        }
+    }
+
+    fn contradicting(failures: usize) -> Self {
+        Self::new(
+            failures,
+            WfcError::Contradiction {
+                cell: (0, 0),
+                direction: None,
+            },
+        )
+    }
+}
+
+impl WaveFunction for FailsFirst {
+    fn collapse(&self, map: &Map, rules: &Rules, rng: &mut impl Rng) -> Result<Map> {
+        if self.calls.fetch_add(1, Ordering::Relaxed) < self.failures {
+            return Err(self.error.clone().into());
+        }
+        WaveFunctionFast::default().collapse(map, rules, rng)
+    }
+
+    fn info(&self) -> AlgorithmInfo {
+        AlgorithmInfo {
+            name: "fails-first",
+            supports_backtracking: false,
+            deterministic: true,
+            complexity: String::new(),
+        }
+    }
+}
+
+fn is_complete(map: &Map) -> bool {
+    (0..SIZE.0).all(|y| (0..SIZE.1).all(|x| matches!(map[(y, x)], Cell::Fixed(_))))
+}
+
+#[test]
+fn contradictions_are_retried() {
+    let restarts = WaveFunctionWithRestarts::builder().max_restarts(5).build(
+        FailsFirst::contradicting(3),
+        FailsFirst::contradicting(usize::MAX),
+    );
+    let (map, stats) = restarts
+        .collapse_with_stats(&Map::empty(SIZE), &rules(), &mut Pcg64::seed_from_u64(0))
+        .unwrap();
+    assert!(is_complete(&map));
+    assert_eq!(stats.restarts, 3);
+}
+
+#[test]
+fn restarts_run_out_into_the_fallback() {
+    let algorithm = FailsFirst::contradicting(usize::MAX);
+    let fallback = FailsFirst::contradicting(0);
+    let restarts = WaveFunctionWithRestarts::builder()
+        .max_restarts(2)
+        .build(algorithm, fallback);
+    let (map, stats) = restarts
+        .collapse_with_stats(&Map::empty(SIZE), &rules(), &mut Pcg64::seed_from_u64(0))
+        .unwrap();
+    assert!(is_complete(&map));
+    assert_eq!(stats.restarts, 3);
+
+    // Restarting with no restarts left goes straight to the fallback
+    let restarts = WaveFunctionWithRestarts::builder().max_restarts(0).build(
+        FailsFirst::contradicting(usize::MAX),
+        WaveFunctionFast::default(),
+    );
+    let (map, stats) = restarts
+        .collapse_with_stats(&Map::empty(SIZE), &rules(), &mut Pcg64::seed_from_u64(0))
+        .unwrap();
+    assert!(is_complete(&map));
+    assert_eq!(stats.restarts, 1);
+}
+
+#[test]
+fn other_errors_are_not_retried() {
+    let error = WfcError::InvalidRules("broken".to_string());
+    let restarts = WaveFunctionWithRestarts::builder().max_restarts(5).build(
+        FailsFirst::new(1, error.clone()),
+        WaveFunctionFast::default(),
+    );
+    let result = restarts.collapse(&Map::empty(SIZE), &rules(), &mut Pcg64::seed_from_u64(0));
+    let failure = result.err().unwrap();
+    assert_eq!(failure.downcast_ref::<WfcError>(), Some(&error));
+}
+
+#[test]
+fn restarts_are_reproducible_from_the_seed() {
+    let rules = rules();
+    let collapse = |seed| {
+        WaveFunctionWithRestarts::builder()
+            .max_restarts(5)
+            .build(FailsFirst::contradicting(2), WaveFunctionFast::default())
+            .collapse(&Map::empty(SIZE), &rules, &mut Pcg64::seed_from_u64(seed))
+            .unwrap()
+    };
+    assert_eq!(collapse(7).to_string(), collapse(7).to_string());
+}
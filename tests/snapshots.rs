@@ -0,0 +1,110 @@
+//! Checks that collapser snapshots restore exactly the state they captured, nest like a stack, and are only
+//! restored into the collapser they were taken from.
+
+use fixedbitset::FixedBitSet;
+use ndarray::Array3;
+use wave_function::{Collapser, Map, Rules};
+
+const SIZE: (usize, usize) = (8, 8);
+
+// Three tiles where the last may not sit beside itself, so decisions narrow their neighbours
+fn rules() -> Rules {
+    let mut adjacency = Array3::from_elem((3, 3, 2), true);
+    for dir in 0..2 {
+        adjacency[[2, 2, dir]] = false;
+    }
+    Rules::new(adjacency, vec![1, 1, 1])
+}
+
+// Everything a snapshot should bring back: every cell's options and the decisions made
+fn state(collapser: &Collapser) -> (Vec<FixedBitSet>, Vec<((usize, usize), usize)>) {
+    let domains = (0..SIZE.0)
+        .flat_map(|y| (0..SIZE.1).map(move |x| (y, x)))
+        .map(|pos| collapser.domain(pos).clone())
+        .collect();
+    (domains, collapser.trail().to_vec())
+}
+
+fn steps(collapser: &mut Collapser, count: usize) {
+    for _ in 0..count {
+        collapser.step().unwrap();
+    }
+}
+
+#[test]
+fn snapshots_restore_in_stack_order() {
+    let rules = rules();
+    let mut collapser = Collapser::new(&Map::empty(SIZE), &rules).unwrap();
+    collapser.seed(1);
+
+    let first = collapser.snapshot();
+    let first_state = state(&collapser);
+    steps(&mut collapser, 5);
+    let second = collapser.snapshot();
+    let second_state = state(&collapser);
+    steps(&mut collapser, 5);
+
+    collapser.restore(&second).unwrap();
+    assert!(state(&collapser) == second_state);
+    // A snapshot may be returned to after exploring from it, as often as needed
+    steps(&mut collapser, 3);
+    collapser.restore(&second).unwrap();
+    assert!(state(&collapser) == second_state);
+
+    collapser.restore(&first).unwrap();
+    assert!(state(&collapser) == first_state);
+    assert!(collapser.restore(&second).is_err());
+    collapser.restore(&first).unwrap();
+    assert!(state(&collapser) == first_state);
+}
+
+#[test]
+fn restored_collapsers_repeat_their_choices() {
+    let rules = rules();
+    let mut collapser = Collapser::new(&Map::empty(SIZE), &rules).unwrap();
+    collapser.seed(2);
+    steps(&mut collapser, 4);
+    let snapshot = collapser.snapshot();
+    collapser.run().unwrap();
+    let finished = state(&collapser);
+
+    collapser.restore(&snapshot).unwrap();
+    collapser.run().unwrap();
+    assert!(state(&collapser) == finished);
+}
+
+#[test]
+fn snapshots_only_restore_into_their_collapser() {
+    let rules = rules();
+    let mut collapser = Collapser::new(&Map::empty(SIZE), &rules).unwrap();
+    let mut other = Collapser::new(&Map::empty(SIZE), &rules).unwrap();
+    let snapshot = collapser.snapshot();
+    steps(&mut other, 3);
+    let other_state = state(&other);
+    assert!(other.restore(&snapshot).is_err());
+    assert!(state(&other) == other_state);
+    collapser.restore(&snapshot).unwrap();
+}
+
+#[test]
+fn snapshots_restore_pending_propagation() {
+    let rules = rules();
+    let mut collapser = Collapser::new(&Map::empty(SIZE), &rules).unwrap();
+    collapser.seed(3);
+    collapser.set_propagation_budget(Some(1));
+    steps(&mut collapser, 3);
+    assert!(!collapser.is_settled());
+
+    let snapshot = collapser.snapshot();
+    let pending_state = state(&collapser);
+    collapser.settle().unwrap();
+    let settled_state = state(&collapser);
+    steps(&mut collapser, 3);
+
+    collapser.restore(&snapshot).unwrap();
+    assert!(!collapser.is_settled());
+    assert!(state(&collapser) == pending_state);
+    // The deferred work is still to do, and does the same as before
+    collapser.settle().unwrap();
+    assert!(state(&collapser) == settled_state);
+}
@@ -0,0 +1,181 @@
+//! Checks that soft holes stay empty wherever the rules allow, and are filled, and reported as filled, only
+//! where leaving them empty would break the rules.
+
+use ndarray::Array3;
+use rand::SeedableRng;
+use rand_pcg::Pcg64;
+use wave_function::{
+    ALL_DIRECTIONS, Cell, Collapser, Direction, Map, Rules, WaveFunction, WaveFunctionBacktracking,
+    WaveFunctionFast, WaveFunctionLocalReset, WaveFunctionPortfolio,
+};
+
+const SEEDS: u64 = 10;
+const EMPTY: usize = 0;
+const WALL: usize = 1;
+const FLOOR: usize = 2;
+const DOOR: usize = 3;
+
+// Empty space, walls, floor and doors, where walls go beside anything, empty space and floor never meet, and
+// doors only go beside walls, with floor far more common than the others so cells left to chance rarely come
+// out empty
+fn rules(hole_tile: Option<usize>) -> Rules {
+    let mut adjacency = Array3::from_elem((4, 4, 2), true);
+    for dir in 0..2 {
+        adjacency[[EMPTY, FLOOR, dir]] = false;
+        adjacency[[FLOOR, EMPTY, dir]] = false;
+        for tile in [EMPTY, FLOOR, DOOR] {
+            adjacency[[DOOR, tile, dir]] = false;
+            adjacency[[tile, DOOR, dir]] = false;
+        }
+    }
+    let mut rules = Rules::new(adjacency, vec![1, 1, 100, 1]);
+    rules.set_hole_tile(hole_tile);
+    rules
+}
+
+// A row of holes ending beside a fixed door, so the last hole must be filled with a wall
+fn template() -> Map {
+    let mut map = Map::empty((4, 6));
+    for x in 1..5 {
+        map[(1, x)] = Cell::Hole;
+    }
+    map[(1, 5)] = Cell::Fixed(DOOR);
+    map
+}
+
+fn holes() -> Vec<(usize, usize)> {
+    (1..5).map(|x| (1, x)).collect()
+}
+
+// Whether every cell is fixed to a tile allowing the tile of each neighbour inside the map
+fn obeys(map: &Map, rules: &Rules) -> bool {
+    let (height, width) = map.size();
+    let tile = |pos: (usize, usize)| match map[pos] {
+        Cell::Fixed(tile) => Some(tile),
+        _ => None,
+    };
+    (0..height).all(|y| {
+        (0..width).all(|x| {
+            ALL_DIRECTIONS.iter().all(|&dir| {
+                let (dy, dx) = match dir {
+                    Direction::North => (-1, 0),
+                    Direction::East => (0, 1),
+                    Direction::South => (1, 0),
+                    Direction::West => (0, -1),
+                };
+                let (ny, nx) = (y as isize + dy, x as isize + dx);
+                if ny < 0 || nx < 0 || ny >= height as isize || nx >= width as isize {
+                    return tile((y, x)).is_some();
+                }
+                match (tile((y, x)), tile((ny as usize, nx as usize))) {
+                    (Some(a), Some(b)) => rules.is_allowed(a, b, dir),
+                    _ => false,
+                }
+            })
+        })
+    })
+}
+
+fn check_solver(algorithm: &impl WaveFunction) {
+    let rules = rules(Some(EMPTY));
+    let template = template();
+    for seed in 0..SEEDS {
+        let map = algorithm
+            .collapse(&template, &rules, &mut Pcg64::seed_from_u64(seed))
+            .unwrap();
+        assert!(obeys(&map, &rules), "seed {seed}");
+        assert_eq!(
+            map.filled_holes(&template, &rules),
+            vec![(1, 4)],
+            "seed {seed}"
+        );
+        assert!(map[(1, 4)] == Cell::Fixed(WALL), "seed {seed}");
+    }
+}
+
+#[test]
+fn solvers_leave_holes_empty_where_the_rules_allow() {
+    check_solver(&WaveFunctionFast::default());
+    check_solver(&WaveFunctionBacktracking::default());
+    check_solver(&WaveFunctionLocalReset::default());
+    check_solver(&WaveFunctionPortfolio::default());
+}
+
+#[test]
+fn collapser_leaves_holes_empty_where_the_rules_allow() {
+    let rules = rules(Some(EMPTY));
+    let template = template();
+    for seed in 0..SEEDS {
+        let mut collapser = Collapser::new(&template, &rules).unwrap();
+        collapser.seed(seed);
+        collapser.run().unwrap();
+        let map = collapser.to_map();
+        assert!(obeys(&map, &rules), "seed {seed}");
+        assert_eq!(
+            map.filled_holes(&template, &rules),
+            vec![(1, 4)],
+            "seed {seed}"
+        );
+    }
+}
+
+#[test]
+fn holes_are_filled_without_a_hole_tile() {
+    let rules = rules(None);
+    let template = template();
+    let map = WaveFunctionBacktracking::default()
+        .collapse(&template, &rules, &mut Pcg64::seed_from_u64(0))
+        .unwrap();
+    assert!(obeys(&map, &rules));
+    assert_eq!(map.filled_holes(&template, &rules), holes());
+}
+
+// Four tiles with one-way adjacencies, under which a hole in the corner of a two by three map can take the
+// hole tile without propagation finding any contradiction, though no map has it empty
+fn dead_end_rules() -> Rules {
+    let mut adjacency = Array3::from_elem((4, 4, 2), false);
+    for index in [
+        [0, 0, 1],
+        [0, 2, 0],
+        [0, 2, 1],
+        [0, 3, 0],
+        [1, 1, 0],
+        [1, 3, 1],
+        [2, 1, 1],
+        [2, 3, 0],
+        [3, 0, 0],
+        [3, 0, 1],
+        [3, 1, 0],
+        [3, 1, 1],
+        [3, 2, 0],
+    ] {
+        adjacency[index] = true;
+    }
+    let mut rules = Rules::new(adjacency, vec![1, 1, 1, 1]);
+    rules.set_hole_tile(Some(EMPTY));
+    rules
+}
+
+#[test]
+fn searches_backtrack_out_of_leaving_a_hole_empty() {
+    let rules = dead_end_rules();
+    let mut template = Map::empty((2, 3));
+    template[(0, 0)] = Cell::Hole;
+    for seed in 0..SEEDS {
+        for map in [
+            WaveFunctionBacktracking::default()
+                .collapse(&template, &rules, &mut Pcg64::seed_from_u64(seed))
+                .unwrap(),
+            WaveFunctionPortfolio::default()
+                .collapse(&template, &rules, &mut Pcg64::seed_from_u64(seed))
+                .unwrap(),
+        ] {
+            assert!(obeys(&map, &rules), "seed {seed}");
+            assert_eq!(
+                map.filled_holes(&template, &rules),
+                vec![(0, 0)],
+                "seed {seed}"
+            );
+        }
+    }
+}
@@ -0,0 +1,61 @@
+//! Checks that views of a tileset can be taken by tag as well as by index, and keep the rules of the tiles
+//! they expose.
+//! Run with `cargo test --features render`.
+#![cfg(feature = "render")]
+
+use ndarray::Array3;
+use photo::ImageRGBA;
+use wave_function::{Direction, Rules, TileMetadata, Tileset};
+
+// Grass, sand and water, where water may not sit next to grass, tagged by where they are found
+fn tileset() -> Tileset {
+    let mut adjacency = Array3::from_elem((3, 3, 2), true);
+    for dir in 0..2 {
+        adjacency[[0, 2, dir]] = false;
+        adjacency[[2, 0, dir]] = false;
+    }
+    let tiles = (0..3)
+        .map(|tile| ImageRGBA::new(Array3::from_elem((1, 1, 4), tile)))
+        .collect();
+    let mut tileset = Tileset::new(1, 0, tiles, Rules::new(adjacency, vec![1, 2, 3]));
+    for (index, tags) in [(0, &["land"][..]), (1, &["land", "coast"]), (2, &["sea"])] {
+        tileset.set_metadata(
+            index,
+            TileMetadata {
+                tags: tags.iter().map(ToString::to_string).collect(),
+                ..TileMetadata::default()
+            },
+        );
+    }
+    tileset
+}
+
+#[test]
+fn views_are_taken_by_tag() {
+    let tileset = tileset();
+    assert_eq!(tileset.subset_tagged(&["land"]).indices(), [0, 1]);
+    assert_eq!(tileset.subset_tagged(&["sea", "coast"]).indices(), [1, 2]);
+    assert_eq!(
+        tileset.subset_tagged(&["coast"]).indices(),
+        tileset.subset(&[1]).indices()
+    );
+
+    let view = tileset.subset_tagged(&["land", "sea"]);
+    assert_eq!(view.len(), 3);
+    assert!(!view.rules().is_allowed(0, 2, Direction::East));
+    assert!(view.rules().is_allowed(1, 2, Direction::East));
+}
+
+#[test]
+fn views_are_never_empty() {
+    let tileset = tileset();
+    assert!(!tileset.is_empty());
+    assert!(!tileset.subset(&[2]).is_empty());
+    assert!(!tileset.subset_tagged(&["sea"]).is_empty());
+}
+
+#[test]
+#[should_panic(expected = "Tile subset must not be empty")]
+fn views_need_a_tagged_tile() {
+    let _ = tileset().subset_tagged(&["cave"]);
+}
@@ -0,0 +1,110 @@
+//! Checks that volumes keep their rules in all six directions, and that collapsing one backtracks out of
+//! contradictions rather than failing on the first.
+
+use ndarray::{Array2, Array3};
+use rand::SeedableRng;
+use rand_pcg::Pcg64;
+use wave_function::{
+    ALL_DIRECTIONS_3, Cell, CollapseOptions, Direction3, Map, Map3, Rules, Rules3, collapse_volume,
+    collapse_volume_with_options,
+};
+
+const SEEDS: u64 = 10;
+const SIZE: (usize, usize, usize) = (3, 3, 3);
+const CENTRE: (usize, usize, usize) = (1, 1, 1);
+
+// Three tiles which may go anywhere, except that the first needs the second next to it in one direction
+fn rules(direction: Direction3) -> Rules3 {
+    let mut layer = Array3::from_elem((3, 3, 2), true);
+    let mut vertical = Array2::from_elem((3, 3), true);
+    for other in [0, 2] {
+        match direction {
+            Direction3::North => layer[[0, other, 1]] = false,
+            Direction3::East => layer[[0, other, 0]] = false,
+            Direction3::South => layer[[other, 0, 1]] = false,
+            Direction3::West => layer[[other, 0, 0]] = false,
+            Direction3::Up => vertical[[0, other]] = false,
+            Direction3::Down => vertical[[other, 0]] = false,
+        }
+    }
+    Rules3::new(Rules::new(layer, vec![3, 1, 1]), &vertical)
+}
+
+fn tile(map: &Map3, pos: (usize, usize, usize)) -> usize {
+    match map[pos] {
+        Cell::Fixed(tile) => tile,
+        _ => panic!("Cell {pos:?} was not collapsed"),
+    }
+}
+
+#[test]
+fn volumes_keep_rules_in_every_direction() {
+    for direction in ALL_DIRECTIONS_3 {
+        let rules = rules(direction);
+        let mut template = Map3::empty(SIZE);
+        template[CENTRE] = Cell::Fixed(0);
+        for seed in 0..SEEDS {
+            let map = collapse_volume(&template, &rules, &mut Pcg64::seed_from_u64(seed)).unwrap();
+            assert!(rules.is_valid(&map), "{direction:?}, seed {seed}");
+            let neighbour = direction.apply_to(CENTRE, SIZE).unwrap();
+            assert_eq!(tile(&map, neighbour), 1, "{direction:?}, seed {seed}");
+            for pos in template.positions() {
+                if tile(&map, pos) == 0
+                    && let Some(other) = direction.apply_to(pos, SIZE)
+                {
+                    assert_eq!(tile(&map, other), 1, "{direction:?}, seed {seed}");
+                }
+            }
+        }
+    }
+}
+
+// Four tiles with one-way adjacencies, under which the first tile in the corner of a two by three floor
+// propagates without contradiction, though no floor can be filled around it
+fn dead_end_rules() -> Rules3 {
+    let mut layer = Array3::from_elem((4, 4, 2), false);
+    for index in [
+        [0, 0, 1],
+        [0, 2, 0],
+        [0, 2, 1],
+        [0, 3, 0],
+        [1, 1, 0],
+        [1, 3, 1],
+        [2, 1, 1],
+        [2, 3, 0],
+        [3, 0, 0],
+        [3, 0, 1],
+        [3, 1, 0],
+        [3, 1, 1],
+        [3, 2, 0],
+    ] {
+        layer[index] = true;
+    }
+    Rules3::new(
+        Rules::new(layer, vec![1; 4]),
+        &Array2::from_elem((4, 4), true),
+    )
+}
+
+#[test]
+fn volumes_backtrack_out_of_contradictions() {
+    let rules = dead_end_rules();
+    let template = Map3::from_floors(&[Map::empty((2, 3))]).unwrap();
+    let no_backtracking = CollapseOptions {
+        max_backtracks: 0,
+        ..CollapseOptions::default()
+    };
+    let mut needed_backtracking = false;
+    for seed in 0..4 * SEEDS {
+        let map = collapse_volume(&template, &rules, &mut Pcg64::seed_from_u64(seed)).unwrap();
+        assert!(rules.is_valid(&map), "seed {seed}");
+        needed_backtracking |= collapse_volume_with_options(
+            &template,
+            &rules,
+            &no_backtracking,
+            &mut Pcg64::seed_from_u64(seed),
+        )
+        .is_err();
+    }
+    assert!(needed_backtracking);
+}
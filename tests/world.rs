@@ -0,0 +1,92 @@
+//! Checks that the world generator takes the chunks nearest its focus points first, generates the prefetch
+//! ring in the background without breaking the rules across chunk edges, and always gives the same world.
+
+use ndarray::Array3;
+use wave_function::{Cell, Rules, WaveFunctionFast, WorldGenerator};
+
+const CHUNK_SIZE: (usize, usize) = (4, 4);
+
+// Three tiles which may go anywhere, except that the last may not sit next to itself
+fn rules() -> Rules {
+    let mut adjacency = Array3::from_elem((3, 3, 2), true);
+    for dir in 0..2 {
+        adjacency[[2, 2, dir]] = false;
+    }
+    Rules::new(adjacency, vec![1, 1, 1])
+}
+
+fn world(rules: &Rules) -> WorldGenerator<WaveFunctionFast> {
+    let mut world = WorldGenerator::builder()
+        .chunk_size(CHUNK_SIZE)
+        .view_radius(1)
+        .prefetch_radius(1)
+        .budget(12)
+        .build(WaveFunctionFast::default(), rules, 7);
+    world.set_focus_points(&[(1, 1)]);
+    world
+}
+
+// Every cell in the square around the origin, where it has been generated
+fn cells(world: &WorldGenerator<WaveFunctionFast>) -> Vec<Option<Cell>> {
+    (-12..12)
+        .flat_map(|y| (-12..12).map(move |x| (y, x)))
+        .map(|pos| world.get(pos))
+        .collect()
+}
+
+#[test]
+fn view_chunks_come_before_prefetch_chunks() {
+    let rules = rules();
+    let mut world = world(&rules);
+    let pending = world.pending();
+    assert_eq!(pending.len(), 25);
+    assert_eq!(pending[0], (0, 0));
+    assert!(
+        pending[..9]
+            .iter()
+            .all(|&(y, x)| y.abs() <= 1 && x.abs() <= 1)
+    );
+
+    // The view is generated at once, while the prefetch ring is left to the background
+    assert_eq!(world.tick().unwrap().len(), 9);
+    assert!(world.is_ready());
+    assert_eq!(world.num_chunks(), 9);
+    let started = 25 - 9 - world.pending().len();
+    assert!(started > 0);
+    assert_eq!(world.tick().unwrap().len(), started);
+    assert_eq!(world.num_chunks(), 9 + started);
+}
+
+#[test]
+fn prefetch_chunks_keep_the_rules_across_edges() {
+    let rules = rules();
+    let mut world = world(&rules);
+    while !world.pending().is_empty() {
+        world.tick().unwrap();
+    }
+    world.tick().unwrap();
+    assert_eq!(world.num_chunks(), 25);
+    let last = |pos| world.get(pos) == Some(Cell::Fixed(2));
+    for y in -12..12 {
+        for x in -12..12 {
+            assert!(!(last((y, x)) && last((y, x + 1))), "({y}, {x})");
+            assert!(!(last((y, x)) && last((y + 1, x))), "({y}, {x})");
+        }
+    }
+}
+
+#[test]
+fn worlds_are_deterministic() {
+    let rules = rules();
+    let generate = || {
+        let mut world = world(&rules);
+        for _ in 0..4 {
+            world.tick().unwrap();
+        }
+        cells(&world)
+    };
+    let first = generate();
+    for _ in 0..3 {
+        assert!(generate() == first);
+    }
+}